@@ -0,0 +1,453 @@
+//! Security alerting over audit events.
+//!
+//! Every `AuditEvent` passed to `log_event` — and every ERP ABAC denial, via
+//! `observe_erp_error` — is evaluated against the `AlertRule`s. A rule counts
+//! matching events per group (user, resource, ...) over a sliding window and
+//! raises an `Alert` once the threshold is reached. Raising an alert logs a
+//! CRITICAL `SECURITY_ALERT` event and runs the rule's responses
+//! (`AlertAction`) through responders registered by the app at startup.
+//!
+//! Rules are loaded from `{app_data}/alert_rules.json` when present,
+//! otherwise `default_rules()` apply.
+
+use super::{log_event, AuditEvent};
+use crate::erp::errors::ErpError;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Action of the events raised by this module; never evaluated against rules.
+pub const ALERT_ACTION: &str = "SECURITY_ALERT";
+
+/// Unacknowledged alerts kept in memory; the oldest are dropped first.
+const MAX_ACTIVE_ALERTS: usize = 500;
+
+/// Field pattern: exact match, or a prefix match when it ends with `*`.
+fn pattern_matches(pattern: &str, value: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => value.starts_with(prefix),
+        None => pattern == value,
+    }
+}
+
+/// Which events a rule counts. Unset fields match anything.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventMatch {
+    pub action: Option<String>,
+    pub user_id: Option<String>,
+    pub resource_id: Option<String>,
+    pub severity: Option<String>,
+}
+
+impl EventMatch {
+    pub fn matches(&self, event: &AuditEvent) -> bool {
+        let field =
+            |p: &Option<String>, v: &str| p.as_deref().is_none_or(|p| pattern_matches(p, v));
+        field(&self.action, &event.action)
+            && field(&self.user_id, &event.user_id)
+            && field(&self.resource_id, &event.resource_id)
+            && field(&self.severity, &event.severity)
+    }
+}
+
+/// How matching events are bucketed before counting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupBy {
+    #[default]
+    User,
+    Resource,
+    UserResource,
+    Global,
+}
+
+impl GroupBy {
+    fn key(&self, event: &AuditEvent) -> String {
+        match self {
+            GroupBy::User => event.user_id.clone(),
+            GroupBy::Resource => event.resource_id.clone(),
+            GroupBy::UserResource => format!("{}|{}", event.user_id, event.resource_id),
+            GroupBy::Global => String::new(),
+        }
+    }
+}
+
+/// Automatic response to an alert, carried out by a registered responder.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertAction {
+    /// Revoke the capability tokens issued to the alert's user (a collab
+    /// user id; ERP actor pubkeys own no tokens)
+    RevokeTokens,
+    /// Disconnect the alert's user from the collaboration server
+    DisconnectWs,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub description: String,
+    #[serde(default)]
+    pub matcher: EventMatch,
+    /// Matching events within `window_ms` that raise the alert
+    #[serde(default = "default_threshold")]
+    pub threshold: usize,
+    #[serde(default = "default_window_ms")]
+    pub window_ms: i64,
+    #[serde(default)]
+    pub group_by: GroupBy,
+    #[serde(default)]
+    pub actions: Vec<AlertAction>,
+}
+
+fn default_threshold() -> usize {
+    1
+}
+fn default_window_ms() -> i64 {
+    60_000
+}
+
+/// Built-in rules: bursts of collab write rejections and of ERP ABAC denials.
+pub fn default_rules() -> Vec<AlertRule> {
+    vec![
+        AlertRule {
+            id: "ws_write_rejected_burst".to_string(),
+            description: "Repeated read-only write attempts on the collaboration server"
+                .to_string(),
+            matcher: EventMatch {
+                action: Some("WRITE_REJECTED".to_string()),
+                ..Default::default()
+            },
+            threshold: 5,
+            window_ms: 60_000,
+            group_by: GroupBy::User,
+            // Dropping the socket alone lets the client reconnect with the
+            // same capability token
+            actions: vec![AlertAction::DisconnectWs, AlertAction::RevokeTokens],
+        },
+        AlertRule {
+            id: "erp_abac_deny_burst".to_string(),
+            description: "Burst of ERP permission denials".to_string(),
+            matcher: EventMatch {
+                action: Some("ERR_ABAC_DENY".to_string()),
+                ..Default::default()
+            },
+            threshold: 10,
+            window_ms: 60_000,
+            group_by: GroupBy::User,
+            // Grouped by ERP actor pubkey, which owns no collab tokens or
+            // sessions: raise the alert only
+            actions: Vec::new(),
+        },
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alert {
+    pub id: String,
+    pub rule_id: String,
+    pub description: String,
+    pub user_id: String,
+    /// Resource of the event that crossed the threshold
+    pub resource_id: String,
+    /// Matching events in the window when raised
+    pub count: usize,
+    pub first_event_ms: i64,
+    pub last_event_ms: i64,
+    pub raised_at: String,
+    /// Responses that were carried out
+    pub actions_taken: Vec<AlertAction>,
+    pub acknowledged: bool,
+}
+
+/// Rules plus per-(rule, group) sliding windows of event times.
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    windows: HashMap<(String, String), VecDeque<i64>>,
+    alerts: VecDeque<Alert>,
+}
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self {
+            rules,
+            windows: HashMap::new(),
+            alerts: VecDeque::new(),
+        }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Replace the rules; open windows are discarded.
+    pub fn set_rules(&mut self, rules: Vec<AlertRule>) {
+        self.rules = rules;
+        self.windows.clear();
+    }
+
+    /// Count `event` (at `at_ms`) against every rule and return the alerts it
+    /// raises. A group's window is emptied when it fires, so a sustained
+    /// burst raises one alert per `threshold` events.
+    pub fn observe(&mut self, event: &AuditEvent, at_ms: i64) -> Vec<Alert> {
+        if event.action == ALERT_ACTION {
+            return Vec::new();
+        }
+        let mut raised = Vec::new();
+        for rule in &self.rules {
+            if !rule.matcher.matches(event) {
+                continue;
+            }
+            let key = (rule.id.clone(), rule.group_by.key(event));
+            let window = self.windows.entry(key).or_default();
+            window.push_back(at_ms);
+            while window.front().is_some_and(|&t| t <= at_ms - rule.window_ms) {
+                window.pop_front();
+            }
+            if window.len() >= rule.threshold.max(1) {
+                raised.push(Alert {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    rule_id: rule.id.clone(),
+                    description: rule.description.clone(),
+                    user_id: event.user_id.clone(),
+                    resource_id: event.resource_id.clone(),
+                    count: window.len(),
+                    first_event_ms: *window.front().unwrap_or(&at_ms),
+                    last_event_ms: at_ms,
+                    raised_at: chrono::Utc::now().to_rfc3339(),
+                    actions_taken: Vec::new(),
+                    acknowledged: false,
+                });
+                window.clear();
+            }
+        }
+        raised
+    }
+
+    fn record(&mut self, alert: Alert) {
+        self.alerts.push_back(alert);
+        while self.alerts.len() > MAX_ACTIVE_ALERTS {
+            self.alerts.pop_front();
+        }
+    }
+
+    /// Unacknowledged alerts, newest first.
+    pub fn active(&self) -> Vec<Alert> {
+        self.alerts
+            .iter()
+            .rev()
+            .filter(|a| !a.acknowledged)
+            .cloned()
+            .collect()
+    }
+
+    pub fn acknowledge(&mut self, alert_id: &str) -> bool {
+        match self.alerts.iter_mut().find(|a| a.id == alert_id) {
+            Some(alert) => {
+                alert.acknowledged = true;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Carries out an `AlertAction`; returns whether anything was done.
+pub type Responder = Arc<dyn Fn(&Alert) -> bool + Send + Sync>;
+
+lazy_static! {
+    static ref ENGINE: Mutex<AlertEngine> = Mutex::new(AlertEngine::new(default_rules()));
+    static ref RESPONDERS: Mutex<HashMap<AlertAction, Responder>> = Mutex::new(HashMap::new());
+    static ref RULES_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Register the handler for `action` (replacing any previous one).
+pub fn register_responder(action: AlertAction, responder: Responder) {
+    RESPONDERS.lock().unwrap().insert(action, responder);
+}
+
+/// Load `{app_data_dir}/alert_rules.json` if present. Called from `run()` setup.
+pub fn configure(app_data_dir: &Path) {
+    let path = app_data_dir.join("alert_rules.json");
+    if let Ok(s) = fs::read_to_string(&path) {
+        match serde_json::from_str(&s) {
+            Ok(rules) => ENGINE.lock().unwrap().set_rules(rules),
+            Err(e) => eprintln!(
+                "⚠️  Invalid {}: {e}; using default alert rules",
+                path.display()
+            ),
+        }
+    }
+    *RULES_PATH.lock().unwrap() = Some(path);
+}
+
+pub fn rules() -> Vec<AlertRule> {
+    ENGINE.lock().unwrap().rules().to_vec()
+}
+
+/// Replace the rules and persist them.
+pub fn set_rules(rules: Vec<AlertRule>) -> std::io::Result<()> {
+    if let Some(path) = RULES_PATH.lock().unwrap().as_ref() {
+        fs::write(path, serde_json::to_vec_pretty(&rules)?)?;
+    }
+    ENGINE.lock().unwrap().set_rules(rules);
+    Ok(())
+}
+
+pub fn active_alerts() -> Vec<Alert> {
+    ENGINE.lock().unwrap().active()
+}
+
+pub fn acknowledge(alert_id: &str) -> bool {
+    ENGINE.lock().unwrap().acknowledge(alert_id)
+}
+
+/// Evaluate `event` against the rules, respond to and log any alerts raised.
+pub fn observe(event: &AuditEvent) {
+    let at_ms = match event.timestamp_ms() {
+        0 => chrono::Utc::now().timestamp_millis(),
+        t => t,
+    };
+    let raised = ENGINE.lock().unwrap().observe(event, at_ms);
+    for alert in raised {
+        raise(alert);
+    }
+}
+
+/// Evaluate an ERP error (e.g. an ABAC denial) as if it were an audit event
+/// with the error code as its action. Nothing is written to the audit log
+/// unless an alert is raised.
+pub fn observe_erp_error(actor: &str, resource_id: &str, error: &ErpError) {
+    observe(&AuditEvent::new(
+        actor,
+        error.code(),
+        resource_id,
+        &error.to_string(),
+        "WARN",
+    ));
+}
+
+fn raise(mut alert: Alert) {
+    let actions = ENGINE
+        .lock()
+        .unwrap()
+        .rules()
+        .iter()
+        .find(|r| r.id == alert.rule_id)
+        .map(|r| r.actions.clone())
+        .unwrap_or_default();
+    for action in actions {
+        let responder = RESPONDERS.lock().unwrap().get(&action).cloned();
+        if responder.is_some_and(|r| r(&alert)) {
+            alert.actions_taken.push(action);
+        }
+    }
+
+    log_event(AuditEvent::new(
+        &alert.user_id,
+        ALERT_ACTION,
+        &alert.resource_id,
+        &format!(
+            "{}: {} ({} events in window; actions: {:?})",
+            alert.rule_id, alert.description, alert.count, alert.actions_taken
+        ),
+        "CRITICAL",
+    ));
+    ENGINE.lock().unwrap().record(alert);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(user: &str, action: &str, resource: &str) -> AuditEvent {
+        AuditEvent::new(user, action, resource, "", "WARN")
+    }
+
+    fn rule(threshold: usize, window_ms: i64, group_by: GroupBy) -> AlertRule {
+        AlertRule {
+            id: "r".to_string(),
+            description: "test".to_string(),
+            matcher: EventMatch {
+                action: Some("WRITE_*".to_string()),
+                ..Default::default()
+            },
+            threshold,
+            window_ms,
+            group_by,
+            actions: vec![AlertAction::DisconnectWs],
+        }
+    }
+
+    #[test]
+    fn test_threshold_within_sliding_window() {
+        let mut engine = AlertEngine::new(vec![rule(3, 1_000, GroupBy::User)]);
+        let e = event("mallory", "WRITE_REJECTED", "doc1");
+        assert!(engine.observe(&e, 0).is_empty());
+        assert!(engine.observe(&e, 500).is_empty());
+        // The first event has left the window by t = 1000
+        assert!(engine.observe(&e, 1_000).is_empty());
+        let raised = engine.observe(&e, 1_200);
+        assert_eq!(raised.len(), 1);
+        assert_eq!(raised[0].count, 3);
+        assert_eq!(raised[0].first_event_ms, 500);
+        // The window restarts after firing
+        assert!(engine.observe(&e, 1_300).is_empty());
+    }
+
+    #[test]
+    fn test_groups_and_patterns() {
+        let mut engine = AlertEngine::new(vec![rule(2, 60_000, GroupBy::User)]);
+        assert!(engine
+            .observe(&event("a", "WRITE_REJECTED", "d"), 0)
+            .is_empty());
+        assert!(engine
+            .observe(&event("b", "WRITE_REJECTED", "d"), 1)
+            .is_empty());
+        assert!(engine.observe(&event("a", "WS_CONNECT", "d"), 2).is_empty());
+        assert!(engine.observe(&event("a", ALERT_ACTION, "d"), 3).is_empty());
+        assert_eq!(engine.observe(&event("a", "WRITE_DENIED", "d"), 4).len(), 1);
+
+        let mut global = AlertEngine::new(vec![rule(2, 60_000, GroupBy::Global)]);
+        assert!(global
+            .observe(&event("a", "WRITE_REJECTED", "d"), 0)
+            .is_empty());
+        assert_eq!(
+            global.observe(&event("b", "WRITE_REJECTED", "e"), 1).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_active_alerts_and_acknowledge() {
+        let mut engine = AlertEngine::new(vec![rule(1, 60_000, GroupBy::User)]);
+        for alert in engine.observe(&event("a", "WRITE_REJECTED", "d"), 0) {
+            engine.record(alert);
+        }
+        let active = engine.active();
+        assert_eq!(active.len(), 1);
+        assert!(engine.acknowledge(&active[0].id));
+        assert!(engine.active().is_empty());
+        assert!(!engine.acknowledge("missing"));
+    }
+
+    #[test]
+    fn test_rules_parse_with_defaults() {
+        let rules: Vec<AlertRule> = serde_json::from_str(
+            r#"[{"id":"x","description":"d","matcher":{"action":"ERR_ABAC_DENY"},"actions":["revoke_tokens"]}]"#,
+        )
+        .unwrap();
+        assert_eq!(rules[0].threshold, 1);
+        assert_eq!(rules[0].window_ms, 60_000);
+        assert_eq!(rules[0].group_by, GroupBy::User);
+        assert_eq!(rules[0].actions, [AlertAction::RevokeTokens]);
+
+        let ws = &default_rules()[0];
+        assert_eq!(ws.id, "ws_write_rejected_burst");
+        assert!(ws.actions.contains(&AlertAction::RevokeTokens));
+    }
+}
@@ -1,16 +1,17 @@
+//! Security audit log for documents and collaboration (WS_CONNECT,
+//! WRITE_REJECTED, BLOCK_SIGNED, ...).
+//!
+//! Events are stored in the same segmented hash chain as the ERP audit log
+//! (`erp::audit_log::ChainEntry`), in its `SECURITY_CHAIN` directory: the
+//! chain hash covers the whole event JSON, and `verify_log` uses the ERP
+//! verifier. `timeline` merges both logs into one view.
+
 use chrono::Utc;
-use lazy_static::lazy_static;
 use serde::{Deserialize, Serialize};
-use std::fs::{File, OpenOptions};
-use std::io::{BufRead, BufReader, Write};
-use std::sync::Mutex;
 
-lazy_static! {
-    static ref AUDIT_LOCK: Mutex<()> = Mutex::new(());
-    // Global state for hash chaining (in-memory only for prototype, lost on restart)
-    static ref LAST_HASH: Mutex<String> = Mutex::new("genesis_hash".to_string());
-}
+use crate::erp::audit_log::{self, ChainEntry, ErpAuditEntry, SECURITY_CHAIN};
 
+pub mod alerts;
 pub mod shipper;
 
 #[cfg(test)]
@@ -24,7 +25,10 @@ pub struct AuditEvent {
     pub resource_id: String, // e.g., Block ID, Doc ID, or "SYSTEM"
     pub details: String,
     pub severity: String, // "INFO", "WARN", "ERROR", "CRITICAL"
+    /// Chain links, filled in from the stored entry; not part of the hashed event
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub prev_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub hash: Option<String>,
 }
 
@@ -47,6 +51,57 @@ impl AuditEvent {
             hash: None,      // Calculated at append time
         }
     }
+
+    fn timestamp_ms(&self) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(&self.timestamp)
+            .map(|t| t.timestamp_millis())
+            .unwrap_or(0)
+    }
+}
+
+/// A security event with its chain links, as stored on disk.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SecurityAuditEntry {
+    pub chain_prev_hash: String,
+    /// SHA-256 of the event JSON (every field) + chain_prev_hash
+    pub chain_hash: String,
+    pub event: AuditEvent,
+}
+
+impl ChainEntry for SecurityAuditEntry {
+    type Payload = AuditEvent;
+    const GENESIS: &'static str = "security_genesis";
+
+    fn link(event: AuditEvent, chain_prev_hash: String, chain_hash: String) -> Self {
+        SecurityAuditEntry {
+            chain_prev_hash,
+            chain_hash,
+            event,
+        }
+    }
+    fn payload(&self) -> &AuditEvent {
+        &self.event
+    }
+    fn chain_prev_hash(&self) -> &str {
+        &self.chain_prev_hash
+    }
+    fn chain_hash(&self) -> &str {
+        &self.chain_hash
+    }
+    fn issued_at_ms(event: &AuditEvent) -> i64 {
+        event.timestamp_ms()
+    }
+}
+
+impl SecurityAuditEntry {
+    /// The event with `prev_hash` / `hash` filled from the chain links.
+    fn linked_event(self) -> AuditEvent {
+        AuditEvent {
+            prev_hash: Some(self.chain_prev_hash),
+            hash: Some(self.chain_hash),
+            ..self.event
+        }
+    }
 }
 
 pub fn log_event(event: AuditEvent) {
@@ -62,103 +117,181 @@ pub fn log_event(event: AuditEvent) {
         icon, event.timestamp, event.user_id, event.action, event.resource_id, event.details
     );
 
-    // 2. Append to persistent file
-    let _lock = AUDIT_LOCK.lock().unwrap(); // Use mut if we add state later, currently just locking file access
-
-    // For Merkle/Chain, we need the last hash.
-    // In a real app we'd read the last line or cache it in memory.
-    // Let's modify append_log to handle this calculation.
-
-    // We need to pass a mutable event to update its hash, but `log_event` takes ownership.
-    // Let's modify `event` locally before appending.
+    // 2. Append to the security chain
     let mut chained_event = event.clone();
-
-    // Calculate Hash (Simplified for Phase 1: Just hash this event + salt)
-    // To do real chaining, we need state. Let's add a static LAST_HASH?
-    // Doing it inside append_log is safer if we hold the lock.
-
     if let Err(e) = append_log_chained(&mut chained_event) {
         eprintln!("CRITICAL: Failed to write to audit log: {}", e);
     }
 
-    // 3. Ship to External SIEM (Async)
-    let event_to_ship = chained_event.clone();
-    tauri::async_runtime::spawn(async move {
-        use crate::audit::shipper::{LogShipper, MockShipper};
-        let shipper = MockShipper;
-        if let Err(e) = shipper.ship_event(&event_to_ship).await {
-            eprintln!("❌ FILTERED: Failed to ship audit event: {}", e);
-        }
-    });
+    // 3. Queue for shipping to the external SIEM (see `shipper`)
+    shipper::enqueue(&chained_event);
+
+    // 4. Evaluate alert rules (may log a SECURITY_ALERT in turn)
+    alerts::observe(&chained_event);
 }
 
+/// Append `event` to the security chain and set its `prev_hash` / `hash`.
 pub fn append_log_chained(event: &mut AuditEvent) -> std::io::Result<()> {
-    use sha2::{Digest, Sha256};
+    event.prev_hash = None;
+    event.hash = None;
+    let (_, entry) = audit_log::append_chain::<SecurityAuditEntry>(SECURITY_CHAIN, event.clone())?;
+    event.prev_hash = Some(entry.chain_prev_hash);
+    event.hash = Some(entry.chain_hash);
+    Ok(())
+}
+
+/// Last `limit` security events, newest first.
+pub fn read_log(limit: usize) -> std::io::Result<Vec<AuditEvent>> {
+    let mut events: Vec<AuditEvent> = audit_log::read_chain::<SecurityAuditEntry>(SECURITY_CHAIN)
+        .into_iter()
+        .map(SecurityAuditEntry::linked_event)
+        .collect();
+    events.reverse(); // Newest first
+    events.truncate(limit);
+    Ok(events)
+}
 
-    let file_path = if cfg!(target_os = "windows") {
+/// Verify the security chain with the ERP chain verifier.
+pub fn verify_log() -> bool {
+    audit_log::verify_dir::<SecurityAuditEntry>(&audit_log::org_dir(SECURITY_CHAIN))
+}
+
+/// Path of the single-file security log written by earlier builds.
+fn legacy_log_path() -> &'static str {
+    if cfg!(target_os = "windows") {
         "C:\\Windows\\Temp\\audit.jsonl"
     } else {
         "/tmp/audit.jsonl"
-    };
-
-    let file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(file_path)?;
-
-    let mut writer = std::io::LineWriter::new(file);
-
-    // update chaining
-    let mut last_hash_guard = LAST_HASH.lock().unwrap();
-    event.prev_hash = Some(last_hash_guard.clone());
+    }
+}
 
-    // Calculate current hash: sha256(timestamp + user + action + prev_hash)
+/// Hash the legacy log linked events with (timestamp, user, action, details,
+/// prev_hash).
+fn legacy_hash(event: &AuditEvent) -> String {
+    use sha2::{Digest, Sha256};
     let mut hasher = Sha256::new();
     hasher.update(&event.timestamp);
     hasher.update(&event.user_id);
     hasher.update(&event.action);
     hasher.update(&event.details);
-    hasher.update(event.prev_hash.as_ref().unwrap());
-
-    let result = hasher.finalize();
-    let hash_hex = hex::encode(result);
-
-    event.hash = Some(hash_hex.clone());
-    *last_hash_guard = hash_hex;
+    hasher.update(event.prev_hash.as_deref().unwrap_or_default());
+    hex::encode(hasher.finalize())
+}
 
-    let json = serde_json::to_string(event)?;
-    writeln!(writer, "{}", json)?;
-    Ok(())
+/// Move the legacy security log into the security chain. Called once the
+/// audit dir is configured; does nothing if the chain already has entries.
+pub fn migrate_legacy_log() {
+    match import_legacy(std::path::Path::new(legacy_log_path()), SECURITY_CHAIN) {
+        Ok(0) => {}
+        Ok(n) => println!("✅ Security audit log: imported {n} legacy event(s)"),
+        Err(e) => eprintln!("⚠️  Security legacy audit log import failed: {e}"),
+    }
 }
 
-pub fn read_log(limit: usize) -> std::io::Result<Vec<AuditEvent>> {
-    let file_path = if cfg!(target_os = "windows") {
-        "C:\\Windows\\Temp\\audit.jsonl"
-    } else {
-        "/tmp/audit.jsonl"
+/// Append the events of `legacy` to `chain`, oldest first. Each event keeps
+/// its legacy `prev_hash` / `hash`, so the old links are covered by the new
+/// chain hash; events whose legacy hash does not recompute are still imported
+/// but reported.
+fn import_legacy(legacy: &std::path::Path, chain: &str) -> std::io::Result<usize> {
+    use std::io::BufRead;
+    if !audit_log::read_chain::<SecurityAuditEntry>(chain).is_empty() {
+        return Ok(0);
+    }
+    let Ok(f) = std::fs::File::open(legacy) else {
+        return Ok(0);
     };
-    let file = File::open(file_path);
-    match file {
-        Ok(f) => {
-            let reader = BufReader::new(f);
-            let mut events = Vec::new();
-
-            // Read all lines, parse, then take last N
-            // Ideally we read from end, but for MVP reading all is fine (audit log small dev)
-            for line in reader.lines() {
-                if let Ok(l) = line {
-                    if let Ok(event) = serde_json::from_str::<AuditEvent>(&l) {
-                        events.push(event);
-                    }
-                }
-            }
+    let events: Vec<AuditEvent> = std::io::BufReader::new(f)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str(&line).ok())
+        .collect();
+    let mismatched = events
+        .iter()
+        .filter(|e| e.hash.as_deref() != Some(legacy_hash(e).as_str()))
+        .count();
+    if mismatched > 0 {
+        eprintln!(
+            "⚠️  Security legacy audit log: {mismatched} event(s) do not match their legacy hash"
+        );
+    }
+    for event in &events {
+        audit_log::append_chain::<SecurityAuditEntry>(chain, event.clone())?;
+    }
+    Ok(events.len())
+}
 
-            events.reverse(); // Newest first
-            if events.len() > limit {
-                events.truncate(limit);
+/// One row of the combined security + ERP timeline.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TimelineEvent {
+    /// "security" | "erp"
+    pub source: String,
+    pub issued_at_ms: i64,
+    /// user id, or the ERP actor pubkey
+    pub actor: String,
+    /// Security action, or "erp:" + the distinct op kinds of the envelope
+    pub action: String,
+    /// Resource id, or the ERP tx id / first fragment
+    pub resource_id: String,
+    pub severity: String,
+    pub details: String,
+    pub chain_hash: String,
+}
+
+fn erp_timeline_event(entry: &ErpAuditEntry) -> TimelineEvent {
+    let env = &entry.envelope;
+    let mut kinds: Vec<String> = Vec::new();
+    let mut first_fragment = None;
+    for op in &env.ops {
+        let value = serde_json::to_value(op).unwrap_or_default();
+        if let Some(kind) = value.get("kind").and_then(|k| k.as_str()) {
+            if !kinds.iter().any(|k| k == kind) {
+                kinds.push(kind.to_string());
             }
-            Ok(events)
         }
-        Err(_) => Ok(Vec::new()), // Return empty if no log file yet
+        first_fragment = first_fragment.or_else(|| {
+            ["fragment_id", "from_fragment", "source_fragment"]
+                .iter()
+                .find_map(|f| value.get(*f).and_then(|v| v.as_str()).map(str::to_string))
+        });
+    }
+    TimelineEvent {
+        source: "erp".to_string(),
+        issued_at_ms: env.issued_at_ms,
+        actor: env.actor_pubkey.clone(),
+        action: format!("erp:{}", kinds.join(",")),
+        resource_id: env
+            .policy_context
+            .tx_id
+            .clone()
+            .or(first_fragment)
+            .unwrap_or_default(),
+        severity: "INFO".to_string(),
+        details: format!("mutation {} ({} ops)", env.mutation_id, env.ops.len()),
+        chain_hash: entry.chain_hash.clone(),
     }
 }
+
+/// Security events and every org's ERP entries within
+/// `[from_ms, to_ms]`, merged newest first, up to `limit`.
+pub fn timeline(from_ms: i64, to_ms: i64, limit: usize) -> std::io::Result<Vec<TimelineEvent>> {
+    let security = audit_log::read_chain::<SecurityAuditEntry>(SECURITY_CHAIN)
+        .into_iter()
+        .map(|e| TimelineEvent {
+            source: "security".to_string(),
+            issued_at_ms: e.event.timestamp_ms(),
+            actor: e.event.user_id,
+            action: e.event.action,
+            resource_id: e.event.resource_id,
+            severity: e.event.severity,
+            details: e.event.details,
+            chain_hash: e.chain_hash,
+        })
+        .filter(|e| e.issued_at_ms >= from_ms && e.issued_at_ms <= to_ms);
+    let erp = audit_log::read_all(from_ms, to_ms)?;
+
+    let mut events: Vec<TimelineEvent> =
+        security.chain(erp.iter().map(erp_timeline_event)).collect();
+    events.sort_by(|a, b| b.issued_at_ms.cmp(&a.issued_at_ms));
+    events.truncate(limit);
+    Ok(events)
+}
@@ -1,24 +1,70 @@
+//! Shipping security audit events to external systems (SIEM, syslog, ELK).
+//!
+//! `log_event` only appends the event to a durable on-disk outbox
+//! (`Outbox`); a single background worker drains it in batches through the
+//! configured `LogShipper`, retrying with exponential backoff while the
+//! receiver is unreachable. A batch the receiver refuses outright (`Rejected`,
+//! e.g. an HTTP 4xx) is moved to `dead_letter.jsonl` instead of blocking the
+//! queue. Events not yet acknowledged survive restarts, and `shutdown` makes a
+//! final flush attempt before the app exits.
+//!
+//! The backend is selected by `{app_data}/audit_shipper.json`
+//! (`ShipperConfig`): mock (stdout), file forwarding, syslog (RFC 5424 over
+//! UDP or a Unix datagram socket) or HTTP POST of JSON batches.
+
 use super::AuditEvent;
 use async_trait::async_trait;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::Notify;
+
+type ShipResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+/// A batch the receiver refused in a way retrying will not fix. Any other
+/// shipping error is treated as transient and retried.
+#[derive(Debug)]
+pub struct Rejected(pub String);
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rejected: {}", self.0)
+    }
+}
+
+impl Error for Rejected {}
 
 /// Trait for shipping audit logs to external systems (SIEM, Splunk, ELK)
 #[async_trait]
 pub trait LogShipper: Send + Sync {
     /// Ship a single event asynchronously
-    async fn ship_event(&self, event: &AuditEvent) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn ship_event(&self, event: &AuditEvent) -> ShipResult;
+
+    /// Ship a batch; the whole batch is retried if this fails
+    async fn ship_batch(&self, events: &[AuditEvent]) -> ShipResult {
+        for event in events {
+            self.ship_event(event).await?;
+        }
+        Ok(())
+    }
 
     /// Flush any buffered events
-    async fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>>;
+    async fn flush(&self) -> ShipResult;
 }
 
 pub struct MockShipper;
 
 #[async_trait]
 impl LogShipper for MockShipper {
-    async fn ship_event(&self, event: &AuditEvent) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // In a real implementation, this would HTTP POST to Splunk/ELK
-        // For Phase 1, we just println to stdout with a special prefix
+    async fn ship_event(&self, event: &AuditEvent) -> ShipResult {
+        // Prints to stdout with a special prefix
         println!(
             "🚢 SHIP_LOG: [{}][{}] {}",
             event.severity, event.action, event.details
@@ -26,7 +72,892 @@ impl LogShipper for MockShipper {
         Ok(())
     }
 
-    async fn flush(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+    async fn flush(&self) -> ShipResult {
+        Ok(())
+    }
+}
+
+// ─── File forwarding ─────────────────────────────────────────────────────────
+
+/// Appends events as JSON lines to a file, e.g. one tailed by a log agent.
+pub struct FileShipper {
+    pub path: PathBuf,
+}
+
+#[async_trait]
+impl LogShipper for FileShipper {
+    async fn ship_event(&self, event: &AuditEvent) -> ShipResult {
+        self.ship_batch(std::slice::from_ref(event)).await
+    }
+
+    async fn ship_batch(&self, events: &[AuditEvent]) -> ShipResult {
+        let mut buf = String::new();
+        for event in events {
+            buf.push_str(&serde_json::to_string(event)?);
+            buf.push('\n');
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(buf.as_bytes()).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    async fn flush(&self) -> ShipResult {
         Ok(())
     }
 }
+
+// ─── Syslog (RFC 5424) ───────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyslogTransport {
+    /// `address` is host:port, e.g. "127.0.0.1:514"
+    Udp,
+    /// `address` is a Unix datagram socket path, e.g. "/dev/log"
+    Unix,
+}
+
+pub struct SyslogShipper {
+    pub transport: SyslogTransport,
+    pub address: String,
+    pub hostname: String,
+    pub app_name: String,
+}
+
+/// Syslog facility 13 ("log audit").
+const SYSLOG_FACILITY: u8 = 13;
+
+fn syslog_severity(severity: &str) -> u8 {
+    match severity {
+        "CRITICAL" => 2,
+        "ERROR" => 3,
+        "WARN" => 4,
+        _ => 6, // informational
+    }
+}
+
+/// Escape an RFC 5424 SD-PARAM value.
+fn sd_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+/// Format `event` as an RFC 5424 message.
+pub fn rfc5424(event: &AuditEvent, hostname: &str, app_name: &str) -> String {
+    let pri = SYSLOG_FACILITY * 8 + syslog_severity(&event.severity);
+    let timestamp = chrono::DateTime::parse_from_rfc3339(&event.timestamp)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Micros, true))
+        .unwrap_or_else(|_| "-".to_string());
+    let msgid: String = event
+        .action
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(32)
+        .collect();
+    format!(
+        "<{}>1 {} {} {} {} {} [corngr@32473 user=\"{}\" resource=\"{}\" severity=\"{}\" hash=\"{}\"] {}",
+        pri,
+        timestamp,
+        if hostname.is_empty() { "-" } else { hostname },
+        app_name,
+        std::process::id(),
+        if msgid.is_empty() { "-".to_string() } else { msgid },
+        sd_escape(&event.user_id),
+        sd_escape(&event.resource_id),
+        sd_escape(&event.severity),
+        sd_escape(event.hash.as_deref().unwrap_or("")),
+        event.details
+    )
+}
+
+#[async_trait]
+impl LogShipper for SyslogShipper {
+    async fn ship_event(&self, event: &AuditEvent) -> ShipResult {
+        self.ship_batch(std::slice::from_ref(event)).await
+    }
+
+    async fn ship_batch(&self, events: &[AuditEvent]) -> ShipResult {
+        let messages: Vec<String> = events
+            .iter()
+            .map(|e| rfc5424(e, &self.hostname, &self.app_name))
+            .collect();
+        match self.transport {
+            SyslogTransport::Udp => {
+                let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+                socket.connect(&self.address).await?;
+                for m in &messages {
+                    socket.send(m.as_bytes()).await?;
+                }
+            }
+            #[cfg(unix)]
+            SyslogTransport::Unix => {
+                let socket = tokio::net::UnixDatagram::unbound()?;
+                for m in &messages {
+                    socket.send_to(m.as_bytes(), &self.address).await?;
+                }
+            }
+            #[cfg(not(unix))]
+            SyslogTransport::Unix => {
+                return Err("unix syslog sockets are not available on this platform".into());
+            }
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> ShipResult {
+        Ok(())
+    }
+}
+
+// ─── HTTP POST ───────────────────────────────────────────────────────────────
+
+/// POSTs each batch as a JSON array. Plain `http://` only — put a local
+/// forwarder in front for TLS endpoints. Only I/O errors, timeouts and 5xx
+/// answers are retried; any other answer is `Rejected`.
+pub struct HttpShipper {
+    pub url: String,
+    /// Sent as the `Authorization` header if set
+    pub authorization: Option<String>,
+    pub timeout: Duration,
+}
+
+/// Split `http://host[:port][/path]` into (host, port, path).
+pub(crate) fn parse_http_url(url: &str) -> Result<(String, u16, String), String> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("only http:// URLs are supported: {}", url))?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], rest[i..].to_string()),
+        None => (rest, "/".to_string()),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) => (
+            h.to_string(),
+            p.parse().map_err(|_| format!("invalid port in {}", url))?,
+        ),
+        None => (authority.to_string(), 80),
+    };
+    if host.is_empty() {
+        return Err(format!("missing host in {}", url));
+    }
+    Ok((host, port, path))
+}
+
+impl HttpShipper {
+    async fn post(&self, body: &[u8]) -> ShipResult {
+        let (host, port, path) = parse_http_url(&self.url).map_err(Rejected)?;
+        let mut stream = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+        let mut request = format!(
+            "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+            path,
+            host,
+            port,
+            body.len()
+        );
+        if let Some(auth) = &self.authorization {
+            request.push_str(&format!("Authorization: {}\r\n", auth));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+        stream.write_all(body).await?;
+
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        let status_line = String::from_utf8_lossy(&response)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let status: u16 = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| Rejected(format!("malformed HTTP response: {:?}", status_line)))?;
+        match status {
+            200..=299 => Ok(()),
+            500..=599 => Err(format!("receiver answered {}", status_line).into()),
+            _ => Err(Rejected(format!("receiver answered {}", status_line)).into()),
+        }
+    }
+}
+
+#[async_trait]
+impl LogShipper for HttpShipper {
+    async fn ship_event(&self, event: &AuditEvent) -> ShipResult {
+        self.ship_batch(std::slice::from_ref(event)).await
+    }
+
+    async fn ship_batch(&self, events: &[AuditEvent]) -> ShipResult {
+        let body = serde_json::to_vec(events)?;
+        tokio::time::timeout(self.timeout, self.post(&body))
+            .await
+            .map_err(|_| format!("POST {} timed out", self.url))?
+    }
+
+    async fn flush(&self) -> ShipResult {
+        Ok(())
+    }
+}
+
+// ─── Config ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Backend {
+    Mock,
+    File {
+        path: PathBuf,
+    },
+    Syslog {
+        transport: SyslogTransport,
+        address: String,
+    },
+    Http {
+        url: String,
+        #[serde(default)]
+        authorization: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShipperConfig {
+    #[serde(flatten)]
+    pub backend: Backend,
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_base_backoff_ms")]
+    pub base_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+}
+
+fn default_batch_size() -> usize {
+    50
+}
+fn default_base_backoff_ms() -> u64 {
+    500
+}
+fn default_max_backoff_ms() -> u64 {
+    60_000
+}
+
+/// Stands in for secrets in configs handed to the UI.
+pub const REDACTED: &str = "<redacted>";
+
+impl ShipperConfig {
+    /// The config with the HTTP `authorization` value replaced by `REDACTED`.
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        if let Backend::Http {
+            authorization: Some(auth),
+            ..
+        } = &mut config.backend
+        {
+            *auth = REDACTED.to_string();
+        }
+        config
+    }
+}
+
+impl Default for ShipperConfig {
+    fn default() -> Self {
+        Self {
+            backend: Backend::Mock,
+            batch_size: default_batch_size(),
+            base_backoff_ms: default_base_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+        }
+    }
+}
+
+/// Build the shipper for a backend.
+pub fn build(backend: &Backend) -> Box<dyn LogShipper> {
+    match backend {
+        Backend::Mock => Box::new(MockShipper),
+        Backend::File { path } => Box::new(FileShipper { path: path.clone() }),
+        Backend::Syslog { transport, address } => Box::new(SyslogShipper {
+            transport: *transport,
+            address: address.clone(),
+            hostname: std::env::var("HOSTNAME").unwrap_or_default(),
+            app_name: "corngr".to_string(),
+        }),
+        Backend::Http { url, authorization } => Box::new(HttpShipper {
+            url: url.clone(),
+            authorization: authorization.clone(),
+            timeout: Duration::from_secs(10),
+        }),
+    }
+}
+
+/// Delay before retry number `attempt` (0-based): base · 2^attempt, capped.
+pub fn backoff(attempt: u32, base: Duration, max: Duration) -> Duration {
+    base.saturating_mul(2u32.saturating_pow(attempt)).min(max)
+}
+
+// ─── Durable outbox ──────────────────────────────────────────────────────────
+
+/// Events waiting to be shipped: `outbox.jsonl` plus the byte offset up to
+/// which events have been acknowledged (`outbox.cursor`). Once everything is
+/// acknowledged the file is truncated.
+pub struct Outbox {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl Outbox {
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn log_path(&self) -> PathBuf {
+        self.dir.join("outbox.jsonl")
+    }
+
+    fn cursor_path(&self) -> PathBuf {
+        self.dir.join("outbox.cursor")
+    }
+
+    fn dead_letter_path(&self) -> PathBuf {
+        self.dir.join("dead_letter.jsonl")
+    }
+
+    fn cursor(&self) -> u64 {
+        fs::read_to_string(self.cursor_path())
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+            .unwrap_or(0)
+    }
+
+    pub fn push(&self, event: &AuditEvent) -> std::io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let line = serde_json::to_string(event)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.log_path())?;
+        writeln!(file, "{}", line)?;
+        file.sync_data()
+    }
+
+    /// Up to `max` unacknowledged events and the offset to `ack` once they
+    /// are shipped.
+    pub fn peek(&self, max: usize) -> std::io::Result<(Vec<AuditEvent>, u64)> {
+        let _lock = self.lock.lock().unwrap();
+        let mut offset = self.cursor();
+        let Ok(mut file) = File::open(self.log_path()) else {
+            return Ok((Vec::new(), offset));
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(file);
+        let mut events = Vec::new();
+        let mut line = String::new();
+        while events.len() < max {
+            line.clear();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 || !line.ends_with('\n') {
+                break;
+            }
+            offset += n as u64;
+            // Unparseable lines are skipped (and acknowledged with the batch)
+            if let Ok(event) = serde_json::from_str(line.trim_end()) {
+                events.push(event);
+            }
+        }
+        Ok((events, offset))
+    }
+
+    /// Acknowledge everything before `offset`.
+    pub fn ack(&self, offset: u64) -> std::io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let len = fs::metadata(self.log_path()).map(|m| m.len()).unwrap_or(0);
+        if offset >= len {
+            File::create(self.log_path())?;
+            let _ = fs::remove_file(self.cursor_path());
+            return Ok(());
+        }
+        let tmp = self.dir.join("outbox.cursor.tmp");
+        fs::write(&tmp, offset.to_string())?;
+        fs::rename(tmp, self.cursor_path())
+    }
+
+    /// Append events the receiver rejected to `dead_letter.jsonl`, where they
+    /// are kept for inspection and manual replay.
+    pub fn dead_letter(&self, events: &[AuditEvent]) -> std::io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dead_letter_path())?;
+        for event in events {
+            writeln!(file, "{}", serde_json::to_string(event)?)?;
+        }
+        file.sync_data()
+    }
+
+    /// Number of unacknowledged events.
+    pub fn pending(&self) -> std::io::Result<usize> {
+        Ok(self.peek(usize::MAX)?.0.len())
+    }
+
+    /// Move every unacknowledged event to `dest`; returns how many moved.
+    pub fn drain_into(&self, dest: &Outbox) -> std::io::Result<usize> {
+        let (events, end) = self.peek(usize::MAX)?;
+        for event in &events {
+            dest.push(event)?;
+        }
+        self.ack(end)?;
+        Ok(events.len())
+    }
+}
+
+// ─── Worker ──────────────────────────────────────────────────────────────────
+
+lazy_static! {
+    /// Temp-dir outbox until `configure` opens the app data one; `None` if
+    /// neither could be opened, in which case events are not shipped (they
+    /// are still in the local chain).
+    static ref OUTBOX: Mutex<Option<Arc<Outbox>>> = Mutex::new(
+        Outbox::open(&std::env::temp_dir().join("corngr_audit_outbox"))
+            .map(Arc::new)
+            .map_err(|e| eprintln!("⚠️  Temp audit outbox unavailable: {e}"))
+            .ok(),
+    );
+    static ref SHIPPER_CONFIG: Mutex<ShipperConfig> = Mutex::new(ShipperConfig::default());
+    static ref CONFIG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+    static ref OUTBOX_NOTIFY: Notify = Notify::new();
+}
+
+/// Bumped whenever the worker is restarted or stopped; a worker exits once
+/// its generation is stale.
+static WORKER_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Queue an event for shipping. Never blocks on the network.
+pub fn enqueue(event: &AuditEvent) {
+    let Some(outbox) = OUTBOX.lock().unwrap().clone() else {
+        return;
+    };
+    if let Err(e) = outbox.push(event) {
+        eprintln!("❌ Failed to queue audit event for shipping: {}", e);
+    }
+    OUTBOX_NOTIFY.notify_one();
+}
+
+/// Load `{app_data_dir}/audit_shipper.json`, open the outbox under
+/// `{app_data_dir}/audit_outbox` (moving in anything queued in the temp one)
+/// and start the worker. Called from `run()` setup.
+pub fn configure(app_data_dir: &Path) {
+    let path = app_data_dir.join("audit_shipper.json");
+    let config = match fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("⚠️  Invalid {}: {e}; using mock shipper", path.display());
+            ShipperConfig::default()
+        }),
+        Err(_) => ShipperConfig::default(),
+    };
+    match Outbox::open(&app_data_dir.join("audit_outbox")) {
+        Ok(outbox) => {
+            let mut current = OUTBOX.lock().unwrap();
+            if let Some(temp) = current.as_ref() {
+                if let Err(e) = temp.drain_into(&outbox) {
+                    eprintln!("⚠️  Failed to move queued audit events: {e}");
+                }
+            }
+            *current = Some(Arc::new(outbox));
+        }
+        Err(e) if OUTBOX.lock().unwrap().is_some() => {
+            eprintln!("⚠️  Audit outbox unavailable, using temp dir: {e}")
+        }
+        Err(e) => eprintln!("⚠️  Audit outbox unavailable, not shipping: {e}"),
+    }
+    *CONFIG_PATH.lock().unwrap() = Some(path);
+    *SHIPPER_CONFIG.lock().unwrap() = config;
+    start_worker();
+}
+
+pub fn config() -> ShipperConfig {
+    SHIPPER_CONFIG.lock().unwrap().clone()
+}
+
+/// Replace the shipper config, persist it, and restart the worker. An
+/// `authorization` of `REDACTED` (a config read back from `redacted`) keeps
+/// the current value.
+pub fn set_config(mut config: ShipperConfig) -> std::io::Result<()> {
+    if let Backend::Http {
+        authorization: Some(auth),
+        ..
+    } = &mut config.backend
+    {
+        if auth == REDACTED {
+            match &SHIPPER_CONFIG.lock().unwrap().backend {
+                Backend::Http {
+                    authorization: Some(current),
+                    ..
+                } => *auth = current.clone(),
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        "no authorization to keep",
+                    ))
+                }
+            }
+        }
+    }
+    if let Some(path) = CONFIG_PATH.lock().unwrap().as_ref() {
+        fs::write(path, serde_json::to_vec_pretty(&config)?)?;
+    }
+    *SHIPPER_CONFIG.lock().unwrap() = config;
+    start_worker();
+    Ok(())
+}
+
+fn start_worker() {
+    let generation = WORKER_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    OUTBOX_NOTIFY.notify_waiters();
+    let config = config();
+    let Some(outbox) = OUTBOX.lock().unwrap().clone() else {
+        return;
+    };
+    tauri::async_runtime::spawn(run_worker(generation, config, outbox));
+}
+
+async fn run_worker(generation: u64, config: ShipperConfig, outbox: Arc<Outbox>) {
+    let shipper = build(&config.backend);
+    let base = Duration::from_millis(config.base_backoff_ms);
+    let max = Duration::from_millis(config.max_backoff_ms);
+    let mut attempt = 0u32;
+    while WORKER_GENERATION.load(Ordering::SeqCst) == generation {
+        let (batch, end) = match outbox.peek(config.batch_size.max(1)) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("❌ Audit outbox read failed: {}", e);
+                tokio::time::sleep(max).await;
+                continue;
+            }
+        };
+        if batch.is_empty() {
+            let _ = tokio::time::timeout(Duration::from_secs(30), OUTBOX_NOTIFY.notified()).await;
+            continue;
+        }
+        match shipper.ship_batch(&batch).await {
+            Ok(()) => {
+                attempt = 0;
+                if let Err(e) = outbox.ack(end) {
+                    eprintln!("❌ Audit outbox ack failed: {}", e);
+                }
+            }
+            Err(e) if dead_letter_rejected(&outbox, &batch, end, e.as_ref()) => attempt = 0,
+            Err(e) => {
+                let delay = backoff(attempt, base, max);
+                eprintln!(
+                    "❌ Failed to ship {} audit events (retry in {:?}): {}",
+                    batch.len(),
+                    delay,
+                    e
+                );
+                attempt = attempt.saturating_add(1);
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Stop the worker, ship whatever is still queued (stopping at the first
+/// failure — the rest stays in the outbox for the next start) and flush the
+/// shipper. Called when the app exits.
+pub async fn shutdown() {
+    WORKER_GENERATION.fetch_add(1, Ordering::SeqCst);
+    OUTBOX_NOTIFY.notify_waiters();
+    let config = config();
+    let Some(outbox) = OUTBOX.lock().unwrap().clone() else {
+        return;
+    };
+    let shipper = build(&config.backend);
+    drain(shipper.as_ref(), &outbox, config.batch_size.max(1)).await;
+    if let Err(e) = shipper.flush().await {
+        eprintln!("❌ Audit shipper flush failed: {}", e);
+    }
+}
+
+/// Ship queued batches until the outbox is empty or a batch fails; returns
+/// how many events were shipped.
+async fn drain(shipper: &dyn LogShipper, outbox: &Outbox, batch_size: usize) -> usize {
+    let mut shipped = 0;
+    loop {
+        let Ok((batch, end)) = outbox.peek(batch_size) else {
+            return shipped;
+        };
+        if batch.is_empty() {
+            return shipped;
+        }
+        if let Err(e) = shipper.ship_batch(&batch).await {
+            if dead_letter_rejected(outbox, &batch, end, e.as_ref()) {
+                continue;
+            }
+            eprintln!("❌ Failed to ship audit events: {}", e);
+            return shipped;
+        }
+        if outbox.ack(end).is_err() {
+            return shipped;
+        }
+        shipped += batch.len();
+    }
+}
+
+/// If `e` is `Rejected`, move `batch` to the dead letter file and acknowledge
+/// it; returns whether it did.
+fn dead_letter_rejected(
+    outbox: &Outbox,
+    batch: &[AuditEvent],
+    end: u64,
+    e: &(dyn Error + Send + Sync + 'static),
+) -> bool {
+    if e.downcast_ref::<Rejected>().is_none() {
+        return false;
+    }
+    eprintln!(
+        "❌ {} audit events {}; moved to the dead letter file",
+        batch.len(),
+        e
+    );
+    match outbox.dead_letter(batch).and_then(|()| outbox.ack(end)) {
+        Ok(()) => true,
+        Err(io) => {
+            eprintln!("❌ Audit dead letter failed: {}", io);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}_{}", name, uuid::Uuid::new_v4()))
+    }
+
+    fn event(n: usize, severity: &str) -> AuditEvent {
+        AuditEvent::new(
+            "alice",
+            "WRITE_REJECTED",
+            &format!("doc{}", n),
+            "read-only",
+            severity,
+        )
+    }
+
+    #[test]
+    fn test_outbox_persists_until_acked() {
+        let dir = temp_dir("outbox");
+        let outbox = Outbox::open(&dir).unwrap();
+        for n in 0..3 {
+            outbox.push(&event(n, "INFO")).unwrap();
+        }
+        let (batch, end) = outbox.peek(2).unwrap();
+        assert_eq!(batch.len(), 2);
+        outbox.ack(end).unwrap();
+
+        // A reopened outbox resumes after the acknowledged events
+        let reopened = Outbox::open(&dir).unwrap();
+        let (rest, end) = reopened.peek(10).unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].resource_id, "doc2");
+        reopened.ack(end).unwrap();
+        assert_eq!(reopened.pending().unwrap(), 0);
+        assert_eq!(fs::metadata(dir.join("outbox.jsonl")).unwrap().len(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_drain_into_moves_pending_events() {
+        let (from_dir, to_dir) = (temp_dir("outbox_from"), temp_dir("outbox_to"));
+        let from = Outbox::open(&from_dir).unwrap();
+        let to = Outbox::open(&to_dir).unwrap();
+        for n in 0..3 {
+            from.push(&event(n, "INFO")).unwrap();
+        }
+        let (acked, end) = from.peek(1).unwrap();
+        assert_eq!(acked.len(), 1);
+        from.ack(end).unwrap();
+
+        assert_eq!(from.drain_into(&to).unwrap(), 2);
+        assert_eq!(from.pending().unwrap(), 0);
+        let (moved, _) = to.peek(10).unwrap();
+        assert_eq!(moved.len(), 2);
+        assert_eq!(moved[0].resource_id, "doc1");
+        let _ = fs::remove_dir_all(&from_dir);
+        let _ = fs::remove_dir_all(&to_dir);
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let base = Duration::from_millis(500);
+        let max = Duration::from_secs(60);
+        assert_eq!(backoff(0, base, max), base);
+        assert_eq!(backoff(3, base, max), Duration::from_secs(4));
+        assert_eq!(backoff(40, base, max), max);
+    }
+
+    #[test]
+    fn test_rfc5424_format() {
+        let mut e = event(1, "WARN");
+        e.timestamp = "2026-01-02T03:04:05.123456789+00:00".to_string();
+        e.user_id = "al\"ice]".to_string();
+        let msg = rfc5424(&e, "host1", "corngr");
+        // facility 13 (log audit) * 8 + warning (4)
+        assert!(msg.starts_with("<108>1 2026-01-02T03:04:05.123456Z host1 corngr "));
+        assert!(msg.contains(" WRITE_REJECTED [corngr@32473 user=\"al\\\"ice\\]\""));
+        assert!(msg.ends_with("] read-only"));
+    }
+
+    #[tokio::test]
+    async fn test_syslog_udp_reaches_receiver() {
+        let receiver = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let shipper = build(&Backend::Syslog {
+            transport: SyslogTransport::Udp,
+            address: receiver.local_addr().unwrap().to_string(),
+        });
+        shipper
+            .ship_batch(&[event(1, "INFO"), event(2, "CRITICAL")])
+            .await
+            .unwrap();
+        let mut buf = [0u8; 2048];
+        let n = receiver.recv(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("<110>1 "));
+        let n = receiver.recv(&mut buf).await.unwrap();
+        assert!(String::from_utf8_lossy(&buf[..n]).starts_with("<106>1 "));
+    }
+
+    /// Accept one connection, answer with `status`, return the request body.
+    async fn http_receiver(status: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ingest", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = Vec::new();
+            let mut chunk = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut chunk).await.unwrap();
+                buf.extend_from_slice(&chunk[..n]);
+                let text = String::from_utf8_lossy(&buf).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let len: usize = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .and_then(|v| v.trim().parse().ok())
+                        .unwrap_or(0);
+                    if body.len() >= len {
+                        let reply = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                        stream.write_all(reply.as_bytes()).await.unwrap();
+                        return text;
+                    }
+                }
+                if n == 0 {
+                    return text;
+                }
+            }
+        });
+        (url, handle)
+    }
+
+    #[tokio::test]
+    async fn test_http_drain_ships_batches_and_keeps_failures_queued() {
+        let dir = temp_dir("outbox_http");
+        let outbox = Outbox::open(&dir).unwrap();
+        for n in 0..3 {
+            outbox.push(&event(n, "INFO")).unwrap();
+        }
+
+        // A failing receiver leaves everything queued
+        let (url, rx) = http_receiver("503 Service Unavailable").await;
+        let shipper = build(&Backend::Http {
+            url,
+            authorization: None,
+        });
+        assert_eq!(drain(shipper.as_ref(), &outbox, 10).await, 0);
+        rx.await.unwrap();
+        assert_eq!(outbox.pending().unwrap(), 3);
+
+        // A healthy receiver gets one JSON batch and the outbox empties
+        let (url, rx) = http_receiver("200 OK").await;
+        let shipper = build(&Backend::Http {
+            url,
+            authorization: Some("Bearer t".to_string()),
+        });
+        assert_eq!(drain(shipper.as_ref(), &outbox, 10).await, 3);
+        let request = rx.await.unwrap();
+        assert!(request.starts_with("POST /ingest HTTP/1.1"));
+        assert!(request.contains("Authorization: Bearer t"));
+        let body = request.split_once("\r\n\r\n").unwrap().1;
+        let shipped: Vec<AuditEvent> = serde_json::from_str(body).unwrap();
+        assert_eq!(shipped.len(), 3);
+        assert_eq!(outbox.pending().unwrap(), 0);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_http_rejection_is_dead_lettered() {
+        let dir = temp_dir("outbox_dead_letter");
+        let outbox = Outbox::open(&dir).unwrap();
+        for n in 0..3 {
+            outbox.push(&event(n, "INFO")).unwrap();
+        }
+
+        // A 4xx will not succeed on retry: the batch leaves the queue
+        let (url, rx) = http_receiver("400 Bad Request").await;
+        let shipper = build(&Backend::Http {
+            url,
+            authorization: None,
+        });
+        assert_eq!(drain(shipper.as_ref(), &outbox, 10).await, 0);
+        rx.await.unwrap();
+        assert_eq!(outbox.pending().unwrap(), 0);
+        let dead = fs::read_to_string(dir.join("dead_letter.jsonl")).unwrap();
+        assert_eq!(dead.lines().count(), 3);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_file_shipper_appends_lines() {
+        let path = temp_dir("shipped").with_extension("jsonl");
+        let shipper = build(&Backend::File { path: path.clone() });
+        shipper.ship_batch(&[event(1, "INFO")]).await.unwrap();
+        shipper.ship_event(&event(2, "INFO")).await.unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_config_parses_backends() {
+        let cfg: ShipperConfig =
+            serde_json::from_str(r#"{"kind":"http","url":"http://siem:8080/x","batch_size":10}"#)
+                .unwrap();
+        assert_eq!(cfg.batch_size, 10);
+        assert_eq!(cfg.max_backoff_ms, 60_000);
+        assert!(matches!(cfg.backend, Backend::Http { .. }));
+        assert_eq!(
+            parse_http_url("http://siem:8080/x").unwrap(),
+            ("siem".to_string(), 8080, "/x".to_string())
+        );
+        assert!(parse_http_url("https://siem/x").is_err());
+
+        let cfg: ShipperConfig = serde_json::from_str(
+            r#"{"kind":"http","url":"http://siem:8080/x","authorization":"Bearer s3cret"}"#,
+        )
+        .unwrap();
+        let shown = serde_json::to_string(&cfg.redacted()).unwrap();
+        assert!(!shown.contains("s3cret"));
+        assert!(shown.contains(REDACTED));
+    }
+}
@@ -1,5 +1,6 @@
 use super::*;
 use std::fs;
+use std::io::Write;
 
 #[test]
 fn test_merkle_chain_integrity() {
@@ -30,3 +31,83 @@ fn test_merkle_chain_integrity() {
     println!("E2 Hash: {:?}", event2.hash);
     println!("E3 Prev: {:?}", event3.prev_hash);
 }
+
+#[test]
+fn test_appended_events_verify() {
+    let mut event = AuditEvent::new("user1", "LOGIN", "system", "verified event", "INFO");
+    append_log_chained(&mut event).expect("Failed to log event");
+    assert!(verify_log());
+}
+
+#[test]
+fn test_every_field_is_covered_by_the_hash() {
+    let dir = std::env::temp_dir().join(format!("security_chain_{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&dir).unwrap();
+    let segment = dir.join("000000_2026-01.jsonl");
+
+    let write = |entries: &[SecurityAuditEntry]| {
+        let mut f = fs::File::create(&segment).unwrap();
+        for e in entries {
+            writeln!(f, "{}", serde_json::to_string(e).unwrap()).unwrap();
+        }
+    };
+    let mut prev = SecurityAuditEntry::GENESIS.to_string();
+    let entries: Vec<SecurityAuditEntry> = [
+        AuditEvent::new("alice", "WS_CONNECT", "doc1", "Role: editor", "INFO"),
+        AuditEvent::new("bob", "WRITE_REJECTED", "doc1", "read-only", "WARN"),
+    ]
+    .into_iter()
+    .map(|event| {
+        let hash = audit_log::chain_hash_of(&event, &prev);
+        let entry = SecurityAuditEntry::link(event, prev.clone(), hash.clone());
+        prev = hash;
+        entry
+    })
+    .collect();
+    write(&entries);
+    assert!(audit_log::verify_dir::<SecurityAuditEntry>(&dir));
+
+    // Fields the old hash left out are now covered
+    let mut tampered = entries.clone();
+    tampered[1].event.severity = "INFO".to_string();
+    write(&tampered);
+    assert!(!audit_log::verify_dir::<SecurityAuditEntry>(&dir));
+
+    let mut tampered = entries;
+    tampered[0].event.resource_id = "doc2".to_string();
+    write(&tampered);
+    assert!(!audit_log::verify_dir::<SecurityAuditEntry>(&dir));
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_legacy_log_is_imported_once() {
+    let chain = format!("_legacy_test_{}", uuid::Uuid::new_v4());
+    let legacy = std::env::temp_dir().join(format!("{chain}.jsonl"));
+    let mut prev = "genesis_hash".to_string();
+    let mut f = fs::File::create(&legacy).unwrap();
+    for action in ["LOGIN", "EDIT"] {
+        let mut event = AuditEvent::new("user1", action, "system", "legacy event", "INFO");
+        event.prev_hash = Some(prev);
+        event.hash = Some(legacy_hash(&event));
+        prev = event.hash.clone().unwrap();
+        writeln!(f, "{}", serde_json::to_string(&event).unwrap()).unwrap();
+    }
+    drop(f);
+
+    assert_eq!(import_legacy(&legacy, &chain).unwrap(), 2);
+    let entries = audit_log::read_chain::<SecurityAuditEntry>(&chain);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[1].event.action, "EDIT");
+    // Legacy links are kept inside the hashed event
+    assert_eq!(entries[1].event.prev_hash, entries[0].event.hash);
+    assert!(audit_log::verify_dir::<SecurityAuditEntry>(
+        &audit_log::org_dir(&chain)
+    ));
+
+    // A chain with entries is left alone
+    assert_eq!(import_legacy(&legacy, &chain).unwrap(), 0);
+    assert_eq!(audit_log::read_chain::<SecurityAuditEntry>(&chain).len(), 2);
+    let _ = fs::remove_file(&legacy);
+    let _ = fs::remove_dir_all(audit_log::org_dir(&chain));
+}
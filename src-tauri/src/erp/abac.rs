@@ -14,6 +14,11 @@ pub enum Action {
     InvMoveCreate,
     IndexUpdate, // engine-only
     AuditRead,
+    AccountManage,
+    DbRebuild,
+    NodeManage,
+    ProposalReview,
+    InventoryManage,
 }
 
 impl Action {
@@ -29,6 +34,11 @@ impl Action {
             "invmove.create" => Some(Action::InvMoveCreate),
             "index.update" => Some(Action::IndexUpdate),
             "audit.read" => Some(Action::AuditRead),
+            "account.manage" => Some(Action::AccountManage),
+            "db.rebuild" => Some(Action::DbRebuild),
+            "node.manage" => Some(Action::NodeManage),
+            "proposal.review" => Some(Action::ProposalReview),
+            "inventory.manage" => Some(Action::InventoryManage),
             _ => None,
         }
     }
@@ -41,6 +51,20 @@ pub fn check_abac(
     actor: &ActorContext,
     action: &Action,
     context: &PolicyContext,
+) -> Result<(), ErpError> {
+    let result = evaluate(actor, action, context);
+    if let Err(e) = &result {
+        // Denial bursts feed the security alert rules
+        let resource = context.tx_id.as_deref().unwrap_or(&context.org_id);
+        crate::audit::alerts::observe_erp_error(&actor.pubkey, resource, e);
+    }
+    result
+}
+
+fn evaluate(
+    actor: &ActorContext,
+    action: &Action,
+    context: &PolicyContext,
 ) -> Result<(), ErpError> {
     match action {
         // P1: tx.create/edit — staff/manager/finance; status ∈ {draft, proposed}
@@ -129,6 +153,37 @@ pub fn check_abac(
             require_role(actor, &[Role::Auditor, Role::Finance, Role::OwnerAdmin])?;
             Ok(())
         }
+
+        // account.manage — CoA add/rename/deactivate/re-parent: finance/owner_admin
+        Action::AccountManage => {
+            require_role(actor, &[Role::Finance, Role::OwnerAdmin])?;
+            Ok(())
+        }
+
+        // db.rebuild — replace corngr.db from the audit log: owner_admin only
+        Action::DbRebuild => {
+            require_role(actor, &[Role::OwnerAdmin])?;
+            Ok(())
+        }
+
+        // node.manage — primary/satellite mode and satellite enrollment: owner_admin only
+        Action::NodeManage => {
+            require_role(actor, &[Role::OwnerAdmin])?;
+            Ok(())
+        }
+
+        // proposal.review — accept/reject CAIO proposals: manager/finance/owner_admin
+        // (accepting also needs the permissions of the proposal's own ops)
+        Action::ProposalReview => {
+            require_role(actor, &[Role::Manager, Role::Finance, Role::OwnerAdmin])?;
+            Ok(())
+        }
+
+        // inventory.manage — reorder points / preferred suppliers: manager/finance/owner_admin
+        Action::InventoryManage => {
+            require_role(actor, &[Role::Manager, Role::Finance, Role::OwnerAdmin])?;
+            Ok(())
+        }
     }
 }
 
@@ -0,0 +1,598 @@
+//! anomaly.rs — Deterministic anomaly detectors for CAIO Guardian
+//!
+//! Rule-based checks over an org's transactions in `ErpStore`, each finding
+//! an `anomaly_flag` proposal pointing at the fragment it refers to:
+//! - `duplicate_invoice` — same type, party, amount and ref within N days
+//! - `unusual_amount` — far above/below the party's median for that tx type
+//! - `weekend_posting` — posted tx dated on a weekend or configured holiday
+//! - `round_amount` — large amounts that are exact multiples of a round unit
+//! - `benford` — first digits of line amounts deviate from Benford's law
+//! - `payment_without_invoice` — supplier paid beyond what it invoiced
+//!
+//! Amounts are tx gross totals (lines incl. tax; journal debits). Void txs
+//! are ignored. Findings are stable for a given store: ids derive from the
+//! detector and the tx, so the UI can dismiss a flag across queries.
+//!
+//! Thresholds are per org (`erp_detector_config.json` in the app data dir,
+//! like the LLM config).
+
+use chrono::{Datelike, NaiveDate, Weekday};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::erp::caio_llm::LlmProposal;
+use crate::erp::engine::ErpStore;
+use crate::erp::fragments;
+use crate::erp::journal;
+use crate::erp::types::{TxHeader, TxLine, TxStatus, TxType};
+
+/// Expected share of leading digit d (index d-1) under Benford's law.
+fn benford_expected(digit: usize) -> f64 {
+    (1.0 + 1.0 / digit as f64).log10()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DetectorConfig {
+    /// Duplicate invoices: max days between the two
+    pub duplicate_window_days: i64,
+    /// Unusual amounts: prior txs of the party needed, and the ratio to
+    /// their median that is flagged (either way)
+    pub unusual_min_history: usize,
+    pub unusual_ratio: f64,
+    /// Holidays as "YYYY-MM-DD", or "MM-DD" for every year
+    pub holidays: Vec<String>,
+    /// Round amounts: at least `round_min` and a multiple of `round_unit`
+    pub round_unit: f64,
+    pub round_min: f64,
+    /// Benford: line amounts needed, and the mean absolute deviation flagged
+    /// (0.015 is Nigrini's nonconformity bound for first digits)
+    pub benford_min_samples: usize,
+    pub benford_mad_threshold: f64,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            duplicate_window_days: 7,
+            unusual_min_history: 5,
+            unusual_ratio: 3.0,
+            holidays: Vec::new(),
+            round_unit: 1000.0,
+            round_min: 5000.0,
+            benford_min_samples: 100,
+            benford_mad_threshold: 0.015,
+        }
+    }
+}
+
+impl DetectorConfig {
+    /// Reject thresholds no detector can use.
+    pub fn validate(&self) -> Result<(), String> {
+        let finite_non_negative = [
+            self.unusual_ratio,
+            self.round_unit,
+            self.round_min,
+            self.benford_mad_threshold,
+        ]
+        .iter()
+        .all(|v| v.is_finite() && *v >= 0.0);
+        if !finite_non_negative || self.duplicate_window_days < 0 {
+            return Err("detector thresholds must be non-negative".to_string());
+        }
+        if let Some(h) = self.holidays.iter().find(|h| {
+            NaiveDate::parse_from_str(h, "%Y-%m-%d").is_err()
+                && NaiveDate::parse_from_str(&format!("2000-{}", h), "%Y-%m-%d").is_err()
+        }) {
+            return Err(format!("holiday {} is not YYYY-MM-DD or MM-DD", h));
+        }
+        Ok(())
+    }
+}
+
+lazy_static! {
+    static ref DETECTOR_CONFIGS: Mutex<BTreeMap<String, DetectorConfig>> =
+        Mutex::new(BTreeMap::new());
+    static ref DETECTOR_CONFIG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Load `erp_detector_config.json` (org_id → config) from `app_data_dir`;
+/// called from `engine::init_erp_db`.
+pub fn configure(app_data_dir: &Path) {
+    let path = app_data_dir.join("erp_detector_config.json");
+    if let Ok(s) = fs::read_to_string(&path) {
+        match serde_json::from_str(&s) {
+            Ok(configs) => *DETECTOR_CONFIGS.lock().unwrap() = configs,
+            Err(e) => eprintln!("⚠️  Invalid {}: {e}; using defaults", path.display()),
+        }
+    }
+    *DETECTOR_CONFIG_PATH.lock().unwrap() = Some(path);
+}
+
+pub fn config_for(org_id: &str) -> DetectorConfig {
+    DETECTOR_CONFIGS
+        .lock()
+        .unwrap()
+        .get(org_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Replace the org's thresholds and persist all. The in-memory config only
+/// changes once the file is written.
+pub fn set_config(org_id: &str, config: DetectorConfig) -> std::io::Result<()> {
+    let mut configs = DETECTOR_CONFIGS.lock().unwrap();
+    let mut updated = configs.clone();
+    updated.insert(org_id.to_string(), config);
+    if let Some(path) = DETECTOR_CONFIG_PATH.lock().unwrap().as_ref() {
+        fs::write(path, serde_json::to_vec_pretty(&updated)?)?;
+    }
+    *configs = updated;
+    Ok(())
+}
+
+/// A non-void tx with its gross amount and parsed date.
+struct TxFact<'a> {
+    tx: &'a TxHeader,
+    amount: f64,
+    date: Option<NaiveDate>,
+}
+
+/// Run every detector over `org_id`'s transactions.
+pub fn detect(store: &ErpStore, org_id: &str, config: &DetectorConfig) -> Vec<LlmProposal> {
+    let lines_by_tx = lines_by_tx(store);
+    let mut facts: Vec<TxFact> = store
+        .transactions
+        .values()
+        .filter(|t| t.org_id == org_id && t.status != TxStatus::Void)
+        .map(|tx| TxFact {
+            tx,
+            amount: tx_amount(tx, lines_by_tx.get(tx.tx_id.as_str())),
+            date: parse_date(&tx.tx_date),
+        })
+        .collect();
+    // Deterministic order: by date, then id
+    facts.sort_by(|a, b| {
+        a.date
+            .cmp(&b.date)
+            .then_with(|| a.tx.tx_id.cmp(&b.tx.tx_id))
+    });
+
+    let mut out = Vec::new();
+    duplicate_invoices(&facts, config, &mut out);
+    unusual_amounts(&facts, config, &mut out);
+    weekend_postings(&facts, config, &mut out);
+    round_amounts(&facts, config, &mut out);
+    benford(store, org_id, &lines_by_tx, config, &mut out);
+    payments_without_invoice(&facts, &mut out);
+    out
+}
+
+/// Lines grouped by tx_id.
+pub(crate) fn lines_by_tx(store: &ErpStore) -> HashMap<&str, Vec<&TxLine>> {
+    let mut by_tx: HashMap<&str, Vec<&TxLine>> = HashMap::new();
+    for line in store.lines.values() {
+        by_tx.entry(line.tx_id.as_str()).or_default().push(line);
+    }
+    by_tx
+}
+
+/// Gross amount of a tx: lines incl. tax, or debits for a journal.
+pub(crate) fn tx_amount(tx: &TxHeader, lines: Option<&Vec<&TxLine>>) -> f64 {
+    let lines = lines.map(Vec::as_slice).unwrap_or_default();
+    let total: f64 = if tx.tx_type == TxType::Journal {
+        lines.iter().map(|l| l.debit_amount).sum()
+    } else {
+        lines
+            .iter()
+            .map(|l| l.qty * l.unit_price * (1.0 + l.tax_rate))
+            .sum()
+    };
+    (total * 100.0).round() / 100.0
+}
+
+fn parse_date(s: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(s.get(..10).unwrap_or(s), "%Y-%m-%d").ok()
+}
+
+fn flag(detector: &str, tx: &TxHeader, title: &str, rationale: String) -> LlmProposal {
+    LlmProposal {
+        id: format!("caio-anomaly-{}-{}", detector, tx.tx_id),
+        r#type: "anomaly_flag".into(),
+        title: title.into(),
+        rationale,
+        source_fragment: fragments::tx_hdr_id(&tx.tx_id),
+        source: Some("rules".into()),
+        draft: None,
+        proposal_id: None,
+        citations: vec![],
+    }
+}
+
+fn describe(tx: &TxHeader) -> String {
+    match tx.ref_number.as_deref().filter(|r| !r.is_empty()) {
+        Some(r) => format!("{} {}", tx.tx_type.as_str(), r),
+        None => format!("{} {}", tx.tx_type.as_str(), tx.tx_id),
+    }
+}
+
+fn is_invoice(t: &TxType) -> bool {
+    matches!(t, TxType::InvoiceOut | TxType::InvoiceIn)
+}
+
+fn duplicate_invoices(facts: &[TxFact], config: &DetectorConfig, out: &mut Vec<LlmProposal>) {
+    let normalized_ref = |tx: &TxHeader| {
+        tx.ref_number
+            .as_deref()
+            .map(|r| r.trim().to_lowercase())
+            .filter(|r| !r.is_empty())
+    };
+    let mut seen: BTreeMap<(&str, &str, i64, Option<String>), Vec<&TxFact>> = BTreeMap::new();
+    for f in facts
+        .iter()
+        .filter(|f| is_invoice(&f.tx.tx_type) && f.amount > 0.0)
+    {
+        let Some(party) = f.tx.party_id.as_deref() else {
+            continue;
+        };
+        let key = (
+            f.tx.tx_type.as_str(),
+            party,
+            (f.amount * 100.0).round() as i64,
+            normalized_ref(f.tx),
+        );
+        let earlier = seen.entry(key).or_default();
+        let original = earlier.iter().find(|e| match (e.date, f.date) {
+            (Some(a), Some(b)) => (b - a).num_days().abs() <= config.duplicate_window_days,
+            _ => false,
+        });
+        if let Some(original) = original {
+            out.push(flag(
+                "duplicate_invoice",
+                f.tx,
+                "Possible Duplicate Invoice",
+                format!(
+                    "{} repeats {} for party {} ({:.2}) within {} days.",
+                    describe(f.tx),
+                    describe(original.tx),
+                    party,
+                    f.amount,
+                    config.duplicate_window_days
+                ),
+            ));
+        }
+        earlier.push(f);
+    }
+}
+
+fn unusual_amounts(facts: &[TxFact], config: &DetectorConfig, out: &mut Vec<LlmProposal>) {
+    let mut history: BTreeMap<(&str, &str), Vec<f64>> = BTreeMap::new();
+    for f in facts.iter().filter(|f| f.amount > 0.0) {
+        let Some(party) = f.tx.party_id.as_deref() else {
+            continue;
+        };
+        let prior = history.entry((party, f.tx.tx_type.as_str())).or_default();
+        if prior.len() >= config.unusual_min_history {
+            let median = median(prior);
+            let ratio = f.amount / median;
+            if median > 0.0
+                && (ratio >= config.unusual_ratio || ratio <= 1.0 / config.unusual_ratio)
+            {
+                out.push(flag(
+                    "unusual_amount",
+                    f.tx,
+                    "Unusual Amount For Party",
+                    format!(
+                        "{} is {:.2}, {:.1}× the median {:.2} of party {}'s previous {} {}s.",
+                        describe(f.tx),
+                        f.amount,
+                        ratio,
+                        median,
+                        party,
+                        prior.len(),
+                        f.tx.tx_type.as_str()
+                    ),
+                ));
+            }
+        }
+        prior.push(f.amount);
+    }
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    }
+}
+
+fn weekend_postings(facts: &[TxFact], config: &DetectorConfig, out: &mut Vec<LlmProposal>) {
+    for f in facts.iter().filter(|f| f.tx.status == TxStatus::Posted) {
+        let Some(date) = f.date else {
+            continue;
+        };
+        let ymd = date.format("%Y-%m-%d").to_string();
+        let holiday = config.holidays.iter().any(|h| *h == ymd || *h == ymd[5..]);
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        if weekend || holiday {
+            let when = if holiday {
+                "a holiday".to_string()
+            } else {
+                format!("a {}", date.format("%A"))
+            };
+            out.push(flag(
+                "weekend_posting",
+                f.tx,
+                "Posted On Non-Business Day",
+                format!("{} was posted dated {} ({}).", describe(f.tx), ymd, when),
+            ));
+        }
+    }
+}
+
+fn round_amounts(facts: &[TxFact], config: &DetectorConfig, out: &mut Vec<LlmProposal>) {
+    if config.round_unit <= 0.0 {
+        return;
+    }
+    for f in facts.iter().filter(|f| f.amount >= config.round_min) {
+        let units = f.amount / config.round_unit;
+        if (units - units.round()).abs() < 1e-9 {
+            out.push(flag(
+                "round_amount",
+                f.tx,
+                "Round-Number Amount",
+                format!(
+                    "{} is exactly {:.2}, a multiple of {:.0}; estimates and fabricated entries often are.",
+                    describe(f.tx),
+                    f.amount,
+                    config.round_unit
+                ),
+            ));
+        }
+    }
+}
+
+fn benford(
+    store: &ErpStore,
+    org_id: &str,
+    lines_by_tx: &HashMap<&str, Vec<&TxLine>>,
+    config: &DetectorConfig,
+    out: &mut Vec<LlmProposal>,
+) {
+    let mut counts = [0usize; 9];
+    for tx in store
+        .transactions
+        .values()
+        .filter(|t| t.org_id == org_id && t.status != TxStatus::Void)
+    {
+        let lines = lines_by_tx.get(tx.tx_id.as_str());
+        // A journal's amounts sit on its lines' debit/credit sides; other
+        // txs count once, at their gross amount
+        let amounts: Vec<f64> = if tx.tx_type == TxType::Journal {
+            lines
+                .into_iter()
+                .flatten()
+                .map(|l| {
+                    let (debit, credit) = journal::line_sides(l);
+                    debit + credit
+                })
+                .collect()
+        } else {
+            vec![tx_amount(tx, lines)]
+        };
+        for amount in amounts {
+            if let Some(d) = leading_digit(amount.abs()) {
+                counts[d - 1] += 1;
+            }
+        }
+    }
+    let n: usize = counts.iter().sum();
+    if n < config.benford_min_samples.max(1) {
+        return;
+    }
+    let deviation = |d: usize| counts[d - 1] as f64 / n as f64 - benford_expected(d);
+    let mad = (1..=9).map(|d| deviation(d).abs()).sum::<f64>() / 9.0;
+    if mad <= config.benford_mad_threshold {
+        return;
+    }
+    let worst = (1..=9)
+        .max_by(|a, b| deviation(*a).abs().total_cmp(&deviation(*b).abs()))
+        .unwrap_or(1);
+    out.push(LlmProposal {
+        id: "caio-anomaly-benford".into(),
+        r#type: "anomaly_flag".into(),
+        title: "Benford's Law Deviation".into(),
+        rationale: format!(
+            "Leading digits of {} amounts deviate from Benford's law (MAD {:.3}); \
+             digit {} appears in {:.1}% of amounts vs {:.1}% expected.",
+            n,
+            mad,
+            worst,
+            100.0 * counts[worst - 1] as f64 / n as f64,
+            100.0 * benford_expected(worst)
+        ),
+        source_fragment: fragments::org_indexes_id(org_id),
+        source: Some("rules".into()),
+        draft: None,
+        proposal_id: None,
+        citations: vec![],
+    });
+}
+
+/// First significant digit of amounts ≥ 1 (smaller amounts are skipped).
+fn leading_digit(amount: f64) -> Option<usize> {
+    if !amount.is_finite() || amount < 1.0 {
+        return None;
+    }
+    let scaled = amount / 10f64.powi(amount.log10().floor() as i32);
+    Some((scaled.floor() as usize).clamp(1, 9))
+}
+
+fn payments_without_invoice(facts: &[TxFact], out: &mut Vec<LlmProposal>) {
+    // facts are in date order: compare running totals per supplier
+    let mut invoiced: BTreeMap<&str, f64> = BTreeMap::new();
+    let mut paid: BTreeMap<&str, f64> = BTreeMap::new();
+    for f in facts {
+        let party = f.tx.party_id.as_deref().unwrap_or("");
+        match f.tx.tx_type {
+            TxType::InvoiceIn if !party.is_empty() => {
+                *invoiced.entry(party).or_default() += f.amount;
+            }
+            TxType::PaymentOut => {
+                let total_paid = paid.entry(party).or_default();
+                *total_paid += f.amount;
+                let total_invoiced = invoiced.get(party).copied().unwrap_or(0.0);
+                if *total_paid > total_invoiced + 0.005 {
+                    let who = if party.is_empty() {
+                        "no supplier".to_string()
+                    } else {
+                        format!("supplier {}", party)
+                    };
+                    out.push(flag(
+                        "payment_without_invoice",
+                        f.tx,
+                        "Payment Without Invoice",
+                        format!(
+                            "{} brings payments to {} to {:.2}, but only {:.2} was invoiced by then.",
+                            describe(f.tx),
+                            who,
+                            total_paid,
+                            total_invoiced
+                        ),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::TxBuilder;
+
+    fn ids(found: &[LlmProposal], detector: &str) -> Vec<String> {
+        let prefix = format!("caio-anomaly-{}-", detector);
+        found
+            .iter()
+            .filter_map(|p| p.id.strip_prefix(&prefix).map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn test_detectors() {
+        let mut store = ErpStore::new();
+        // Mondays to Fridays in October 2026 (the 5th is a Monday)
+        for (i, day) in ["05", "06", "07", "08", "09"].iter().enumerate() {
+            TxBuilder::new(&format!("s{}", i), TxType::InvoiceOut)
+                .party("cust")
+                .date(&format!("2026-10-{}", day))
+                .lines(&[120.0 + i as f64])
+                .insert(&mut store);
+        }
+        // Unusual for cust, on a Saturday, and a round number
+        TxBuilder::new("big", TxType::InvoiceOut)
+            .party("cust")
+            .date("2026-10-10")
+            .lines(&[9000.0])
+            .insert(&mut store);
+        // Same party/amount/ref three days apart
+        TxBuilder::new("d1", TxType::InvoiceIn)
+            .party("supp")
+            .ref_number("INV-9")
+            .date("2026-10-12")
+            .lines(&[450.5])
+            .insert(&mut store);
+        TxBuilder::new("d2", TxType::InvoiceIn)
+            .party("supp")
+            .ref_number("INV-9")
+            .date("2026-10-15")
+            .lines(&[450.5])
+            .insert(&mut store);
+        // Paid 1000 against 901 invoiced; another supplier never invoiced
+        TxBuilder::new("p1", TxType::PaymentOut)
+            .party("supp")
+            .date("2026-10-16")
+            .lines(&[901.0])
+            .insert(&mut store);
+        TxBuilder::new("p2", TxType::PaymentOut)
+            .party("supp")
+            .date("2026-10-19")
+            .lines(&[99.0])
+            .insert(&mut store);
+        TxBuilder::new("p3", TxType::PaymentOut)
+            .party("ghost")
+            .date("2026-10-20")
+            .lines(&[77.0])
+            .insert(&mut store);
+
+        let config = DetectorConfig {
+            holidays: vec!["10-20".to_string()],
+            ..DetectorConfig::default()
+        };
+        let found = detect(&store, "org1", &config);
+        assert_eq!(ids(&found, "duplicate_invoice"), vec!["d2"]);
+        assert_eq!(ids(&found, "unusual_amount"), vec!["big"]);
+        assert_eq!(ids(&found, "weekend_posting"), vec!["big", "p3"]);
+        assert_eq!(ids(&found, "round_amount"), vec!["big"]);
+        assert_eq!(ids(&found, "payment_without_invoice"), vec!["p2", "p3"]);
+        assert!(found
+            .iter()
+            .all(|p| p.r#type == "anomaly_flag" && p.source_fragment.starts_with("tx:")));
+        // Too few amounts for Benford
+        assert!(!found.iter().any(|p| p.id == "caio-anomaly-benford"));
+        assert!(detect(&store, "org2", &config).is_empty());
+    }
+
+    #[test]
+    fn test_benford() {
+        assert_eq!(leading_digit(0.5), None);
+        assert_eq!(leading_digit(1.0), Some(1));
+        assert_eq!(leading_digit(987.0), Some(9));
+        assert_eq!(leading_digit(20_000.0), Some(2));
+
+        // Amounts all starting with 5 or 6: far from Benford
+        let mut store = ErpStore::new();
+        for i in 0..120 {
+            let amount = if i % 2 == 0 { 55.0 } else { 610.0 } + i as f64 * 0.01;
+            TxBuilder::new(&format!("t{}", i), TxType::Journal)
+                .party("x")
+                .date("2026-10-05")
+                .lines(&[amount])
+                .insert(&mut store);
+        }
+        let config = DetectorConfig {
+            round_min: f64::MAX,
+            ..DetectorConfig::default()
+        };
+        let found = detect(&store, "org1", &config);
+        let flag = found
+            .iter()
+            .find(|p| p.id == "caio-anomaly-benford")
+            .unwrap();
+        assert_eq!(flag.source_fragment, "org:org1:indexes");
+
+        // Benford-distributed amounts pass: 10^(i/n) spreads leading digits logarithmically
+        let mut store = ErpStore::new();
+        for i in 0..200 {
+            let amount = 10f64.powf(1.0 + i as f64 / 200.0);
+            TxBuilder::new(&format!("t{}", i), TxType::Journal)
+                .party("x")
+                .date("2026-10-05")
+                .lines(&[amount])
+                .insert(&mut store);
+        }
+        assert!(!detect(&store, "org1", &config)
+            .iter()
+            .any(|p| p.id == "caio-anomaly-benford"));
+    }
+}
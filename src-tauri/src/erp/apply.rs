@@ -0,0 +1,748 @@
+//! apply.rs — Materialise ops into typed `ErpStore` records
+//!
+//! Every ERP mutation is a signed envelope of `Op`s against the fragment
+//! layout in `fragments.rs`. `apply_ops` is the single path that turns those
+//! ops into `TxHeader` / `TxLine` / `InvMove` / `Posting` / `AccountRecord` /
+//! `Party` / `ReorderPolicy` / `CountSheet` records: engine commands, audit replay
+//! (`timetravel`, `checkpoint`) and remote envelopes all go through it, so
+//! the same log always yields the same store.
+//!
+//! Per op kind:
+//! - `MapSet` / `MapDel` — header, account, party and reorder policy fragments
+//!   are per field (deleting a policy's `item_id` removes the policy);
+//!   `txline` / `invmove` / `posting` / `stocktake` fragments carry the whole
+//!   record under `data` (deleting `data` removes the record).
+//! - `ArrayInsert` / `ArrayDelete` — kept in `store.arrays`; removing ids from
+//!   `tx:{id}:lines` also removes those lines.
+//! - `LinkAdd` — recorded once in `store.links`.
+//! - `ProposalCreate` — queued in `store.proposals`; its ops are NOT applied.
+//!   Its review fields (status, decision …) are per-field `proposal:{id}` ops.
+//!
+//! Fragments this module does not recognise are ignored.
+
+use std::collections::BTreeSet;
+
+use crate::erp::engine::{AccountRecord, ErpStore};
+use crate::erp::reorder::ReorderPolicy;
+use crate::erp::types::{
+    FragmentLink, Op, Party, PartyKind, PendingProposal, ProposalStatus, TxHeader, TxStatus, TxType,
+};
+
+/// Apply ops in order to `store`.
+pub fn apply_ops(store: &mut ErpStore, ops: &[Op]) {
+    for op in ops {
+        match op {
+            Op::MapSet {
+                fragment_id,
+                key,
+                value,
+            } => apply_map_set(store, fragment_id, key, value),
+            Op::MapDel { fragment_id, key } => apply_map_del(store, fragment_id, key),
+            Op::ArrayInsert {
+                fragment_id,
+                index,
+                values,
+            } => {
+                let arr = store.arrays.entry(fragment_id.clone()).or_default();
+                let at = (*index as usize).min(arr.len());
+                arr.splice(at..at, values.iter().cloned());
+            }
+            Op::ArrayDelete {
+                fragment_id,
+                index,
+                len,
+            } => {
+                let removed: Vec<serde_json::Value> = match store.arrays.get_mut(fragment_id) {
+                    Some(arr) => {
+                        let from = (*index as usize).min(arr.len());
+                        let to = (from + *len as usize).min(arr.len());
+                        arr.drain(from..to).collect()
+                    }
+                    None => Vec::new(),
+                };
+                if is_line_array(fragment_id) {
+                    for line_id in removed.iter().filter_map(|v| v.as_str()) {
+                        store.lines.remove(line_id);
+                    }
+                }
+            }
+            Op::LinkAdd {
+                from_fragment,
+                to_fragment,
+                rel_type,
+            } => {
+                let link = FragmentLink {
+                    from_fragment: from_fragment.clone(),
+                    to_fragment: to_fragment.clone(),
+                    rel_type: rel_type.clone(),
+                };
+                if !store.links.contains(&link) {
+                    store.links.push(link);
+                }
+            }
+            Op::ProposalCreate {
+                proposal_id,
+                source_fragment,
+                ops,
+                rationale,
+            } => {
+                let p = store
+                    .proposals
+                    .entry(proposal_id.clone())
+                    .or_insert_with(|| empty_proposal(proposal_id));
+                p.source_fragment = source_fragment.clone();
+                p.ops = ops.iter().map(|o| (**o).clone()).collect();
+                p.rationale = rationale.clone();
+            }
+        }
+    }
+}
+
+fn apply_map_set(store: &mut ErpStore, fragment_id: &str, key: &str, value: &serde_json::Value) {
+    let (kind, rest) = fragment_id.split_once(':').unwrap_or((fragment_id, ""));
+    match kind {
+        // tx:{id}:hdr — one key per header field
+        "tx" => {
+            if let Some(tx_id) = rest.strip_suffix(":hdr") {
+                let header = store
+                    .transactions
+                    .entry(tx_id.to_string())
+                    .or_insert_with(|| empty_header(tx_id));
+                set_header_field(header, key, value);
+            }
+        }
+        "txline" if key == "data" => {
+            if let Ok(mut line) = serde_json::from_value::<crate::erp::types::TxLine>(value.clone())
+            {
+                // Moves applied earlier stay linked if the line is rewritten
+                if let Some(prev) = store.lines.get(&line.line_id) {
+                    for m in &prev.move_ids {
+                        if !line.move_ids.contains(m) {
+                            line.move_ids.push(m.clone());
+                        }
+                    }
+                }
+                store.lines.insert(line.line_id.clone(), line);
+            }
+        }
+        "invmove" if key == "data" => {
+            if let Ok(m) = serde_json::from_value::<crate::erp::types::InvMove>(value.clone()) {
+                if let Some(line) = store.lines.get_mut(&m.tx_line_id) {
+                    if !line.move_ids.contains(&m.move_id) {
+                        line.move_ids.push(m.move_id.clone());
+                    }
+                }
+                store.invmoves.insert(m.move_id.clone(), m);
+            }
+        }
+        "posting" if key == "data" => {
+            if let Ok(p) = serde_json::from_value::<crate::erp::types::Posting>(value.clone()) {
+                store.postings.insert(p.posting_id.clone(), p);
+            }
+        }
+        "account" => {
+            let acct = store
+                .accounts
+                .entry(rest.to_string())
+                .or_insert_with(|| empty_account(rest));
+            set_account_field(acct, key, value);
+        }
+        "party" => {
+            let party = store
+                .parties
+                .entry(rest.to_string())
+                .or_insert_with(|| empty_party(rest));
+            set_party_field(party, key, value);
+        }
+        // reorder:{org_id}:{item_id}
+        "reorder" => {
+            let policy = store
+                .reorder_policies
+                .entry(rest.to_string())
+                .or_insert_with(|| ReorderPolicy::for_item(""));
+            set_reorder_field(policy, key, value);
+        }
+        "proposal" => {
+            let p = store
+                .proposals
+                .entry(rest.to_string())
+                .or_insert_with(|| empty_proposal(rest));
+            set_proposal_field(p, key, value);
+        }
+        "stocktake" if key == "data" => {
+            if let Ok(sheet) =
+                serde_json::from_value::<crate::erp::stocktake::CountSheet>(value.clone())
+            {
+                store.count_sheets.insert(sheet.sheet_id.clone(), sheet);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn apply_map_del(store: &mut ErpStore, fragment_id: &str, key: &str) {
+    let (kind, rest) = fragment_id.split_once(':').unwrap_or((fragment_id, ""));
+    match kind {
+        "tx" => {
+            if let Some(h) = rest
+                .strip_suffix(":hdr")
+                .and_then(|id| store.transactions.get_mut(id))
+            {
+                set_header_field(h, key, &serde_json::Value::Null);
+            }
+        }
+        "txline" if key == "data" => {
+            store.lines.remove(rest);
+        }
+        "invmove" if key == "data" => {
+            if let Some(m) = store.invmoves.remove(rest) {
+                if let Some(line) = store.lines.get_mut(&m.tx_line_id) {
+                    line.move_ids.retain(|id| id != &m.move_id);
+                }
+            }
+        }
+        // Posting ids are full `posting:{uuid}` fragment ids
+        "posting" if key == "data" => {
+            store.postings.remove(fragment_id);
+        }
+        "account" => {
+            if let Some(a) = store.accounts.get_mut(rest) {
+                set_account_field(a, key, &serde_json::Value::Null);
+            }
+        }
+        "party" => {
+            if let Some(p) = store.parties.get_mut(rest) {
+                set_party_field(p, key, &serde_json::Value::Null);
+            }
+        }
+        "reorder" if key == "item_id" => {
+            store.reorder_policies.remove(rest);
+        }
+        "reorder" => {
+            if let Some(p) = store.reorder_policies.get_mut(rest) {
+                set_reorder_field(p, key, &serde_json::Value::Null);
+            }
+        }
+        "proposal" => {
+            if let Some(p) = store.proposals.get_mut(rest) {
+                set_proposal_field(p, key, &serde_json::Value::Null);
+            }
+        }
+        "stocktake" if key == "data" => {
+            store.count_sheets.remove(rest);
+        }
+        _ => {}
+    }
+}
+
+/// Record ids touched by a set of ops, by record kind — used by the engine to
+/// write the affected rows through to SQLite after `apply_ops`.
+#[derive(Debug, Default, PartialEq)]
+pub struct Touched {
+    pub txs: BTreeSet<String>,
+    pub lines: BTreeSet<String>,
+    pub moves: BTreeSet<String>,
+    pub postings: BTreeSet<String>,
+    pub accounts: BTreeSet<String>,
+    pub parties: BTreeSet<String>,
+    /// `{org_id}:{item_id}` keys of `store.reorder_policies`
+    pub reorder_policies: BTreeSet<String>,
+    pub sheets: BTreeSet<String>,
+    pub proposals: BTreeSet<String>,
+    /// `tx:{id}:lines` arrays with deletions — their dropped lines are removed
+    pub line_arrays: BTreeSet<String>,
+}
+
+/// Collect the records `ops` write to (a proposal's held ops are not included).
+pub fn touched(ops: &[Op]) -> Touched {
+    let mut t = Touched::default();
+    for op in ops {
+        let (fragment_id, key) = match op {
+            Op::MapSet {
+                fragment_id, key, ..
+            }
+            | Op::MapDel { fragment_id, key } => (fragment_id.as_str(), key.as_str()),
+            Op::ArrayDelete { fragment_id, .. } if is_line_array(fragment_id) => {
+                t.line_arrays.insert(fragment_id.clone());
+                continue;
+            }
+            Op::ProposalCreate { proposal_id, .. } => {
+                t.proposals.insert(proposal_id.clone());
+                continue;
+            }
+            _ => continue,
+        };
+        let (kind, rest) = fragment_id.split_once(':').unwrap_or((fragment_id, ""));
+        let id = rest.to_string();
+        match kind {
+            "tx" => {
+                if let Some(tx_id) = rest.strip_suffix(":hdr") {
+                    t.txs.insert(tx_id.to_string());
+                }
+            }
+            "txline" if key == "data" => {
+                t.lines.insert(id);
+            }
+            "invmove" if key == "data" => {
+                t.moves.insert(id);
+            }
+            "posting" if key == "data" => {
+                t.postings.insert(fragment_id.to_string());
+            }
+            "account" => {
+                t.accounts.insert(id);
+            }
+            "party" => {
+                t.parties.insert(id);
+            }
+            "reorder" => {
+                t.reorder_policies.insert(id);
+            }
+            "stocktake" if key == "data" => {
+                t.sheets.insert(id);
+            }
+            "proposal" => {
+                t.proposals.insert(id);
+            }
+            _ => {}
+        }
+    }
+    t
+}
+
+fn is_line_array(fragment_id: &str) -> bool {
+    fragment_id.starts_with("tx:") && fragment_id.ends_with(":lines")
+}
+
+fn empty_header(tx_id: &str) -> TxHeader {
+    TxHeader {
+        tx_id: tx_id.to_string(),
+        org_id: String::new(),
+        tx_type: TxType::Journal,
+        status: TxStatus::Draft,
+        party_id: None,
+        currency: "AUD".to_string(),
+        ref_number: None,
+        description: None,
+        tx_date: String::new(),
+        created_at_ms: 0,
+        created_by_pubkey: String::new(),
+        site_id: "primary".to_string(),
+        source_tx_id: None,
+    }
+}
+
+fn set_header_field(h: &mut TxHeader, key: &str, value: &serde_json::Value) {
+    let text = || value.as_str().map(str::to_string);
+    match key {
+        "tx_type" => {
+            if let Some(t) = value.as_str().and_then(|s| TxType::from_str(s).ok()) {
+                h.tx_type = t;
+            }
+        }
+        "status" => {
+            if let Some(s) = value.as_str().and_then(|s| TxStatus::from_str(s).ok()) {
+                h.status = s;
+            }
+        }
+        "org_id" => h.org_id = text().unwrap_or_default(),
+        "party_id" => h.party_id = text(),
+        "currency" => h.currency = text().unwrap_or_else(|| "AUD".to_string()),
+        "ref_number" => h.ref_number = text(),
+        "description" => h.description = text(),
+        "tx_date" => h.tx_date = text().unwrap_or_default(),
+        "created_at_ms" => h.created_at_ms = value.as_i64().unwrap_or_default(),
+        "created_by_pubkey" => h.created_by_pubkey = text().unwrap_or_default(),
+        "site_id" => h.site_id = text().unwrap_or_else(|| "primary".to_string()),
+        "source_tx_id" => h.source_tx_id = text(),
+        _ => {}
+    }
+}
+
+fn empty_account(code: &str) -> AccountRecord {
+    AccountRecord {
+        code: code.to_string(),
+        name: "(unnamed)".to_string(),
+        acct_type: "asset".to_string(),
+        normal_balance: "debit".to_string(),
+        parent_code: None,
+        is_header: false,
+        active: true,
+        role: None,
+    }
+}
+
+fn set_account_field(a: &mut AccountRecord, key: &str, value: &serde_json::Value) {
+    let text = || value.as_str().map(str::to_string).filter(|s| !s.is_empty());
+    match key {
+        "name" => a.name = text().unwrap_or_else(|| "(unnamed)".to_string()),
+        "type" => a.acct_type = text().unwrap_or_else(|| "asset".to_string()),
+        "normal_balance" => a.normal_balance = text().unwrap_or_else(|| "debit".to_string()),
+        "parent_code" => a.parent_code = text(),
+        "is_header" => a.is_header = value.as_bool().unwrap_or(false),
+        "active" => a.active = value.as_bool().unwrap_or(true),
+        "role" => a.role = text(),
+        _ => {}
+    }
+}
+
+fn empty_party(party_id: &str) -> Party {
+    Party {
+        party_id: party_id.to_string(),
+        org_id: String::new(),
+        name: "(unnamed)".to_string(),
+        kind: PartyKind::Other,
+        email: None,
+        contact: None,
+        abn: None,
+        created_at_ms: 0,
+    }
+}
+
+fn set_party_field(p: &mut Party, key: &str, value: &serde_json::Value) {
+    let text = || value.as_str().map(str::to_string).filter(|s| !s.is_empty());
+    match key {
+        "org_id" => p.org_id = text().unwrap_or_default(),
+        "name" => p.name = text().unwrap_or_else(|| "(unnamed)".to_string()),
+        "kind" => p.kind = PartyKind::from_str(value.as_str().unwrap_or("other")),
+        "email" => p.email = text(),
+        "contact" => p.contact = text(),
+        "abn" => p.abn = text(),
+        "created_at_ms" => p.created_at_ms = value.as_i64().unwrap_or_default(),
+        _ => {}
+    }
+}
+
+fn set_reorder_field(p: &mut ReorderPolicy, key: &str, value: &serde_json::Value) {
+    let text = || value.as_str().map(str::to_string).filter(|s| !s.is_empty());
+    let defaults = ReorderPolicy::for_item("");
+    match key {
+        "org_id" => p.org_id = text().unwrap_or_default(),
+        "item_id" => p.item_id = text().unwrap_or_default(),
+        "reorder_point" => p.reorder_point = value.as_f64(),
+        "lead_time_days" => p.lead_time_days = value.as_f64().unwrap_or(defaults.lead_time_days),
+        "safety_days" => p.safety_days = value.as_f64().unwrap_or(defaults.safety_days),
+        "cover_days" => p.cover_days = value.as_f64().unwrap_or(defaults.cover_days),
+        "reorder_qty" => p.reorder_qty = value.as_f64(),
+        "preferred_supplier_id" => p.preferred_supplier_id = text(),
+        "unit_cost" => p.unit_cost = value.as_f64(),
+        "currency" => p.currency = text().unwrap_or(defaults.currency),
+        _ => {}
+    }
+}
+
+fn empty_proposal(proposal_id: &str) -> PendingProposal {
+    PendingProposal {
+        proposal_id: proposal_id.to_string(),
+        source_fragment: String::new(),
+        ops: Vec::new(),
+        rationale: String::new(),
+        org_id: String::new(),
+        kind: String::new(),
+        title: String::new(),
+        proposed_by: String::new(),
+        created_at_ms: None,
+        status: ProposalStatus::Pending,
+        decided_by: None,
+        decided_at_ms: None,
+        reason: None,
+    }
+}
+
+fn set_proposal_field(p: &mut PendingProposal, key: &str, value: &serde_json::Value) {
+    let text = || value.as_str().map(str::to_string).filter(|s| !s.is_empty());
+    match key {
+        "org_id" => p.org_id = text().unwrap_or_default(),
+        "kind" => p.kind = text().unwrap_or_default(),
+        "title" => p.title = text().unwrap_or_default(),
+        "proposed_by" => p.proposed_by = text().unwrap_or_default(),
+        "created_at_ms" => p.created_at_ms = value.as_i64(),
+        "status" => p.status = serde_json::from_value(value.clone()).unwrap_or_default(),
+        "decided_by" => p.decided_by = text(),
+        "decided_at_ms" => p.decided_at_ms = value.as_i64(),
+        "reason" => p.reason = text(),
+        _ => {}
+    }
+}
+
+/// Per-field ops for a party record (`party:{id}`).
+pub fn party_ops(p: &Party) -> Vec<Op> {
+    let fragment_id = crate::erp::fragments::party_id(&p.party_id);
+    [
+        ("party_id", serde_json::json!(p.party_id)),
+        ("org_id", serde_json::json!(p.org_id)),
+        ("name", serde_json::json!(p.name)),
+        ("kind", serde_json::json!(p.kind.as_str())),
+        ("email", serde_json::json!(p.email)),
+        ("contact", serde_json::json!(p.contact)),
+        ("abn", serde_json::json!(p.abn)),
+        ("created_at_ms", serde_json::json!(p.created_at_ms)),
+    ]
+    .into_iter()
+    .map(|(key, value)| Op::MapSet {
+        fragment_id: fragment_id.clone(),
+        key: key.to_string(),
+        value,
+    })
+    .collect()
+}
+
+/// Per-field ops for a reorder policy (`reorder:{org_id}:{item_id}`).
+pub fn reorder_policy_ops(p: &ReorderPolicy) -> Vec<Op> {
+    let fragment_id = crate::erp::fragments::reorder_policy_id(&p.org_id, &p.item_id);
+    [
+        ("org_id", serde_json::json!(p.org_id)),
+        ("item_id", serde_json::json!(p.item_id)),
+        ("reorder_point", serde_json::json!(p.reorder_point)),
+        ("lead_time_days", serde_json::json!(p.lead_time_days)),
+        ("safety_days", serde_json::json!(p.safety_days)),
+        ("cover_days", serde_json::json!(p.cover_days)),
+        ("reorder_qty", serde_json::json!(p.reorder_qty)),
+        (
+            "preferred_supplier_id",
+            serde_json::json!(p.preferred_supplier_id),
+        ),
+        ("unit_cost", serde_json::json!(p.unit_cost)),
+        ("currency", serde_json::json!(p.currency)),
+    ]
+    .into_iter()
+    .map(|(key, value)| Op::MapSet {
+        fragment_id: fragment_id.clone(),
+        key: key.to_string(),
+        value,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::fragments;
+    use crate::erp::test_support::set;
+    use crate::erp::types::{InvMove, InventoryEffect, TxLine};
+
+    #[test]
+    fn test_apply_builds_tx_line_and_move() {
+        let hdr = fragments::tx_hdr_id("tx1");
+        let line = TxLine {
+            line_id: "l1".to_string(),
+            tx_id: "tx1".to_string(),
+            item_id: Some("widget".to_string()),
+            account_id: None,
+            description: None,
+            qty: 5.0,
+            unit_price: 2.0,
+            inventory_effect: InventoryEffect::Increase,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
+        };
+        let mv = InvMove {
+            move_id: "m1".to_string(),
+            tx_id: "tx1".to_string(),
+            tx_line_id: "l1".to_string(),
+            item_id: "widget".to_string(),
+            qty_delta: 5.0,
+            location_id: None,
+            moved_at_ms: 0,
+            moved_by_pubkey: "pk".to_string(),
+            site_id: "primary".to_string(),
+        };
+        let ops = vec![
+            set(&hdr, "tx_id", serde_json::json!("tx1")),
+            set(&hdr, "tx_type", serde_json::json!("stock_receipt")),
+            set(&hdr, "status", serde_json::json!("draft")),
+            set(&hdr, "org_id", serde_json::json!("org1")),
+            set(
+                &fragments::txline_id("l1"),
+                "data",
+                serde_json::to_value(&line).unwrap(),
+            ),
+            set(
+                &fragments::invmove_id("m1"),
+                "data",
+                serde_json::to_value(&mv).unwrap(),
+            ),
+            set(&hdr, "status", serde_json::json!("posted")),
+        ];
+
+        let mut store = ErpStore::new();
+        apply_ops(&mut store, &ops);
+
+        let tx = &store.transactions["tx1"];
+        assert_eq!(tx.tx_type, TxType::StockReceipt);
+        assert_eq!(tx.status, TxStatus::Posted);
+        assert_eq!(tx.org_id, "org1");
+        assert_eq!(store.lines["l1"].move_ids, vec!["m1"]);
+        assert_eq!(store.invmoves["m1"].qty_delta, 5.0);
+    }
+
+    #[test]
+    fn test_apply_account_ops_round_trip() {
+        let acct = AccountRecord {
+            code: "1200".to_string(),
+            name: "Trade Debtors".to_string(),
+            acct_type: "asset".to_string(),
+            normal_balance: "debit".to_string(),
+            parent_code: Some("1000".to_string()),
+            is_header: false,
+            active: true,
+            role: Some("accounts_receivable".to_string()),
+        };
+        let mut store = ErpStore::new();
+        apply_ops(&mut store, &crate::erp::coa::account_ops(&acct));
+        apply_ops(
+            &mut store,
+            &[crate::erp::coa::account_field_op(
+                "1200",
+                "active",
+                serde_json::json!(false),
+            )],
+        );
+
+        let got = &store.accounts["1200"];
+        assert_eq!(got.name, "Trade Debtors");
+        assert_eq!(got.parent_code.as_deref(), Some("1000"));
+        assert_eq!(got.role.as_deref(), Some("accounts_receivable"));
+        assert!(!got.active);
+    }
+
+    #[test]
+    fn test_apply_party_ops_round_trip() {
+        let party = Party {
+            party_id: "party_1".to_string(),
+            org_id: "org1".to_string(),
+            name: "Acme Pty Ltd".to_string(),
+            kind: PartyKind::Supplier,
+            email: Some("ap@acme.test".to_string()),
+            contact: None,
+            abn: Some("51 824 753 556".to_string()),
+            created_at_ms: 42,
+        };
+        let mut store = ErpStore::new();
+        apply_ops(&mut store, &party_ops(&party));
+        let got = &store.parties["party_1"];
+        assert_eq!(
+            serde_json::to_value(got).unwrap(),
+            serde_json::to_value(&party).unwrap()
+        );
+
+        apply_ops(
+            &mut store,
+            &[Op::MapDel {
+                fragment_id: fragments::party_id("party_1"),
+                key: "email".to_string(),
+            }],
+        );
+        assert_eq!(store.parties["party_1"].email, None);
+        assert_eq!(
+            touched(&party_ops(&party)).parties,
+            BTreeSet::from(["party_1".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_apply_reorder_policy_ops_and_removal() {
+        let policy = ReorderPolicy {
+            org_id: "org1".to_string(),
+            reorder_point: Some(12.0),
+            preferred_supplier_id: Some("supp".to_string()),
+            unit_cost: Some(4.5),
+            ..ReorderPolicy::for_item("widget")
+        };
+        let mut store = ErpStore::new();
+        apply_ops(&mut store, &reorder_policy_ops(&policy));
+        assert_eq!(store.reorder_policies["org1:widget"], policy);
+        assert_eq!(
+            touched(&reorder_policy_ops(&policy)).reorder_policies,
+            BTreeSet::from(["org1:widget".to_string()])
+        );
+
+        let fragment_id = fragments::reorder_policy_id("org1", "widget");
+        apply_ops(
+            &mut store,
+            &[Op::MapDel {
+                fragment_id: fragment_id.clone(),
+                key: "unit_cost".to_string(),
+            }],
+        );
+        assert_eq!(store.reorder_policies["org1:widget"].unit_cost, None);
+        apply_ops(
+            &mut store,
+            &[Op::MapDel {
+                fragment_id,
+                key: "item_id".to_string(),
+            }],
+        );
+        assert!(store.reorder_policies.is_empty());
+    }
+
+    #[test]
+    fn test_array_delete_removes_lines_and_proposals_are_held() {
+        let lines_frag = fragments::tx_lines_id("tx1");
+        let line = |id: &str| TxLine {
+            line_id: id.to_string(),
+            tx_id: "tx1".to_string(),
+            item_id: None,
+            account_id: Some("bank".to_string()),
+            description: None,
+            qty: 1.0,
+            unit_price: 0.0,
+            inventory_effect: InventoryEffect::None,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: 10.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
+        };
+        let mut ops = Vec::new();
+        for (i, id) in ["l1", "l2", "l3"].iter().enumerate() {
+            ops.push(set(
+                &fragments::txline_id(id),
+                "data",
+                serde_json::to_value(line(id)).unwrap(),
+            ));
+            ops.push(Op::ArrayInsert {
+                fragment_id: lines_frag.clone(),
+                index: i as u32,
+                values: vec![serde_json::json!(id)],
+            });
+        }
+        let delete = Op::ArrayDelete {
+            fragment_id: lines_frag.clone(),
+            index: 1,
+            len: 1,
+        };
+        ops.push(delete.clone());
+        ops.push(Op::ProposalCreate {
+            proposal_id: "prop1".to_string(),
+            source_fragment: fragments::tx_hdr_id("tx1"),
+            ops: vec![Box::new(set(
+                &fragments::tx_hdr_id("tx1"),
+                "status",
+                serde_json::json!("void"),
+            ))],
+            rationale: "duplicate entry".to_string(),
+        });
+
+        let mut store = ErpStore::new();
+        apply_ops(&mut store, &ops);
+
+        assert_eq!(
+            store.arrays[&lines_frag],
+            vec![serde_json::json!("l1"), serde_json::json!("l3")]
+        );
+        assert!(!store.lines.contains_key("l2"));
+        assert_eq!(store.lines.len(), 2);
+        assert!(touched(&[delete]).line_arrays.contains(&lines_frag));
+        // The proposal is queued but its ops are not applied
+        assert_eq!(store.proposals["prop1"].ops.len(), 1);
+        assert!(!store.transactions.contains_key("tx1"));
+    }
+}
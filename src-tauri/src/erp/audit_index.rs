@@ -0,0 +1,563 @@
+//! audit_index.rs — SQLite side index over the ERP audit log
+//!
+//! The audit segments stay the source of truth; corngr.db keeps an index of
+//! them so filtered queries don't rescan the JSONL:
+//!
+//!   erp_audit_index       one row per entry — actor, time, tx from the policy
+//!                         context, and the segment / byte range of its line
+//!   erp_audit_index_refs  one row per (entry, op kind, fragment) with the tx
+//!                         the fragment belongs to
+//!
+//! The index is brought up to date lazily before each query by scanning only
+//! the entries after the last indexed one. If the last indexed entry is no
+//! longer where the index says (log replaced or restored), the org's index is
+//! rebuilt from scratch.
+
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::erp::audit_log::{self, EntryLocation, ErpAuditEntry};
+use crate::erp::envelope::MutationEnvelope;
+use crate::erp::errors::ErpError;
+use crate::erp::types::Op;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS erp_audit_index (
+    org_id        TEXT NOT NULL,
+    seq           INTEGER NOT NULL,
+    mutation_id   TEXT NOT NULL,
+    actor_pubkey  TEXT NOT NULL,
+    issued_at_ms  INTEGER NOT NULL,
+    tx_id         TEXT,
+    segment       TEXT NOT NULL,
+    byte_offset   INTEGER NOT NULL,
+    byte_len      INTEGER NOT NULL,
+    PRIMARY KEY (org_id, seq)
+);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_mutation ON erp_audit_index(org_id, mutation_id);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_actor    ON erp_audit_index(org_id, actor_pubkey, seq);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_time     ON erp_audit_index(org_id, issued_at_ms);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_tx       ON erp_audit_index(org_id, tx_id);
+
+CREATE TABLE IF NOT EXISTS erp_audit_index_refs (
+    org_id       TEXT NOT NULL,
+    seq          INTEGER NOT NULL,
+    op_kind      TEXT NOT NULL,
+    fragment_id  TEXT NOT NULL,
+    tx_id        TEXT,
+    PRIMARY KEY (org_id, seq, op_kind, fragment_id)
+);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_refs_fragment ON erp_audit_index_refs(org_id, fragment_id);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_refs_tx       ON erp_audit_index_refs(org_id, tx_id);
+CREATE INDEX IF NOT EXISTS idx_erp_audit_refs_kind     ON erp_audit_index_refs(org_id, op_kind);
+";
+
+/// Default and maximum page sizes for `query`.
+pub const DEFAULT_PAGE: usize = 100;
+pub const MAX_PAGE: usize = 1000;
+
+/// Filters for `query`. Every filter is optional; entries come newest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditQuery {
+    /// Defaults to `audit_log::DEFAULT_ORG`
+    pub org_id: Option<String>,
+    pub actor_pubkey: Option<String>,
+    /// Matches the envelope's policy context and any op touching the tx
+    pub tx_id: Option<String>,
+    pub fragment_id: Option<String>,
+    /// Serde tag of an op, e.g. "map_set", "proposal_create"
+    pub op_kind: Option<String>,
+    pub from_ms: Option<i64>,
+    pub to_ms: Option<i64>,
+    /// Only entries before this seq — the `next_cursor` of the previous page
+    pub cursor: Option<u64>,
+    /// 0 means `DEFAULT_PAGE`; capped at `MAX_PAGE`
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexedEntry {
+    pub seq: u64,
+    pub entry: ErpAuditEntry,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub org_id: String,
+    pub entries: Vec<IndexedEntry>,
+    /// Pass as `cursor` to fetch the next (older) page; `None` at the end
+    pub next_cursor: Option<u64>,
+}
+
+/// Create the index tables. Called from `db::init_db`.
+pub fn init(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(SCHEMA)
+}
+
+fn sql_err(e: rusqlite::Error) -> ErpError {
+    ErpError::ValidationFail(format!("audit index: {}", e))
+}
+
+fn io_err(e: std::io::Error) -> ErpError {
+    ErpError::ValidationFail(format!("audit log: {}", e))
+}
+
+/// (op kind, fragment, tx) for every op of an envelope. Ops queued inside a
+/// proposal are indexed under "proposal_create", since they were not applied.
+fn refs_of(envelope: &MutationEnvelope) -> Vec<(String, String, Option<String>)> {
+    fn tx_of(fragment_id: &str, value: Option<&serde_json::Value>) -> Option<String> {
+        if let Some(rest) = fragment_id.strip_prefix("tx:") {
+            return rest.split(':').next().map(str::to_string);
+        }
+        value
+            .and_then(|v| v.get("tx_id"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+    }
+    fn collect(op: &Op, kind: &str, out: &mut Vec<(String, String, Option<String>)>) {
+        match op {
+            Op::MapSet {
+                fragment_id,
+                key,
+                value,
+            } => {
+                let data = (key == "data").then_some(value);
+                out.push((kind.into(), fragment_id.clone(), tx_of(fragment_id, data)));
+            }
+            Op::MapDel { fragment_id, .. }
+            | Op::ArrayInsert { fragment_id, .. }
+            | Op::ArrayDelete { fragment_id, .. } => {
+                out.push((kind.into(), fragment_id.clone(), tx_of(fragment_id, None)));
+            }
+            Op::LinkAdd {
+                from_fragment,
+                to_fragment,
+                ..
+            } => {
+                for f in [from_fragment, to_fragment] {
+                    out.push((kind.into(), f.clone(), tx_of(f, None)));
+                }
+            }
+            Op::ProposalCreate {
+                source_fragment,
+                ops,
+                ..
+            } => {
+                out.push((
+                    kind.into(),
+                    source_fragment.clone(),
+                    tx_of(source_fragment, None),
+                ));
+                for inner in ops {
+                    collect(inner, kind, out);
+                }
+            }
+        }
+    }
+
+    let mut out = Vec::new();
+    for op in &envelope.ops {
+        let kind = serde_json::to_value(op)
+            .ok()
+            .and_then(|v| v.get("kind").and_then(|k| k.as_str()).map(str::to_string))
+            .unwrap_or_default();
+        collect(op, &kind, &mut out);
+    }
+    out
+}
+
+/// Location of the newest indexed entry of `org_id`.
+fn last_indexed(
+    conn: &Connection,
+    org_id: &str,
+) -> rusqlite::Result<Option<(EntryLocation, String)>> {
+    conn.query_row(
+        "SELECT seq, segment, byte_offset, byte_len, mutation_id FROM erp_audit_index
+         WHERE org_id = ?1 ORDER BY seq DESC LIMIT 1",
+        params![org_id],
+        |r| {
+            Ok((
+                EntryLocation {
+                    seq: r.get::<_, i64>(0)? as u64,
+                    segment: r.get(1)?,
+                    offset: r.get::<_, i64>(2)? as u64,
+                    len: r.get::<_, i64>(3)? as u64,
+                },
+                r.get(4)?,
+            ))
+        },
+    )
+    .optional()
+}
+
+/// Index the entries of `dir` (the chain of `org_id`) not indexed yet;
+/// returns how many were added.
+pub fn catch_up_dir(conn: &Connection, org_id: &str, dir: &Path) -> Result<usize, ErpError> {
+    let mut after = last_indexed(conn, org_id).map_err(sql_err)?;
+    if let Some((loc, mutation_id)) = &after {
+        let still_there = audit_log::read_in(dir, loc)
+            .map(|e| &e.envelope.mutation_id == mutation_id)
+            .unwrap_or(false);
+        if !still_there {
+            for table in ["erp_audit_index", "erp_audit_index_refs"] {
+                conn.execute(
+                    &format!("DELETE FROM {table} WHERE org_id = ?1"),
+                    params![org_id],
+                )
+                .map_err(sql_err)?;
+            }
+            after = None;
+        }
+    }
+
+    let new = audit_log::scan_dir(dir, after.as_ref().map(|(loc, _)| loc)).map_err(io_err)?;
+    if new.is_empty() {
+        return Ok(0);
+    }
+    let tx = conn.unchecked_transaction().map_err(sql_err)?;
+    for (loc, entry) in &new {
+        let env = &entry.envelope;
+        tx.execute(
+            "INSERT OR REPLACE INTO erp_audit_index
+             (org_id, seq, mutation_id, actor_pubkey, issued_at_ms, tx_id, segment, byte_offset, byte_len)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                org_id,
+                loc.seq as i64,
+                env.mutation_id,
+                env.actor_pubkey,
+                env.issued_at_ms,
+                env.policy_context.tx_id,
+                loc.segment,
+                loc.offset as i64,
+                loc.len as i64,
+            ],
+        )
+        .map_err(sql_err)?;
+        for (kind, fragment_id, tx_id) in refs_of(env) {
+            tx.execute(
+                "INSERT OR IGNORE INTO erp_audit_index_refs (org_id, seq, op_kind, fragment_id, tx_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![org_id, loc.seq as i64, kind, fragment_id, tx_id],
+            )
+            .map_err(sql_err)?;
+        }
+    }
+    tx.commit().map_err(sql_err)?;
+    Ok(new.len())
+}
+
+/// Run `q` against the index of the chain in `dir`, catching the index up first.
+pub fn query_dir(
+    conn: &Connection,
+    org_id: &str,
+    dir: &Path,
+    q: &AuditQuery,
+) -> Result<AuditPage, ErpError> {
+    catch_up_dir(conn, org_id, dir)?;
+
+    let mut sql = String::from(
+        "SELECT i.seq, i.segment, i.byte_offset, i.byte_len FROM erp_audit_index i
+         WHERE i.org_id = ?",
+    );
+    let mut args: Vec<rusqlite::types::Value> = vec![org_id.to_string().into()];
+    let refs = |cond: &str| {
+        format!(
+            " AND i.seq IN (SELECT r.seq FROM erp_audit_index_refs r WHERE r.org_id = i.org_id AND {cond})"
+        )
+    };
+    if let Some(actor) = &q.actor_pubkey {
+        sql.push_str(" AND i.actor_pubkey = ?");
+        args.push(actor.clone().into());
+    }
+    if let Some(tx_id) = &q.tx_id {
+        sql.push_str(" AND (i.tx_id = ? OR i.seq IN (SELECT r.seq FROM erp_audit_index_refs r WHERE r.org_id = i.org_id AND r.tx_id = ?))");
+        args.push(tx_id.clone().into());
+        args.push(tx_id.clone().into());
+    }
+    match (&q.fragment_id, &q.op_kind) {
+        (Some(fragment_id), Some(kind)) => {
+            sql.push_str(&refs("r.fragment_id = ? AND r.op_kind = ?"));
+            args.push(fragment_id.clone().into());
+            args.push(kind.clone().into());
+        }
+        (Some(fragment_id), None) => {
+            sql.push_str(&refs("r.fragment_id = ?"));
+            args.push(fragment_id.clone().into());
+        }
+        (None, Some(kind)) => {
+            sql.push_str(&refs("r.op_kind = ?"));
+            args.push(kind.clone().into());
+        }
+        (None, None) => {}
+    }
+    if let Some(from_ms) = q.from_ms {
+        sql.push_str(" AND i.issued_at_ms >= ?");
+        args.push(from_ms.into());
+    }
+    if let Some(to_ms) = q.to_ms {
+        sql.push_str(" AND i.issued_at_ms <= ?");
+        args.push(to_ms.into());
+    }
+    if let Some(cursor) = q.cursor {
+        sql.push_str(" AND i.seq < ?");
+        args.push((cursor as i64).into());
+    }
+    let limit = match q.limit {
+        0 => DEFAULT_PAGE,
+        n => n.min(MAX_PAGE),
+    };
+    sql.push_str(" ORDER BY i.seq DESC LIMIT ?");
+    args.push(((limit + 1) as i64).into());
+
+    let mut stmt = conn.prepare(&sql).map_err(sql_err)?;
+    let mut locs: Vec<EntryLocation> = stmt
+        .query_map(params_from_iter(args), |r| {
+            Ok(EntryLocation {
+                seq: r.get::<_, i64>(0)? as u64,
+                segment: r.get(1)?,
+                offset: r.get::<_, i64>(2)? as u64,
+                len: r.get::<_, i64>(3)? as u64,
+            })
+        })
+        .map_err(sql_err)?
+        .collect::<rusqlite::Result<_>>()
+        .map_err(sql_err)?;
+
+    let more = locs.len() > limit;
+    locs.truncate(limit);
+    let entries = locs
+        .iter()
+        .map(|loc| {
+            audit_log::read_in(dir, loc)
+                .map(|entry| IndexedEntry {
+                    seq: loc.seq,
+                    entry,
+                })
+                .map_err(io_err)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(AuditPage {
+        org_id: org_id.to_string(),
+        next_cursor: if more {
+            entries.last().map(|e| e.seq)
+        } else {
+            None
+        },
+        entries,
+    })
+}
+
+/// The entry of the chain in `dir` with `mutation_id`, catching the index up
+/// first.
+pub fn find_mutation_dir(
+    conn: &Connection,
+    org_id: &str,
+    dir: &Path,
+    mutation_id: &str,
+) -> Result<Option<ErpAuditEntry>, ErpError> {
+    catch_up_dir(conn, org_id, dir)?;
+    let loc = conn
+        .query_row(
+            "SELECT seq, segment, byte_offset, byte_len FROM erp_audit_index
+             WHERE org_id = ?1 AND mutation_id = ?2 LIMIT 1",
+            params![org_id, mutation_id],
+            |r| {
+                Ok(EntryLocation {
+                    seq: r.get::<_, i64>(0)? as u64,
+                    segment: r.get(1)?,
+                    offset: r.get::<_, i64>(2)? as u64,
+                    len: r.get::<_, i64>(3)? as u64,
+                })
+            },
+        )
+        .optional()
+        .map_err(sql_err)?;
+    loc.map(|loc| audit_log::read_in(dir, &loc).map_err(io_err))
+        .transpose()
+}
+
+/// `find_mutation_dir` over the chain of `org_id`.
+pub fn find_mutation(
+    conn: &Connection,
+    org_id: &str,
+    mutation_id: &str,
+) -> Result<Option<ErpAuditEntry>, ErpError> {
+    find_mutation_dir(conn, org_id, &audit_log::org_dir(org_id), mutation_id)
+}
+
+/// Run `q` against the index of `q.org_id` (or `audit_log::DEFAULT_ORG`).
+pub fn query(conn: &Connection, q: &AuditQuery) -> Result<AuditPage, ErpError> {
+    let org_id = q
+        .org_id
+        .clone()
+        .unwrap_or_else(|| audit_log::DEFAULT_ORG.to_string());
+    query_dir(conn, &org_id, &audit_log::org_dir(&org_id), q)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::{set, signed_envelope};
+    use rand::rngs::OsRng;
+    use std::io::Write;
+
+    fn entry(n: u64, actor: &str, tx_id: Option<&str>, ops: Vec<Op>) -> ErpAuditEntry {
+        let key = ed25519_dalek::SigningKey::generate(&mut OsRng);
+        let mut envelope = signed_envelope(&key, &format!("m{}", n), actor, ops, tx_id, n);
+        envelope.issued_at_ms = n as i64 * 1000;
+        ErpAuditEntry {
+            chain_prev_hash: String::new(),
+            chain_hash: String::new(),
+            envelope,
+        }
+    }
+
+    fn write(dir: &Path, segment: &str, entries: &[ErpAuditEntry]) {
+        std::fs::create_dir_all(dir).unwrap();
+        let mut f = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(segment))
+            .unwrap();
+        for e in entries {
+            writeln!(f, "{}", serde_json::to_string(e).unwrap()).unwrap();
+        }
+    }
+
+    fn seqs(page: &AuditPage) -> Vec<u64> {
+        page.entries.iter().map(|e| e.seq).collect()
+    }
+
+    #[test]
+    fn test_refs_cover_fragments_data_tx_and_proposals() {
+        let line = serde_json::json!({"line_id": "l1", "tx_id": "tx9"});
+        let e = entry(
+            1,
+            "pk",
+            None,
+            vec![
+                set("tx:tx1:hdr", "status", serde_json::json!("posted")),
+                set("txline:l1", "data", line),
+                Op::ProposalCreate {
+                    proposal_id: "p1".to_string(),
+                    source_fragment: "caio".to_string(),
+                    ops: vec![Box::new(set("tx:tx2:hdr", "memo", serde_json::json!("x")))],
+                    rationale: String::new(),
+                },
+            ],
+        );
+        let refs = refs_of(&e.envelope);
+        let tx = |i: usize| refs[i].2.as_deref();
+        assert_eq!(refs.len(), 4);
+        assert_eq!((refs[0].0.as_str(), tx(0)), ("map_set", Some("tx1")));
+        assert_eq!((refs[1].1.as_str(), tx(1)), ("txline:l1", Some("tx9")));
+        assert_eq!(
+            (refs[3].0.as_str(), tx(3)),
+            ("proposal_create", Some("tx2"))
+        );
+    }
+
+    #[test]
+    fn test_filters_pagination_and_incremental_catch_up() {
+        let dir = std::env::temp_dir().join(format!("erp_audit_idx_{}", uuid::Uuid::new_v4()));
+        let conn = Connection::open_in_memory().unwrap();
+        init(&conn).unwrap();
+
+        let hdr = |tx: &str| {
+            set(
+                &format!("tx:{}:hdr", tx),
+                "status",
+                serde_json::json!("draft"),
+            )
+        };
+        write(
+            &dir,
+            "000000_1970-01.jsonl",
+            &[
+                entry(1, "alice", Some("tx1"), vec![hdr("tx1")]),
+                entry(2, "bob", Some("tx2"), vec![hdr("tx2")]),
+                entry(
+                    3,
+                    "alice",
+                    None,
+                    vec![set("account:1000", "name", serde_json::json!("Bank"))],
+                ),
+            ],
+        );
+
+        let q = |q: AuditQuery| query_dir(&conn, "org1", &dir, &q).unwrap();
+        assert_eq!(seqs(&q(AuditQuery::default())), [3, 2, 1]);
+        let by_actor = AuditQuery {
+            actor_pubkey: Some("alice".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(seqs(&q(by_actor.clone())), [3, 1]);
+        let by_tx = AuditQuery {
+            tx_id: Some("tx2".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(seqs(&q(by_tx)), [2]);
+        let by_fragment = AuditQuery {
+            fragment_id: Some("account:1000".to_string()),
+            op_kind: Some("map_set".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(seqs(&q(by_fragment)), [3]);
+        let by_time = AuditQuery {
+            from_ms: Some(1500),
+            to_ms: Some(2500),
+            ..Default::default()
+        };
+        assert_eq!(seqs(&q(by_time)), [2]);
+
+        // Cursor pagination
+        let first = q(AuditQuery {
+            limit: 2,
+            ..Default::default()
+        });
+        assert_eq!((seqs(&first), first.next_cursor), (vec![3, 2], Some(2)));
+        let second = q(AuditQuery {
+            limit: 2,
+            cursor: first.next_cursor,
+            ..Default::default()
+        });
+        assert_eq!((seqs(&second), second.next_cursor), (vec![1], None));
+
+        // New entries, also in a new segment, are indexed incrementally
+        write(
+            &dir,
+            "000000_1970-01.jsonl",
+            &[entry(4, "alice", None, vec![])],
+        );
+        write(
+            &dir,
+            "000001_1970-02.jsonl",
+            &[entry(5, "carol", None, vec![])],
+        );
+        assert_eq!(catch_up_dir(&conn, "org1", &dir).unwrap(), 2);
+        assert_eq!(catch_up_dir(&conn, "org1", &dir).unwrap(), 0);
+        assert_eq!(seqs(&q(by_actor)), [4, 3, 1]);
+
+        // Lookup by mutation id reads the single entry back
+        let found = find_mutation_dir(&conn, "org1", &dir, "m5").unwrap();
+        assert_eq!(found.unwrap().envelope.actor_pubkey, "carol");
+        assert!(find_mutation_dir(&conn, "org1", &dir, "m42")
+            .unwrap()
+            .is_none());
+
+        // A replaced log invalidates the index and it is rebuilt
+        std::fs::remove_dir_all(&dir).unwrap();
+        write(
+            &dir,
+            "000000_1970-01.jsonl",
+            &[entry(9, "dave", None, vec![])],
+        );
+        let page = q(AuditQuery::default());
+        assert_eq!(seqs(&page), [1]);
+        assert_eq!(page.entries[0].entry.envelope.mutation_id, "m9");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
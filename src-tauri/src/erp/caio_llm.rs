@@ -1,19 +1,28 @@
 //! caio_llm.rs — Local LLM integration for the CAIO Guardian (Phase B M12)
 //!
 //! ## Architecture
-//! Shells out to `ollama run mistral` via `std::process::Command` (stdin/stdout).
-//! This avoids the `reqwest::blocking` deadlock that occurs when called from within
-//! Tauri's tokio async runtime.
+//! Sends the prompt to the org's `LlmBackend` (llm_backend.rs): Ollama over
+//! HTTP or its CLI, or any OpenAI-compatible local server.
 //!
 //! Falls back to `deterministic_proposals()` if:
-//!   - `ollama` is not in PATH or not installed
-//!   - The process times out (20s)
+//!   - The backend is unreachable or not installed
+//!   - The request times out (per-org `timeout_secs`)
 //!   - The response cannot be parsed as a JSON array
+//!
+//! Before answering, the model may request read-only ledger queries
+//! (caio_tools.rs) for up to `MAX_TOOL_ROUNDS` rounds; results are injected
+//! into the next prompt and every call is returned with the answer. Each
+//! proposal cites the call ids it relied on.
+//!
+//! `draft_invoice` / `reorder_proposal` cards may carry a concrete draft
+//! (`ProposedTx`); the caller queues those as signed proposals for review
+//! (see proposals.rs).
 
 use serde::{Deserialize, Serialize};
-use std::io::Write;
-use std::process::{Command, Stdio};
-use std::time::Duration;
+
+use crate::erp::caio_tools::{ToolCall, ToolRecord, MAX_CALLS_PER_ROUND, MAX_TOOL_ROUNDS, TOOLS};
+use crate::erp::llm_backend::LlmBackend;
+use crate::erp::proposals::ProposedTx;
 
 // ─── Types ────────────────────────────────────────────────────────────────────
 
@@ -27,6 +36,15 @@ pub struct LlmProposal {
     pub source_fragment: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>, // "llm" | "rules"
+    /// The transaction this card proposes, if it is concrete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft: Option<ProposedTx>,
+    /// Set once the draft is queued for review
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proposal_id: Option<String>,
+    /// Tool call ids (`ToolRecord::call_id`) whose data this card relies on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<String>,
 }
 
 // ─── Public API ───────────────────────────────────────────────────────────────
@@ -38,77 +56,107 @@ pub struct CaioContext {
     pub posted_count: usize,
     pub party_count: usize,
     pub account_count: usize,
+    /// Findings of the deterministic detectors (`anomaly::detect`); always
+    /// returned, whether or not the backend answers
+    pub anomalies: Vec<LlmProposal>,
+    /// Items below their reorder point (`reorder::proposals`); returned
+    /// like `anomalies`
+    pub reorders: Vec<LlmProposal>,
 }
 
-/// Query the local Ollama instance for CAIO proposals.
-/// Returns `(Vec<LlmProposal>, used_llm: bool)`.
-pub fn query_caio(ctx: &CaioContext, user_query: &str) -> (Vec<LlmProposal>, bool) {
-    match try_ollama_cli(ctx, user_query) {
-        Some(proposals) if !proposals.is_empty() => (proposals, true),
-        _ => (deterministic_proposals(ctx), false),
-    }
+pub struct CaioAnswer {
+    pub proposals: Vec<LlmProposal>,
+    /// True if the proposals came from the backend, not the rules fallback
+    pub used_llm: bool,
+    /// Every tool call the model made, in order
+    pub tool_calls: Vec<ToolRecord>,
 }
 
-// ─── Ollama CLI subprocess ────────────────────────────────────────────────────
-
-fn try_ollama_cli(ctx: &CaioContext, user_query: &str) -> Option<Vec<LlmProposal>> {
-    let prompt = build_prompt(ctx, user_query);
-
-    // Try common ollama binary locations
-    let ollama_paths = [
-        "/usr/local/bin/ollama",
-        "/usr/bin/ollama",
-        "/opt/homebrew/bin/ollama",
-        "ollama", // rely on PATH
-    ];
+/// Query `backend` for CAIO proposals, running the tool calls it requests
+/// with `run_tool(call, seq)` (`seq` is 1-based across the whole query).
+pub fn query_caio(
+    backend: &dyn LlmBackend,
+    ctx: &CaioContext,
+    user_query: &str,
+    run_tool: &mut dyn FnMut(&ToolCall, usize) -> ToolRecord,
+) -> CaioAnswer {
+    let mut tool_calls: Vec<ToolRecord> = Vec::new();
+    for round in 0..=MAX_TOOL_ROUNDS {
+        let prompt = build_prompt(ctx, user_query, &tool_calls, round < MAX_TOOL_ROUNDS);
+        let raw = match backend.complete(&prompt) {
+            Ok(raw) => raw,
+            Err(e) => {
+                eprintln!("⚠️  CAIO backend {} failed: {}", backend.label(), e);
+                break;
+            }
+        };
+        match parse_reply(&raw) {
+            Some(Reply::ToolCalls(calls)) if round < MAX_TOOL_ROUNDS && !calls.is_empty() => {
+                for call in calls.iter().take(MAX_CALLS_PER_ROUND) {
+                    let record = run_tool(call, tool_calls.len() + 1);
+                    tool_calls.push(record);
+                }
+            }
+            Some(Reply::Proposals(mut proposals)) if !proposals.is_empty() => {
+                for p in proposals.iter_mut() {
+                    p.citations = resolve_citations(&p.citations, &tool_calls);
+                }
+                proposals.extend(ctx.anomalies.iter().cloned());
+                proposals.extend(ctx.reorders.iter().cloned());
+                return CaioAnswer {
+                    proposals,
+                    used_llm: true,
+                    tool_calls,
+                };
+            }
+            _ => break,
+        }
+    }
+    CaioAnswer {
+        proposals: deterministic_proposals(ctx),
+        used_llm: false,
+        tool_calls,
+    }
+}
 
-    let ollama_bin = ollama_paths
+/// Keep citations naming successful calls. A card left without any is
+/// ungrounded; no citations are made up for it.
+fn resolve_citations(cited: &[String], tool_calls: &[ToolRecord]) -> Vec<String> {
+    cited
         .iter()
-        .find(|p| {
-            if **p == "ollama" {
-                return true; // try PATH fallback last
-            }
-            std::path::Path::new(p).exists()
+        .filter(|c| {
+            tool_calls
+                .iter()
+                .any(|r| r.error.is_none() && r.call_id == **c)
         })
-        .copied()
-        .unwrap_or("ollama");
-
-    let mut child = Command::new(ollama_bin)
-        .args(["run", "mistral", "--format", "json"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .spawn()
-        .ok()?;
-
-    // Write prompt to stdin
-    if let Some(stdin) = child.stdin.take() {
-        let mut stdin = stdin;
-        let _ = stdin.write_all(prompt.as_bytes());
-        // stdin closes when dropped, signalling EOF to ollama
-    }
+        .cloned()
+        .collect()
+}
 
-    // Wait with a timeout using a thread
-    use std::sync::mpsc;
-    let (tx, rx) = mpsc::channel();
-    std::thread::spawn(move || {
-        let result = child.wait_with_output();
-        let _ = tx.send(result);
-    });
+// ─── Response parsing ─────────────────────────────────────────────────────────
 
-    let output = rx.recv_timeout(Duration::from_secs(30)).ok()?.ok()?;
+enum Reply {
+    ToolCalls(Vec<ToolCall>),
+    Proposals(Vec<LlmProposal>),
+}
 
-    if !output.status.success() && output.stdout.is_empty() {
-        return None;
+/// A completion is either `{"tool_calls": [...]}` or the proposals.
+fn parse_reply(raw: &str) -> Option<Reply> {
+    let value: Option<serde_json::Value> = serde_json::from_str(strip_fences(raw)).ok();
+    if let Some(calls) = value.and_then(|mut v| Some(v.get_mut("tool_calls")?.take())) {
+        return serde_json::from_value(calls).ok().map(Reply::ToolCalls);
     }
+    parse_proposals(raw).map(Reply::Proposals)
+}
 
-    let raw = String::from_utf8_lossy(&output.stdout);
-    let raw = raw.trim();
-
-    // The model may wrap the array in ```json fences — strip them
+/// Proposals from a completion: a JSON array, possibly in ```json fences or
+/// wrapped as `{"proposals": [...]}` (JSON-mode backends return objects).
+fn parse_proposals(raw: &str) -> Option<Vec<LlmProposal>> {
     let json_str = strip_fences(raw);
-
-    let proposals: Vec<LlmProposal> = serde_json::from_str(json_str).ok()?;
+    let proposals: Vec<LlmProposal> = serde_json::from_str(json_str).ok().or_else(|| {
+        let mut wrapped: serde_json::Value = serde_json::from_str(json_str).ok()?;
+        serde_json::from_value(wrapped.get_mut("proposals")?.take()).ok()
+    })?;
 
     Some(
         proposals
@@ -137,7 +185,53 @@ fn strip_fences(s: &str) -> &str {
     s
 }
 
-fn build_prompt(ctx: &CaioContext, user_query: &str) -> String {
+/// Longest tool result (in bytes) quoted back into the prompt.
+const MAX_RESULT_CHARS: usize = 2000;
+
+fn build_prompt(
+    ctx: &CaioContext,
+    user_query: &str,
+    tool_calls: &[ToolRecord],
+    tools_allowed: bool,
+) -> String {
+    let tool_list: String = TOOLS
+        .iter()
+        .map(|(name, desc)| format!("- {} {}\n", name, desc))
+        .collect();
+    let mut results = String::new();
+    for r in tool_calls {
+        let args = serde_json::to_string(&r.args).unwrap_or_default();
+        let out = match &r.error {
+            Some(e) => format!("error: {}", e),
+            None => {
+                let mut out = serde_json::to_string(&r.result).unwrap_or_default();
+                if out.len() > MAX_RESULT_CHARS {
+                    let mut end = MAX_RESULT_CHARS;
+                    while !out.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    out.truncate(end);
+                    out.push_str("…(truncated)");
+                }
+                out
+            }
+        };
+        results.push_str(&format!("[{}] {} {} → {}\n", r.call_id, r.tool, args, out));
+    }
+    let tool_section = if tools_allowed {
+        format!(
+            "Read-only ledger tools for this organisation:\n{tool_list}\
+To query them, respond with ONLY {{\"tool_calls\": [{{\"tool\": \"party_balances\", \"args\": {{\"kind\": \"customer\"}}}}]}} (at most {MAX_CALLS_PER_ROUND} calls); the results come back in the next prompt. \
+Never state figures you have not queried.\n"
+        )
+    } else {
+        "No more tool calls: answer now from the results below.\n".to_string()
+    };
+    let results_section = if results.is_empty() {
+        String::new()
+    } else {
+        format!("\nTool results so far:\n{}", results)
+    };
     format!(
         r#"You are CAIO Guardian, an AI CFO assistant for a small business ERP system called Corngr.
 
@@ -147,10 +241,13 @@ Current ledger context:
 - Parties on file: {party_count}
 - Chart of Accounts entries: {account_count}
 
+{tool_section}{results_section}
 User question: {user_query}
 
-Respond with ONLY a JSON array of 1-3 proposal objects. No markdown, no explanation.
+To answer, respond with ONLY a JSON array of 1-3 proposal objects. No markdown, no explanation.
 Each object must have: "id" (unique slug), "type" (one of: reorder_proposal, draft_invoice, anomaly_flag, briefing), "title" (max 8 words), "rationale" (one sentence), "source_fragment" (e.g. "org:{org_id}:indexes").
+A draft_invoice or reorder_proposal may add "draft": {{"tx_type": "invoice_out" or "invoice_in", "party_id": optional, "currency": "AUD", "description": short text, "tx_date": "YYYY-MM-DD", "lines": [{{"description": text, "qty": number, "unit_price": number, "item_id": optional, "account_id": optional}}]}}. Only propose a draft when the question or tool results give its figures.
+A proposal based on tool results adds "citations": the call ids it used, e.g. ["t1"].
 
 Example: [{{"id":"caio-1","type":"briefing","title":"Ledger Status","rationale":"You have {draft_count} drafts pending.","source_fragment":"org:{org_id}:indexes"}}]"#,
         org_id = ctx.org_id,
@@ -159,6 +256,8 @@ Example: [{{"id":"caio-1","type":"briefing","title":"Ledger Status","rationale":
         posted_count = ctx.posted_count,
         party_count = ctx.party_count,
         account_count = ctx.account_count,
+        tool_section = tool_section,
+        results_section = results_section,
         user_query = user_query,
     )
 }
@@ -166,7 +265,8 @@ Example: [{{"id":"caio-1","type":"briefing","title":"Ledger Status","rationale":
 // ─── Deterministic fallback ───────────────────────────────────────────────────
 
 fn deterministic_proposals(ctx: &CaioContext) -> Vec<LlmProposal> {
-    let mut proposals = Vec::new();
+    let mut proposals = ctx.anomalies.clone();
+    proposals.extend(ctx.reorders.iter().cloned());
 
     if ctx.draft_count >= 3 {
         proposals.push(LlmProposal {
@@ -179,6 +279,9 @@ fn deterministic_proposals(ctx: &CaioContext) -> Vec<LlmProposal> {
             ),
             source_fragment: format!("org:{}:indexes", ctx.org_id),
             source: Some("rules".into()),
+            draft: None,
+            proposal_id: None,
+            citations: vec![],
         });
     }
 
@@ -192,6 +295,9 @@ fn deterministic_proposals(ctx: &CaioContext) -> Vec<LlmProposal> {
                     .into(),
             source_fragment: format!("org:{}:coa", ctx.org_id),
             source: Some("rules".into()),
+            draft: None,
+            proposal_id: None,
+            citations: vec![],
         });
     }
 
@@ -206,8 +312,116 @@ fn deterministic_proposals(ctx: &CaioContext) -> Vec<LlmProposal> {
             ),
             source_fragment: format!("org:{}:indexes", ctx.org_id),
             source: Some("rules".into()),
+            draft: None,
+            proposal_id: None,
+            citations: vec![],
         });
     }
 
     proposals
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::llm_backend::MockBackend;
+
+    fn ctx() -> CaioContext {
+        CaioContext {
+            org_id: "org1".to_string(),
+            tx_count: 4,
+            draft_count: 3,
+            posted_count: 1,
+            party_count: 2,
+            account_count: 10,
+            anomalies: vec![],
+            reorders: vec![],
+        }
+    }
+
+    #[test]
+    fn test_backend_reply_or_rules_fallback() {
+        let mock = MockBackend {
+            response: "```json\n{\"proposals\":[{\"id\":\"c1\",\"type\":\"briefing\",\"title\":\"Status\",\"rationale\":\"ok\",\"source_fragment\":\"org:org1:indexes\"}]}\n```".to_string(),
+        };
+        let mut no_tools = |_: &ToolCall, _: usize| -> ToolRecord { unreachable!() };
+        let answer = query_caio(&mock, &ctx(), "how are we doing?", &mut no_tools);
+        assert!(answer.used_llm);
+        assert_eq!(answer.proposals[0].id, "c1");
+        assert_eq!(answer.proposals[0].source.as_deref(), Some("llm"));
+        assert!(answer.proposals[0].citations.is_empty());
+
+        for response in ["", "not json", "[]"] {
+            let mock = MockBackend {
+                response: response.to_string(),
+            };
+            let answer = query_caio(&mock, &ctx(), "", &mut no_tools);
+            assert!(!answer.used_llm);
+            assert_eq!(answer.proposals[0].id, "caio-rules-anomaly");
+        }
+    }
+
+    /// Replies in order, keeping the prompts it was sent.
+    struct ScriptedBackend {
+        replies: std::sync::Mutex<Vec<&'static str>>,
+        prompts: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl LlmBackend for ScriptedBackend {
+        fn label(&self) -> String {
+            "Scripted".to_string()
+        }
+
+        fn complete(&self, prompt: &str) -> Result<String, String> {
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            let mut replies = self.replies.lock().unwrap();
+            if replies.is_empty() {
+                return Err("script exhausted".to_string());
+            }
+            Ok(replies.remove(0).to_string())
+        }
+    }
+
+    #[test]
+    fn test_tool_calls_are_run_injected_and_cited() {
+        let backend = ScriptedBackend {
+            replies: std::sync::Mutex::new(vec![
+                r#"{"tool_calls":[{"tool":"party_balances","args":{"kind":"customer"}},{"tool":"nope"}]}"#,
+                r#"[{"id":"c1","type":"briefing","title":"Bolt Owes Most","rationale":"Bolt owes 900.","source_fragment":"org:org1:indexes","citations":["t1","t2","t9"]},
+                   {"id":"c2","type":"briefing","title":"Chase Acme","rationale":"Acme owes 600.","source_fragment":"org:org1:indexes"}]"#,
+            ]),
+            prompts: std::sync::Mutex::new(vec![]),
+        };
+        let mut run_tool = |call: &ToolCall, seq: usize| ToolRecord {
+            call_id: format!("t{}", seq),
+            tool: call.tool.clone(),
+            args: call.args.clone(),
+            result: serde_json::json!([{"party_id": "bolt", "receivable": 900.0}]),
+            error: (call.tool == "nope").then(|| "unknown tool".to_string()),
+        };
+        let answer = query_caio(&backend, &ctx(), "who owes us the most?", &mut run_tool);
+        assert!(answer.used_llm);
+        assert_eq!(answer.tool_calls.len(), 2);
+        // Only successful calls can be cited; an uncited card stays uncited
+        assert_eq!(answer.proposals[0].citations, vec!["t1"]);
+        assert!(answer.proposals[1].citations.is_empty());
+        let prompts = backend.prompts.lock().unwrap();
+        assert!(prompts[1].contains(
+            r#"[t1] party_balances {"kind":"customer"} → [{"party_id":"bolt","receivable":900.0}]"#
+        ));
+        assert!(prompts[1].contains("[t2] nope null → error: unknown tool"));
+
+        // A model that never stops calling tools falls back to the rules
+        let looping = ScriptedBackend {
+            replies: std::sync::Mutex::new(vec![
+                r#"{"tool_calls":[{"tool":"ledger_summary"}]}"#;
+                10
+            ]),
+            prompts: std::sync::Mutex::new(vec![]),
+        };
+        let answer = query_caio(&looping, &ctx(), "", &mut run_tool);
+        assert!(!answer.used_llm);
+        assert_eq!(answer.tool_calls.len(), MAX_TOOL_ROUNDS);
+        assert!(looping.prompts.lock().unwrap()[MAX_TOOL_ROUNDS].contains("No more tool calls"));
+    }
+}
@@ -0,0 +1,365 @@
+//! caio_tools.rs — Read-only ledger queries CAIO's model can request
+//!
+//! Instead of answering from a handful of counts, the model may reply with
+//! `{"tool_calls": [{"tool": "party_balances", "args": {...}}]}`. Each call
+//! runs against `ErpStore` for the querying org only, its result is injected
+//! into the next prompt, and the call is recorded (`ToolRecord`) so the final
+//! proposals can cite the data they used (`LlmProposal::citations`).
+//!
+//! Tools never mutate the store. Amounts are gross (incl. tax); balances and
+//! the ledger summary count posted transactions only.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+use crate::erp::anomaly::{lines_by_tx, tx_amount};
+use crate::erp::engine::ErpStore;
+use crate::erp::stocktake;
+use crate::erp::types::{TxStatus, TxType};
+
+/// Rounds of tool calls before the model must answer.
+pub const MAX_TOOL_ROUNDS: usize = 3;
+/// Calls honoured per round; extra calls are dropped.
+pub const MAX_CALLS_PER_ROUND: usize = 4;
+/// Rows any tool returns at most.
+const MAX_ROWS: usize = 50;
+
+/// Tool name and what it returns, as listed in the prompt.
+pub const TOOLS: &[(&str, &str)] = &[
+    (
+        "ledger_summary",
+        "{} → transaction counts by status and type, and posted debit/credit totals per account",
+    ),
+    (
+        "party_balances",
+        "{\"kind\": \"customer\"|\"supplier\" (optional), \"limit\": n} → receivable and payable per party, largest first",
+    ),
+    (
+        "stock_on_hand",
+        "{\"item_id\": optional, \"site_id\": optional} → quantity per item and location",
+    ),
+    (
+        "recent_transactions",
+        "{\"limit\": n, \"party_id\": optional, \"tx_type\": optional} → latest transactions with amounts",
+    ),
+];
+
+/// A tool request from the model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub tool: String,
+    #[serde(default)]
+    pub args: Value,
+}
+
+/// A tool call as run, for the prompt and the query result.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolRecord {
+    /// "t1", "t2", … in call order; proposals cite these
+    pub call_id: String,
+    pub tool: String,
+    pub args: Value,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub result: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Run `call` for `org_id`, recording it as the `seq`-th call (1-based).
+pub fn run(store: &ErpStore, org_id: &str, call: &ToolCall, seq: usize) -> ToolRecord {
+    let args = &call.args;
+    let result = match call.tool.as_str() {
+        "ledger_summary" => Ok(ledger_summary(store, org_id)),
+        "party_balances" => Ok(party_balances(
+            store,
+            org_id,
+            arg_str(args, "kind"),
+            arg_limit(args, 10),
+        )),
+        "stock_on_hand" => Ok(stock_on_hand(
+            store,
+            org_id,
+            arg_str(args, "item_id"),
+            arg_str(args, "site_id").unwrap_or("primary"),
+        )),
+        "recent_transactions" => Ok(recent_transactions(
+            store,
+            org_id,
+            arg_str(args, "party_id"),
+            arg_str(args, "tx_type"),
+            arg_limit(args, 10),
+        )),
+        other => Err(format!("unknown tool '{}'", other)),
+    };
+    let (result, error) = match result {
+        Ok(v) => (v, None),
+        Err(e) => (Value::Null, Some(e)),
+    };
+    ToolRecord {
+        call_id: format!("t{}", seq),
+        tool: call.tool.clone(),
+        args: args.clone(),
+        result,
+        error,
+    }
+}
+
+fn arg_str<'a>(args: &'a Value, key: &str) -> Option<&'a str> {
+    args.get(key)
+        .and_then(Value::as_str)
+        .filter(|s| !s.is_empty())
+}
+
+fn arg_limit(args: &Value, default: usize) -> usize {
+    args.get("limit")
+        .and_then(Value::as_u64)
+        .map_or(default, |n| n as usize)
+        .clamp(1, MAX_ROWS)
+}
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+fn ledger_summary(store: &ErpStore, org_id: &str) -> Value {
+    let mut by_status: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut by_type: BTreeMap<&str, usize> = BTreeMap::new();
+    for tx in store.transactions.values().filter(|t| t.org_id == org_id) {
+        *by_status.entry(tx.status.as_str()).or_default() += 1;
+        *by_type.entry(tx.tx_type.as_str()).or_default() += 1;
+    }
+    let mut accounts: BTreeMap<&str, (f64, f64)> = BTreeMap::new();
+    for p in store.postings.values() {
+        let posted = store
+            .transactions
+            .get(&p.tx_id)
+            .is_some_and(|t| t.org_id == org_id && t.status == TxStatus::Posted);
+        if posted {
+            let totals = accounts.entry(p.account_id.as_str()).or_default();
+            totals.0 += p.debit_amount;
+            totals.1 += p.credit_amount;
+        }
+    }
+    let accounts: Vec<Value> = accounts
+        .into_iter()
+        .take(MAX_ROWS)
+        .map(|(account_id, (dr, cr))| {
+            json!({
+                "account_id": account_id,
+                "debit": round2(dr),
+                "credit": round2(cr),
+                "net_debit": round2(dr - cr),
+            })
+        })
+        .collect();
+    json!({ "tx_by_status": by_status, "tx_by_type": by_type, "accounts": accounts })
+}
+
+fn party_balances(store: &ErpStore, org_id: &str, kind: Option<&str>, limit: usize) -> Value {
+    let lines = lines_by_tx(store);
+    // party_id → (receivable, payable)
+    let mut balances: BTreeMap<&str, (f64, f64)> = BTreeMap::new();
+    for tx in store
+        .transactions
+        .values()
+        .filter(|t| t.org_id == org_id && t.status == TxStatus::Posted)
+    {
+        let Some(party) = tx.party_id.as_deref() else {
+            continue;
+        };
+        let amount = tx_amount(tx, lines.get(tx.tx_id.as_str()));
+        let entry = balances.entry(party).or_default();
+        match tx.tx_type {
+            TxType::InvoiceOut => entry.0 += amount,
+            TxType::CreditNote | TxType::PaymentIn => entry.0 -= amount,
+            TxType::InvoiceIn => entry.1 += amount,
+            TxType::DebitNote | TxType::PaymentOut => entry.1 -= amount,
+            _ => {}
+        }
+    }
+    let mut rows: Vec<(&str, f64, f64)> = balances
+        .into_iter()
+        .filter(|(_, (r, p))| match kind {
+            Some("customer") => r.abs() >= 0.005,
+            Some("supplier") => p.abs() >= 0.005,
+            _ => r.abs() >= 0.005 || p.abs() >= 0.005,
+        })
+        .map(|(party, (r, p))| (party, round2(r), round2(p)))
+        .collect();
+    let key = |row: &(&str, f64, f64)| match kind {
+        Some("supplier") => row.2,
+        Some("customer") => row.1,
+        _ => row.1.abs().max(row.2.abs()),
+    };
+    rows.sort_by(|a, b| key(b).total_cmp(&key(a)).then_with(|| a.0.cmp(b.0)));
+    let rows: Vec<Value> = rows
+        .into_iter()
+        .take(limit)
+        .map(|(party_id, receivable, payable)| {
+            let name = store
+                .parties
+                .get(party_id)
+                .filter(|p| p.org_id == org_id)
+                .map(|p| p.name.clone());
+            json!({
+                "party_id": party_id,
+                "name": name,
+                "receivable": receivable,
+                "payable": payable,
+            })
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+fn stock_on_hand(store: &ErpStore, org_id: &str, item_id: Option<&str>, site_id: &str) -> Value {
+    let rows: Vec<Value> =
+        stocktake::stock_on_hand(&store.transactions, &store.invmoves, org_id, site_id, None)
+            .into_iter()
+            .filter(|((item, _), _)| item_id.is_none_or(|i| i == item))
+            .take(MAX_ROWS)
+            .map(|((item_id, location_id), qty)| {
+                json!({ "item_id": item_id, "location_id": location_id, "qty": qty })
+            })
+            .collect();
+    Value::Array(rows)
+}
+
+fn recent_transactions(
+    store: &ErpStore,
+    org_id: &str,
+    party_id: Option<&str>,
+    tx_type: Option<&str>,
+    limit: usize,
+) -> Value {
+    let lines = lines_by_tx(store);
+    let mut txs: Vec<_> = store
+        .transactions
+        .values()
+        .filter(|t| {
+            t.org_id == org_id
+                && party_id.is_none_or(|p| t.party_id.as_deref() == Some(p))
+                && tx_type.is_none_or(|ty| t.tx_type.as_str() == ty)
+        })
+        .collect();
+    txs.sort_by(|a, b| {
+        b.tx_date
+            .cmp(&a.tx_date)
+            .then_with(|| b.created_at_ms.cmp(&a.created_at_ms))
+            .then_with(|| a.tx_id.cmp(&b.tx_id))
+    });
+    let rows: Vec<Value> = txs
+        .into_iter()
+        .take(limit)
+        .map(|t| {
+            json!({
+                "tx_id": t.tx_id,
+                "tx_type": t.tx_type.as_str(),
+                "status": t.status.as_str(),
+                "party_id": t.party_id,
+                "ref_number": t.ref_number,
+                "tx_date": t.tx_date,
+                "amount": tx_amount(t, lines.get(t.tx_id.as_str())),
+                "currency": t.currency,
+            })
+        })
+        .collect();
+    Value::Array(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::TxBuilder;
+    use crate::erp::types::{Party, PartyKind};
+
+    fn call(tool: &str, args: Value) -> ToolCall {
+        ToolCall {
+            tool: tool.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn test_tools_answer_from_the_store() {
+        let mut store = ErpStore::new();
+        TxBuilder::new("i1", TxType::InvoiceOut)
+            .party("acme")
+            .date("2026-10-01")
+            .lines(&[500.0])
+            .insert(&mut store);
+        TxBuilder::new("i2", TxType::InvoiceOut)
+            .party("acme")
+            .date("2026-10-02")
+            .lines(&[300.0])
+            .insert(&mut store);
+        TxBuilder::new("p1", TxType::PaymentIn)
+            .party("acme")
+            .date("2026-10-03")
+            .lines(&[200.0])
+            .insert(&mut store);
+        TxBuilder::new("i3", TxType::InvoiceOut)
+            .party("bolt")
+            .date("2026-10-04")
+            .lines(&[900.0])
+            .insert(&mut store);
+        TxBuilder::new("b1", TxType::InvoiceIn)
+            .party("supp")
+            .date("2026-10-05")
+            .lines(&[50.0])
+            .insert(&mut store);
+        store.parties.insert(
+            "bolt".to_string(),
+            Party {
+                party_id: "bolt".to_string(),
+                org_id: "org1".to_string(),
+                name: "Bolt Pty Ltd".to_string(),
+                kind: PartyKind::Customer,
+                email: None,
+                contact: None,
+                abn: None,
+                created_at_ms: 0,
+            },
+        );
+
+        let owed = run(
+            &store,
+            "org1",
+            &call("party_balances", json!({"kind": "customer"})),
+            1,
+        );
+        assert_eq!(owed.call_id, "t1");
+        assert_eq!(
+            owed.result,
+            json!([
+                {"party_id": "bolt", "name": "Bolt Pty Ltd", "receivable": 900.0, "payable": 0.0},
+                {"party_id": "acme", "name": null, "receivable": 600.0, "payable": 0.0},
+            ])
+        );
+
+        let recent = run(
+            &store,
+            "org1",
+            &call("recent_transactions", json!({"limit": 2})),
+            2,
+        );
+        let ids: Vec<&str> = recent
+            .result
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["tx_id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["b1", "i3"]);
+
+        let summary = run(&store, "org1", &call("ledger_summary", Value::Null), 3);
+        assert_eq!(summary.result["tx_by_status"]["posted"], json!(5));
+
+        // Other orgs see nothing; unknown tools are recorded as errors
+        let other = run(&store, "org2", &call("party_balances", json!({})), 4);
+        assert_eq!(other.result, json!([]));
+        let bad = run(&store, "org1", &call("drop_tables", json!({})), 5);
+        assert!(bad.error.is_some() && bad.result.is_null());
+    }
+}
@@ -0,0 +1,477 @@
+//! coa.rs — Chart of Accounts management rules
+//!
+//! Pure validation and roll-up helpers over the `accounts` / `postings` maps in
+//! `ErpStore`.  The signed mutations that use them live in `engine.rs`
+//! (`add_account`, `rename_account`, `deactivate_account`, `reparent_account`).
+//!
+//! Hierarchy: an account may name a `parent_code`, which must be an active
+//! *header* account of the same `acct_type`.  Header accounts exist only for
+//! roll-up reporting and cannot be posted to directly.
+
+use std::collections::HashMap;
+
+use crate::erp::engine::AccountRecord;
+use crate::erp::errors::ErpError;
+use crate::erp::fragments;
+use crate::erp::ledger::ROUNDING_TOLERANCE;
+use crate::erp::types::{Op, Posting};
+
+/// Valid `acct_type` values, in reporting order.
+pub const ACCT_TYPES: [&str; 5] = ["asset", "liability", "equity", "income", "expense"];
+
+/// Symbolic ledger account IDs emitted by `ledger::generate_postings`.
+/// An account's optional `role` binds it to one of these.
+pub const SYMBOLIC_ROLES: [&str; 12] = [
+    "bank",
+    "accounts_receivable",
+    "tax_receivable",
+    "inventory_asset",
+    "accounts_payable",
+    "tax_payable",
+    "goods_received_not_invoiced",
+    "revenue",
+    "cogs",
+    "expense",
+    "stock_adjustment_gain",
+    "stock_adjustment_loss",
+];
+
+/// Returns the normal balance side for an account type.
+/// asset / expense → "debit"; liability / equity / income → "credit".
+pub fn expected_normal_balance(acct_type: &str) -> Result<&'static str, ErpError> {
+    match acct_type {
+        "asset" | "expense" => Ok("debit"),
+        "liability" | "equity" | "income" => Ok("credit"),
+        other => Err(ErpError::InvalidField(format!(
+            "unknown acct_type: {} (expected one of {})",
+            other,
+            ACCT_TYPES.join(", ")
+        ))),
+    }
+}
+
+/// Contra accounts: (`acct_type`, `normal_balance`) pairs that offset their
+/// type's balance, e.g. accumulated depreciation (asset, credit), owner
+/// drawings (equity, debit) or sales returns (income, debit).
+pub const CONTRA_BALANCES: [(&str, &str); 4] = [
+    ("asset", "credit"),
+    ("liability", "debit"),
+    ("equity", "debit"),
+    ("income", "debit"),
+];
+
+/// Rejects `acct_type` / `normal_balance` combinations that break double-entry
+/// sign conventions (e.g. an expense account with a credit normal balance).
+/// Contra accounts (`CONTRA_BALANCES`) are allowed.
+pub fn validate_type_balance(acct_type: &str, normal_balance: &str) -> Result<(), ErpError> {
+    let expected = expected_normal_balance(acct_type)?;
+    if normal_balance != "debit" && normal_balance != "credit" {
+        return Err(ErpError::InvalidField(format!(
+            "unknown normal_balance: {}",
+            normal_balance
+        )));
+    }
+    if normal_balance != expected && !CONTRA_BALANCES.contains(&(acct_type, normal_balance)) {
+        return Err(ErpError::ValidationFail(format!(
+            "{} accounts must have a {} normal balance, got {}",
+            acct_type, expected, normal_balance
+        )));
+    }
+    Ok(())
+}
+
+/// Validates a proposed parent for account `code`.
+/// The parent must exist, be an active header account of the same type, and
+/// must not be `code` itself or one of its descendants.
+pub fn validate_parent(
+    accounts: &HashMap<String, AccountRecord>,
+    code: &str,
+    parent_code: &str,
+    acct_type: &str,
+) -> Result<(), ErpError> {
+    let parent = accounts.get(parent_code).ok_or_else(|| {
+        ErpError::ValidationFail(format!("parent account {} not found", parent_code))
+    })?;
+    if !parent.is_header {
+        return Err(ErpError::ValidationFail(format!(
+            "parent account {} is not a header account",
+            parent_code
+        )));
+    }
+    if !parent.active {
+        return Err(ErpError::ValidationFail(format!(
+            "parent account {} is inactive",
+            parent_code
+        )));
+    }
+    if parent.acct_type != acct_type {
+        return Err(ErpError::ValidationFail(format!(
+            "parent account {} is {} but account {} is {}",
+            parent_code, parent.acct_type, code, acct_type
+        )));
+    }
+
+    // Walk up from the proposed parent — reaching `code` means a cycle.
+    let mut cursor = Some(parent_code.to_string());
+    let mut steps = 0;
+    while let Some(c) = cursor {
+        if c == code {
+            return Err(ErpError::ValidationFail(format!(
+                "re-parenting {} under {} would create a cycle",
+                code, parent_code
+            )));
+        }
+        steps += 1;
+        if steps > accounts.len() {
+            break; // pre-existing corrupt cycle; bail out rather than spin
+        }
+        cursor = accounts.get(&c).and_then(|a| a.parent_code.clone());
+    }
+    Ok(())
+}
+
+/// Validates a symbolic role for account `code`: it must be a known role and
+/// not already bound to a different account.
+pub fn validate_role(
+    accounts: &HashMap<String, AccountRecord>,
+    code: &str,
+    role: &str,
+) -> Result<(), ErpError> {
+    if !SYMBOLIC_ROLES.contains(&role) {
+        return Err(ErpError::InvalidField(format!(
+            "unknown account role: {}",
+            role
+        )));
+    }
+    if let Some(other) = accounts
+        .values()
+        .find(|a| a.code != code && a.role.as_deref() == Some(role))
+    {
+        return Err(ErpError::ValidationFail(format!(
+            "role {} is already assigned to account {}",
+            role, other.code
+        )));
+    }
+    Ok(())
+}
+
+/// Rejects lines/postings against header or inactive accounts.
+/// Symbolic ledger IDs that don't match a CoA code (e.g. "accounts_receivable")
+/// are allowed through — they are resolved by name at reporting time.
+pub fn validate_postable(
+    accounts: &HashMap<String, AccountRecord>,
+    account_id: &str,
+) -> Result<(), ErpError> {
+    if let Some(acct) = accounts.get(account_id) {
+        if acct.is_header {
+            return Err(ErpError::ValidationFail(format!(
+                "account {} is a header account and cannot be posted to",
+                account_id
+            )));
+        }
+        if !acct.active {
+            return Err(ErpError::ValidationFail(format!(
+                "account {} is inactive",
+                account_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// True if a posting's `account_id` refers to `acct`: by code, by its bound
+/// symbolic role, or by the snake_case form of its name
+/// ("Accounts Receivable" → "accounts_receivable").
+pub fn posting_matches(acct: &AccountRecord, posting_account_id: &str) -> bool {
+    acct.code == posting_account_id
+        || acct.role.as_deref() == Some(posting_account_id)
+        || name_slug(&acct.name) == posting_account_id.to_lowercase()
+}
+
+fn name_slug(name: &str) -> String {
+    name.to_lowercase().replace(' ', "_")
+}
+
+/// The symbolic role an unbound account answers to only through its name.
+/// Renaming it would orphan those postings, so `rename_account` binds this
+/// role first and the postings keep matching by account id.
+pub fn role_implied_by_name(
+    accounts: &HashMap<String, AccountRecord>,
+    code: &str,
+) -> Option<&'static str> {
+    let acct = accounts.get(code).filter(|a| a.role.is_none())?;
+    let slug = name_slug(&acct.name);
+    let role = SYMBOLIC_ROLES.iter().find(|r| **r == slug)?;
+    validate_role(accounts, code, role).ok().map(|_| *role)
+}
+
+/// Sum of (debit, credit) across all postings made directly to `acct`.
+pub fn account_totals<'a>(
+    acct: &AccountRecord,
+    postings: impl Iterator<Item = &'a Posting>,
+) -> (f64, f64) {
+    postings
+        .filter(|p| posting_matches(acct, &p.account_id))
+        .fold((0.0, 0.0), |(dr, cr), p| {
+            (dr + p.debit_amount, cr + p.credit_amount)
+        })
+}
+
+/// Validates that `code` can be deactivated: it must exist, be active, have no
+/// active children and carry no balance.
+pub fn validate_deactivate(
+    accounts: &HashMap<String, AccountRecord>,
+    postings: &HashMap<String, Posting>,
+    code: &str,
+) -> Result<(), ErpError> {
+    let acct = accounts
+        .get(code)
+        .ok_or_else(|| ErpError::ValidationFail(format!("account {} not found", code)))?;
+    if !acct.active {
+        return Err(ErpError::ValidationFail(format!(
+            "account {} is already inactive",
+            code
+        )));
+    }
+    if let Some(child) = accounts
+        .values()
+        .find(|a| a.active && a.parent_code.as_deref() == Some(code))
+    {
+        return Err(ErpError::ValidationFail(format!(
+            "account {} still has active child account {}",
+            code, child.code
+        )));
+    }
+    let (dr, cr) = account_totals(acct, postings.values());
+    if (dr - cr).abs() > ROUNDING_TOLERANCE {
+        return Err(ErpError::ValidationFail(format!(
+            "account {} has a non-zero balance ({:.2}) and cannot be deactivated",
+            code,
+            dr - cr
+        )));
+    }
+    Ok(())
+}
+
+/// Per-account (debit, credit) totals including every descendant, for header roll-up.
+pub fn rollup_totals(
+    accounts: &HashMap<String, AccountRecord>,
+    postings: &HashMap<String, Posting>,
+) -> HashMap<String, (f64, f64)> {
+    let mut totals: HashMap<String, (f64, f64)> = HashMap::new();
+    for acct in accounts.values() {
+        let (dr, cr) = account_totals(acct, postings.values());
+        // Credit the account itself and every ancestor.
+        let mut cursor = Some(acct.code.clone());
+        let mut steps = 0;
+        while let Some(c) = cursor {
+            let entry = totals.entry(c.clone()).or_insert((0.0, 0.0));
+            entry.0 += dr;
+            entry.1 += cr;
+            steps += 1;
+            if steps > accounts.len() {
+                break;
+            }
+            cursor = accounts.get(&c).and_then(|a| a.parent_code.clone());
+        }
+    }
+    totals
+}
+
+/// Build the `account:{code}` MapSet ops describing every field of `acct`.
+/// Keys match `coa_templates::seed_accounts` plus the hierarchy fields.
+pub fn account_ops(acct: &AccountRecord) -> Vec<Op> {
+    let frag = fragments::account_id(&acct.code);
+    vec![
+        map_set(&frag, "code", serde_json::json!(acct.code)),
+        map_set(&frag, "name", serde_json::json!(acct.name)),
+        map_set(&frag, "type", serde_json::json!(acct.acct_type)),
+        map_set(
+            &frag,
+            "normal_balance",
+            serde_json::json!(acct.normal_balance),
+        ),
+        map_set(&frag, "parent_code", serde_json::json!(acct.parent_code)),
+        map_set(&frag, "is_header", serde_json::json!(acct.is_header)),
+        map_set(&frag, "active", serde_json::json!(acct.active)),
+        map_set(&frag, "role", serde_json::json!(acct.role)),
+    ]
+}
+
+/// Single-key MapSet on an `account:{code}` fragment.
+pub fn account_field_op(code: &str, key: &str, value: serde_json::Value) -> Op {
+    map_set(&fragments::account_id(code), key, value)
+}
+
+fn map_set(fragment_id: &str, key: &str, value: serde_json::Value) -> Op {
+    Op::MapSet {
+        fragment_id: fragment_id.to_string(),
+        key: key.to_string(),
+        value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::engine::{self, ERP_STORE};
+    use crate::erp::test_support::EngineFixture;
+    use crate::erp::types::{ActorContext, Role};
+
+    fn acct(code: &str, acct_type: &str, parent: Option<&str>, is_header: bool) -> AccountRecord {
+        AccountRecord {
+            code: code.to_string(),
+            name: format!("Account {}", code),
+            acct_type: acct_type.to_string(),
+            normal_balance: expected_normal_balance(acct_type).unwrap().to_string(),
+            parent_code: parent.map(str::to_string),
+            is_header,
+            active: true,
+            role: None,
+        }
+    }
+
+    fn posting(account_id: &str, dr: f64, cr: f64) -> Posting {
+        Posting {
+            posting_id: format!("p-{}-{}-{}", account_id, dr, cr),
+            tx_id: "tx1".to_string(),
+            account_id: account_id.to_string(),
+            debit_amount: dr,
+            credit_amount: cr,
+            currency: "AUD".to_string(),
+            description: None,
+            status: "final".to_string(),
+            generated_by: "engine".to_string(),
+        }
+    }
+
+    fn chart() -> HashMap<String, AccountRecord> {
+        [
+            acct("1000", "asset", None, true),
+            acct("1010", "asset", Some("1000"), false),
+            acct("1020", "asset", Some("1000"), false),
+            acct("2000", "liability", None, true),
+        ]
+        .into_iter()
+        .map(|a| (a.code.clone(), a))
+        .collect()
+    }
+
+    #[test]
+    fn test_type_balance_combinations() {
+        assert!(validate_type_balance("asset", "debit").is_ok());
+        assert!(validate_type_balance("income", "credit").is_ok());
+        // Contra accounts: accumulated depreciation, sales returns
+        assert!(validate_type_balance("asset", "credit").is_ok());
+        assert!(validate_type_balance("income", "debit").is_ok());
+        assert!(matches!(
+            validate_type_balance("expense", "credit"),
+            Err(ErpError::ValidationFail(_))
+        ));
+        assert!(matches!(
+            validate_type_balance("goodwill", "debit"),
+            Err(ErpError::InvalidField(_))
+        ));
+    }
+
+    #[test]
+    fn test_parent_rules() {
+        let accounts = chart();
+        assert!(validate_parent(&accounts, "1030", "1000", "asset").is_ok());
+        // Parent must be a header
+        assert!(validate_parent(&accounts, "1030", "1010", "asset").is_err());
+        // Parent must share the account type
+        assert!(validate_parent(&accounts, "2010", "1000", "liability").is_err());
+        // Header cannot be moved under itself
+        assert!(validate_parent(&accounts, "1000", "1000", "asset").is_err());
+    }
+
+    #[test]
+    fn test_parent_cycle_rejected() {
+        let mut accounts = chart();
+        accounts.insert("1100".into(), acct("1100", "asset", Some("1000"), true));
+        // 1000 → under 1100, but 1100 is already a child of 1000
+        assert!(validate_parent(&accounts, "1000", "1100", "asset").is_err());
+    }
+
+    #[test]
+    fn test_deactivate_requires_zero_balance() {
+        let accounts = chart();
+        let mut postings = HashMap::new();
+        let p = posting("1010", 50.0, 0.0);
+        postings.insert(p.posting_id.clone(), p);
+
+        assert!(validate_deactivate(&accounts, &postings, "1010").is_err());
+        assert!(validate_deactivate(&accounts, &postings, "1020").is_ok());
+        // Header with active children is rejected
+        assert!(validate_deactivate(&accounts, &postings, "1000").is_err());
+    }
+
+    #[test]
+    fn test_rollup_to_header() {
+        let accounts = chart();
+        let mut postings = HashMap::new();
+        for p in [posting("1010", 50.0, 0.0), posting("1020", 30.0, 10.0)] {
+            postings.insert(p.posting_id.clone(), p);
+        }
+        let totals = rollup_totals(&accounts, &postings);
+        assert_eq!(totals.get("1000"), Some(&(80.0, 10.0)));
+        assert_eq!(totals.get("1010"), Some(&(50.0, 0.0)));
+        assert_eq!(totals.get("2000"), Some(&(0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_role_binding() {
+        let mut accounts = chart();
+        assert!(validate_role(&accounts, "1010", "bank").is_ok());
+        assert!(validate_role(&accounts, "1010", "petty_cash").is_err());
+        accounts.get_mut("1010").unwrap().role = Some("bank".into());
+        // Same role on a second account is rejected; re-binding the owner is fine
+        assert!(validate_role(&accounts, "1020", "bank").is_err());
+        assert!(validate_role(&accounts, "1010", "bank").is_ok());
+        assert!(posting_matches(&accounts["1010"], "bank"));
+    }
+
+    #[test]
+    fn test_rename_keeps_symbolic_postings() {
+        let _engine = EngineFixture::new();
+        {
+            let mut store = ERP_STORE.lock().unwrap();
+            store.accounts = chart();
+            store.accounts.get_mut("1020").unwrap().name = "Accounts Receivable".into();
+            let p = posting("accounts_receivable", 40.0, 0.0);
+            store.postings.insert(p.posting_id.clone(), p);
+            assert_eq!(
+                role_implied_by_name(&store.accounts, "1020"),
+                Some("accounts_receivable")
+            );
+            assert_eq!(role_implied_by_name(&store.accounts, "1010"), None);
+        }
+
+        // rename_account binds the implied role before renaming
+        let actor = ActorContext {
+            pubkey: "finance".to_string(),
+            role: Role::Finance,
+            org_id: "org1".to_string(),
+            lamport: 0,
+        };
+        let renamed = engine::rename_account(&actor, "1020", "Trade Debtors").unwrap();
+        assert_eq!(renamed.role.as_deref(), Some("accounts_receivable"));
+
+        let store = ERP_STORE.lock().unwrap();
+        assert_eq!(store.accounts["1020"].name, "Trade Debtors");
+        assert_eq!(store.accounts["1020"].role, renamed.role);
+        assert!(validate_deactivate(&store.accounts, &store.postings, "1020").is_err());
+        assert_eq!(role_implied_by_name(&store.accounts, "1020"), None);
+    }
+
+    #[test]
+    fn test_postable_rules() {
+        let mut accounts = chart();
+        assert!(validate_postable(&accounts, "1010").is_ok());
+        assert!(validate_postable(&accounts, "1000").is_err());
+        assert!(validate_postable(&accounts, "accounts_receivable").is_ok());
+        accounts.get_mut("1020").unwrap().active = false;
+        assert!(validate_postable(&accounts, "1020").is_err());
+    }
+}
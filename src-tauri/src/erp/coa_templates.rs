@@ -1,3 +1,9 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::erp::coa;
+use crate::erp::engine::AccountRecord;
+use crate::erp::errors::ErpError;
 use crate::erp::types::Op;
 
 /// Seed Chart of Accounts templates (ADR-0001 §1).
@@ -5,6 +11,8 @@ use crate::erp::types::Op;
 /// when applied against the org's yrs doc (Phase A: stored as fragment map ops).
 ///
 /// Account structure per fragment: `account:{id}` → MapSet { code, name, type, normal_balance }
+/// User-supplied templates (`parse_template_csv` / `parse_template_json`) add optional
+/// parent_code, is_header and role keys.
 
 pub fn general_sme_au_gst() -> Vec<Op> {
     seed_accounts(vec![
@@ -84,8 +92,348 @@ pub fn get_template(template: &CoATemplate) -> Vec<Op> {
     }
 }
 
+// ─── Custom templates (CSV / JSON) ──────────────────────────────────────────
+
+/// One account row in a user-supplied CoA template.
+/// CSV header / JSON keys: code, name, type, normal_balance, parent, role, is_header.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CoaTemplateRow {
+    pub code: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub acct_type: String,
+    pub normal_balance: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub is_header: bool,
+}
+
+impl From<&AccountRecord> for CoaTemplateRow {
+    fn from(a: &AccountRecord) -> Self {
+        CoaTemplateRow {
+            code: a.code.clone(),
+            name: a.name.clone(),
+            acct_type: a.acct_type.clone(),
+            normal_balance: a.normal_balance.clone(),
+            parent: a.parent_code.clone(),
+            role: a.role.clone(),
+            is_header: a.is_header,
+        }
+    }
+}
+
+const CSV_COLUMNS: [&str; 7] = [
+    "code",
+    "name",
+    "type",
+    "normal_balance",
+    "parent",
+    "role",
+    "is_header",
+];
+
+/// Parse a template file's contents, choosing the format from its extension.
+pub fn parse_template_file(path: &std::path::Path) -> Result<Vec<CoaTemplateRow>, ErpError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| ErpError::ValidationFail(format!("cannot read {}: {}", path.display(), e)))?;
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+        .as_deref()
+    {
+        Some("csv") => parse_template_csv(&contents),
+        Some("json") => parse_template_json(&contents),
+        _ => Err(ErpError::InvalidField(format!(
+            "unsupported CoA template format: {} (expected .csv or .json)",
+            path.display()
+        ))),
+    }
+}
+
+/// Parse a JSON array of `CoaTemplateRow` objects.
+pub fn parse_template_json(contents: &str) -> Result<Vec<CoaTemplateRow>, ErpError> {
+    serde_json::from_str(contents)
+        .map_err(|e| ErpError::InvalidField(format!("CoA template JSON: {}", e)))
+}
+
+/// Parse a CSV template. The first row is a header naming the columns; `code`,
+/// `name`, `type` and `normal_balance` are required, the rest are optional.
+/// Quoted fields may span lines, as `export_csv` writes them.
+pub fn parse_template_csv(contents: &str) -> Result<Vec<CoaTemplateRow>, ErpError> {
+    let mut records = csv_records(contents)?.into_iter();
+    let (_, header) = records
+        .next()
+        .ok_or_else(|| ErpError::InvalidField("CoA template CSV is empty".to_string()))?;
+    let header: Vec<String> = header
+        .into_iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let col = |name: &str| header.iter().position(|h| h == name);
+    let required = |name: &str| {
+        col(name).ok_or_else(|| {
+            ErpError::InvalidField(format!("CoA template CSV missing column '{}'", name))
+        })
+    };
+    let (c_code, c_name, c_type, c_nb) = (
+        required("code")?,
+        required("name")?,
+        required("type")?,
+        required("normal_balance")?,
+    );
+    let (c_parent, c_role, c_header) = (col("parent"), col("role"), col("is_header"));
+
+    let mut rows = Vec::new();
+    for (line_no, fields) in records {
+        let get = |i: Option<usize>| {
+            i.and_then(|i| fields.get(i))
+                .map(|f| f.trim().to_string())
+                .filter(|f| !f.is_empty())
+        };
+        let req = |i: usize, name: &str| {
+            get(Some(i)).ok_or_else(|| {
+                ErpError::InvalidField(format!("line {}: missing {}", line_no, name))
+            })
+        };
+        rows.push(CoaTemplateRow {
+            code: req(c_code, "code")?,
+            name: req(c_name, "name")?,
+            acct_type: req(c_type, "type")?.to_lowercase(),
+            normal_balance: req(c_nb, "normal_balance")?.to_lowercase(),
+            parent: get(c_parent),
+            role: get(c_role),
+            is_header: get(c_header)
+                .map(|v| matches!(v.to_lowercase().as_str(), "true" | "1" | "yes" | "y"))
+                .unwrap_or(false),
+        });
+    }
+    Ok(rows)
+}
+
+/// Validate a template as a self-contained chart: unique codes, valid
+/// type/normal_balance pairs, parents that are header accounts of the same type
+/// defined in the same template, no cycles, and unique known roles. Roles are
+/// also checked against `existing` (the store's chart the template is seeded
+/// into), so a role already bound to another account is not granted twice.
+pub fn validate_template(
+    existing: &HashMap<String, AccountRecord>,
+    rows: &[CoaTemplateRow],
+) -> Result<(), ErpError> {
+    if rows.is_empty() {
+        return Err(ErpError::ValidationFail(
+            "CoA template has no accounts".to_string(),
+        ));
+    }
+
+    let mut accounts: HashMap<String, AccountRecord> = HashMap::new();
+    for row in rows {
+        coa::validate_type_balance(&row.acct_type, &row.normal_balance)
+            .map_err(|e| ErpError::ValidationFail(format!("account {}: {}", row.code, e)))?;
+        let record = template_record(row);
+        if accounts.insert(row.code.clone(), record).is_some() {
+            return Err(ErpError::ValidationFail(format!(
+                "duplicate account code {} in template",
+                row.code
+            )));
+        }
+    }
+
+    let mut roles: HashSet<&str> = HashSet::new();
+    for row in rows {
+        if let Some(ref parent) = row.parent {
+            coa::validate_parent(&accounts, &row.code, parent, &row.acct_type)?;
+        }
+        if let Some(ref role) = row.role {
+            if !coa::SYMBOLIC_ROLES.contains(&role.as_str()) {
+                return Err(ErpError::InvalidField(format!(
+                    "account {}: unknown role {}",
+                    row.code, role
+                )));
+            }
+            if !roles.insert(role.as_str()) {
+                return Err(ErpError::ValidationFail(format!(
+                    "role {} is assigned to more than one account",
+                    role
+                )));
+            }
+        }
+    }
+
+    // Seeding overwrites the template's codes but never clears a stored role
+    let mut merged = existing.clone();
+    for row in rows {
+        let mut record = template_record(row);
+        if record.role.is_none() {
+            record.role = existing.get(&row.code).and_then(|a| a.role.clone());
+        }
+        merged.insert(row.code.clone(), record);
+    }
+    for row in rows {
+        if let Some(ref role) = row.role {
+            coa::validate_role(&merged, &row.code, role)
+                .map_err(|e| ErpError::ValidationFail(format!("account {}: {}", row.code, e)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Convert template rows into the same `account:{code}` MapSet ops that
+/// `seed_accounts` produces, plus parent_code / is_header / role when set.
+pub fn template_ops(rows: &[CoaTemplateRow]) -> Vec<Op> {
+    use crate::erp::fragments::account_id;
+    let mut ops = Vec::new();
+    for row in rows {
+        ops.extend(seed_accounts(vec![(
+            row.code.as_str(),
+            row.name.as_str(),
+            row.acct_type.as_str(),
+            row.normal_balance.as_str(),
+        )]));
+        let frag = account_id(&row.code);
+        if let Some(ref parent) = row.parent {
+            ops.push(Op::MapSet {
+                fragment_id: frag.clone(),
+                key: "parent_code".to_string(),
+                value: serde_json::json!(parent),
+            });
+        }
+        if row.is_header {
+            ops.push(Op::MapSet {
+                fragment_id: frag.clone(),
+                key: "is_header".to_string(),
+                value: serde_json::json!(true),
+            });
+        }
+        if let Some(ref role) = row.role {
+            ops.push(Op::MapSet {
+                fragment_id: frag,
+                key: "role".to_string(),
+                value: serde_json::json!(role),
+            });
+        }
+    }
+    ops
+}
+
+/// The account codes a seed op list (template or `template_ops`) writes.
+pub fn seeded_codes(ops: &[Op]) -> Vec<String> {
+    ops.iter()
+        .filter_map(|op| match op {
+            Op::MapSet { key, value, .. } if key == "code" => value.as_str().map(str::to_string),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Export accounts as a CSV template (sorted by code), re-importable via
+/// `parse_template_csv`.
+pub fn export_csv(accounts: &[AccountRecord]) -> String {
+    let mut sorted: Vec<&AccountRecord> = accounts.iter().collect();
+    sorted.sort_by(|a, b| a.code.cmp(&b.code));
+    let mut out = CSV_COLUMNS.join(",");
+    out.push('\n');
+    for a in sorted {
+        let row = CoaTemplateRow::from(a);
+        let fields = [
+            row.code,
+            row.name,
+            row.acct_type,
+            row.normal_balance,
+            row.parent.unwrap_or_default(),
+            row.role.unwrap_or_default(),
+            row.is_header.to_string(),
+        ];
+        let escaped: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&escaped.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Export accounts as a pretty-printed JSON template (sorted by code).
+pub fn export_json(accounts: &[AccountRecord]) -> Result<String, ErpError> {
+    let mut rows: Vec<CoaTemplateRow> = accounts.iter().map(CoaTemplateRow::from).collect();
+    rows.sort_by(|a, b| a.code.cmp(&b.code));
+    serde_json::to_string_pretty(&rows)
+        .map_err(|e| ErpError::ValidationFail(format!("CoA export: {}", e)))
+}
+
 // ─── helpers ────────────────────────────────────────────────────────────────
 
+fn template_record(row: &CoaTemplateRow) -> AccountRecord {
+    AccountRecord {
+        code: row.code.clone(),
+        name: row.name.clone(),
+        acct_type: row.acct_type.clone(),
+        normal_balance: row.normal_balance.clone(),
+        parent_code: row.parent.clone(),
+        is_header: row.is_header,
+        active: true,
+        role: row.role.clone(),
+    }
+}
+
+/// Split CSV text into records (RFC 4180): double-quoted fields may hold
+/// commas, `""` escapes and line breaks. Each record comes with the 1-based
+/// line it starts on; blank lines are skipped.
+fn csv_records(contents: &str) -> Result<Vec<(usize, Vec<String>)>, ErpError> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let (mut line, mut start) = (1, 1);
+    let mut chars = contents.chars().peekable();
+    loop {
+        let c = chars.next();
+        match c {
+            Some('"') if in_quotes && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            Some('"') => in_quotes = !in_quotes,
+            Some(c) if in_quotes => {
+                if c == '\n' {
+                    line += 1;
+                }
+                current.push(c);
+            }
+            Some(',') => fields.push(std::mem::take(&mut current)),
+            Some('\r') if chars.peek() == Some(&'\n') => {}
+            None if in_quotes => {
+                return Err(ErpError::InvalidField(format!(
+                    "line {}: unterminated quoted field",
+                    start
+                )))
+            }
+            Some('\n') | Some('\r') | None => {
+                fields.push(std::mem::take(&mut current));
+                let blank = fields.len() == 1 && fields[0].trim().is_empty();
+                let record = std::mem::take(&mut fields);
+                if !blank {
+                    records.push((start, record));
+                }
+                if c.is_none() {
+                    return Ok(records);
+                }
+                line += 1;
+                start = line;
+            }
+            Some(c) => current.push(c),
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
 fn seed_accounts(accounts: Vec<(&str, &str, &str, &str)>) -> Vec<Op> {
     use crate::erp::fragments::account_id;
     let mut ops = Vec::new();
@@ -126,6 +474,115 @@ mod tests {
         assert!(!product_manufacturing().is_empty());
     }
 
+    const SAMPLE_CSV: &str = "code,name,type,normal_balance,parent,role,is_header
+1000,Current Assets,asset,debit,,,true
+1010,\"Bank, Operating\",asset,debit,1000,bank,
+1100,Debtors,asset,debit,1000,accounts_receivable,false
+4000,Sales,income,credit,,revenue,
+";
+
+    #[test]
+    fn test_parse_template_csv() {
+        let rows = parse_template_csv(SAMPLE_CSV).expect("csv should parse");
+        assert_eq!(rows.len(), 4);
+        assert!(rows[0].is_header);
+        assert_eq!(rows[1].name, "Bank, Operating");
+        assert_eq!(rows[1].parent.as_deref(), Some("1000"));
+        assert_eq!(rows[1].role.as_deref(), Some("bank"));
+        assert!(validate_template(&HashMap::new(), &rows).is_ok());
+    }
+
+    #[test]
+    fn test_parse_template_json() {
+        let json = r#"[
+            {"code":"1000","name":"Bank","type":"asset","normal_balance":"debit","role":"bank"},
+            {"code":"3000","name":"Equity","type":"equity","normal_balance":"credit"}
+        ]"#;
+        let rows = parse_template_json(json).expect("json should parse");
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1].parent, None);
+        assert!(validate_template(&HashMap::new(), &rows).is_ok());
+    }
+
+    #[test]
+    fn test_validate_template_rejects_bad_rows() {
+        let mut rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        rows[3].acct_type = "expense".into(); // expense with credit normal balance
+        assert!(validate_template(&HashMap::new(), &rows).is_err());
+
+        let mut rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        rows[2].parent = Some("9999".into()); // unknown parent
+        assert!(validate_template(&HashMap::new(), &rows).is_err());
+
+        let mut rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        rows[2].role = Some("bank".into()); // role already used by 1010
+        assert!(validate_template(&HashMap::new(), &rows).is_err());
+
+        let mut rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        rows[2].code = "1010".into(); // duplicate code
+        assert!(validate_template(&HashMap::new(), &rows).is_err());
+
+        // A role the store already binds to an account outside the template
+        let rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        let mut existing = HashMap::new();
+        let mut bank = template_record(&rows[1]);
+        bank.code = "1090".into();
+        existing.insert(bank.code.clone(), bank);
+        assert!(validate_template(&existing, &rows).is_err());
+        // Re-seeding the account that holds it is fine
+        let mut existing = HashMap::new();
+        existing.insert("1010".to_string(), template_record(&rows[1]));
+        assert!(validate_template(&existing, &rows).is_ok());
+    }
+
+    #[test]
+    fn test_template_ops_match_seed_accounts() {
+        let rows = parse_template_json(
+            r#"[{"code":"1000","name":"Cash & Bank","type":"asset","normal_balance":"debit"}]"#,
+        )
+        .unwrap();
+        let ops = serde_json::to_value(template_ops(&rows)).unwrap();
+        let seeded = serde_json::to_value(seed_accounts(vec![(
+            "1000",
+            "Cash & Bank",
+            "asset",
+            "debit",
+        )]))
+        .unwrap();
+        assert_eq!(ops, seeded);
+    }
+
+    #[test]
+    fn test_export_csv_round_trip() {
+        let rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        let mut store = crate::erp::engine::ErpStore::new();
+        crate::erp::apply::apply_ops(&mut store, &template_ops(&rows));
+        let records: Vec<AccountRecord> = store.accounts.into_values().collect();
+
+        let reparsed = parse_template_csv(&export_csv(&records)).unwrap();
+        assert_eq!(reparsed, rows);
+    }
+
+    #[test]
+    fn test_csv_quoted_newlines_round_trip() {
+        let mut rows = parse_template_csv(SAMPLE_CSV).unwrap();
+        rows[1].name = "Bank\r\n\"Operating\"\nline 3".to_string();
+        let mut store = crate::erp::engine::ErpStore::new();
+        crate::erp::apply::apply_ops(&mut store, &template_ops(&rows));
+        let records: Vec<AccountRecord> = store.accounts.into_values().collect();
+
+        let reparsed = parse_template_csv(&export_csv(&records)).unwrap();
+        assert_eq!(reparsed, rows);
+        assert_eq!(
+            seeded_codes(&template_ops(&rows)),
+            ["1000", "1010", "1100", "4000"]
+        );
+
+        let err = parse_template_csv("code,name,type,normal_balance\n1000,\"Cash,asset,debit\n")
+            .unwrap_err();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+
     #[test]
     fn test_coa_fragment_ids_prefixed() {
         let ops = general_sme_au_gst();
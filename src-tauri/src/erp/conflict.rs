@@ -0,0 +1,447 @@
+//! conflict.rs — Detect concurrent edits to the same fragment
+//!
+//! Every map fragment (`tx:{id}:hdr`, `txline:{id}`, `account:{code}` …) has a
+//! version: the number of committed envelopes that wrote it, plus the
+//! mutation, actor and lamport clock of the latest one (`FragmentVersion`).
+//! Envelopes carry the versions their ops were based on
+//! (`MutationEnvelope::base_versions`, signed), filled in by the engine when
+//! it signs. A remote envelope whose base no longer matches — another
+//! envelope wrote the fragment after the author last saw it — is an edit of
+//! a stale copy: the engine rejects it with `ErpError::Conflict` instead of
+//! letting the last write win, and keeps it here for review. Array fragments
+//! (`tx:{id}:lines` …) only collect ids and merge as sequences; they are not
+//! versioned.
+//!
+//! The UI lists open conflicts with a per-field comparison and settles each
+//! with `resolve`: keep the current state, re-apply the rejected edit on top
+//! of it, or apply a merged set of ops. Re-applied and merged ops are signed
+//! by the resolving actor and validated like any remote edit.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::erp::engine::{self, ErpStore};
+use crate::erp::envelope::MutationEnvelope;
+use crate::erp::errors::ErpError;
+use crate::erp::types::{ActorContext, Op};
+
+/// The latest write of a map fragment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FragmentVersion {
+    /// Number of envelopes that wrote the fragment
+    pub version: u64,
+    pub mutation_id: String,
+    pub actor_pubkey: String,
+    pub lamport: u64,
+    pub issued_at_ms: i64,
+}
+
+/// Map fragments `ops` write (the versioned ones).
+pub fn written_fragments(ops: &[Op]) -> BTreeSet<String> {
+    ops.iter()
+        .filter_map(|op| match op {
+            Op::MapSet { fragment_id, .. } | Op::MapDel { fragment_id, .. } => {
+                Some(fragment_id.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Current version of `fragment_id` (0 = never written).
+pub fn version_of(store: &ErpStore, fragment_id: &str) -> u64 {
+    store
+        .fragment_versions
+        .get(fragment_id)
+        .map_or(0, |v| v.version)
+}
+
+/// The versions to sign into an envelope carrying `ops`.
+pub fn base_versions(store: &ErpStore, ops: &[Op]) -> BTreeMap<String, u64> {
+    written_fragments(ops)
+        .into_iter()
+        .map(|f| {
+            let v = version_of(store, &f);
+            (f, v)
+        })
+        .collect()
+}
+
+/// Reject `envelope` if any fragment it was based on changed since.
+/// Envelopes without base versions are not checked.
+pub fn check(store: &ErpStore, envelope: &MutationEnvelope) -> Result<(), ErpError> {
+    for (fragment_id, base) in &envelope.base_versions {
+        let current = version_of(store, fragment_id);
+        if current != *base {
+            return Err(ErpError::Conflict(fragment_id.clone(), *base, current));
+        }
+    }
+    Ok(())
+}
+
+/// Record `envelope` as the latest write of the map fragments it touches.
+/// Returns the new versions (for SQLite write-through).
+pub fn bump(store: &mut ErpStore, envelope: &MutationEnvelope) -> Vec<(String, FragmentVersion)> {
+    written_fragments(&envelope.ops)
+        .into_iter()
+        .map(|fragment_id| {
+            let version = FragmentVersion {
+                version: version_of(store, &fragment_id) + 1,
+                mutation_id: envelope.mutation_id.clone(),
+                actor_pubkey: envelope.actor_pubkey.clone(),
+                lamport: envelope.lamport,
+                issued_at_ms: envelope.issued_at_ms,
+            };
+            store
+                .fragment_versions
+                .insert(fragment_id.clone(), version.clone());
+            (fragment_id, version)
+        })
+        .collect()
+}
+
+// ─── Conflict records ────────────────────────────────────────────────────────
+
+/// One key of a conflicting fragment: what the store holds now and what the
+/// rejected envelope would have written (`None` = absent / deleted).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldConflict {
+    pub key: String,
+    pub current: Option<serde_json::Value>,
+    pub proposed: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FragmentConflict {
+    pub fragment_id: String,
+    pub base_version: u64,
+    /// The write the rejected envelope did not see
+    pub current: Option<FragmentVersion>,
+    /// Keys whose proposed value differs from the current one
+    pub fields: Vec<FieldConflict>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Resolution {
+    /// Discard the rejected edit
+    KeepCurrent,
+    /// Apply the rejected edit's ops on top of the current state
+    ApplyMine,
+    /// Apply ops assembled by the user (e.g. field by field)
+    Merge { ops: Vec<Op> },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResolvedBy {
+    pub actor_pubkey: String,
+    pub resolution: String,
+    /// Envelope applying the resolution, if any
+    pub mutation_id: Option<String>,
+    pub resolved_at_ms: i64,
+}
+
+/// A remote envelope rejected as a concurrent edit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    /// The rejected envelope's mutation_id
+    pub conflict_id: String,
+    pub org_id: String,
+    pub envelope: MutationEnvelope,
+    pub fragments: Vec<FragmentConflict>,
+    pub detected_at_ms: i64,
+    pub resolved: Option<ResolvedBy>,
+}
+
+lazy_static! {
+    static ref CONFLICTS: Mutex<BTreeMap<String, Conflict>> = Mutex::new(BTreeMap::new());
+    static ref CONFLICTS_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Load `erp_conflicts.json` from `app_data_dir`; called from `engine::init_erp_db`.
+pub fn configure(app_data_dir: &Path) {
+    let path = app_data_dir.join("erp_conflicts.json");
+    if let Ok(s) = fs::read_to_string(&path) {
+        match serde_json::from_str::<Vec<Conflict>>(&s) {
+            Ok(list) => {
+                *CONFLICTS.lock().unwrap() = list
+                    .into_iter()
+                    .map(|c| (c.conflict_id.clone(), c))
+                    .collect()
+            }
+            Err(e) => eprintln!("⚠️  Invalid erp_conflicts.json: {e}"),
+        }
+    }
+    *CONFLICTS_PATH.lock().unwrap() = Some(path);
+}
+
+fn save(conflicts: &BTreeMap<String, Conflict>) {
+    if let Some(path) = CONFLICTS_PATH.lock().unwrap().as_ref() {
+        let list: Vec<&Conflict> = conflicts.values().collect();
+        if let Err(e) = serde_json::to_vec_pretty(&list).map(|json| fs::write(path, json)) {
+            eprintln!("❌ Failed to save ERP conflicts: {e}");
+        }
+    }
+}
+
+/// Keep a rejected `envelope` for review, with a per-field comparison
+/// against `store`. Re-recording the same envelope (a retry) is a no-op.
+pub fn record(store: &ErpStore, envelope: &MutationEnvelope) -> Conflict {
+    let mut conflicts = CONFLICTS.lock().unwrap();
+    if let Some(existing) = conflicts.get(&envelope.mutation_id) {
+        return existing.clone();
+    }
+    let fragments = envelope
+        .base_versions
+        .iter()
+        .filter(|(f, base)| version_of(store, f) != **base)
+        .map(|(fragment_id, base)| FragmentConflict {
+            fragment_id: fragment_id.clone(),
+            base_version: *base,
+            current: store.fragment_versions.get(fragment_id).cloned(),
+            fields: field_conflicts(store, fragment_id, &envelope.ops),
+        })
+        .collect();
+    let conflict = Conflict {
+        conflict_id: envelope.mutation_id.clone(),
+        org_id: envelope.org_id.clone(),
+        envelope: envelope.clone(),
+        fragments,
+        detected_at_ms: chrono::Utc::now().timestamp_millis(),
+        resolved: None,
+    };
+    conflicts.insert(conflict.conflict_id.clone(), conflict.clone());
+    save(&conflicts);
+    conflict
+}
+
+/// Keys `ops` write in `fragment_id` whose value differs from the store's.
+fn field_conflicts(store: &ErpStore, fragment_id: &str, ops: &[Op]) -> Vec<FieldConflict> {
+    let current = current_fields(store, fragment_id);
+    let mut proposed: BTreeMap<&str, Option<&serde_json::Value>> = BTreeMap::new();
+    for op in ops {
+        match op {
+            Op::MapSet {
+                fragment_id: f,
+                key,
+                value,
+            } if f == fragment_id => {
+                proposed.insert(key, Some(value));
+            }
+            Op::MapDel {
+                fragment_id: f,
+                key,
+            } if f == fragment_id => {
+                proposed.insert(key, None);
+            }
+            _ => {}
+        }
+    }
+    proposed
+        .into_iter()
+        .map(|(key, value)| FieldConflict {
+            key: key.to_string(),
+            current: current.get(key).filter(|v| !v.is_null()).cloned(),
+            proposed: value.cloned(),
+        })
+        .filter(|f| f.current != f.proposed)
+        .collect()
+}
+
+/// The fragment's current fields, keyed as its ops key them.
+fn current_fields(
+    store: &ErpStore,
+    fragment_id: &str,
+) -> serde_json::Map<String, serde_json::Value> {
+    let (kind, rest) = fragment_id.split_once(':').unwrap_or((fragment_id, ""));
+    let as_map = |v: Option<serde_json::Value>| match v {
+        Some(serde_json::Value::Object(m)) => m,
+        _ => serde_json::Map::new(),
+    };
+    let as_data = |v: Option<serde_json::Value>| {
+        let mut m = serde_json::Map::new();
+        if let Some(v) = v {
+            m.insert("data".to_string(), v);
+        }
+        m
+    };
+    let to_value = |v: Option<serde_json::Result<serde_json::Value>>| v.and_then(Result::ok);
+    match kind {
+        "tx" => {
+            let tx_id = rest.strip_suffix(":hdr").unwrap_or(rest);
+            as_map(to_value(
+                store.transactions.get(tx_id).map(serde_json::to_value),
+            ))
+        }
+        "txline" => as_data(to_value(store.lines.get(rest).map(serde_json::to_value))),
+        "invmove" => as_data(to_value(store.invmoves.get(rest).map(serde_json::to_value))),
+        "posting" => as_data(to_value(
+            store.postings.get(fragment_id).map(serde_json::to_value),
+        )),
+        "stocktake" => as_data(to_value(
+            store.count_sheets.get(rest).map(serde_json::to_value),
+        )),
+        "party" => as_map(to_value(store.parties.get(rest).map(serde_json::to_value))),
+        "reorder" => as_map(to_value(
+            store.reorder_policies.get(rest).map(serde_json::to_value),
+        )),
+        "account" => {
+            let mut m = as_map(to_value(store.accounts.get(rest).map(serde_json::to_value)));
+            // account fragments key the type as "type"
+            if let Some(t) = m.remove("acct_type") {
+                m.insert("type".to_string(), t);
+            }
+            m
+        }
+        _ => serde_json::Map::new(),
+    }
+}
+
+/// Conflicts of `org_id`, oldest first; resolved ones only when asked.
+pub fn list(org_id: &str, include_resolved: bool) -> Vec<Conflict> {
+    let mut list: Vec<Conflict> = CONFLICTS
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|c| c.org_id == org_id && (include_resolved || c.resolved.is_none()))
+        .cloned()
+        .collect();
+    list.sort_by_key(|c| c.detected_at_ms);
+    list
+}
+
+/// Settle an open conflict. `ApplyMine` / `Merge` commit their ops as a new
+/// envelope of `actor` (ABAC and business validation as for remote edits);
+/// returns it.
+pub fn resolve(
+    actor: &ActorContext,
+    conflict_id: &str,
+    resolution: Resolution,
+) -> Result<Option<MutationEnvelope>, ErpError> {
+    let conflict = CONFLICTS
+        .lock()
+        .unwrap()
+        .get(conflict_id)
+        .cloned()
+        .ok_or_else(|| ErpError::ValidationFail(format!("conflict {} not found", conflict_id)))?;
+    if conflict.resolved.is_some() {
+        return Err(ErpError::ValidationFail(format!(
+            "conflict {} is already resolved",
+            conflict_id
+        )));
+    }
+    if conflict.org_id != actor.org_id {
+        return Err(ErpError::AbacDeny(format!(
+            "conflict {} belongs to org {}",
+            conflict_id, conflict.org_id
+        )));
+    }
+
+    let (kind, ops) = match resolution {
+        Resolution::KeepCurrent => ("keep_current", None),
+        Resolution::ApplyMine => ("apply_mine", Some(conflict.envelope.ops.clone())),
+        Resolution::Merge { ops } => ("merge", Some(ops)),
+    };
+    let envelope = match ops {
+        Some(ops) => Some(engine::apply_validated(actor, ops)?),
+        None => {
+            let policy_ctx = crate::erp::types::PolicyContext {
+                org_id: actor.org_id.clone(),
+                tx_id: None,
+                tx_status: None,
+            };
+            crate::erp::abac::check_abac(actor, &crate::erp::abac::Action::TxEdit, &policy_ctx)?;
+            None
+        }
+    };
+
+    let mut conflicts = CONFLICTS.lock().unwrap();
+    if let Some(c) = conflicts.get_mut(conflict_id) {
+        c.resolved = Some(ResolvedBy {
+            actor_pubkey: actor.pubkey.clone(),
+            resolution: kind.to_string(),
+            mutation_id: envelope.as_ref().map(|e| e.mutation_id.clone()),
+            resolved_at_ms: chrono::Utc::now().timestamp_millis(),
+        });
+    }
+    save(&conflicts);
+    Ok(envelope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::{set, signed_envelope};
+    use ed25519_dalek::SigningKey;
+
+    fn envelope(seed: u8, ops: Vec<Op>, base: &[(&str, u64)]) -> MutationEnvelope {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        let pubkey = hex::encode(key.verifying_key().to_bytes());
+        let mut env = signed_envelope(
+            &key,
+            &uuid::Uuid::new_v4().to_string(),
+            &pubkey,
+            ops,
+            None,
+            1,
+        );
+        env.base_versions = base.iter().map(|(f, v)| (f.to_string(), *v)).collect();
+        env.resign(&key).unwrap();
+        env
+    }
+
+    #[test]
+    fn test_stale_edit_is_a_conflict() {
+        let mut store = ErpStore::new();
+        let create = envelope(
+            1,
+            vec![set("tx:t1:hdr", "memo", serde_json::json!("a"))],
+            &[],
+        );
+        crate::erp::apply::apply_ops(&mut store, &create.ops);
+        bump(&mut store, &create);
+        assert_eq!(version_of(&store, "tx:t1:hdr"), 1);
+
+        // Two devices both start from version 1
+        let ops = |v: &str| vec![set("tx:t1:hdr", "description", serde_json::json!(v))];
+        let first = envelope(2, ops("first"), &[("tx:t1:hdr", 1)]);
+        let second = envelope(3, ops("second"), &[("tx:t1:hdr", 1)]);
+        assert!(first.verify().is_ok());
+
+        assert!(check(&store, &first).is_ok());
+        crate::erp::apply::apply_ops(&mut store, &first.ops);
+        let bumped = bump(&mut store, &first);
+        assert_eq!(bumped[0].1.version, 2);
+
+        let err = check(&store, &second).unwrap_err();
+        assert_eq!(err, ErpError::Conflict("tx:t1:hdr".to_string(), 1, 2));
+        let conflict = record(&store, &second);
+        let fields = &conflict.fragments[0].fields;
+        assert_eq!(fields.len(), 1);
+        assert_eq!(fields[0].current, Some(serde_json::json!("first")));
+        assert_eq!(fields[0].proposed, Some(serde_json::json!("second")));
+        assert_eq!(
+            conflict.fragments[0].current.as_ref().unwrap().mutation_id,
+            first.mutation_id
+        );
+
+        // Legacy envelopes carry no base versions and are not checked
+        assert!(check(&store, &envelope(4, ops("x"), &[])).is_ok());
+    }
+
+    #[test]
+    fn test_base_versions_are_signed() {
+        let mut env = envelope(
+            5,
+            vec![set("party:p1", "name", serde_json::json!("A"))],
+            &[("party:p1", 0)],
+        );
+        assert!(env.verify().is_ok());
+        env.base_versions.insert("party:p1".to_string(), 7);
+        assert_eq!(env.verify().unwrap_err().code(), "ERR_SIG_INVALID");
+    }
+}
@@ -0,0 +1,323 @@
+//! consistency.rs — Compare a log-replayed store against SQLite / memory
+//!
+//! The audit log is the source of truth: replaying it through `apply_ops`
+//! yields the expected state. `compare` checks another store (the output of
+//! `db::load_all`, or the live `ERP_STORE`) against it record by record and
+//! reports every discrepancy: records missing on either side, status
+//! mismatches, field mismatches, and lines / moves / postings whose tx does
+//! not exist.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::erp::engine::ErpStore;
+
+/// What kind of discrepancy was found.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    /// In the audit log but not in the compared store
+    Missing,
+    /// In the compared store but never written by the audit log
+    Unlogged,
+    /// Tx header status differs
+    StatusMismatch,
+    /// Record present on both sides but with different fields
+    FieldMismatch,
+    /// Line / move / posting whose tx does not exist in the compared store
+    Orphan,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub kind: DiscrepancyKind,
+    /// "tx" | "line" | "invmove" | "posting" | "account" | "party" | "count_sheet"
+    pub record: String,
+    pub id: String,
+    pub detail: String,
+}
+
+/// Result of comparing one store against the audit log replay.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// What was compared against the log ("sqlite" | "memory")
+    pub source: String,
+    pub records_checked: usize,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+/// Compare `actual` against `expected` (the audit log replay).
+pub fn compare(source: &str, expected: &ErpStore, actual: &ErpStore) -> ConsistencyReport {
+    let mut report = ConsistencyReport {
+        source: source.to_string(),
+        ..Default::default()
+    };
+
+    // Tx headers: a status mismatch is reported on its own, the other fields
+    // are still compared
+    for (id, exp, act) in pairs(
+        &expected.transactions,
+        &actual.transactions,
+        &mut report,
+        "tx",
+    ) {
+        if exp.status != act.status {
+            report.push(
+                DiscrepancyKind::StatusMismatch,
+                "tx",
+                id,
+                format!(
+                    "log has {}, {} has {}",
+                    exp.status.as_str(),
+                    source,
+                    act.status.as_str()
+                ),
+            );
+        }
+        let mut act = act.clone();
+        act.status = exp.status.clone();
+        report.diff_fields("tx", id, exp, &act);
+    }
+
+    // Lines: move_ids are re-linked on load, so compare them as a set
+    for (id, exp, act) in pairs(&expected.lines, &actual.lines, &mut report, "line") {
+        let mut exp = exp.clone();
+        let mut act = act.clone();
+        exp.move_ids.sort();
+        act.move_ids.sort();
+        report.diff_fields("line", id, &exp, &act);
+    }
+    for (id, exp, act) in pairs(&expected.invmoves, &actual.invmoves, &mut report, "invmove") {
+        report.diff_fields("invmove", id, exp, act);
+    }
+    for (id, exp, act) in pairs(&expected.postings, &actual.postings, &mut report, "posting") {
+        report.diff_fields("posting", id, exp, act);
+    }
+    for (id, exp, act) in pairs(&expected.accounts, &actual.accounts, &mut report, "account") {
+        report.diff_fields("account", id, exp, act);
+    }
+    for (id, exp, act) in pairs(&expected.parties, &actual.parties, &mut report, "party") {
+        report.diff_fields("party", id, exp, act);
+    }
+    for (id, exp, act) in pairs(
+        &expected.count_sheets,
+        &actual.count_sheets,
+        &mut report,
+        "count_sheet",
+    ) {
+        report.diff_fields("count_sheet", id, exp, act);
+    }
+    for (id, exp, act) in pairs(
+        &expected.reorder_policies,
+        &actual.reorder_policies,
+        &mut report,
+        "reorder_policy",
+    ) {
+        report.diff_fields("reorder_policy", id, exp, act);
+    }
+
+    // Orphans in the compared store
+    let has_tx = |tx_id: &str| actual.transactions.contains_key(tx_id);
+    let mut orphans = Vec::new();
+    for l in actual.lines.values().filter(|l| !has_tx(&l.tx_id)) {
+        orphans.push(("line", l.line_id.clone(), l.tx_id.clone()));
+    }
+    for m in actual.invmoves.values().filter(|m| !has_tx(&m.tx_id)) {
+        orphans.push(("invmove", m.move_id.clone(), m.tx_id.clone()));
+    }
+    for p in actual.postings.values().filter(|p| !has_tx(&p.tx_id)) {
+        orphans.push(("posting", p.posting_id.clone(), p.tx_id.clone()));
+    }
+    orphans.sort();
+    for (record, id, tx_id) in orphans {
+        report.push(
+            DiscrepancyKind::Orphan,
+            record,
+            &id,
+            format!("tx {} does not exist", tx_id),
+        );
+    }
+
+    report
+}
+
+/// Walk the union of keys in sorted order, reporting records present on only
+/// one side and returning the pairs present on both.
+fn pairs<'a, T>(
+    expected: &'a HashMap<String, T>,
+    actual: &'a HashMap<String, T>,
+    report: &mut ConsistencyReport,
+    record: &str,
+) -> Vec<(&'a str, &'a T, &'a T)> {
+    let keys: BTreeSet<&String> = expected.keys().chain(actual.keys()).collect();
+    let mut both = Vec::new();
+    for key in keys {
+        report.records_checked += 1;
+        match (expected.get(key), actual.get(key)) {
+            (Some(e), Some(a)) => both.push((key.as_str(), e, a)),
+            (Some(_), None) => report.push(
+                DiscrepancyKind::Missing,
+                record,
+                key,
+                format!("in audit log, not in {}", report.source),
+            ),
+            (None, Some(_)) => report.push(
+                DiscrepancyKind::Unlogged,
+                record,
+                key,
+                format!("in {}, not in audit log", report.source),
+            ),
+            (None, None) => {}
+        }
+    }
+    both
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+
+    fn push(&mut self, kind: DiscrepancyKind, record: &str, id: &str, detail: String) {
+        self.discrepancies.push(Discrepancy {
+            kind,
+            record: record.to_string(),
+            id: id.to_string(),
+            detail,
+        });
+    }
+
+    /// Report the top-level fields whose JSON values differ.
+    fn diff_fields<T: Serialize>(&mut self, record: &str, id: &str, expected: &T, actual: &T) {
+        let (Ok(serde_json::Value::Object(e)), Ok(serde_json::Value::Object(a))) =
+            (serde_json::to_value(expected), serde_json::to_value(actual))
+        else {
+            return;
+        };
+        let fields: Vec<&String> = e
+            .keys()
+            .chain(a.keys())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter(|k| e.get(*k) != a.get(*k))
+            .collect();
+        if !fields.is_empty() {
+            let detail = fields
+                .iter()
+                .map(|k| {
+                    format!(
+                        "{}: log {} vs {}",
+                        k,
+                        e.get(*k).unwrap_or(&serde_json::Value::Null),
+                        a.get(*k).unwrap_or(&serde_json::Value::Null)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("; ");
+            self.push(DiscrepancyKind::FieldMismatch, record, id, detail);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::apply::apply_ops;
+    use crate::erp::fragments;
+    use crate::erp::test_support::set;
+    use crate::erp::types::{Posting, TxStatus};
+
+    fn posting(id: &str, tx_id: &str) -> Posting {
+        Posting {
+            posting_id: fragments::posting_id(id),
+            tx_id: tx_id.to_string(),
+            account_id: "bank".to_string(),
+            debit_amount: 10.0,
+            credit_amount: 0.0,
+            currency: "AUD".to_string(),
+            description: None,
+            status: "posted".to_string(),
+            generated_by: "engine".to_string(),
+        }
+    }
+
+    fn logged() -> ErpStore {
+        let hdr = fragments::tx_hdr_id("tx1");
+        let p = posting("p1", "tx1");
+        let mut store = ErpStore::new();
+        apply_ops(
+            &mut store,
+            &[
+                set(&hdr, "tx_type", serde_json::json!("journal")),
+                set(&hdr, "status", serde_json::json!("posted")),
+                set(&p.posting_id, "data", serde_json::to_value(&p).unwrap()),
+            ],
+        );
+        store
+    }
+
+    #[test]
+    fn test_identical_stores_are_consistent() {
+        let report = compare("sqlite", &logged(), &logged());
+        assert!(report.is_consistent());
+        assert_eq!(report.records_checked, 2);
+    }
+
+    #[test]
+    fn test_reports_missing_status_and_orphans() {
+        let expected = logged();
+        let mut actual = logged();
+        actual.postings.clear();
+        actual.transactions.get_mut("tx1").unwrap().status = TxStatus::Approved;
+        let orphan = posting("p9", "gone");
+        actual.postings.insert(orphan.posting_id.clone(), orphan);
+
+        let report = compare("sqlite", &expected, &actual);
+        let kinds: Vec<(&DiscrepancyKind, &str)> = report
+            .discrepancies
+            .iter()
+            .map(|d| (&d.kind, d.record.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (&DiscrepancyKind::StatusMismatch, "tx"),
+                (&DiscrepancyKind::Missing, "posting"),
+                (&DiscrepancyKind::Unlogged, "posting"),
+                (&DiscrepancyKind::Orphan, "posting"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field_mismatch_names_the_field() {
+        let expected = logged();
+        let mut actual = logged();
+        actual.postings.get_mut("posting:p1").unwrap().debit_amount = 11.0;
+        let report = compare("memory", &expected, &actual);
+        assert_eq!(report.discrepancies.len(), 1);
+        assert_eq!(report.discrepancies[0].kind, DiscrepancyKind::FieldMismatch);
+        assert!(report.discrepancies[0].detail.starts_with("debit_amount"));
+    }
+
+    #[test]
+    fn test_status_mismatch_still_diffs_other_fields() {
+        let expected = logged();
+        let mut actual = logged();
+        let tx = actual.transactions.get_mut("tx1").unwrap();
+        tx.status = TxStatus::Approved;
+        tx.description = Some("rent".to_string());
+        let report = compare("sqlite", &expected, &actual);
+        let kinds: Vec<&DiscrepancyKind> = report.discrepancies.iter().map(|d| &d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                &DiscrepancyKind::StatusMismatch,
+                &DiscrepancyKind::FieldMismatch
+            ]
+        );
+        // The status is not reported again as a field
+        assert!(report.discrepancies[1].detail.starts_with("description"));
+        assert!(!report.discrepancies[1].detail.contains("status"));
+    }
+}
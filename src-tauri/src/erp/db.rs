@@ -8,9 +8,13 @@
 use rusqlite::{params, Connection, Error as SqlErr, Result as SqlResult};
 use std::path::Path;
 
+use crate::erp::conflict::FragmentVersion;
 use crate::erp::engine::{AccountRecord, ErpStore};
+use crate::erp::reorder::ReorderPolicy;
+use crate::erp::stocktake::{CountSheet, CountSheetStatus};
 use crate::erp::types::{
-    InvMove, InventoryEffect, Party, PartyKind, Posting, TxHeader, TxLine, TxStatus, TxType,
+    InvMove, InventoryEffect, Party, PartyKind, PendingProposal, Posting, TxHeader, TxLine,
+    TxStatus, TxType,
 };
 
 // ─── Schema ──────────────────────────────────────────────────────────────────
@@ -30,7 +34,8 @@ CREATE TABLE IF NOT EXISTS tx_headers (
     tx_date           TEXT NOT NULL DEFAULT '',
     site_id           TEXT NOT NULL DEFAULT 'primary',
     created_at_ms     INTEGER NOT NULL DEFAULT 0,
-    created_by_pubkey TEXT NOT NULL DEFAULT ''
+    created_by_pubkey TEXT NOT NULL DEFAULT '',
+    source_tx_id      TEXT
 );
 
 CREATE TABLE IF NOT EXISTS tx_lines (
@@ -43,7 +48,11 @@ CREATE TABLE IF NOT EXISTS tx_lines (
     unit_price         REAL NOT NULL DEFAULT 0,
     inventory_effect   TEXT NOT NULL DEFAULT 'none',
     tax_code           TEXT,
-    tax_rate           REAL NOT NULL DEFAULT 0
+    tax_rate           REAL NOT NULL DEFAULT 0,
+    debit_amount       REAL NOT NULL DEFAULT 0,
+    credit_amount      REAL NOT NULL DEFAULT 0,
+    source_line_id     TEXT,
+    reason_code        TEXT
 );
 
 CREATE TABLE IF NOT EXISTS postings (
@@ -85,10 +94,69 @@ CREATE TABLE IF NOT EXISTS accounts (
     code           TEXT PRIMARY KEY,
     name           TEXT NOT NULL,
     acct_type      TEXT NOT NULL,
-    normal_balance TEXT NOT NULL
+    normal_balance TEXT NOT NULL,
+    parent_code    TEXT,
+    is_header      INTEGER NOT NULL DEFAULT 0,
+    active         INTEGER NOT NULL DEFAULT 1,
+    role           TEXT
+);
+
+CREATE TABLE IF NOT EXISTS count_sheets (
+    sheet_id         TEXT PRIMARY KEY,
+    org_id           TEXT NOT NULL,
+    site_id          TEXT NOT NULL DEFAULT 'primary',
+    location_id      TEXT,
+    status           TEXT NOT NULL DEFAULT 'open',
+    frozen_at_ms     INTEGER NOT NULL DEFAULT 0,
+    frozen_by_pubkey TEXT NOT NULL DEFAULT '',
+    adjust_tx_id     TEXT,
+    lines_json       TEXT NOT NULL DEFAULT '[]'
+);
+
+CREATE TABLE IF NOT EXISTS fragment_versions (
+    fragment_id   TEXT PRIMARY KEY,
+    version       INTEGER NOT NULL,
+    mutation_id   TEXT NOT NULL,
+    actor_pubkey  TEXT NOT NULL,
+    lamport       INTEGER NOT NULL,
+    issued_at_ms  INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS proposals (
+    proposal_id  TEXT PRIMARY KEY,
+    org_id       TEXT NOT NULL,
+    status       TEXT NOT NULL,
+    data_json    TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS reorder_policies (
+    policy_key   TEXT PRIMARY KEY,
+    org_id       TEXT NOT NULL,
+    item_id      TEXT NOT NULL,
+    data_json    TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS actor_clocks (
+    actor_pubkey  TEXT PRIMARY KEY,
+    clock         INTEGER NOT NULL
 );
 ";
 
+/// Columns added after the initial M10 schema: `(table, column, declaration)`.
+/// `CREATE TABLE IF NOT EXISTS` skips existing tables, so databases created by an
+/// older build get these via `ALTER TABLE` in `apply_column_migrations`.
+const COLUMN_MIGRATIONS: &[(&str, &str, &str)] = &[
+    ("accounts", "parent_code", "TEXT"),
+    ("accounts", "is_header", "INTEGER NOT NULL DEFAULT 0"),
+    ("accounts", "active", "INTEGER NOT NULL DEFAULT 1"),
+    ("accounts", "role", "TEXT"),
+    ("tx_lines", "debit_amount", "REAL NOT NULL DEFAULT 0"),
+    ("tx_lines", "credit_amount", "REAL NOT NULL DEFAULT 0"),
+    ("tx_headers", "source_tx_id", "TEXT"),
+    ("tx_lines", "source_line_id", "TEXT"),
+    ("tx_lines", "reason_code", "TEXT"),
+];
+
 // ─── Init ─────────────────────────────────────────────────────────────────────
 
 /// Open (or create) the SQLite database at `db_path`, apply the schema, and return
@@ -96,17 +164,34 @@ CREATE TABLE IF NOT EXISTS accounts (
 pub fn init_db(db_path: &Path) -> SqlResult<Connection> {
     let conn = Connection::open(db_path)?;
     conn.execute_batch(SCHEMA)?;
+    apply_column_migrations(&conn)?;
+    crate::erp::audit_index::init(&conn)?;
     Ok(conn)
 }
 
+/// Add any `COLUMN_MIGRATIONS` entries missing from the live schema.
+fn apply_column_migrations(conn: &Connection) -> SqlResult<()> {
+    for (table, column, decl) in COLUMN_MIGRATIONS {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+        let exists = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .filter_map(Result::ok)
+            .any(|name| name == *column);
+        if !exists {
+            conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))?;
+        }
+    }
+    Ok(())
+}
+
 // ─── Upsert helpers ───────────────────────────────────────────────────────────
 
 pub fn upsert_tx(conn: &Connection, tx: &TxHeader) -> SqlResult<()> {
     conn.execute(
         "INSERT OR REPLACE INTO tx_headers
          (tx_id, org_id, tx_type, status, party_id, currency, ref_number, description,
-          tx_date, site_id, created_at_ms, created_by_pubkey)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+          tx_date, site_id, created_at_ms, created_by_pubkey, source_tx_id)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
         params![
             tx.tx_id,
             tx.org_id,
@@ -120,6 +205,7 @@ pub fn upsert_tx(conn: &Connection, tx: &TxHeader) -> SqlResult<()> {
             tx.site_id,
             tx.created_at_ms,
             tx.created_by_pubkey,
+            tx.source_tx_id,
         ],
     )?;
     Ok(())
@@ -129,8 +215,9 @@ pub fn upsert_line(conn: &Connection, line: &TxLine) -> SqlResult<()> {
     conn.execute(
         "INSERT OR REPLACE INTO tx_lines
          (line_id, tx_id, item_id, account_id, description, qty, unit_price,
-          inventory_effect, tax_code, tax_rate)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+          inventory_effect, tax_code, tax_rate, debit_amount, credit_amount, source_line_id,
+          reason_code)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
         params![
             line.line_id,
             line.tx_id,
@@ -142,6 +229,10 @@ pub fn upsert_line(conn: &Connection, line: &TxLine) -> SqlResult<()> {
             line.inventory_effect.as_str(),
             line.tax_code,
             line.tax_rate,
+            line.debit_amount,
+            line.credit_amount,
+            line.source_line_id,
+            line.reason_code,
         ],
     )?;
     Ok(())
@@ -210,13 +301,174 @@ pub fn upsert_party(conn: &Connection, p: &Party) -> SqlResult<()> {
 
 pub fn upsert_account(conn: &Connection, a: &AccountRecord) -> SqlResult<()> {
     conn.execute(
-        "INSERT OR REPLACE INTO accounts (code, name, acct_type, normal_balance)
+        "INSERT OR REPLACE INTO accounts
+         (code, name, acct_type, normal_balance, parent_code, is_header, active, role)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            a.code,
+            a.name,
+            a.acct_type,
+            a.normal_balance,
+            a.parent_code,
+            a.is_header,
+            a.active,
+            a.role,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn upsert_count_sheet(conn: &Connection, s: &CountSheet) -> SqlResult<()> {
+    let status = match s.status {
+        CountSheetStatus::Open => "open",
+        CountSheetStatus::Reconciled => "reconciled",
+    };
+    let lines_json = serde_json::to_string(&s.lines).unwrap_or_else(|_| "[]".into());
+    conn.execute(
+        "INSERT OR REPLACE INTO count_sheets
+         (sheet_id, org_id, site_id, location_id, status, frozen_at_ms, frozen_by_pubkey,
+          adjust_tx_id, lines_json)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            s.sheet_id,
+            s.org_id,
+            s.site_id,
+            s.location_id,
+            status,
+            s.frozen_at_ms,
+            s.frozen_by_pubkey,
+            s.adjust_tx_id,
+            lines_json,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn upsert_fragment_version(
+    conn: &Connection,
+    fragment_id: &str,
+    v: &FragmentVersion,
+) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO fragment_versions
+         (fragment_id, version, mutation_id, actor_pubkey, lamport, issued_at_ms)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            fragment_id,
+            v.version as i64,
+            v.mutation_id,
+            v.actor_pubkey,
+            v.lamport as i64,
+            v.issued_at_ms,
+        ],
+    )?;
+    Ok(())
+}
+
+pub fn upsert_proposal(conn: &Connection, p: &PendingProposal) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO proposals (proposal_id, org_id, status, data_json)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![
+            p.proposal_id,
+            p.org_id,
+            p.status.as_str(),
+            serde_json::to_string(p).unwrap_or_default(),
+        ],
+    )?;
+    Ok(())
+}
+
+/// Upsert a reorder policy, keyed `{org_id}:{item_id}` like the store.
+pub fn upsert_reorder_policy(conn: &Connection, p: &ReorderPolicy) -> SqlResult<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO reorder_policies (policy_key, org_id, item_id, data_json)
          VALUES (?1, ?2, ?3, ?4)",
-        params![a.code, a.name, a.acct_type, a.normal_balance],
+        params![
+            format!("{}:{}", p.org_id, p.item_id),
+            p.org_id,
+            p.item_id,
+            serde_json::to_string(p).unwrap_or_default(),
+        ],
     )?;
     Ok(())
 }
 
+/// Record an actor's clock; never moves it back.
+pub fn upsert_actor_clock(conn: &Connection, actor_pubkey: &str, clock: u64) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO actor_clocks (actor_pubkey, clock) VALUES (?1, ?2)
+         ON CONFLICT(actor_pubkey) DO UPDATE SET clock = MAX(clock, excluded.clock)",
+        params![actor_pubkey, clock as i64],
+    )?;
+    Ok(())
+}
+
+/// Delete one row by primary key — for records removed by `MapDel` /
+/// `ArrayDelete` ops. `table` and `key_col` are fixed names from the engine.
+pub fn delete_row(conn: &Connection, table: &str, key_col: &str, id: &str) -> SqlResult<()> {
+    conn.execute(
+        &format!("DELETE FROM {} WHERE {} = ?1", table, key_col),
+        params![id],
+    )?;
+    Ok(())
+}
+
+/// Replace every ERP table with the contents of `store` in one transaction.
+/// Used to rebuild corngr.db from an audit log replay.
+pub fn replace_all(conn: &Connection, store: &ErpStore) -> SqlResult<()> {
+    let tx = conn.unchecked_transaction()?;
+    for table in [
+        "tx_headers",
+        "tx_lines",
+        "postings",
+        "inv_moves",
+        "parties",
+        "accounts",
+        "count_sheets",
+        "fragment_versions",
+        "proposals",
+        "reorder_policies",
+        "actor_clocks",
+    ] {
+        tx.execute(&format!("DELETE FROM {}", table), [])?;
+    }
+    for t in store.transactions.values() {
+        upsert_tx(&tx, t)?;
+    }
+    for l in store.lines.values() {
+        upsert_line(&tx, l)?;
+    }
+    for p in store.postings.values() {
+        upsert_posting(&tx, p)?;
+    }
+    for m in store.invmoves.values() {
+        upsert_invmove(&tx, m)?;
+    }
+    for p in store.parties.values() {
+        upsert_party(&tx, p)?;
+    }
+    for a in store.accounts.values() {
+        upsert_account(&tx, a)?;
+    }
+    for s in store.count_sheets.values() {
+        upsert_count_sheet(&tx, s)?;
+    }
+    for (f, v) in &store.fragment_versions {
+        upsert_fragment_version(&tx, f, v)?;
+    }
+    for p in store.proposals.values() {
+        upsert_proposal(&tx, p)?;
+    }
+    for p in store.reorder_policies.values() {
+        upsert_reorder_policy(&tx, p)?;
+    }
+    for (actor, clock) in &store.replay.max_lamport {
+        upsert_actor_clock(&tx, actor, *clock)?;
+    }
+    tx.commit()
+}
+
 // ─── Load all — warms ErpStore from SQLite on startup ────────────────────────
 
 /// Convert an `ErpError` string form to a `rusqlite::Error` so it can bubble
@@ -234,7 +486,8 @@ pub fn load_all(conn: &Connection) -> SqlResult<ErpStore> {
     {
         let mut stmt = conn.prepare(
             "SELECT tx_id, org_id, tx_type, status, party_id, currency,
-                    ref_number, description, tx_date, site_id, created_at_ms, created_by_pubkey
+                    ref_number, description, tx_date, site_id, created_at_ms, created_by_pubkey,
+                    source_tx_id
              FROM tx_headers",
         )?;
         let rows = stmt.query_map([], |row| {
@@ -257,6 +510,7 @@ pub fn load_all(conn: &Connection) -> SqlResult<ErpStore> {
                     .unwrap_or_else(|| "primary".into()),
                 created_at_ms: row.get(10)?,
                 created_by_pubkey: row.get::<_, Option<String>>(11)?.unwrap_or_default(),
+                source_tx_id: row.get(12)?,
             })
         })?;
         for r in rows {
@@ -269,7 +523,8 @@ pub fn load_all(conn: &Connection) -> SqlResult<ErpStore> {
     {
         let mut stmt = conn.prepare(
             "SELECT line_id, tx_id, item_id, account_id, description,
-                    qty, unit_price, inventory_effect, tax_code, tax_rate
+                    qty, unit_price, inventory_effect, tax_code, tax_rate,
+                    debit_amount, credit_amount, source_line_id, reason_code
              FROM tx_lines",
         )?;
         let rows = stmt.query_map([], |row| {
@@ -285,6 +540,10 @@ pub fn load_all(conn: &Connection) -> SqlResult<ErpStore> {
                 inventory_effect: InventoryEffect::from_str(&inv_s),
                 tax_code: row.get(8)?,
                 tax_rate: row.get(9)?,
+                debit_amount: row.get(10)?,
+                credit_amount: row.get(11)?,
+                source_line_id: row.get(12)?,
+                reason_code: row.get(13)?,
                 move_ids: vec![], // re-linked from inv_moves below
             })
         })?;
@@ -385,14 +644,20 @@ pub fn load_all(conn: &Connection) -> SqlResult<ErpStore> {
 
     // ── accounts ─────────────────────────────────────────────────────────────
     {
-        let mut stmt =
-            conn.prepare("SELECT code, name, acct_type, normal_balance FROM accounts")?;
+        let mut stmt = conn.prepare(
+            "SELECT code, name, acct_type, normal_balance, parent_code, is_header, active, role
+             FROM accounts",
+        )?;
         let rows = stmt.query_map([], |row| {
             Ok(AccountRecord {
                 code: row.get(0)?,
                 name: row.get(1)?,
                 acct_type: row.get(2)?,
                 normal_balance: row.get(3)?,
+                parent_code: row.get(4)?,
+                is_header: row.get(5)?,
+                active: row.get(6)?,
+                role: row.get(7)?,
             })
         })?;
         for r in rows {
@@ -401,5 +666,98 @@ pub fn load_all(conn: &Connection) -> SqlResult<ErpStore> {
         }
     }
 
+    // ── count_sheets ─────────────────────────────────────────────────────────
+    {
+        let mut stmt = conn.prepare(
+            "SELECT sheet_id, org_id, site_id, location_id, status, frozen_at_ms,
+                    frozen_by_pubkey, adjust_tx_id, lines_json
+             FROM count_sheets",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let status_s: String = row.get(4)?;
+            let lines_s: String = row.get(8)?;
+            Ok(CountSheet {
+                sheet_id: row.get(0)?,
+                org_id: row.get(1)?,
+                site_id: row.get(2)?,
+                location_id: row.get(3)?,
+                status: if status_s == "reconciled" {
+                    CountSheetStatus::Reconciled
+                } else {
+                    CountSheetStatus::Open
+                },
+                frozen_at_ms: row.get(5)?,
+                frozen_by_pubkey: row.get(6)?,
+                adjust_tx_id: row.get(7)?,
+                lines: serde_json::from_str(&lines_s).map_err(|e| erp_err_to_sql(e.to_string()))?,
+            })
+        })?;
+        for r in rows {
+            let s = r?;
+            store.count_sheets.insert(s.sheet_id.clone(), s);
+        }
+    }
+
+    // ── fragment_versions ────────────────────────────────────────────────────
+    {
+        let mut stmt = conn.prepare(
+            "SELECT fragment_id, version, mutation_id, actor_pubkey, lamport, issued_at_ms
+             FROM fragment_versions",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                FragmentVersion {
+                    version: row.get::<_, i64>(1)? as u64,
+                    mutation_id: row.get(2)?,
+                    actor_pubkey: row.get(3)?,
+                    lamport: row.get::<_, i64>(4)? as u64,
+                    issued_at_ms: row.get(5)?,
+                },
+            ))
+        })?;
+        for r in rows {
+            let (f, v) = r?;
+            store.fragment_versions.insert(f, v);
+        }
+    }
+
+    // ── proposals ────────────────────────────────────────────────────────────
+    {
+        let mut stmt = conn.prepare("SELECT data_json FROM proposals")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        for r in rows {
+            if let Ok(p) = serde_json::from_str::<PendingProposal>(&r?) {
+                store.proposals.insert(p.proposal_id.clone(), p);
+            }
+        }
+    }
+
+    // ── reorder_policies ─────────────────────────────────────────────────────
+    {
+        let mut stmt = conn.prepare("SELECT policy_key, data_json FROM reorder_policies")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for r in rows {
+            let (key, data) = r?;
+            if let Ok(p) = serde_json::from_str::<ReorderPolicy>(&data) {
+                store.reorder_policies.insert(key, p);
+            }
+        }
+    }
+
+    // ── actor_clocks ─────────────────────────────────────────────────────────
+    {
+        let mut stmt = conn.prepare("SELECT actor_pubkey, clock FROM actor_clocks")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+        })?;
+        for r in rows {
+            let (actor, clock) = r?;
+            store.replay.max_lamport.insert(actor, clock);
+        }
+    }
+
     Ok(store)
 }
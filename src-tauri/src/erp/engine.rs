@@ -4,16 +4,28 @@ use std::sync::Mutex;
 use uuid::Uuid;
 
 use crate::erp::abac::{check_abac, Action};
+use crate::erp::apply;
 use crate::erp::audit_log;
+use crate::erp::coa;
+use crate::erp::conflict::{self, FragmentVersion};
 use crate::erp::db;
 use crate::erp::envelope::MutationEnvelope;
 use crate::erp::errors::ErpError;
 use crate::erp::fragments;
+use crate::erp::hlc;
+use crate::erp::journal;
+use crate::erp::notes;
+use crate::erp::reorder::ReorderPolicy;
 use crate::erp::replay::ReplayGuard;
+use crate::erp::satellite;
+use crate::erp::stocktake::{self, CountSheet, CountSheetStatus};
 use crate::erp::types::{
-    ActorContext, AddLineRequest, CreateInvMoveRequest, CreateTxRequest, InvMove, InventoryEffect,
-    Op, Party, PolicyContext, Posting, TxHeader, TxLine, TxRef, TxStatus, TxType,
+    ActorContext, AddLineRequest, CreateAccountRequest, CreateInvMoveRequest, CreateJournalRequest,
+    CreatePartyRequest, CreateTxRequest, FragmentLink, FreezeCountSheetRequest, InvMove,
+    InventoryEffect, Op, Party, PartyKind, PendingProposal, PolicyContext, Posting,
+    ReconcileCountSheetRequest, RecordCountRequest, TxHeader, TxLine, TxRef, TxStatus, TxType,
 };
+use crate::erp::ydoc;
 
 /// A Chart of Accounts record — stored in ErpStore::accounts keyed by account code.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -22,6 +34,23 @@ pub struct AccountRecord {
     pub name: String,
     pub acct_type: String, // "asset" | "liability" | "equity" | "income" | "expense"
     pub normal_balance: String, // "debit" | "credit"
+    /// Code of the parent header account, if any (roll-up hierarchy).
+    #[serde(default)]
+    pub parent_code: Option<String>,
+    /// Header accounts only aggregate their children and cannot be posted to.
+    #[serde(default)]
+    pub is_header: bool,
+    /// Inactive accounts are kept for history but reject new lines.
+    #[serde(default = "default_true")]
+    pub active: bool,
+    /// Symbolic ledger role this account fulfils ("accounts_receivable", "bank", …),
+    /// so engine-generated postings resolve to it regardless of its name.
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 /// In-memory ERP document store.
@@ -40,6 +69,18 @@ pub struct ErpStore {
     pub postings: std::collections::HashMap<String, Posting>,
     /// Party master — keyed by party_id (M9)
     pub parties: std::collections::HashMap<String, Party>,
+    /// Stocktake count sheets — keyed by sheet_id
+    pub count_sheets: std::collections::HashMap<String, CountSheet>,
+    /// Reorder policies — keyed by `{org_id}:{item_id}` (see reorder.rs)
+    pub reorder_policies: std::collections::HashMap<String, ReorderPolicy>,
+    /// Array fragments (`tx:{id}:lines`, …) in op order — keyed by fragment_id
+    pub arrays: std::collections::HashMap<String, Vec<serde_json::Value>>,
+    /// Fragment links from `LinkAdd` ops
+    pub links: Vec<FragmentLink>,
+    /// Proposals from `ProposalCreate` ops — keyed by proposal_id
+    pub proposals: std::collections::HashMap<String, PendingProposal>,
+    /// Latest write of each map fragment — keyed by fragment_id (see conflict.rs)
+    pub fragment_versions: std::collections::HashMap<String, FragmentVersion>,
 }
 
 impl ErpStore {
@@ -53,55 +94,16 @@ impl ErpStore {
             accounts: Default::default(),
             postings: Default::default(),
             parties: Default::default(),
+            count_sheets: Default::default(),
+            reorder_policies: Default::default(),
+            arrays: Default::default(),
+            links: Default::default(),
+            proposals: Default::default(),
+            fragment_versions: Default::default(),
         }
     }
 }
 
-/// Apply a slice of CoA template ops into the given accounts HashMap.
-/// Processes MapSet {key=code/name/type/normal_balance} on account:{code} fragments.
-pub fn seed_coa_ops(ops: &[Op], accounts: &mut std::collections::HashMap<String, AccountRecord>) {
-    use std::collections::HashMap;
-    // Group ops by fragment_id
-    let mut groups: HashMap<String, HashMap<String, String>> = HashMap::new();
-    for op in ops {
-        if let Op::MapSet {
-            fragment_id,
-            key,
-            value,
-        } = op
-        {
-            if fragment_id.starts_with("account:") {
-                let entry = groups.entry(fragment_id.clone()).or_default();
-                entry.insert(key.clone(), value.as_str().unwrap_or("").to_string());
-            }
-        }
-    }
-    for (_, fields) in groups {
-        let code = fields.get("code").cloned().unwrap_or_default();
-        if code.is_empty() {
-            continue;
-        }
-        accounts.insert(
-            code.clone(),
-            AccountRecord {
-                code,
-                name: fields
-                    .get("name")
-                    .cloned()
-                    .unwrap_or_else(|| "(unnamed)".to_string()),
-                acct_type: fields
-                    .get("type")
-                    .cloned()
-                    .unwrap_or_else(|| "asset".to_string()),
-                normal_balance: fields
-                    .get("normal_balance")
-                    .cloned()
-                    .unwrap_or_else(|| "debit".to_string()),
-            },
-        );
-    }
-}
-
 lazy_static::lazy_static! {
     /// In-memory working set — populated from SQLite at first access via `erp::db::load_all()`
     /// when the app-data path becomes available (set by lib.rs setup via `init_erp_db`).
@@ -116,6 +118,13 @@ lazy_static::lazy_static! {
 /// Opens (creates) `corngr.db`, loads all rows into ERP_STORE, and stores the
 /// connection in ERP_DB for subsequent write-through upserts.
 pub fn init_erp_db(db_path: &std::path::Path) {
+    // Audit segments live alongside corngr.db in the app data dir
+    if let Some(app_data_dir) = db_path.parent() {
+        audit_log::configure(app_data_dir, audit_log::Rotation::Monthly);
+        conflict::configure(app_data_dir);
+        crate::erp::llm_backend::configure(app_data_dir);
+        crate::erp::anomaly::configure(app_data_dir);
+    }
     match db::init_db(db_path) {
         Ok(conn) => {
             // Warm the in-memory store from persisted data
@@ -125,11 +134,29 @@ pub fn init_erp_db(db_path: &std::path::Path) {
                     *store = loaded;
                     println!("✅ ERP store loaded from SQLite: {:?}", db_path);
                 }
-                Err(e) => eprintln!("⚠️  ERP load_all failed: {e}"),
+                Err(e) => {
+                    eprintln!("⚠️  ERP load_all failed: {e}");
+                    recover_from_audit_log();
+                }
             }
             *ERP_DB.lock().unwrap() = Some(conn);
         }
-        Err(e) => eprintln!("⚠️  ERP DB init failed: {e}"),
+        Err(e) => {
+            eprintln!("⚠️  ERP DB init failed: {e}");
+            recover_from_audit_log();
+        }
+    }
+}
+
+/// Fallback when SQLite cannot be read: rebuild ERP_STORE from the nearest
+/// signed checkpoint plus the audit entries after it.
+fn recover_from_audit_log() {
+    match crate::erp::checkpoint::recover_store() {
+        Ok(recovered) => {
+            *ERP_STORE.lock().unwrap() = recovered;
+            println!("✅ ERP store recovered from audit log checkpoints");
+        }
+        Err(e) => eprintln!("⚠️  ERP audit log recovery failed: {e}"),
     }
 }
 
@@ -149,66 +176,30 @@ pub fn create_tx(actor: &ActorContext, req: &CreateTxRequest) -> Result<TxRef, E
     };
     check_abac(actor, &Action::TxCreate, &policy_ctx)?;
 
+    // Credit / debit notes: validate the source invoice and inherit its party
+    let source_tx_id = req.source_tx_id.clone().filter(|s| !s.trim().is_empty());
+    notes::require_note_source(&tx_type, source_tx_id.as_deref())?;
+    let mut party_id = req.party_id.clone();
+    if let Some(ref src) = source_tx_id {
+        let store = ERP_STORE.lock().unwrap();
+        let source = store
+            .transactions
+            .get(src)
+            .ok_or_else(|| ErpError::ValidationFail(format!("source tx {} not found", src)))?;
+        notes::validate_note_source(&tx_type, &req.org_id, party_id.as_deref(), source)?;
+        if party_id.is_none() {
+            party_id = source.party_id.clone();
+        }
+    }
+
     // 3. Build header
-    let tx_id = Uuid::new_v4().to_string();
-    let now_ms = Utc::now().timestamp_millis();
-    let header = TxHeader {
-        tx_id: tx_id.clone(),
-        org_id: req.org_id.clone(),
-        tx_type,
-        status: TxStatus::Draft,
-        party_id: req.party_id.clone(),
-        currency: req.currency.clone(),
-        ref_number: req.ref_number.clone(),
-        description: req.description.clone(),
-        tx_date: req.tx_date.clone(),
-        created_at_ms: now_ms,
-        created_by_pubkey: actor.pubkey.clone(),
-        site_id: req.site_id.clone().unwrap_or_else(|| "primary".to_string()),
-    };
+    let header = draft_header(actor, req, tx_type, party_id, source_tx_id);
+    let tx_id = header.tx_id.clone();
 
     // 4. Build ops
-    let hdr_fragment = fragments::tx_hdr_id(&tx_id);
-    let ops = vec![
-        Op::MapSet {
-            fragment_id: hdr_fragment.clone(),
-            key: "tx_id".to_string(),
-            value: serde_json::json!(header.tx_id),
-        },
-        Op::MapSet {
-            fragment_id: hdr_fragment.clone(),
-            key: "tx_type".to_string(),
-            value: serde_json::json!(header.tx_type.as_str()),
-        },
-        Op::MapSet {
-            fragment_id: hdr_fragment.clone(),
-            key: "status".to_string(),
-            value: serde_json::json!("draft"),
-        },
-        Op::MapSet {
-            fragment_id: hdr_fragment.clone(),
-            key: "org_id".to_string(),
-            value: serde_json::json!(header.org_id),
-        },
-        Op::MapSet {
-            fragment_id: hdr_fragment.clone(),
-            key: "currency".to_string(),
-            value: serde_json::json!(header.currency),
-        },
-        Op::MapSet {
-            fragment_id: hdr_fragment.clone(),
-            key: "tx_date".to_string(),
-            value: serde_json::json!(header.tx_date),
-        },
-        Op::MapSet {
-            fragment_id: hdr_fragment,
-            key: "site_id".to_string(),
-            value: serde_json::json!(header.site_id),
-        },
-    ];
+    let ops = tx_header_ops(&header);
 
     // 5. Sign envelope + replay check
-    let mutation_id = Uuid::new_v4().to_string();
     let policy_ctx2 = PolicyContext {
         org_id: req.org_id.clone(),
         tx_id: Some(tx_id.clone()),
@@ -216,36 +207,10 @@ pub fn create_tx(actor: &ActorContext, req: &CreateTxRequest) -> Result<TxRef, E
     };
 
     let mut store = ERP_STORE.lock().unwrap();
-    let prev_hash = store
-        .actor_prev_hash
-        .get(&actor.pubkey)
-        .cloned()
-        .unwrap_or_else(|| "genesis".to_string());
-
-    store
-        .replay
-        .check_and_record(&actor.pubkey, &mutation_id, actor.lamport)?;
-
-    let envelope = MutationEnvelope::sign(mutation_id, actor, ops, policy_ctx2, prev_hash)?;
+    let envelope = sign_next(&mut store, actor, ops, policy_ctx2)?;
 
-    store
-        .actor_prev_hash
-        .insert(actor.pubkey.clone(), envelope.envelope_hash());
-
-    // 6. Store (in-memory + SQLite write-through)
-    let header_for_db = header.clone();
-    store.transactions.insert(tx_id.clone(), header);
-
-    // 7. Audit log + SQLite persist (both best-effort)
-    drop(store);
-    let _ = audit_log::append(&envelope);
-    if let Ok(db_guard) = ERP_DB.lock() {
-        if let Some(ref conn) = *db_guard {
-            if let Err(e) = db::upsert_tx(conn, &header_for_db) {
-                eprintln!("⚠️  ERP DB upsert_tx failed: {e}");
-            }
-        }
-    }
+    // 6. Apply ops → store, audit log, SQLite write-through
+    commit(store, &envelope);
 
     Ok(TxRef {
         tx_id,
@@ -254,6 +219,80 @@ pub fn create_tx(actor: &ActorContext, req: &CreateTxRequest) -> Result<TxRef, E
     })
 }
 
+fn draft_header(
+    actor: &ActorContext,
+    req: &CreateTxRequest,
+    tx_type: TxType,
+    party_id: Option<String>,
+    source_tx_id: Option<String>,
+) -> TxHeader {
+    TxHeader {
+        tx_id: Uuid::new_v4().to_string(),
+        org_id: req.org_id.clone(),
+        tx_type,
+        status: TxStatus::Draft,
+        party_id,
+        currency: req.currency.clone(),
+        ref_number: req.ref_number.clone(),
+        description: req.description.clone(),
+        tx_date: req.tx_date.clone(),
+        created_at_ms: Utc::now().timestamp_millis(),
+        created_by_pubkey: actor.pubkey.clone(),
+        site_id: req.site_id.clone().unwrap_or_else(|| "primary".to_string()),
+        source_tx_id,
+    }
+}
+
+/// Ops creating a draft tx with `lines` (their `tx_id` is ignored), built as
+/// `create_tx` + `add_line` would but not applied — what a proposal holds
+/// (see proposals.rs). Returns the new tx_id with the ops.
+pub fn draft_tx_ops(
+    actor: &ActorContext,
+    req: &CreateTxRequest,
+    lines: &[AddLineRequest],
+) -> Result<(String, Vec<Op>), ErpError> {
+    let tx_type = TxType::from_str(&req.tx_type)?;
+    notes::require_note_source(&tx_type, None)?;
+    let header = draft_header(actor, req, tx_type, req.party_id.clone(), None);
+    let mut ops = tx_header_ops(&header);
+    for (i, line_req) in lines.iter().enumerate() {
+        let line = new_line(&header.tx_id, line_req);
+        ops.extend(line_ops(&line, i as u32));
+    }
+    Ok((header.tx_id, ops))
+}
+
+/// Header field ops for a newly created draft tx — every field is logged so
+/// the header can be rebuilt from the audit log (see `apply.rs`).
+fn tx_header_ops(header: &TxHeader) -> Vec<Op> {
+    let hdr_fragment = fragments::tx_hdr_id(&header.tx_id);
+    [
+        ("tx_id", serde_json::json!(header.tx_id)),
+        ("tx_type", serde_json::json!(header.tx_type.as_str())),
+        ("status", serde_json::json!("draft")),
+        ("org_id", serde_json::json!(header.org_id)),
+        ("party_id", serde_json::json!(header.party_id)),
+        ("currency", serde_json::json!(header.currency)),
+        ("ref_number", serde_json::json!(header.ref_number)),
+        ("description", serde_json::json!(header.description)),
+        ("tx_date", serde_json::json!(header.tx_date)),
+        ("created_at_ms", serde_json::json!(header.created_at_ms)),
+        (
+            "created_by_pubkey",
+            serde_json::json!(header.created_by_pubkey),
+        ),
+        ("site_id", serde_json::json!(header.site_id)),
+        ("source_tx_id", serde_json::json!(header.source_tx_id)),
+    ]
+    .into_iter()
+    .map(|(key, value)| Op::MapSet {
+        fragment_id: hdr_fragment.clone(),
+        key: key.to_string(),
+        value,
+    })
+    .collect()
+}
+
 // ─── add_line ───────────────────────────────────────────────────────────────
 
 /// Add a business line to an existing draft transaction.
@@ -280,73 +319,89 @@ pub fn add_line(actor: &ActorContext, req: &AddLineRequest) -> Result<String, Er
         return Err(ErpError::LineImmutable(req.tx_id.clone()));
     }
 
-    let line_id = Uuid::new_v4().to_string();
-    let inv_effect = InventoryEffect::from_str(&req.inventory_effect);
+    // Header / inactive CoA accounts cannot receive lines
+    if let Some(ref acct) = req.account_id {
+        coa::validate_postable(&store.accounts, acct)?;
+    }
 
-    let line = TxLine {
-        line_id: line_id.clone(),
-        tx_id: req.tx_id.clone(),
+    // Reason codes classify stock adjustments only, and must match the direction
+    if let Some(ref reason) = req.reason_code {
+        if tx.tx_type != TxType::StockAdjust {
+            return Err(ErpError::InvalidField(format!(
+                "reason_code is only valid on stock_adjust, not {}",
+                tx.tx_type.as_str()
+            )));
+        }
+        stocktake::ReasonCode::parse(reason)?.validate_variance(req.qty)?;
+    }
+
+    let line = new_line(&req.tx_id, req);
+    let line_id = line.line_id.clone();
+
+    // Note lines must stay within what the source invoice line charged
+    if let Some(ref source_tx_id) = tx.source_tx_id {
+        let source_lines: Vec<TxLine> = store
+            .lines
+            .values()
+            .filter(|l| &l.tx_id == source_tx_id)
+            .cloned()
+            .collect();
+        let noted_lines = note_lines_for(&store, source_tx_id);
+        notes::validate_note_line(&tx.tx_type, &source_lines, &noted_lines, &line)?;
+    }
+
+    // Append after the tx's current lines
+    let index = store
+        .arrays
+        .get(&fragments::tx_lines_id(&req.tx_id))
+        .map_or(0, Vec::len);
+    let ops = line_ops(&line, index as u32);
+
+    let policy_ctx2 = PolicyContext {
+        org_id: tx.org_id.clone(),
+        tx_id: Some(tx.tx_id.clone()),
+        tx_status: Some(tx.status.clone()),
+    };
+    let envelope = sign_next(&mut store, actor, ops, policy_ctx2)?;
+    commit(store, &envelope);
+
+    Ok(line_id)
+}
+
+fn new_line(tx_id: &str, req: &AddLineRequest) -> TxLine {
+    TxLine {
+        line_id: Uuid::new_v4().to_string(),
+        tx_id: tx_id.to_string(),
         item_id: req.item_id.clone(),
         account_id: req.account_id.clone(),
         description: req.description.clone(),
         qty: req.qty,
         unit_price: req.unit_price,
-        inventory_effect: inv_effect,
+        inventory_effect: InventoryEffect::from_str(&req.inventory_effect),
         move_ids: vec![],
         tax_code: req.tax_code.clone(),
         tax_rate: req.tax_rate.unwrap_or(0.0),
-    };
+        debit_amount: req.debit_amount.unwrap_or(0.0),
+        credit_amount: req.credit_amount.unwrap_or(0.0),
+        source_line_id: req.source_line_id.clone(),
+        reason_code: req.reason_code.clone(),
+    }
+}
 
-    // Build ops
-    let line_frag = fragments::txline_id(&line_id);
-    let lines_frag = fragments::tx_lines_id(&req.tx_id);
-    let ops = vec![
+/// The line record plus its id inserted at `index` of `tx:{id}:lines`.
+fn line_ops(line: &TxLine, index: u32) -> Vec<Op> {
+    vec![
         Op::MapSet {
-            fragment_id: line_frag.clone(),
+            fragment_id: fragments::txline_id(&line.line_id),
             key: "data".to_string(),
-            value: serde_json::to_value(&line).unwrap_or_default(),
+            value: serde_json::to_value(line).unwrap_or_default(),
         },
         Op::ArrayInsert {
-            fragment_id: lines_frag,
-            index: 0,
-            values: vec![serde_json::json!(line_id)],
+            fragment_id: fragments::tx_lines_id(&line.tx_id),
+            index,
+            values: vec![serde_json::json!(line.line_id)],
         },
-    ];
-
-    let mutation_id = Uuid::new_v4().to_string();
-    let policy_ctx2 = PolicyContext {
-        org_id: tx.org_id.clone(),
-        tx_id: Some(tx.tx_id.clone()),
-        tx_status: Some(tx.status.clone()),
-    };
-    let prev_hash = store
-        .actor_prev_hash
-        .get(&actor.pubkey)
-        .cloned()
-        .unwrap_or_else(|| "genesis".to_string());
-
-    store
-        .replay
-        .check_and_record(&actor.pubkey, &mutation_id, actor.lamport)?;
-
-    let envelope = MutationEnvelope::sign(mutation_id, actor, ops, policy_ctx2, prev_hash)?;
-    store
-        .actor_prev_hash
-        .insert(actor.pubkey.clone(), envelope.envelope_hash());
-    let line_for_db = line.clone();
-    store.lines.insert(line_id.clone(), line);
-
-    drop(store);
-    let _ = audit_log::append(&envelope);
-    if let Ok(db_guard) = ERP_DB.lock() {
-        if let Some(ref conn) = *db_guard {
-            if let Err(e) = db::upsert_line(conn, &line_for_db) {
-                eprintln!("⚠️  ERP DB upsert_line failed: {e}");
-            }
-        }
-    }
-
-    Ok(line_id)
+    ]
 }
 
 // ─── create_invmove ─────────────────────────────────────────────────────────
@@ -412,53 +467,807 @@ pub fn create_invmove(
         value: serde_json::to_value(&invmove).unwrap_or_default(),
     }];
 
-    let mutation_id = Uuid::new_v4().to_string();
     let policy_ctx2 = PolicyContext {
         org_id: tx.org_id.clone(),
         tx_id: Some(tx.tx_id.clone()),
         tx_status: Some(tx.status.clone()),
     };
+    let envelope = sign_next(&mut store, actor, ops, policy_ctx2)?;
+    // Applying the move also links it onto the line's move_ids
+    commit(store, &envelope);
+
+    Ok(move_id)
+}
+
+/// Lines on every non-void note that references `source_tx_id`.
+pub(crate) fn note_lines_for(store: &ErpStore, source_tx_id: &str) -> Vec<TxLine> {
+    store
+        .lines
+        .values()
+        .filter(|l| {
+            store.transactions.get(&l.tx_id).is_some_and(|t| {
+                t.source_tx_id.as_deref() == Some(source_tx_id) && t.status != TxStatus::Void
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+fn validate_qty_sign(qty_delta: f64, effect: &InventoryEffect) -> Result<(), ErpError> {
+    match effect.expected_sign() {
+        Some(expected) if (qty_delta * expected) < 0.0 => Err(ErpError::InventoryEffectMismatch(
+            qty_delta,
+            format!("{:?}", effect).to_lowercase(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// ─── create_journal ─────────────────────────────────────────────────────────
+
+/// Create a draft manual journal with all of its lines in a single envelope.
+/// Lines are validated up front (postable accounts, one side per line,
+/// debits = credits) so a journal can never be saved unbalanced.
+pub fn create_journal(actor: &ActorContext, req: &CreateJournalRequest) -> Result<TxRef, ErpError> {
+    let policy_ctx = PolicyContext {
+        org_id: req.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    check_abac(actor, &Action::TxCreate, &policy_ctx)?;
+
+    let mut store = ERP_STORE.lock().unwrap();
+    journal::validate_journal_lines(&store.accounts, &req.lines)?;
+
+    let tx_id = Uuid::new_v4().to_string();
+    let header = TxHeader {
+        tx_id: tx_id.clone(),
+        org_id: req.org_id.clone(),
+        tx_type: TxType::Journal,
+        status: TxStatus::Draft,
+        party_id: None,
+        currency: req.currency.clone(),
+        ref_number: req.ref_number.clone(),
+        description: req.description.clone(),
+        tx_date: req.tx_date.clone(),
+        created_at_ms: Utc::now().timestamp_millis(),
+        created_by_pubkey: actor.pubkey.clone(),
+        site_id: req.site_id.clone().unwrap_or_else(|| "primary".to_string()),
+        source_tx_id: None,
+    };
+
+    let mut ops = tx_header_ops(&header);
+    let lines: Vec<TxLine> = req
+        .lines
+        .iter()
+        .map(|input| TxLine {
+            line_id: Uuid::new_v4().to_string(),
+            tx_id: tx_id.clone(),
+            item_id: None,
+            account_id: Some(input.account_id.clone()),
+            description: input.description.clone(),
+            qty: 1.0,
+            unit_price: 0.0,
+            inventory_effect: InventoryEffect::None,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: input.debit,
+            credit_amount: input.credit,
+            source_line_id: None,
+            reason_code: None,
+        })
+        .collect();
+    for (index, line) in lines.iter().enumerate() {
+        ops.extend(line_ops(line, index as u32));
+    }
+
+    let policy_ctx2 = PolicyContext {
+        org_id: req.org_id.clone(),
+        tx_id: Some(tx_id.clone()),
+        tx_status: Some(TxStatus::Draft),
+    };
+    let envelope = sign_next(&mut store, actor, ops, policy_ctx2)?;
+    commit(store, &envelope);
+
+    Ok(TxRef {
+        tx_id,
+        org_id: req.org_id.clone(),
+        status: TxStatus::Draft,
+    })
+}
+
+// ─── Stocktake ──────────────────────────────────────────────────────────────
+
+fn sheet_op(sheet: &CountSheet) -> Op {
+    Op::MapSet {
+        fragment_id: fragments::stocktake_id(&sheet.sheet_id),
+        key: "data".to_string(),
+        value: serde_json::to_value(sheet).unwrap_or_default(),
+    }
+}
+
+/// Sign, store, audit and persist a count sheet update.
+fn commit_sheet(
+    mut store: std::sync::MutexGuard<'_, ErpStore>,
+    actor: &ActorContext,
+    sheet: CountSheet,
+) -> Result<CountSheet, ErpError> {
+    let policy_ctx = PolicyContext {
+        org_id: sheet.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    let envelope = sign_next(&mut store, actor, vec![sheet_op(&sheet)], policy_ctx)?;
+    commit(store, &envelope);
+    Ok(sheet)
+}
+
+/// Freeze a count sheet from current stock on hand for a site / location.
+pub fn freeze_count_sheet(
+    actor: &ActorContext,
+    req: &FreezeCountSheetRequest,
+) -> Result<CountSheet, ErpError> {
+    let policy_ctx = PolicyContext {
+        org_id: req.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    check_abac(actor, &Action::InvMoveCreate, &policy_ctx)?;
+
+    let site_id = req.site_id.clone().unwrap_or_else(|| "primary".to_string());
+    let location_id = req.location_id.clone().filter(|l| !l.trim().is_empty());
+
+    let store = ERP_STORE.lock().unwrap();
+    if let Some(open) = store.count_sheets.values().find(|s| {
+        s.org_id == req.org_id
+            && s.site_id == site_id
+            && s.status == CountSheetStatus::Open
+            && (s.location_id.is_none() || location_id.is_none() || s.location_id == location_id)
+    }) {
+        return Err(ErpError::ValidationFail(format!(
+            "count sheet {} is already open for this site",
+            open.sheet_id
+        )));
+    }
+
+    let soh = stocktake::stock_on_hand(
+        &store.transactions,
+        &store.invmoves,
+        &req.org_id,
+        &site_id,
+        location_id.as_deref(),
+    );
+    let costs = stocktake::last_receipt_costs(&store.transactions, &store.lines);
+    let sheet = CountSheet {
+        sheet_id: Uuid::new_v4().to_string(),
+        org_id: req.org_id.clone(),
+        site_id,
+        location_id,
+        status: CountSheetStatus::Open,
+        frozen_at_ms: Utc::now().timestamp_millis(),
+        frozen_by_pubkey: actor.pubkey.clone(),
+        lines: stocktake::freeze_lines(&soh, &costs),
+        adjust_tx_id: None,
+    };
+    commit_sheet(store, actor, sheet)
+}
+
+/// Enter a counted quantity on an open count sheet.
+pub fn record_count(
+    actor: &ActorContext,
+    req: &RecordCountRequest,
+) -> Result<CountSheet, ErpError> {
+    let store = ERP_STORE.lock().unwrap();
+    let mut sheet = store
+        .count_sheets
+        .get(&req.sheet_id)
+        .ok_or_else(|| ErpError::ValidationFail(format!("count sheet {} not found", req.sheet_id)))?
+        .clone();
+    let policy_ctx = PolicyContext {
+        org_id: sheet.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    check_abac(actor, &Action::InvMoveCreate, &policy_ctx)?;
+
+    // Found stock is valued at the given cost, else its last receipt price
+    let found_cost = req.unit_cost.or_else(|| {
+        stocktake::last_receipt_costs(&store.transactions, &store.lines)
+            .get(&req.item_id)
+            .copied()
+    });
+    let reason = req
+        .reason_code
+        .as_deref()
+        .map(stocktake::ReasonCode::parse)
+        .transpose()?;
+    stocktake::record_count(
+        &mut sheet,
+        &req.item_id,
+        req.location_id.as_deref(),
+        req.counted_qty,
+        reason,
+        found_cost,
+    )?;
+    commit_sheet(store, actor, sheet)
+}
+
+/// The currency of the org's most recently created tx (the ledger has no
+/// org-level setting), or AUD for an empty ledger.
+fn org_currency(store: &ErpStore, org_id: &str) -> String {
+    store
+        .transactions
+        .values()
+        .filter(|t| t.org_id == org_id)
+        .max_by_key(|t| t.created_at_ms)
+        .map_or_else(|| "AUD".to_string(), |t| t.currency.clone())
+}
+
+/// Turn a fully counted sheet into one draft `stock_adjust` tx: a line and an
+/// InvMove per non-zero variance, tagged with its reason code.
+pub fn reconcile_count_sheet(
+    actor: &ActorContext,
+    req: &ReconcileCountSheetRequest,
+) -> Result<TxRef, ErpError> {
+    let mut store = ERP_STORE.lock().unwrap();
+    let mut sheet = store
+        .count_sheets
+        .get(&req.sheet_id)
+        .ok_or_else(|| ErpError::ValidationFail(format!("count sheet {} not found", req.sheet_id)))?
+        .clone();
+    let policy_ctx = PolicyContext {
+        org_id: sheet.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    check_abac(actor, &Action::TxCreate, &policy_ctx)?;
+    check_abac(actor, &Action::InvMoveCreate, &policy_ctx)?;
+    if sheet.status != CountSheetStatus::Open {
+        return Err(ErpError::ValidationFail(format!(
+            "count sheet {} is already reconciled",
+            sheet.sheet_id
+        )));
+    }
+
+    let variances = stocktake::variances(&sheet)?;
+    if variances.is_empty() {
+        return Err(ErpError::ValidationFail(
+            "count matches stock on hand — nothing to adjust".to_string(),
+        ));
+    }
+
+    let tx_id = Uuid::new_v4().to_string();
+    let now_ms = Utc::now().timestamp_millis();
+    let header = TxHeader {
+        tx_id: tx_id.clone(),
+        org_id: sheet.org_id.clone(),
+        tx_type: TxType::StockAdjust,
+        status: TxStatus::Draft,
+        party_id: None,
+        currency: req
+            .currency
+            .clone()
+            .unwrap_or_else(|| org_currency(&store, &sheet.org_id)),
+        ref_number: req.ref_number.clone(),
+        description: req
+            .description
+            .clone()
+            .or_else(|| Some(format!("Stocktake {}", sheet.sheet_id))),
+        tx_date: req.tx_date.clone(),
+        created_at_ms: now_ms,
+        created_by_pubkey: actor.pubkey.clone(),
+        site_id: sheet.site_id.clone(),
+        source_tx_id: None,
+    };
+
+    let mut ops = tx_header_ops(&header);
+    let mut lines = Vec::new();
+    let mut moves = Vec::new();
+    for (index, v) in variances.iter().enumerate() {
+        let line_id = Uuid::new_v4().to_string();
+        let move_id = Uuid::new_v4().to_string();
+        let line = TxLine {
+            line_id: line_id.clone(),
+            tx_id: tx_id.clone(),
+            item_id: Some(v.item_id.clone()),
+            account_id: None,
+            description: Some(format!("Stocktake variance - {}", v.reason.as_str())),
+            qty: v.qty,
+            unit_price: v.unit_cost,
+            inventory_effect: if v.qty > 0.0 {
+                InventoryEffect::Increase
+            } else {
+                InventoryEffect::Decrease
+            },
+            move_ids: vec![move_id.clone()],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: Some(v.reason.as_str().to_string()),
+        };
+        let invmove = InvMove {
+            move_id: move_id.clone(),
+            tx_id: tx_id.clone(),
+            tx_line_id: line_id.clone(),
+            item_id: v.item_id.clone(),
+            qty_delta: v.qty,
+            location_id: v.location_id.clone(),
+            moved_at_ms: now_ms,
+            moved_by_pubkey: actor.pubkey.clone(),
+            site_id: sheet.site_id.clone(),
+        };
+        ops.extend(line_ops(&line, index as u32));
+        ops.push(Op::MapSet {
+            fragment_id: fragments::invmove_id(&move_id),
+            key: "data".to_string(),
+            value: serde_json::to_value(&invmove).unwrap_or_default(),
+        });
+        lines.push(line);
+        moves.push(invmove);
+    }
+
+    sheet.status = CountSheetStatus::Reconciled;
+    sheet.adjust_tx_id = Some(tx_id.clone());
+    ops.push(sheet_op(&sheet));
+
+    let policy_ctx2 = PolicyContext {
+        org_id: sheet.org_id.clone(),
+        tx_id: Some(tx_id.clone()),
+        tx_status: Some(TxStatus::Draft),
+    };
+    let envelope = sign_next(&mut store, actor, ops, policy_ctx2)?;
+
+    commit(store, &envelope);
+
+    Ok(TxRef {
+        tx_id,
+        org_id: sheet.org_id,
+        status: TxStatus::Draft,
+    })
+}
+
+// ─── Party master ───────────────────────────────────────────────────────────
+
+/// Create a party as a signed `party:{id}` envelope.
+pub fn create_party(actor: &ActorContext, req: &CreatePartyRequest) -> Result<Party, ErpError> {
+    if req.name.trim().is_empty() {
+        return Err(ErpError::ValidationFail(
+            "party name must not be empty".to_string(),
+        ));
+    }
+    let non_empty = |v: &Option<String>| v.clone().filter(|s| !s.is_empty());
+    let party = Party {
+        party_id: format!("party_{}", Uuid::new_v4().simple()),
+        org_id: req.org_id.clone(),
+        name: req.name.trim().to_string(),
+        kind: PartyKind::from_str(&req.kind),
+        email: non_empty(&req.email),
+        contact: non_empty(&req.contact),
+        abn: non_empty(&req.abn),
+        created_at_ms: Utc::now().timestamp_millis(),
+    };
+
+    let policy_ctx = PolicyContext {
+        org_id: req.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    let mut store = ERP_STORE.lock().unwrap();
+    let envelope = sign_next(&mut store, actor, apply::party_ops(&party), policy_ctx)?;
+    commit(store, &envelope);
+    Ok(party)
+}
+
+// ─── Remote envelopes ───────────────────────────────────────────────────────
+
+/// Apply an envelope signed on another node: verify its signature, run the
+/// replay guard, check it continues the actor's prev_hash chain and was not
+/// based on stale fragments (`conflict::check`), run `check` against the
+/// store under the same lock, then commit it through `apply_ops` exactly
+/// like a local mutation. `check` carries the signer's authorisation — use
+/// `satellite::accept_envelope` rather than calling this directly.
+pub fn apply_remote_envelope_checked<F>(
+    envelope: &MutationEnvelope,
+    check: F,
+) -> Result<(), ErpError>
+where
+    F: FnOnce(&ErpStore) -> Result<(), ErpError>,
+{
+    envelope.verify()?;
+
+    let mut store = ERP_STORE.lock().unwrap();
+    let expected_prev = store
+        .actor_prev_hash
+        .get(&envelope.actor_pubkey)
+        .cloned()
+        .unwrap_or_else(|| "genesis".to_string());
+    if envelope.prev_hash != expected_prev {
+        return Err(ErpError::ValidationFail(format!(
+            "envelope {} does not continue actor chain (prev_hash {})",
+            envelope.mutation_id, envelope.prev_hash
+        )));
+    }
+    if let Err(e) = conflict::check(&store, envelope) {
+        conflict::record(&store, envelope);
+        return Err(e);
+    }
+    check(&store)?;
+    store.replay.check_and_record(
+        &envelope.actor_pubkey,
+        &envelope.mutation_id,
+        envelope.lamport,
+    )?;
+    store
+        .actor_prev_hash
+        .insert(envelope.actor_pubkey.clone(), envelope.envelope_hash());
+    commit_envelope(store, envelope, false);
+    Ok(())
+}
+
+/// Validate `ops` for `actor` as a remote edit would be (per-op ABAC and
+/// business checks, `satellite::validate_ops`), then sign and commit them.
+/// Used to apply conflict resolutions and proposal decisions.
+pub fn apply_validated(actor: &ActorContext, ops: Vec<Op>) -> Result<MutationEnvelope, ErpError> {
+    let mut store = ERP_STORE.lock().unwrap();
+    satellite::validate_ops(&store, actor, &ops)?;
+    let policy_ctx = PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    let envelope = sign_next(&mut store, actor, ops, policy_ctx)?;
+    commit(store, &envelope);
+    Ok(envelope)
+}
+
+// ─── Sign & commit ──────────────────────────────────────────────────────────
+
+/// Sign `ops` as the actor's next envelope: allocates a mutation_id and the
+/// actor's next clock (`hlc::assign`; the caller's `lamport` is ignored), runs
+/// the replay guard and advances the actor's prev_hash chain.
+pub(crate) fn sign_next(
+    store: &mut ErpStore,
+    actor: &ActorContext,
+    ops: Vec<Op>,
+    policy_ctx: PolicyContext,
+) -> Result<MutationEnvelope, ErpError> {
+    let mutation_id = Uuid::new_v4().to_string();
     let prev_hash = store
         .actor_prev_hash
         .get(&actor.pubkey)
         .cloned()
         .unwrap_or_else(|| "genesis".to_string());
 
+    let actor = &ActorContext {
+        lamport: hlc::assign(store, &actor.pubkey),
+        ..actor.clone()
+    };
     store
         .replay
         .check_and_record(&actor.pubkey, &mutation_id, actor.lamport)?;
 
-    let envelope = MutationEnvelope::sign(mutation_id, actor, ops, policy_ctx2, prev_hash)?;
+    let base = conflict::base_versions(store, &ops);
+    let envelope = MutationEnvelope::sign(mutation_id, actor, ops, policy_ctx, prev_hash, base)?;
     store
         .actor_prev_hash
         .insert(actor.pubkey.clone(), envelope.envelope_hash());
-    let invmove_for_db = invmove.clone();
-    store.invmoves.insert(move_id.clone(), invmove);
-
-    // Update line's move_ids
-    if let Some(l) = store.lines.get_mut(&req.tx_line_id) {
-        l.move_ids.push(move_id.clone());
-    }
+    Ok(envelope)
+}
 
-    drop(store);
-    let _ = audit_log::append(&envelope);
+/// Run a best-effort SQLite write-through against ERP_DB (no-op before init).
+pub(crate) fn persist<F>(what: &str, f: F)
+where
+    F: FnOnce(&Connection) -> rusqlite::Result<()>,
+{
     if let Ok(db_guard) = ERP_DB.lock() {
         if let Some(ref conn) = *db_guard {
-            if let Err(e) = db::upsert_invmove(conn, &invmove_for_db) {
-                eprintln!("⚠️  ERP DB upsert_invmove failed: {e}");
+            if let Err(e) = f(conn) {
+                eprintln!("⚠️  ERP DB {what} failed: {e}");
             }
         }
     }
+}
 
-    Ok(move_id)
+/// Apply a signed envelope to the store through `apply::apply_ops` — the same
+/// path audit replay and remote envelopes take — then append it to the audit
+/// log, mirror it into the org's yrs doc and write the touched records
+/// through to SQLite.
+pub(crate) fn commit(store: std::sync::MutexGuard<'_, ErpStore>, envelope: &MutationEnvelope) {
+    commit_envelope(store, envelope, true);
 }
 
-fn validate_qty_sign(qty_delta: f64, effect: &InventoryEffect) -> Result<(), ErpError> {
-    match effect.expected_sign() {
-        Some(expected) if (qty_delta * expected) < 0.0 => Err(ErpError::InventoryEffectMismatch(
-            qty_delta,
-            format!("{:?}", effect).to_lowercase(),
-        )),
-        _ => Ok(()),
+/// `commit`; `local` envelopes were signed on this node and are queued for
+/// the primary when running as a satellite.
+fn commit_envelope(
+    mut store: std::sync::MutexGuard<'_, ErpStore>,
+    envelope: &MutationEnvelope,
+    local: bool,
+) {
+    let touched = apply::touched(&envelope.ops);
+    let mut line_ids = touched.lines.clone();
+    for fragment_id in &touched.line_arrays {
+        if let Some(arr) = store.arrays.get(fragment_id) {
+            line_ids.extend(arr.iter().filter_map(|v| v.as_str().map(str::to_string)));
+        }
     }
+
+    let versions = conflict::bump(&mut store, envelope);
+    apply::apply_ops(&mut store, &envelope.ops);
+
+    let mut removed = Vec::new();
+    let txs: Vec<TxHeader> = touched
+        .txs
+        .iter()
+        .filter_map(|id| store.transactions.get(id).cloned())
+        .collect();
+    let lines = rows_or_removed(
+        &line_ids,
+        &store.lines,
+        ("tx_lines", "line_id"),
+        &mut removed,
+    );
+    let moves = rows_or_removed(
+        &touched.moves,
+        &store.invmoves,
+        ("inv_moves", "move_id"),
+        &mut removed,
+    );
+    let postings = rows_or_removed(
+        &touched.postings,
+        &store.postings,
+        ("postings", "posting_id"),
+        &mut removed,
+    );
+    let sheets = rows_or_removed(
+        &touched.sheets,
+        &store.count_sheets,
+        ("count_sheets", "sheet_id"),
+        &mut removed,
+    );
+    let policies = rows_or_removed(
+        &touched.reorder_policies,
+        &store.reorder_policies,
+        ("reorder_policies", "policy_key"),
+        &mut removed,
+    );
+    let accounts: Vec<AccountRecord> = touched
+        .accounts
+        .iter()
+        .filter_map(|code| store.accounts.get(code).cloned())
+        .collect();
+    let parties: Vec<Party> = touched
+        .parties
+        .iter()
+        .filter_map(|id| store.parties.get(id).cloned())
+        .collect();
+    let proposals: Vec<PendingProposal> = touched
+        .proposals
+        .iter()
+        .filter_map(|id| store.proposals.get(id).cloned())
+        .collect();
+    drop(store);
+
+    ydoc::append_and_mirror(envelope, || {
+        let _ = audit_log::append(envelope);
+        if local {
+            satellite::queue_local(envelope);
+        }
+    });
+    persist("upsert_tx", |conn| {
+        txs.iter().try_for_each(|t| db::upsert_tx(conn, t))
+    });
+    persist("upsert_line", |conn| {
+        lines.iter().try_for_each(|l| db::upsert_line(conn, l))
+    });
+    persist("upsert_invmove", |conn| {
+        moves.iter().try_for_each(|m| db::upsert_invmove(conn, m))
+    });
+    persist("upsert_posting", |conn| {
+        postings
+            .iter()
+            .try_for_each(|p| db::upsert_posting(conn, p))
+    });
+    persist("upsert_account", |conn| {
+        accounts
+            .iter()
+            .try_for_each(|a| db::upsert_account(conn, a))
+    });
+    persist("upsert_party", |conn| {
+        parties.iter().try_for_each(|p| db::upsert_party(conn, p))
+    });
+    persist("upsert_count_sheet", |conn| {
+        sheets
+            .iter()
+            .try_for_each(|s| db::upsert_count_sheet(conn, s))
+    });
+    persist("upsert_reorder_policy", |conn| {
+        policies
+            .iter()
+            .try_for_each(|p| db::upsert_reorder_policy(conn, p))
+    });
+    persist("upsert_fragment_version", |conn| {
+        versions
+            .iter()
+            .try_for_each(|(f, v)| db::upsert_fragment_version(conn, f, v))
+    });
+    persist("upsert_proposal", |conn| {
+        proposals
+            .iter()
+            .try_for_each(|p| db::upsert_proposal(conn, p))
+    });
+    persist("upsert_actor_clock", |conn| {
+        db::upsert_actor_clock(conn, &envelope.actor_pubkey, envelope.lamport)
+    });
+    persist("delete_row", |conn| {
+        removed
+            .iter()
+            .try_for_each(|(table, key, id)| db::delete_row(conn, table, key, id))
+    });
+}
+
+/// Clone the records in `map` for `ids`; ids no longer present are queued
+/// for deletion from `table`.
+fn rows_or_removed<T: Clone>(
+    ids: &std::collections::BTreeSet<String>,
+    map: &std::collections::HashMap<String, T>,
+    (table, key_col): (&'static str, &'static str),
+    removed: &mut Vec<(&'static str, &'static str, String)>,
+) -> Vec<T> {
+    let mut rows = Vec::new();
+    for id in ids {
+        match map.get(id) {
+            Some(row) => rows.push(row.clone()),
+            None => removed.push((table, key_col, id.clone())),
+        }
+    }
+    rows
+}
+
+// ─── CoA management ─────────────────────────────────────────────────────────
+
+fn coa_policy(actor: &ActorContext) -> PolicyContext {
+    PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    }
+}
+
+/// Sign, store, audit and persist an updated account record.
+fn commit_account(
+    mut store: std::sync::MutexGuard<'_, ErpStore>,
+    actor: &ActorContext,
+    ops: Vec<Op>,
+    record: AccountRecord,
+) -> Result<AccountRecord, ErpError> {
+    let envelope = sign_next(&mut store, actor, ops, coa_policy(actor))?;
+    commit(store, &envelope);
+    Ok(record)
+}
+
+/// Add a new account to the Chart of Accounts.
+pub fn add_account(
+    actor: &ActorContext,
+    req: &CreateAccountRequest,
+) -> Result<AccountRecord, ErpError> {
+    check_abac(actor, &Action::AccountManage, &coa_policy(actor))?;
+
+    let code = req.code.trim().to_string();
+    let name = req.name.trim().to_string();
+    if code.is_empty() || name.is_empty() {
+        return Err(ErpError::ValidationFail(
+            "account code and name must not be empty".to_string(),
+        ));
+    }
+    coa::validate_type_balance(&req.acct_type, &req.normal_balance)?;
+
+    let store = ERP_STORE.lock().unwrap();
+    if store.accounts.contains_key(&code) {
+        return Err(ErpError::ValidationFail(format!(
+            "account {} already exists",
+            code
+        )));
+    }
+    let parent_code = req.parent_code.clone().filter(|p| !p.trim().is_empty());
+    if let Some(ref parent) = parent_code {
+        coa::validate_parent(&store.accounts, &code, parent, &req.acct_type)?;
+    }
+    let role = req.role.clone().filter(|r| !r.trim().is_empty());
+    if let Some(ref r) = role {
+        coa::validate_role(&store.accounts, &code, r)?;
+    }
+
+    let record = AccountRecord {
+        code,
+        name,
+        acct_type: req.acct_type.clone(),
+        normal_balance: req.normal_balance.clone(),
+        parent_code,
+        is_header: req.is_header.unwrap_or(false),
+        active: true,
+        role,
+    };
+    let ops = coa::account_ops(&record);
+    commit_account(store, actor, ops, record)
+}
+
+/// Rename an existing account. The code is immutable — postings reference it.
+/// An unbound account that symbolic postings reach by name gets that role
+/// bound in the same envelope (`coa::role_implied_by_name`).
+pub fn rename_account(
+    actor: &ActorContext,
+    code: &str,
+    name: &str,
+) -> Result<AccountRecord, ErpError> {
+    check_abac(actor, &Action::AccountManage, &coa_policy(actor))?;
+
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ErpError::ValidationFail(
+            "account name must not be empty".to_string(),
+        ));
+    }
+
+    let store = ERP_STORE.lock().unwrap();
+    let mut record = store
+        .accounts
+        .get(code)
+        .ok_or_else(|| ErpError::ValidationFail(format!("account {} not found", code)))?
+        .clone();
+    let mut ops = Vec::new();
+    if let Some(role) = coa::role_implied_by_name(&store.accounts, code) {
+        record.role = Some(role.to_string());
+        ops.push(coa::account_field_op(code, "role", serde_json::json!(role)));
+    }
+    record.name = name.to_string();
+    ops.push(coa::account_field_op(code, "name", serde_json::json!(name)));
+    commit_account(store, actor, ops, record)
+}
+
+/// Deactivate an account. Rejected while it carries a balance or has active children.
+pub fn deactivate_account(actor: &ActorContext, code: &str) -> Result<AccountRecord, ErpError> {
+    check_abac(actor, &Action::AccountManage, &coa_policy(actor))?;
+
+    let store = ERP_STORE.lock().unwrap();
+    coa::validate_deactivate(&store.accounts, &store.postings, code)?;
+    let mut record = store.accounts[code].clone();
+    record.active = false;
+
+    let ops = vec![coa::account_field_op(
+        code,
+        "active",
+        serde_json::json!(false),
+    )];
+    commit_account(store, actor, ops, record)
+}
+
+/// Move an account under a different header account (`None` = top level).
+pub fn reparent_account(
+    actor: &ActorContext,
+    code: &str,
+    parent_code: Option<&str>,
+) -> Result<AccountRecord, ErpError> {
+    check_abac(actor, &Action::AccountManage, &coa_policy(actor))?;
+
+    let store = ERP_STORE.lock().unwrap();
+    let mut record = store
+        .accounts
+        .get(code)
+        .ok_or_else(|| ErpError::ValidationFail(format!("account {} not found", code)))?
+        .clone();
+    let parent_code = parent_code.map(str::trim).filter(|p| !p.is_empty());
+    if let Some(parent) = parent_code {
+        coa::validate_parent(&store.accounts, code, parent, &record.acct_type)?;
+    }
+    record.parent_code = parent_code.map(str::to_string);
+
+    let ops = vec![coa::account_field_op(
+        code,
+        "parent_code",
+        serde_json::json!(record.parent_code),
+    )];
+    commit_account(store, actor, ops, record)
 }
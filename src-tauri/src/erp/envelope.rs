@@ -1,6 +1,7 @@
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
@@ -14,9 +15,11 @@ pub struct MutationEnvelope {
     pub envelope_version: String, // "1"
     pub org_id: String,
     pub mutation_id: String, // ULID string
-    /// Hex-encoded Ed25519 public key of the acting user.
+    /// Identity of the acting user (`ActorContext::pubkey`); replay guard and
+    /// prev_hash chains are kept per actor.
     pub actor_pubkey: String,
-    /// Optional hex-encoded device public key for dual-key audit trail.
+    /// Hex-encoded Ed25519 public key of the node that signed the envelope.
+    /// `None` for envelopes signed with the actor's own key.
     pub device_pubkey: Option<String>,
     pub issued_at_ms: i64,
     /// Per-actor monotonic Lamport clock.
@@ -26,6 +29,11 @@ pub struct MutationEnvelope {
     pub prev_hash: String,
     pub capability_token_id: Option<String>,
     pub ops: Vec<Op>,
+    /// Versions of the fragments the ops were based on, as the actor saw them
+    /// (`conflict::base_versions`). Signed; omitted when empty so envelopes
+    /// from before conflict detection keep their signature and hash.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub base_versions: BTreeMap<String, u64>,
     pub policy_context: PolicyContext,
     /// Hex-encoded SHA-256 of the canonical ops JSON (sorted keys).
     pub content_hash: String,
@@ -42,6 +50,7 @@ impl MutationEnvelope {
         ops: Vec<Op>,
         policy_context: PolicyContext,
         prev_hash: String,
+        base_versions: BTreeMap<String, u64>,
     ) -> Result<Self, ErpError> {
         let now_ms = chrono::Utc::now().timestamp_millis();
 
@@ -54,30 +63,47 @@ impl MutationEnvelope {
 
         // 2. Build signing payload:
         //    "1" || content_hash || prev_hash || lamport_be_bytes || issued_at_ms_be_bytes
-        let payload = build_signing_payload("1", &content_hash, &prev_hash, actor.lamport, now_ms);
+        //    [|| base_versions JSON, when non-empty]
+        let payload = build_signing_payload(
+            "1",
+            &content_hash,
+            &prev_hash,
+            actor.lamport,
+            now_ms,
+            &base_versions,
+        );
 
-        // 3. Sign with node key
+        // 3. Sign with node key, recorded as the device key
         let signing_key = get_signing_key();
         let signature: Signature = signing_key.sign(&payload);
+        let device_pubkey = hex::encode(signing_key.verifying_key().to_bytes());
 
         Ok(MutationEnvelope {
             envelope_version: "1".to_string(),
             org_id: actor.org_id.clone(),
             mutation_id,
             actor_pubkey: actor.pubkey.clone(),
-            device_pubkey: None,
+            device_pubkey: Some(device_pubkey),
             issued_at_ms: now_ms,
             lamport: actor.lamport,
             prev_hash,
             capability_token_id: None,
             ops,
+            base_versions,
             policy_context,
             content_hash,
             signature: hex::encode(signature.to_bytes()),
         })
     }
 
-    /// Verify the envelope's signature and content_hash integrity.
+    /// Hex public key the signature verifies against: the signing node's
+    /// `device_pubkey`, else `actor_pubkey`.
+    pub fn signer_pubkey(&self) -> &str {
+        self.device_pubkey.as_deref().unwrap_or(&self.actor_pubkey)
+    }
+
+    /// Verify the envelope's signature (against `signer_pubkey`) and
+    /// content_hash integrity. Whether that key is trusted is up to the caller.
     pub fn verify(&self) -> Result<(), ErpError> {
         // 1. Recompute content_hash
         let ops_json = serde_json::to_string(&self.ops)
@@ -100,14 +126,15 @@ impl MutationEnvelope {
             &self.prev_hash,
             self.lamport,
             self.issued_at_ms,
+            &self.base_versions,
         );
 
-        // 3. Verify signature against actor_pubkey
-        let pubkey_bytes = hex::decode(&self.actor_pubkey)
-            .map_err(|e| ErpError::SigInvalid(format!("invalid actor_pubkey hex: {}", e)))?;
+        // 3. Verify signature against the signer key
+        let pubkey_bytes = hex::decode(self.signer_pubkey())
+            .map_err(|e| ErpError::SigInvalid(format!("invalid signer pubkey hex: {}", e)))?;
         let pubkey_arr: [u8; 32] = pubkey_bytes
             .try_into()
-            .map_err(|_| ErpError::SigInvalid("actor_pubkey must be 32 bytes".to_string()))?;
+            .map_err(|_| ErpError::SigInvalid("signer pubkey must be 32 bytes".to_string()))?;
         let verifying_key = VerifyingKey::from_bytes(&pubkey_arr)
             .map_err(|e| ErpError::SigInvalid(format!("invalid pubkey: {}", e)))?;
 
@@ -125,6 +152,27 @@ impl MutationEnvelope {
         Ok(())
     }
 
+    /// Re-sign with `signing_key` after changing a signed field such as
+    /// `base_versions` (the content hash is recomputed too).
+    pub fn resign(&mut self, signing_key: &SigningKey) -> Result<(), ErpError> {
+        let ops_json = serde_json::to_string(&self.ops)
+            .map_err(|e| ErpError::ValidationFail(format!("ops serialization: {}", e)))?;
+        self.content_hash = hex::encode(Sha256::digest(ops_json.as_bytes()));
+        let payload = build_signing_payload(
+            &self.envelope_version,
+            &self.content_hash,
+            &self.prev_hash,
+            self.lamport,
+            self.issued_at_ms,
+            &self.base_versions,
+        );
+        self.signature = hex::encode(signing_key.sign(&payload).to_bytes());
+        if self.device_pubkey.is_some() {
+            self.device_pubkey = Some(hex::encode(signing_key.verifying_key().to_bytes()));
+        }
+        Ok(())
+    }
+
     /// Returns the SHA-256 hash of this envelope (used as prev_hash for the next envelope).
     pub fn envelope_hash(&self) -> String {
         let json = serde_json::to_string(self).unwrap_or_default();
@@ -140,6 +188,7 @@ fn build_signing_payload(
     prev_hash: &str,
     lamport: u64,
     issued_at_ms: i64,
+    base_versions: &BTreeMap<String, u64>,
 ) -> Vec<u8> {
     let mut payload = Vec::new();
     payload.extend_from_slice(version.as_bytes());
@@ -147,6 +196,13 @@ fn build_signing_payload(
     payload.extend_from_slice(prev_hash.as_bytes());
     payload.extend_from_slice(&lamport.to_be_bytes());
     payload.extend_from_slice(&issued_at_ms.to_be_bytes());
+    if !base_versions.is_empty() {
+        payload.extend_from_slice(
+            serde_json::to_string(base_versions)
+                .unwrap_or_default()
+                .as_bytes(),
+        );
+    }
     payload
 }
 
@@ -170,7 +226,14 @@ pub fn sign_with_key(
     hasher.update(ops_json.as_bytes());
     let content_hash = hex::encode(hasher.finalize());
 
-    let payload = build_signing_payload("1", &content_hash, &prev_hash, lamport, now_ms);
+    let payload = build_signing_payload(
+        "1",
+        &content_hash,
+        &prev_hash,
+        lamport,
+        now_ms,
+        &BTreeMap::new(),
+    );
     let signature: Signature = signing_key.sign(&payload);
 
     Ok(MutationEnvelope {
@@ -184,6 +247,7 @@ pub fn sign_with_key(
         prev_hash,
         capability_token_id: None,
         ops,
+        base_versions: BTreeMap::new(),
         policy_context,
         content_hash,
         signature: hex::encode(signature.to_bytes()),
@@ -41,7 +41,7 @@ pub enum ErpError {
     #[error("ERR_REPLAY_MUTATION_ID: duplicate mutation {0}")]
     ReplayMutationId(String),
 
-    #[error("ERR_LAMPORT_REWIND: received {0} but max seen is {1}")]
+    #[error("ERR_LAMPORT_REWIND: received {0} but max seen is {1} (fetch the next clock with erp_actor_clock)")]
     LamportRewind(u64, u64),
 
     #[error("ERR_POSTINGS_MISSING: tx {0} has no finalized postings")]
@@ -49,6 +49,15 @@ pub enum ErpError {
 
     #[error("ERR_LINE_IMMUTABLE: line {0} is on a posted tx and cannot be modified")]
     LineImmutable(String),
+
+    #[error("ERR_NOTE_EXCEEDS_SOURCE: {0} {1:.4} exceeds remaining {2:.4} on source invoice line")]
+    NoteExceedsSource(String, f64, f64),
+
+    #[error("ERR_CONFLICT: {0} was changed concurrently (edit based on version {1}, current version {2})")]
+    Conflict(String, u64, u64),
+
+    #[error("ERR_NOT_ENROLLED: {0}")]
+    NotEnrolled(String),
 }
 
 impl ErpError {
@@ -70,6 +79,9 @@ impl ErpError {
             ErpError::LamportRewind(_, _) => "ERR_LAMPORT_REWIND",
             ErpError::PostingsMissing(_) => "ERR_POSTINGS_MISSING",
             ErpError::LineImmutable(_) => "ERR_LINE_IMMUTABLE",
+            ErpError::NoteExceedsSource(_, _, _) => "ERR_NOTE_EXCEEDS_SOURCE",
+            ErpError::Conflict(_, _, _) => "ERR_CONFLICT",
+            ErpError::NotEnrolled(_) => "ERR_NOT_ENROLLED",
         }
     }
 }
@@ -0,0 +1,561 @@
+//! evidence.rs — Offline-verifiable auditor evidence bundles
+//!
+//! An evidence bundle is a plain ustar archive holding:
+//!
+//!   manifest.json  — org, time range, entry count, chain endpoints, the
+//!                    SHA-256 of every other file, signed by the exporting node
+//!   entries.jsonl  — the contiguous run of `ErpAuditEntry` records in range
+//!   keys.json      — every signer public key needed to verify the envelopes
+//!   anchors.json   — Merkle inclusion proofs of the first and last entry
+//!                    against a signed root
+//!
+//! `verify_bundle` re-checks the manifest signature and file hashes, every
+//! envelope's content hash and signature, chain continuity between the
+//! manifest endpoints, and the Merkle anchors (whose root must be signed by
+//! the exporter) — using only the archive.
+
+use ed25519_dalek::{Signature, Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::erp::audit_log::{self, ErpAuditEntry};
+use crate::erp::errors::ErpError;
+use crate::erp::merkle::{self, InclusionProof, SignedRoot};
+
+pub const BUNDLE_FORMAT: &str = "corngr-evidence/1";
+
+const ENTRIES_FILE: &str = "entries.jsonl";
+const KEYS_FILE: &str = "keys.json";
+const ANCHORS_FILE: &str = "anchors.json";
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// A file in the bundle as listed by the manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleFile {
+    pub name: String,
+    pub bytes: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: String,
+    pub org_id: String,
+    pub from_ms: i64,
+    pub to_ms: i64,
+    pub created_at_ms: i64,
+    /// 1-based position of the first exported entry in the org's log
+    pub first_seq: u64,
+    pub entry_count: u64,
+    /// chain_prev_hash of the first entry
+    pub start_prev_hash: String,
+    /// chain_hash of the last entry
+    pub end_hash: String,
+    pub files: Vec<BundleFile>,
+    /// Hex-encoded Ed25519 public key of the exporting node.
+    pub exporter_pubkey: String,
+    /// Signature over the manifest JSON with this field empty.
+    pub signature: String,
+}
+
+/// A key the exported envelopes are signed with (`MutationEnvelope::signer_pubkey`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRecord {
+    pub pubkey: String,
+    pub envelope_count: u64,
+    pub first_seq: u64,
+    pub last_seq: u64,
+}
+
+/// Ties the exported run of entries to a published Merkle root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Anchors {
+    pub signed_root: SignedRoot,
+    pub first: InclusionProof,
+    pub last: InclusionProof,
+}
+
+/// Result of `verify_bundle`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvidenceReport {
+    pub manifest: Option<Manifest>,
+    pub entries_checked: usize,
+    pub anchored: bool,
+    pub issues: Vec<String>,
+}
+
+impl EvidenceReport {
+    pub fn is_valid(&self) -> bool {
+        self.manifest.is_some() && self.issues.is_empty()
+    }
+}
+
+fn sha_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+fn manifest_payload(manifest: &Manifest) -> Vec<u8> {
+    let mut unsigned = manifest.clone();
+    unsigned.signature = String::new();
+    serde_json::to_vec(&unsigned).unwrap_or_default()
+}
+
+/// Pack `entries` (positions `first_seq..` of the org's log) into a bundle
+/// signed with `signing_key`. `anchors` is `None` for an empty range.
+pub fn assemble(
+    signing_key: &SigningKey,
+    org_id: &str,
+    (from_ms, to_ms): (i64, i64),
+    first_seq: u64,
+    entries: &[ErpAuditEntry],
+    anchors: Option<&Anchors>,
+) -> Vec<u8> {
+    let mut keys: BTreeMap<&str, KeyRecord> = BTreeMap::new();
+    for (seq, e) in (first_seq..).zip(entries) {
+        let signer = e.envelope.signer_pubkey();
+        let k = keys.entry(signer).or_insert_with(|| KeyRecord {
+            pubkey: signer.to_string(),
+            envelope_count: 0,
+            first_seq: seq,
+            last_seq: seq,
+        });
+        k.envelope_count += 1;
+        k.last_seq = seq;
+    }
+
+    let entries_bytes: Vec<u8> = entries
+        .iter()
+        .filter_map(|e| serde_json::to_string(e).ok())
+        .flat_map(|line| format!("{}\n", line).into_bytes())
+        .collect();
+    let keys_bytes =
+        serde_json::to_vec_pretty(&keys.into_values().collect::<Vec<_>>()).unwrap_or_default();
+    let anchors_bytes = serde_json::to_vec_pretty(&anchors).unwrap_or_default();
+
+    let payload = [
+        (ENTRIES_FILE, entries_bytes),
+        (KEYS_FILE, keys_bytes),
+        (ANCHORS_FILE, anchors_bytes),
+    ];
+    let mut manifest = Manifest {
+        format: BUNDLE_FORMAT.to_string(),
+        org_id: org_id.to_string(),
+        from_ms,
+        to_ms,
+        created_at_ms: chrono::Utc::now().timestamp_millis(),
+        first_seq,
+        entry_count: entries.len() as u64,
+        start_prev_hash: entries
+            .first()
+            .map(|e| e.chain_prev_hash.clone())
+            .unwrap_or_default(),
+        end_hash: entries
+            .last()
+            .map(|e| e.chain_hash.clone())
+            .unwrap_or_default(),
+        files: payload
+            .iter()
+            .map(|(name, bytes)| BundleFile {
+                name: name.to_string(),
+                bytes: bytes.len() as u64,
+                sha256: sha_hex(bytes),
+            })
+            .collect(),
+        exporter_pubkey: hex::encode(signing_key.verifying_key().to_bytes()),
+        signature: String::new(),
+    };
+    let sig: Signature = signing_key.sign(&manifest_payload(&manifest));
+    manifest.signature = hex::encode(sig.to_bytes());
+    let manifest_bytes = serde_json::to_vec_pretty(&manifest).unwrap_or_default();
+
+    let mut files: Vec<(&str, &[u8])> = vec![(MANIFEST_FILE, &manifest_bytes)];
+    files.extend(payload.iter().map(|(n, b)| (*n, b.as_slice())));
+    tar_pack(&files, (manifest.created_at_ms / 1000).max(0) as u64)
+}
+
+/// Export the org's entries issued within `[from_ms, to_ms]` as a
+/// bundle signed with the node key. The run is contiguous from the first to
+/// the last entry in range so that chain continuity can be checked.
+pub fn export(org_id: &str, from_ms: i64, to_ms: i64) -> Result<Vec<u8>, ErpError> {
+    let log = audit_log::read_log_bounded(org_id, i64::MIN, i64::MAX)
+        .map_err(|e| ErpError::ValidationFail(e.to_string()))?;
+    let in_range =
+        |e: &ErpAuditEntry| e.envelope.issued_at_ms >= from_ms && e.envelope.issued_at_ms <= to_ms;
+    let first = log.iter().position(in_range);
+    let last = log.iter().rposition(in_range);
+    let (run, first_seq, anchors) = match (first, last) {
+        (Some(first), Some(last)) => {
+            let (last_proof, signed_root) = merkle::prove_entry(org_id, &log, last)?;
+            let leaves: Vec<[u8; 32]> = log[..signed_root.tree_size as usize]
+                .iter()
+                .map(|e| merkle::leaf_hash(&e.chain_hash))
+                .collect();
+            let first_proof = merkle::prove(&leaves, first).ok_or_else(|| {
+                ErpError::ValidationFail(format!("no audit entry at position {}", first))
+            })?;
+            let anchors = Anchors {
+                signed_root,
+                first: first_proof,
+                last: last_proof,
+            };
+            (&log[first..=last], first as u64 + 1, Some(anchors))
+        }
+        _ => (&log[..0], 0, None),
+    };
+    Ok(assemble(
+        &crate::erp::envelope::get_signing_key(),
+        org_id,
+        (from_ms, to_ms),
+        first_seq,
+        run,
+        anchors.as_ref(),
+    ))
+}
+
+/// Re-check a bundle using nothing but its bytes. `expected_exporter` pins
+/// the exporting node's key; the anchors' root must be signed by that key
+/// (or, unpinned, by the manifest's exporter).
+pub fn verify_bundle(archive: &[u8], expected_exporter: Option<&str>) -> EvidenceReport {
+    let mut report = EvidenceReport::default();
+    let files = match tar_unpack(archive) {
+        Ok(f) => f,
+        Err(e) => {
+            report.issues.push(format!("archive: {}", e));
+            return report;
+        }
+    };
+    let file = |name: &str| files.iter().find(|(n, _)| n == name).map(|(_, b)| b);
+
+    // Manifest, its signature and the file hashes it lists
+    let Some(manifest) =
+        file(MANIFEST_FILE).and_then(|b| serde_json::from_slice::<Manifest>(b).ok())
+    else {
+        report
+            .issues
+            .push("manifest.json missing or unreadable".to_string());
+        return report;
+    };
+    if manifest.format != BUNDLE_FORMAT {
+        report
+            .issues
+            .push(format!("unsupported format {}", manifest.format));
+    }
+    if let Some(expected) = expected_exporter {
+        if expected != manifest.exporter_pubkey {
+            report.issues.push(format!(
+                "bundle exported by {}, expected {}",
+                manifest.exporter_pubkey, expected
+            ));
+        }
+    }
+    if let Err(e) = merkle::verify_signed(
+        &manifest.exporter_pubkey,
+        &manifest.signature,
+        &manifest_payload(&manifest),
+    ) {
+        report.issues.push(format!("manifest signature: {}", e));
+    }
+    for listed in &manifest.files {
+        match file(&listed.name) {
+            Some(bytes) if sha_hex(bytes) == listed.sha256 => {}
+            Some(_) => report
+                .issues
+                .push(format!("{} does not match its manifest hash", listed.name)),
+            None => report.issues.push(format!("{} is missing", listed.name)),
+        }
+    }
+
+    // Entries: envelope hashes and signatures, known keys, chain continuity
+    let keys: Vec<KeyRecord> = file(KEYS_FILE)
+        .and_then(|b| serde_json::from_slice(b).ok())
+        .unwrap_or_default();
+    let entries: Vec<ErpAuditEntry> = file(ENTRIES_FILE)
+        .map(|b| {
+            String::from_utf8_lossy(b)
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .filter_map(|l| serde_json::from_str(l).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    if entries.len() as u64 != manifest.entry_count {
+        report.issues.push(format!(
+            "manifest lists {} entries, bundle has {}",
+            manifest.entry_count,
+            entries.len()
+        ));
+    }
+    let mut prev = manifest.start_prev_hash.clone();
+    for (seq, entry) in (manifest.first_seq..).zip(&entries) {
+        let env = &entry.envelope;
+        if let Err(e) = env.verify() {
+            report.issues.push(format!("entry {}: {}", seq, e));
+        }
+        if !keys.iter().any(|k| k.pubkey == env.signer_pubkey()) {
+            report.issues.push(format!(
+                "entry {}: signer {} not in keys.json",
+                seq,
+                env.signer_pubkey()
+            ));
+        }
+        if entry.chain_prev_hash != prev {
+            report
+                .issues
+                .push(format!("entry {}: chain break before this entry", seq));
+        }
+        if audit_log::chain_hash_of(env, &entry.chain_prev_hash) != entry.chain_hash {
+            report
+                .issues
+                .push(format!("entry {}: chain_hash does not match content", seq));
+        }
+        prev = entry.chain_hash.clone();
+        report.entries_checked += 1;
+    }
+    if !entries.is_empty() && prev != manifest.end_hash {
+        report
+            .issues
+            .push("last entry does not match the manifest end_hash".to_string());
+    }
+
+    // Anchors: first and last entry included under a signed root
+    let anchors: Option<Anchors> = file(ANCHORS_FILE).and_then(|b| serde_json::from_slice(b).ok());
+    let root_signer = expected_exporter.unwrap_or(&manifest.exporter_pubkey);
+    match (anchors, entries.first(), entries.last()) {
+        (Some(a), Some(first), Some(last)) => {
+            let checks = [
+                (first, &a.first, manifest.first_seq),
+                (last, &a.last, manifest.first_seq + entries.len() as u64 - 1),
+            ];
+            for (entry, proof, seq) in checks {
+                if proof.leaf_index + 1 != seq {
+                    report
+                        .issues
+                        .push(format!("anchor for entry {} is at the wrong position", seq));
+                } else if let Err(e) =
+                    merkle::verify_inclusion(entry, proof, &a.signed_root, Some(root_signer))
+                {
+                    report
+                        .issues
+                        .push(format!("anchor for entry {}: {}", seq, e));
+                }
+            }
+            report.anchored = true;
+        }
+        (None, Some(_), _) => report
+            .issues
+            .push("anchors.json missing for a non-empty range".to_string()),
+        _ => {}
+    }
+
+    report.manifest = Some(manifest);
+    report
+}
+
+// ─── Minimal ustar reader / writer ───────────────────────────────────────────
+
+fn put_octal(field: &mut [u8], value: u64) {
+    let s = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(s.as_bytes());
+}
+
+fn parse_octal(field: &[u8]) -> Result<u64, String> {
+    let s: String = field
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| *b as char)
+        .collect();
+    u64::from_str_radix(s.trim(), 8).map_err(|_| format!("bad octal field {:?}", s))
+}
+
+fn header_checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u64)
+        .sum()
+}
+
+fn tar_pack(files: &[(&str, &[u8])], mtime: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, data) in files {
+        let mut h = [0u8; 512];
+        h[..name.len()].copy_from_slice(name.as_bytes());
+        put_octal(&mut h[100..108], 0o644);
+        put_octal(&mut h[108..116], 0);
+        put_octal(&mut h[116..124], 0);
+        put_octal(&mut h[124..136], data.len() as u64);
+        put_octal(&mut h[136..148], mtime);
+        h[156] = b'0';
+        h[257..263].copy_from_slice(b"ustar\0");
+        h[263..265].copy_from_slice(b"00");
+        let sum = header_checksum(&h);
+        h[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        out.extend_from_slice(&h);
+        out.extend_from_slice(data);
+        out.resize(out.len().div_ceil(512) * 512, 0);
+    }
+    out.extend_from_slice(&[0u8; 1024]);
+    out
+}
+
+fn tar_unpack(bytes: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut files = Vec::new();
+    let mut pos = 0;
+    while pos + 512 <= bytes.len() {
+        let h = &bytes[pos..pos + 512];
+        if h.iter().all(|b| *b == 0) {
+            return Ok(files);
+        }
+        if parse_octal(&h[148..156])? != header_checksum(h) {
+            return Err(format!("header checksum mismatch at offset {}", pos));
+        }
+        let name: String = h[..100]
+            .iter()
+            .take_while(|b| **b != 0)
+            .map(|b| *b as char)
+            .collect();
+        let size = parse_octal(&h[124..136])? as usize;
+        let start = pos + 512;
+        let data = bytes
+            .get(start..start + size)
+            .ok_or_else(|| format!("{} is truncated", name))?;
+        files.push((name, data.to_vec()));
+        pos = start + size.div_ceil(512) * 512;
+    }
+    Err("archive has no end marker".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::engine::{self, ErpStore};
+    use crate::erp::envelope::get_signing_key;
+    use crate::erp::types::{ActorContext, Op, PolicyContext, Role};
+    use rand::rngs::OsRng;
+
+    /// `n` envelopes as `engine::commit` logs them: signed by the node key
+    /// for an actor whose pubkey is not a key at all.
+    fn chain(n: u64) -> Vec<ErpAuditEntry> {
+        let mut store = ErpStore::new();
+        let actor = ActorContext {
+            pubkey: "local_node_pubkey_phase_a".to_string(),
+            role: Role::OwnerAdmin,
+            org_id: "org1".to_string(),
+            lamport: 0,
+        };
+        let mut prev = "erp_genesis".to_string();
+        (1..=n)
+            .map(|i| {
+                let ops = vec![Op::MapSet {
+                    fragment_id: format!("m{}", i),
+                    key: "n".to_string(),
+                    value: serde_json::json!(i),
+                }];
+                let ctx = PolicyContext {
+                    org_id: "org1".to_string(),
+                    tx_id: None,
+                    tx_status: None,
+                };
+                let envelope = engine::sign_next(&mut store, &actor, ops, ctx).unwrap();
+                let chain_hash = audit_log::chain_hash_of(&envelope, &prev);
+                let entry = ErpAuditEntry {
+                    chain_prev_hash: prev.clone(),
+                    chain_hash: chain_hash.clone(),
+                    envelope,
+                };
+                prev = chain_hash;
+                entry
+            })
+            .collect()
+    }
+
+    /// Bundle entries 2..=4 of a five-entry log, anchored to a root over all
+    /// five signed by `root_key`; exported with the node key.
+    fn bundle(root_key: &SigningKey) -> Vec<u8> {
+        let log = chain(5);
+        let leaves: Vec<[u8; 32]> = log
+            .iter()
+            .map(|e| merkle::leaf_hash(&e.chain_hash))
+            .collect();
+        let anchors = Anchors {
+            signed_root: SignedRoot::sign(root_key, "org1", &log),
+            first: merkle::prove(&leaves, 1).unwrap(),
+            last: merkle::prove(&leaves, 3).unwrap(),
+        };
+        assemble(
+            &get_signing_key(),
+            "org1",
+            (0, i64::MAX),
+            2,
+            &log[1..4],
+            Some(&anchors),
+        )
+    }
+
+    #[test]
+    fn test_bundle_round_trips_and_verifies() {
+        let key = get_signing_key();
+        let archive = bundle(&key);
+        let exporter = hex::encode(key.verifying_key().to_bytes());
+        let report = verify_bundle(&archive, Some(&exporter));
+        assert!(report.is_valid(), "{:?}", report.issues);
+        assert_eq!(report.entries_checked, 3);
+        assert!(report.anchored);
+
+        let files = tar_unpack(&archive).unwrap();
+        let keys: Vec<KeyRecord> =
+            serde_json::from_slice(&files.iter().find(|(n, _)| n == KEYS_FILE).unwrap().1).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].pubkey, exporter);
+
+        let names: Vec<String> = tar_unpack(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|(n, _)| n)
+            .collect();
+        assert_eq!(
+            names,
+            [MANIFEST_FILE, ENTRIES_FILE, KEYS_FILE, ANCHORS_FILE]
+        );
+    }
+
+    #[test]
+    fn test_tampered_entries_are_reported() {
+        let mut files = tar_unpack(&bundle(&get_signing_key())).unwrap();
+        let entries = &mut files.iter_mut().find(|(n, _)| n == ENTRIES_FILE).unwrap().1;
+        let text = String::from_utf8(entries.clone()).unwrap();
+        *entries = text.replacen("\"m3\"", "\"m9\"", 1).into_bytes();
+
+        let refs: Vec<(&str, &[u8])> = files
+            .iter()
+            .map(|(n, b)| (n.as_str(), b.as_slice()))
+            .collect();
+        let report = verify_bundle(&tar_pack(&refs, 0), None);
+        assert!(!report.is_valid());
+        assert!(report
+            .issues
+            .iter()
+            .any(|i| i.starts_with("entries.jsonl does not match")));
+        assert!(report.issues.iter().any(|i| i.contains("chain_hash")));
+    }
+
+    #[test]
+    fn test_wrong_exporter_and_corrupt_archive() {
+        let archive = bundle(&get_signing_key());
+        assert!(!verify_bundle(&archive, Some("00")).is_valid());
+
+        // Anchored to a root some other key signed
+        let report = verify_bundle(&bundle(&SigningKey::generate(&mut OsRng)), None);
+        assert!(report
+            .issues
+            .iter()
+            .any(|i| i.starts_with("anchor for entry")));
+
+        let mut corrupt = archive;
+        corrupt[10] ^= 0xff;
+        let report = verify_bundle(&corrupt, None);
+        assert!(report.manifest.is_none());
+        assert!(report.issues[0].starts_with("archive"));
+    }
+}
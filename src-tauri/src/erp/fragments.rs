@@ -38,6 +38,18 @@ pub fn party_id(party_id: &str) -> String {
     format!("party:{}", party_id)
 }
 
+pub fn reorder_policy_id(org_id: &str, item_id: &str) -> String {
+    format!("reorder:{}:{}", org_id, item_id)
+}
+
+pub fn stocktake_id(sheet_id: &str) -> String {
+    format!("stocktake:{}", sheet_id)
+}
+
+pub fn proposal_id(proposal_id: &str) -> String {
+    format!("proposal:{}", proposal_id)
+}
+
 pub fn org_indexes_id(org_id: &str) -> String {
     format!("org:{}:indexes", org_id)
 }
@@ -54,9 +66,12 @@ mod tests {
         assert_eq!(txline_id("line1"), "txline:line1");
         assert_eq!(posting_id("p1"), "posting:p1");
         assert_eq!(invmove_id("m1"), "invmove:m1");
+        assert_eq!(proposal_id("p1"), "proposal:p1");
         assert_eq!(approval_id("a1"), "approval:a1");
         assert_eq!(account_id("acct1"), "account:acct1");
         assert_eq!(party_id("party1"), "party:party1");
+        assert_eq!(stocktake_id("s1"), "stocktake:s1");
+        assert_eq!(reorder_policy_id("org1", "widget"), "reorder:org1:widget");
         assert_eq!(org_indexes_id("org1"), "org:org1:indexes");
     }
 }
@@ -0,0 +1,130 @@
+//! hlc.rs — Engine-assigned hybrid logical clocks
+//!
+//! Every envelope carries a per-actor clock (`MutationEnvelope::lamport`)
+//! that must strictly increase (`ReplayGuard`). The engine assigns it when it
+//! signs (`engine::sign_next`), so callers no longer keep a counter and
+//! `ActorContext::lamport` is ignored.
+//!
+//! A clock packs wall time and a counter into one integer,
+//! `wall_ms * LOGICAL_PER_MS + logical`. It jumps to the wall clock when that
+//! moved past the actor's last clock and otherwise counts up from it, so it
+//! keeps increasing when the system clock stalls or steps back. Values stay
+//! below 2^53 (JS clients read them exactly), and the plain counters issued
+//! before (1, 2, 3 …) all compare below them.
+//!
+//! The last clock per actor is `ReplayGuard::max_lamport`: persisted in the
+//! `actor_clocks` table, carried by checkpoints and rebuilt by audit log
+//! replay. A device that lost its state and signs envelopes itself fetches
+//! its next clock and chain head with `actor_clock` instead of guessing.
+
+use serde::{Deserialize, Serialize};
+
+use crate::erp::engine::ErpStore;
+
+/// Logical ticks available per wall-clock millisecond.
+pub const LOGICAL_PER_MS: u64 = 1000;
+
+pub fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+/// The clock following `last` at wall time `now_ms`.
+pub fn next(last: u64, now_ms: u64) -> u64 {
+    (now_ms * LOGICAL_PER_MS).max(last + 1)
+}
+
+/// Wall time (ms) and counter packed in `clock`.
+pub fn unpack(clock: u64) -> (u64, u64) {
+    (clock / LOGICAL_PER_MS, clock % LOGICAL_PER_MS)
+}
+
+/// Last clock of `actor_pubkey` (0 = none yet).
+pub fn last(store: &ErpStore, actor_pubkey: &str) -> u64 {
+    store
+        .replay
+        .max_lamport
+        .get(actor_pubkey)
+        .copied()
+        .unwrap_or(0)
+}
+
+/// The clock to sign the actor's next envelope with. Not recorded until the
+/// replay guard accepts the envelope.
+pub fn assign(store: &ErpStore, actor_pubkey: &str) -> u64 {
+    next(last(store, actor_pubkey), now_ms())
+}
+
+/// Note a clock seen in an already accepted envelope (audit log replay).
+pub fn observe(store: &mut ErpStore, actor_pubkey: &str, clock: u64) {
+    let max = store
+        .replay
+        .max_lamport
+        .entry(actor_pubkey.to_string())
+        .or_insert(0);
+    *max = (*max).max(clock);
+}
+
+/// What a client needs to sign its next envelope itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActorClock {
+    pub actor_pubkey: String,
+    /// Clock of the actor's latest accepted envelope (0 = none)
+    pub last: u64,
+    /// A clock the engine would accept now
+    pub next: u64,
+    pub wall_ms: u64,
+    pub logical: u64,
+    /// Hash of the actor's latest envelope — the next envelope's prev_hash
+    pub prev_hash: String,
+}
+
+pub fn actor_clock(store: &ErpStore, actor_pubkey: &str) -> ActorClock {
+    let last = last(store, actor_pubkey);
+    let next = next(last, now_ms());
+    let (wall_ms, logical) = unpack(next);
+    ActorClock {
+        actor_pubkey: actor_pubkey.to_string(),
+        last,
+        next,
+        wall_ms,
+        logical,
+        prev_hash: store
+            .actor_prev_hash
+            .get(actor_pubkey)
+            .cloned()
+            .unwrap_or_else(|| "genesis".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_follows_wall_time_and_never_rewinds() {
+        // Legacy counter → wall time
+        assert_eq!(next(7, 1_000), 1_000_000);
+        // Same millisecond → counter
+        assert_eq!(next(1_000_000, 1_000), 1_000_001);
+        // Wall clock stepped back → keep counting
+        assert_eq!(next(1_000_001, 900), 1_000_002);
+        // Counter exhausted → spills into the next millisecond
+        assert_eq!(unpack(next(1_000_999, 1_000)), (1_001, 0));
+
+        // Fresh actor, and a device that reset keeps advancing
+        let mut store = ErpStore::new();
+        let first = assign(&store, "pk1");
+        observe(&mut store, "pk1", first);
+        let clock = actor_clock(&store, "pk1");
+        assert_eq!(clock.last, first);
+        assert!(clock.next > first);
+        assert_eq!(clock.prev_hash, "genesis");
+        assert!(store
+            .replay
+            .check_and_record("pk1", "m2", clock.next)
+            .is_ok());
+        // Replaying an older envelope does not move the clock back
+        observe(&mut store, "pk1", 1);
+        assert_eq!(last(&store, "pk1"), clock.next);
+    }
+}
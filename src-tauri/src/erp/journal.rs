@@ -0,0 +1,437 @@
+//! journal.rs — Manual journal entries with explicit debit/credit columns
+//!
+//! A journal is a `TxType::Journal` transaction whose lines each name a CoA
+//! account and carry exactly one of `debit_amount` / `credit_amount`.
+//! `validate_journal_lines` runs when the entry is created and
+//! `validate_journal_tx` again before it may move to `proposed`, so an
+//! unbalanced or mis-coded journal never reaches approval.
+//!
+//! `JournalTemplate` expands common recurring entries (accruals, accrual
+//! reversals, prepayment releases) into journal lines.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::erp::coa;
+use crate::erp::engine::AccountRecord;
+use crate::erp::errors::ErpError;
+use crate::erp::ledger::ROUNDING_TOLERANCE;
+use crate::erp::types::{JournalLineInput, TxLine};
+
+/// Validate journal lines before they are written: at least two lines, every
+/// line on an active, postable CoA account with exactly one positive side,
+/// and total debits equal to total credits.
+pub fn validate_journal_lines(
+    accounts: &HashMap<String, AccountRecord>,
+    lines: &[JournalLineInput],
+) -> Result<(), ErpError> {
+    if lines.len() < 2 {
+        return Err(ErpError::ValidationFail(
+            "journal entry needs at least two lines".to_string(),
+        ));
+    }
+    for (i, line) in lines.iter().enumerate() {
+        validate_line(accounts, i, &line.account_id, line.debit, line.credit)?;
+    }
+    check_balanced(lines.iter().map(|l| (l.debit, l.credit)))
+}
+
+/// Validate a stored journal tx's lines — the gate for draft → proposed.
+/// Legacy lines without debit/credit columns are read via `line_sides`, and
+/// may name a symbolic account, resolved as postings are (`resolve_account`).
+pub fn validate_journal_tx(
+    accounts: &HashMap<String, AccountRecord>,
+    lines: &[TxLine],
+) -> Result<(), ErpError> {
+    if lines.len() < 2 {
+        return Err(ErpError::ValidationFail(
+            "journal entry needs at least two lines".to_string(),
+        ));
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let acct = resolve_account(accounts, line.account_id.as_deref().unwrap_or_default());
+        let (debit, credit) = line_sides(line);
+        validate_line(accounts, i, acct, debit, credit)?;
+    }
+    check_balanced(lines.iter().map(line_sides))
+}
+
+/// A stored journal line's (debit, credit). Legacy lines with no debit/credit
+/// columns carry the amount as `qty * unit_price`, its sign giving the side.
+pub fn line_sides(line: &TxLine) -> (f64, f64) {
+    if line.debit_amount != 0.0 || line.credit_amount != 0.0 {
+        return (line.debit_amount, line.credit_amount);
+    }
+    let amount = (line.qty * line.unit_price).abs();
+    if line.unit_price >= 0.0 {
+        (amount, 0.0)
+    } else {
+        (0.0, amount)
+    }
+}
+
+/// The CoA code a stored line's account id refers to, matched the way
+/// postings are: by code, then by bound role, then by name slug
+/// (`coa::posting_matches`). Unmatched ids are returned as is.
+fn resolve_account<'a>(
+    accounts: &'a HashMap<String, AccountRecord>,
+    account_id: &'a str,
+) -> &'a str {
+    if accounts.contains_key(account_id) {
+        return account_id;
+    }
+    accounts
+        .values()
+        .find(|a| a.role.as_deref() == Some(account_id))
+        .or_else(|| {
+            accounts
+                .values()
+                .find(|a| coa::posting_matches(a, account_id))
+        })
+        .map_or(account_id, |a| a.code.as_str())
+}
+
+fn validate_line(
+    accounts: &HashMap<String, AccountRecord>,
+    index: usize,
+    account_id: &str,
+    debit: f64,
+    credit: f64,
+) -> Result<(), ErpError> {
+    if account_id.trim().is_empty() {
+        return Err(ErpError::ValidationFail(format!(
+            "journal line {} has no account",
+            index + 1
+        )));
+    }
+    if !accounts.contains_key(account_id) {
+        return Err(ErpError::ValidationFail(format!(
+            "journal line {}: account {} is not in the Chart of Accounts",
+            index + 1,
+            account_id
+        )));
+    }
+    coa::validate_postable(accounts, account_id)?;
+
+    if debit < 0.0 || credit < 0.0 {
+        return Err(ErpError::InvalidField(format!(
+            "journal line {}: debit and credit must not be negative",
+            index + 1
+        )));
+    }
+    if (debit > 0.0) == (credit > 0.0) {
+        return Err(ErpError::ValidationFail(format!(
+            "journal line {}: exactly one of debit or credit must be non-zero",
+            index + 1
+        )));
+    }
+    Ok(())
+}
+
+fn check_balanced(sides: impl Iterator<Item = (f64, f64)>) -> Result<(), ErpError> {
+    let (dr, cr) = sides.fold((0.0, 0.0), |(dr, cr), (d, c)| (dr + d, cr + c));
+    let delta = (dr - cr).abs();
+    if delta > ROUNDING_TOLERANCE {
+        return Err(ErpError::BalanceFail(dr, cr, delta));
+    }
+    Ok(())
+}
+
+// ─── Templates ───────────────────────────────────────────────────────────────
+
+/// Recurring journal patterns. Each expands to a balanced two-line entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "template", rename_all = "snake_case")]
+pub enum JournalTemplate {
+    /// Recognise an expense incurred but not yet invoiced:
+    /// DR expense / CR accrued liability.
+    Accrual {
+        expense_account: String,
+        accrual_account: String,
+        amount: f64,
+    },
+    /// Reverse a prior-period accrual once the invoice arrives:
+    /// DR accrued liability / CR expense.
+    AccrualReversal {
+        expense_account: String,
+        accrual_account: String,
+        amount: f64,
+    },
+    /// Release period `period` (1-based) of a prepayment:
+    /// DR expense / CR prepaid asset, for `total / periods` rounded to cents.
+    /// The last period releases whatever rounding left over.
+    PrepaymentRelease {
+        prepaid_account: String,
+        expense_account: String,
+        total: f64,
+        periods: u32,
+        #[serde(default = "first_period")]
+        period: u32,
+    },
+}
+
+fn first_period() -> u32 {
+    1
+}
+
+impl JournalTemplate {
+    /// Default description for entries created from this template.
+    pub fn label(&self) -> &'static str {
+        match self {
+            JournalTemplate::Accrual { .. } => "Accrual",
+            JournalTemplate::AccrualReversal { .. } => "Accrual reversal",
+            JournalTemplate::PrepaymentRelease { .. } => "Prepayment release",
+        }
+    }
+
+    /// Expand the template into journal lines.
+    pub fn lines(&self) -> Result<Vec<JournalLineInput>, ErpError> {
+        let (debit_acct, credit_acct, amount) = match self {
+            JournalTemplate::Accrual {
+                expense_account,
+                accrual_account,
+                amount,
+            } => (expense_account, accrual_account, *amount),
+            JournalTemplate::AccrualReversal {
+                expense_account,
+                accrual_account,
+                amount,
+            } => (accrual_account, expense_account, *amount),
+            JournalTemplate::PrepaymentRelease {
+                prepaid_account,
+                expense_account,
+                total,
+                periods,
+                period,
+            } => {
+                if *periods == 0 {
+                    return Err(ErpError::InvalidField(
+                        "prepayment release periods must be at least 1".to_string(),
+                    ));
+                }
+                if *period == 0 || period > periods {
+                    return Err(ErpError::InvalidField(format!(
+                        "prepayment release period must be between 1 and {}",
+                        periods
+                    )));
+                }
+                let per_period = (total / *periods as f64 * 100.0).round() / 100.0;
+                let amount = if period == periods {
+                    let released = per_period * (*periods - 1) as f64;
+                    ((total - released) * 100.0).round() / 100.0
+                } else {
+                    per_period
+                };
+                (expense_account, prepaid_account, amount)
+            }
+        };
+        if amount <= 0.0 {
+            return Err(ErpError::InvalidField(
+                "journal template amount must be positive".to_string(),
+            ));
+        }
+        let label = self.label();
+        Ok(vec![
+            JournalLineInput {
+                account_id: debit_acct.clone(),
+                debit: amount,
+                credit: 0.0,
+                description: Some(label.to_string()),
+            },
+            JournalLineInput {
+                account_id: credit_acct.clone(),
+                debit: 0.0,
+                credit: amount,
+                description: Some(label.to_string()),
+            },
+        ])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts() -> HashMap<String, AccountRecord> {
+        [
+            ("1400", "Prepaid Insurance", "asset", false),
+            ("2400", "Accrued Expenses", "liability", false),
+            ("5200", "Rent", "expense", false),
+            ("5000", "Expenses", "expense", true),
+        ]
+        .into_iter()
+        .map(|(code, name, acct_type, is_header)| {
+            (
+                code.to_string(),
+                AccountRecord {
+                    code: code.to_string(),
+                    name: name.to_string(),
+                    acct_type: acct_type.to_string(),
+                    normal_balance: coa::expected_normal_balance(acct_type).unwrap().to_string(),
+                    parent_code: None,
+                    is_header,
+                    active: true,
+                    role: None,
+                },
+            )
+        })
+        .collect()
+    }
+
+    fn line(account_id: &str, debit: f64, credit: f64) -> JournalLineInput {
+        JournalLineInput {
+            account_id: account_id.to_string(),
+            debit,
+            credit,
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_balanced_journal_accepted() {
+        let lines = vec![line("5200", 1000.0, 0.0), line("2400", 0.0, 1000.0)];
+        assert!(validate_journal_lines(&accounts(), &lines).is_ok());
+    }
+
+    #[test]
+    fn test_unbalanced_journal_rejected() {
+        let lines = vec![line("5200", 1000.0, 0.0), line("2400", 0.0, 900.0)];
+        assert_eq!(
+            validate_journal_lines(&accounts(), &lines),
+            Err(ErpError::BalanceFail(1000.0, 900.0, 100.0))
+        );
+    }
+
+    #[test]
+    fn test_journal_line_rules() {
+        let accts = accounts();
+        // Unknown account
+        let lines = vec![line("9999", 10.0, 0.0), line("2400", 0.0, 10.0)];
+        assert!(validate_journal_lines(&accts, &lines).is_err());
+        // Header account
+        let lines = vec![line("5000", 10.0, 0.0), line("2400", 0.0, 10.0)];
+        assert!(validate_journal_lines(&accts, &lines).is_err());
+        // Both sides on one line
+        let lines = vec![line("5200", 10.0, 10.0), line("2400", 0.0, 0.0)];
+        assert!(validate_journal_lines(&accts, &lines).is_err());
+        // Single line
+        assert!(validate_journal_lines(&accts, &[line("5200", 0.0, 0.0)]).is_err());
+    }
+
+    #[test]
+    fn test_legacy_unit_price_journal_accepted() {
+        let legacy = |account_id: &str, unit_price: f64| TxLine {
+            line_id: format!("l-{}", account_id),
+            tx_id: "j1".to_string(),
+            item_id: None,
+            account_id: Some(account_id.to_string()),
+            description: None,
+            qty: 1.0,
+            unit_price,
+            inventory_effect: crate::erp::types::InventoryEffect::None,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
+        };
+        let accts = accounts();
+        let lines = vec![legacy("5200", 250.0), legacy("2400", -250.0)];
+        assert!(validate_journal_tx(&accts, &lines).is_ok());
+        let lines = vec![legacy("5200", 250.0), legacy("2400", -200.0)];
+        assert!(validate_journal_tx(&accts, &lines).is_err());
+    }
+
+    #[test]
+    fn test_legacy_symbolic_journal_resolved() {
+        let legacy = |account_id: &str, unit_price: f64| TxLine {
+            line_id: format!("l-{}", account_id),
+            tx_id: "j1".to_string(),
+            item_id: None,
+            account_id: Some(account_id.to_string()),
+            description: None,
+            qty: 1.0,
+            unit_price,
+            inventory_effect: crate::erp::types::InventoryEffect::None,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
+        };
+        let mut accts = accounts();
+        accts.get_mut("2400").unwrap().role = Some("accounts_payable".to_string());
+        // "rent" matches 5200 by name, "accounts_payable" 2400 by role
+        let lines = vec![legacy("rent", 250.0), legacy("accounts_payable", -250.0)];
+        assert!(validate_journal_tx(&accts, &lines).is_ok());
+        // "expenses" resolves to the 5000 header, which cannot be posted to
+        let lines = vec![
+            legacy("expenses", 250.0),
+            legacy("accounts_payable", -250.0),
+        ];
+        assert!(validate_journal_tx(&accts, &lines).is_err());
+        let lines = vec![legacy("nowhere", 250.0), legacy("accounts_payable", -250.0)];
+        assert!(validate_journal_tx(&accts, &lines).is_err());
+    }
+
+    #[test]
+    fn test_prepayment_release_template() {
+        let tpl = JournalTemplate::PrepaymentRelease {
+            prepaid_account: "1400".into(),
+            expense_account: "5200".into(),
+            total: 1200.0,
+            periods: 12,
+            period: 1,
+        };
+        let lines = tpl.lines().unwrap();
+        assert_eq!(lines[0].account_id, "5200");
+        assert_eq!(lines[0].debit, 100.0);
+        assert_eq!(lines[1].account_id, "1400");
+        assert_eq!(lines[1].credit, 100.0);
+        assert!(validate_journal_lines(&accounts(), &lines).is_ok());
+    }
+
+    #[test]
+    fn test_prepayment_release_remainder_in_last_period() {
+        let release = |period: u32| {
+            JournalTemplate::PrepaymentRelease {
+                prepaid_account: "1400".into(),
+                expense_account: "5200".into(),
+                total: 1000.0,
+                periods: 3,
+                period,
+            }
+            .lines()
+            .map(|l| l[0].debit)
+        };
+        assert_eq!(release(1), Ok(333.33));
+        assert_eq!(release(2), Ok(333.33));
+        assert_eq!(release(3), Ok(333.34));
+        let total: f64 = (1..=3).map(|p| release(p).unwrap()).sum();
+        assert!((total - 1000.0).abs() < 1e-9);
+        assert!(release(0).is_err());
+        assert!(release(4).is_err());
+    }
+
+    #[test]
+    fn test_accrual_reversal_swaps_sides() {
+        let tpl = JournalTemplate::AccrualReversal {
+            expense_account: "5200".into(),
+            accrual_account: "2400".into(),
+            amount: 500.0,
+        };
+        let lines = tpl.lines().unwrap();
+        assert_eq!(
+            (lines[0].account_id.as_str(), lines[0].debit),
+            ("2400", 500.0)
+        );
+        assert_eq!(
+            (lines[1].account_id.as_str(), lines[1].credit),
+            ("5200", 500.0)
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use crate::erp::errors::ErpError;
+use crate::erp::stocktake::ReasonCode;
 use crate::erp::types::{InventoryEffect, Posting, TxLine, TxType};
 
 pub const ROUNDING_TOLERANCE: f64 = 0.01;
@@ -176,6 +177,45 @@ pub fn generate_postings(
             ));
         }
 
+        // ── stock_adjust with reason codes: one pair of postings per reason ─────
+        // found → DR Inventory / CR 5400 gain; shrinkage, damage → DR 5401 loss / CR Inventory
+        TxType::StockAdjust if lines.iter().any(|l| l.reason_code.is_some()) => {
+            for (reason, value) in adjustments_by_reason(lines)? {
+                let label = format!("Stock adjustment - {}", reason.as_str());
+                if value >= 0.0 {
+                    postings.push(draft_posting(
+                        tx_id,
+                        "inventory_asset",
+                        value,
+                        0.0,
+                        Some(&label),
+                    ));
+                    postings.push(draft_posting(
+                        tx_id,
+                        reason.account_role(),
+                        0.0,
+                        value,
+                        Some(&label),
+                    ));
+                } else {
+                    postings.push(draft_posting(
+                        tx_id,
+                        reason.account_role(),
+                        value.abs(),
+                        0.0,
+                        Some(&label),
+                    ));
+                    postings.push(draft_posting(
+                        tx_id,
+                        "inventory_asset",
+                        0.0,
+                        value.abs(),
+                        Some(&label),
+                    ));
+                }
+            }
+        }
+
         // ── stock_adjust: DR/CR Inventory Asset + Adjustment account (signed) ──
         TxType::StockAdjust => {
             // line_total may be negative (write-down) or positive (write-up)
@@ -213,33 +253,30 @@ pub fn generate_postings(
             }
         }
 
-        // ── journal: manual — lines carry explicit account + signed amount ──────
+        // ── journal: manual — lines carry an explicit account + debit/credit ────
+        // Legacy lines with no debit/credit columns fall back to the sign of
+        // unit_price (`journal::line_sides`), as in `validate_journal_tx`.
         TxType::Journal => {
             for line in lines {
-                if let Some(acct) = &line.account_id {
-                    let amount = (line.qty * line.unit_price).abs();
-                    if line.unit_price >= 0.0 {
-                        postings.push(draft_posting(
-                            tx_id,
-                            acct,
-                            amount,
-                            0.0,
-                            line.description.as_deref(),
-                        ));
-                    } else {
-                        postings.push(draft_posting(
-                            tx_id,
-                            acct,
-                            0.0,
-                            amount,
-                            line.description.as_deref(),
-                        ));
-                    }
-                }
+                let acct = line.account_id.as_deref().ok_or_else(|| {
+                    ErpError::ValidationFail(format!(
+                        "journal line {} has no account_id",
+                        line.line_id
+                    ))
+                })?;
+                let (debit, credit) = crate::erp::journal::line_sides(line);
+                postings.push(draft_posting(
+                    tx_id,
+                    acct,
+                    debit,
+                    credit,
+                    line.description.as_deref(),
+                ));
             }
         }
 
         // ── credit_note: mirror of invoice_out with opposite directions ─────────
+        // If returned goods were moved back into stock → also DR Inventory / CR COGS
         TxType::CreditNote => {
             postings.push(draft_posting(
                 tx_id,
@@ -264,6 +301,25 @@ pub fn generate_postings(
                     Some("Tax return"),
                 ));
             }
+            if has_invmoves {
+                let returned = estimate_returned_cogs(lines);
+                if returned > 0.0 {
+                    postings.push(draft_posting(
+                        tx_id,
+                        "inventory_asset",
+                        returned,
+                        0.0,
+                        Some("Inventory - returned to stock"),
+                    ));
+                    postings.push(draft_posting(
+                        tx_id,
+                        "cogs",
+                        0.0,
+                        returned,
+                        Some("COGS reversal - returned"),
+                    ));
+                }
+            }
         }
 
         // ── debit_note: mirror of invoice_in with opposite directions ──────────
@@ -353,6 +409,38 @@ fn estimate_cogs(lines: &[TxLine]) -> f64 {
     round2(total)
 }
 
+fn estimate_returned_cogs(lines: &[TxLine]) -> f64 {
+    // Mirrors estimate_cogs: returned lines are valued at their credited price
+    let total: f64 = lines
+        .iter()
+        .filter(|l| matches!(l.inventory_effect, InventoryEffect::Increase))
+        .map(|l| l.qty * l.unit_price)
+        .sum();
+    round2(total)
+}
+
+/// Signed adjustment value per reason code, in first-seen order.
+/// Lines without a reason default by sign (gain → found, loss → shrinkage).
+fn adjustments_by_reason(lines: &[TxLine]) -> Result<Vec<(ReasonCode, f64)>, ErpError> {
+    let mut totals: Vec<(ReasonCode, f64)> = Vec::new();
+    for line in lines {
+        let value = line.qty * line.unit_price;
+        let reason = match line.reason_code {
+            Some(ref r) => ReasonCode::parse(r)?,
+            None => ReasonCode::default_for(line.qty),
+        };
+        match totals.iter_mut().find(|(r, _)| *r == reason) {
+            Some((_, total)) => *total += value,
+            None => totals.push((reason, value)),
+        }
+    }
+    Ok(totals
+        .into_iter()
+        .map(|(r, v)| (r, round2(v)))
+        .filter(|(_, v)| *v != 0.0)
+        .collect())
+}
+
 fn round2(v: f64) -> f64 {
     (v * 100.0).round() / 100.0
 }
@@ -380,6 +468,10 @@ mod tests {
             move_ids: vec![],
             tax_code: Some("GST".to_string()),
             tax_rate,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
         }
     }
 
@@ -419,6 +511,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_credit_note_return_reverses_cogs() {
+        let lines = vec![sample_line(2.0, 100.0, 0.1, InventoryEffect::Increase)];
+        let postings = generate_postings(&TxType::CreditNote, "tx1", &lines, "AUD", true)
+            .expect("generate_postings should succeed");
+
+        let inv = postings
+            .iter()
+            .find(|p| p.account_id == "inventory_asset")
+            .expect("Inventory posting");
+        assert_eq!(inv.debit_amount, 200.0);
+        let cogs = postings
+            .iter()
+            .find(|p| p.account_id == "cogs")
+            .expect("COGS reversal");
+        assert_eq!(cogs.credit_amount, 200.0);
+        assert!(validate_balance(&postings).is_ok());
+
+        // No stock movement → no COGS reversal
+        let postings = generate_postings(&TxType::CreditNote, "tx1", &lines, "AUD", false).unwrap();
+        assert!(postings.iter().all(|p| p.account_id != "cogs"));
+    }
+
+    #[test]
+    fn test_stock_adjust_posts_by_reason() {
+        let mut lost = sample_line(-3.0, 10.0, 0.0, InventoryEffect::Decrease);
+        lost.reason_code = Some("damage".to_string());
+        let mut found = sample_line(1.0, 10.0, 0.0, InventoryEffect::Increase);
+        found.reason_code = Some("found".to_string());
+        let postings =
+            generate_postings(&TxType::StockAdjust, "tx1", &[lost, found], "AUD", true).unwrap();
+
+        let loss = postings
+            .iter()
+            .find(|p| p.account_id == "stock_adjustment_loss")
+            .expect("loss posting");
+        assert_eq!(loss.debit_amount, 30.0);
+        let gain = postings
+            .iter()
+            .find(|p| p.account_id == "stock_adjustment_gain")
+            .expect("gain posting");
+        assert_eq!(gain.credit_amount, 10.0);
+        assert!(validate_balance(&postings).is_ok());
+    }
+
     #[test]
     fn test_tx_post_balance_invariant() {
         // Manually create imbalanced postings — validate_balance must reject
@@ -0,0 +1,539 @@
+//! llm_backend.rs — Pluggable LLM backends for CAIO
+//!
+//! `LlmBackend` turns a prompt into a completion. Backends:
+//! - `OllamaHttp` — Ollama's `/api/generate` (non-streaming)
+//! - `OpenAiCompatible` — `/chat/completions` of any OpenAI-compatible local
+//!   server (llama.cpp, LM Studio, vLLM …)
+//! - `OllamaCli` — `ollama run {model}` over stdin/stdout (the original path)
+//! - `Mock` — a fixed completion, for tests and demos
+//!
+//! Backend, model, endpoint and timeout are configured per org in
+//! `{app_data_dir}/erp_llm_config.json`; orgs without an entry use the CLI
+//! with `mistral`. HTTP is plain `http://` over a blocking std socket with
+//! timeouts: CAIO runs inside sync commands, where a std socket cannot
+//! deadlock the tokio runtime the way `reqwest::blocking` does.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::audit::shipper::parse_http_url;
+
+/// Responses larger than this are cut off.
+const MAX_RESPONSE: u64 = 4 * 1024 * 1024;
+
+/// How often `OllamaCli` checks whether the child has exited.
+const CLI_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+pub trait LlmBackend: Send + Sync {
+    /// Shown on the CAIO source badge, e.g. "mistral via Ollama HTTP"
+    fn label(&self) -> String;
+    fn complete(&self, prompt: &str) -> Result<String, String>;
+}
+
+// ─── Config ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BackendConfig {
+    OllamaHttp {
+        #[serde(default = "default_ollama_endpoint")]
+        endpoint: String,
+    },
+    /// `endpoint` is the API base, e.g. `http://127.0.0.1:8080/v1`
+    #[serde(rename = "openai_compatible")]
+    OpenAiCompatible {
+        endpoint: String,
+        /// Sent as a bearer token if set
+        #[serde(default)]
+        api_key: Option<String>,
+    },
+    OllamaCli {
+        /// Binary to run; the usual install locations and PATH if unset
+        #[serde(default)]
+        binary: Option<String>,
+    },
+    Mock {
+        #[serde(default)]
+        response: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LlmConfig {
+    #[serde(flatten)]
+    pub backend: BackendConfig,
+    #[serde(default = "default_model")]
+    pub model: String,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_ollama_endpoint() -> String {
+    "http://127.0.0.1:11434".to_string()
+}
+fn default_model() -> String {
+    "mistral".to_string()
+}
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+impl Default for LlmConfig {
+    fn default() -> Self {
+        Self {
+            backend: BackendConfig::OllamaCli { binary: None },
+            model: default_model(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+lazy_static! {
+    static ref LLM_CONFIGS: Mutex<BTreeMap<String, LlmConfig>> = Mutex::new(BTreeMap::new());
+    static ref LLM_CONFIG_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// Load `erp_llm_config.json` (org_id → config) from `app_data_dir`; called
+/// from `engine::init_erp_db`.
+pub fn configure(app_data_dir: &Path) {
+    let path = app_data_dir.join("erp_llm_config.json");
+    if let Ok(s) = fs::read_to_string(&path) {
+        match serde_json::from_str(&s) {
+            Ok(configs) => *LLM_CONFIGS.lock().unwrap() = configs,
+            Err(e) => eprintln!("⚠️  Invalid {}: {e}; using defaults", path.display()),
+        }
+    }
+    *LLM_CONFIG_PATH.lock().unwrap() = Some(path);
+}
+
+pub fn config_for(org_id: &str) -> LlmConfig {
+    LLM_CONFIGS
+        .lock()
+        .unwrap()
+        .get(org_id)
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Replace the org's config and persist all configs. The in-memory config
+/// only changes once the file is written.
+pub fn set_config(org_id: &str, config: LlmConfig) -> std::io::Result<()> {
+    let mut configs = LLM_CONFIGS.lock().unwrap();
+    let mut updated = configs.clone();
+    updated.insert(org_id.to_string(), config);
+    if let Some(path) = LLM_CONFIG_PATH.lock().unwrap().as_ref() {
+        fs::write(path, serde_json::to_vec_pretty(&updated)?)?;
+    }
+    *configs = updated;
+    Ok(())
+}
+
+/// The backend configured for `org_id`.
+pub fn backend_for(org_id: &str) -> Box<dyn LlmBackend> {
+    build(&config_for(org_id))
+}
+
+pub fn build(config: &LlmConfig) -> Box<dyn LlmBackend> {
+    let timeout = Duration::from_secs(config.timeout_secs.max(1));
+    let model = config.model.clone();
+    match &config.backend {
+        BackendConfig::OllamaHttp { endpoint } => Box::new(OllamaHttp {
+            endpoint: endpoint.clone(),
+            model,
+            timeout,
+        }),
+        BackendConfig::OpenAiCompatible { endpoint, api_key } => Box::new(OpenAiCompatible {
+            endpoint: endpoint.clone(),
+            api_key: api_key.clone(),
+            model,
+            timeout,
+        }),
+        BackendConfig::OllamaCli { binary } => Box::new(OllamaCli {
+            binary: binary.clone(),
+            model,
+            timeout,
+        }),
+        BackendConfig::Mock { response } => Box::new(MockBackend {
+            response: response.clone(),
+        }),
+    }
+}
+
+// ─── Backends ────────────────────────────────────────────────────────────────
+
+pub struct OllamaHttp {
+    pub endpoint: String,
+    pub model: String,
+    pub timeout: Duration,
+}
+
+impl LlmBackend for OllamaHttp {
+    fn label(&self) -> String {
+        format!("{} via Ollama HTTP", self.model)
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, String> {
+        let url = format!("{}/api/generate", self.endpoint.trim_end_matches('/'));
+        let body = serde_json::json!({
+            "model": self.model,
+            "prompt": prompt,
+            "stream": false,
+            "format": "json",
+        });
+        let reply = post_json(&url, &body, None, self.timeout)?;
+        reply["response"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("{} returned no response text", url))
+    }
+}
+
+pub struct OpenAiCompatible {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    pub model: String,
+    pub timeout: Duration,
+}
+
+impl LlmBackend for OpenAiCompatible {
+    fn label(&self) -> String {
+        format!("{} via OpenAI-compatible server", self.model)
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, String> {
+        let url = format!("{}/chat/completions", self.endpoint.trim_end_matches('/'));
+        let body = serde_json::json!({
+            "model": self.model,
+            "messages": [{ "role": "user", "content": prompt }],
+            "temperature": 0,
+            "stream": false,
+        });
+        let reply = post_json(&url, &body, self.api_key.as_deref(), self.timeout)?;
+        reply["choices"][0]["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("{} returned no message content", url))
+    }
+}
+
+pub struct OllamaCli {
+    pub binary: Option<String>,
+    pub model: String,
+    pub timeout: Duration,
+}
+
+impl OllamaCli {
+    fn binary(&self) -> String {
+        if let Some(ref b) = self.binary {
+            return b.clone();
+        }
+        [
+            "/usr/local/bin/ollama",
+            "/usr/bin/ollama",
+            "/opt/homebrew/bin/ollama",
+        ]
+        .iter()
+        .find(|p| Path::new(p).exists())
+        .copied()
+        .unwrap_or("ollama") // rely on PATH
+        .to_string()
+    }
+}
+
+impl LlmBackend for OllamaCli {
+    fn label(&self) -> String {
+        format!("{} via Ollama", self.model)
+    }
+
+    fn complete(&self, prompt: &str) -> Result<String, String> {
+        let mut child = Command::new(self.binary())
+            .args(["run", self.model.as_str(), "--format", "json"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("cannot run ollama: {}", e))?;
+
+        // stdin closes when dropped, signalling EOF to ollama
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(prompt.as_bytes());
+        }
+
+        // Drain stdout on a thread while waiting, so a full pipe cannot stall it
+        let mut stdout_pipe = child.stdout.take();
+        let reader = std::thread::spawn(move || {
+            let mut out = Vec::new();
+            if let Some(ref mut pipe) = stdout_pipe {
+                let _ = pipe.read_to_end(&mut out);
+            }
+            out
+        });
+        let deadline = Instant::now() + self.timeout;
+        let status = loop {
+            match child.try_wait() {
+                Ok(Some(status)) => break status,
+                Ok(None) if Instant::now() < deadline => std::thread::sleep(CLI_POLL_INTERVAL),
+                result => {
+                    // Timed out (or cannot be waited on): don't leave it running
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(match result {
+                        Err(e) => e.to_string(),
+                        _ => "ollama timed out".to_string(),
+                    });
+                }
+            }
+        };
+        let stdout = reader.join().unwrap_or_default();
+        if !status.success() && stdout.is_empty() {
+            return Err(format!("ollama exited with {}", status));
+        }
+        Ok(String::from_utf8_lossy(&stdout).into_owned())
+    }
+}
+
+/// Returns `response` for every prompt (an error if it is empty).
+pub struct MockBackend {
+    pub response: String,
+}
+
+impl LlmBackend for MockBackend {
+    fn label(&self) -> String {
+        "Mock LLM".to_string()
+    }
+
+    fn complete(&self, _prompt: &str) -> Result<String, String> {
+        if self.response.is_empty() {
+            Err("mock backend has no response configured".to_string())
+        } else {
+            Ok(self.response.clone())
+        }
+    }
+}
+
+// ─── HTTP transport ──────────────────────────────────────────────────────────
+
+fn post_json(
+    url: &str,
+    body: &serde_json::Value,
+    bearer: Option<&str>,
+    timeout: Duration,
+) -> Result<serde_json::Value, String> {
+    let (host, port, path) = parse_http_url(url)?;
+    let addr = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("cannot resolve {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout).map_err(|e| e.to_string())?;
+    stream
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| stream.set_write_timeout(Some(timeout)))
+        .map_err(|e| e.to_string())?;
+
+    let body = serde_json::to_vec(body).map_err(|e| e.to_string())?;
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path,
+        host,
+        port,
+        body.len()
+    );
+    if let Some(token) = bearer {
+        head.push_str(&format!("Authorization: Bearer {}\r\n", token));
+    }
+    head.push_str("\r\n");
+    stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(&body))
+        .map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream
+        .take(MAX_RESPONSE)
+        .read_to_end(&mut response)
+        .map_err(|e| format!("{} did not answer: {}", url, e))?;
+    let split = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or("malformed HTTP response")?;
+    let headers = String::from_utf8_lossy(&response[..split]).to_ascii_lowercase();
+    let status: u16 = headers
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or("malformed HTTP status line")?;
+    let mut payload = response[split + 4..].to_vec();
+    if headers.contains("transfer-encoding: chunked") {
+        payload = dechunk(&payload)?;
+    }
+    if !(200..300).contains(&status) {
+        return Err(format!(
+            "{} answered {}: {}",
+            url,
+            status,
+            String::from_utf8_lossy(&payload)
+        ));
+    }
+    serde_json::from_slice(&payload).map_err(|e| format!("{} returned invalid JSON: {}", url, e))
+}
+
+/// Decode a chunked transfer-encoded body.
+fn dechunk(mut buf: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = Vec::new();
+    loop {
+        let line_end = buf
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or("malformed chunked body")?;
+        let size_field = String::from_utf8_lossy(&buf[..line_end]);
+        let size = usize::from_str_radix(size_field.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| "malformed chunk size")?;
+        buf = &buf[line_end + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if buf.len() < size {
+            return Err("truncated chunked body".to_string());
+        }
+        out.extend_from_slice(&buf[..size]);
+        buf = buf.get(size + 2..).unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    /// Serve one request with `reply`; returns the base URL and the request.
+    fn serve_once(reply: &'static str) -> (String, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 4096];
+            // Read until the JSON body is complete
+            while !request.ends_with(b"}") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream.write_all(reply.as_bytes()).unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_http_backends() {
+        let (url, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n{\"response\":\"[]\"}",
+        );
+        let ollama = build(&LlmConfig {
+            backend: BackendConfig::OllamaHttp { endpoint: url },
+            model: "llama3".to_string(),
+            timeout_secs: 5,
+        });
+        assert_eq!(ollama.complete("hi").unwrap(), "[]");
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /api/generate "));
+        assert!(request.contains("\"model\":\"llama3\""));
+
+        // Chunked reply, bearer token, endpoint with a path
+        let (url, server) = serve_once(
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             13\r\n{\"choices\":[{\"messa\r\n\
+             17\r\nge\":{\"content\":\"ok\"}}]}\r\n0\r\n\r\n",
+        );
+        let openai = build(&LlmConfig {
+            backend: BackendConfig::OpenAiCompatible {
+                endpoint: format!("{}/v1/", url),
+                api_key: Some("k".to_string()),
+            },
+            model: "qwen".to_string(),
+            timeout_secs: 5,
+        });
+        assert_eq!(openai.complete("hi").unwrap(), "ok");
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions "));
+        assert!(request.contains("Authorization: Bearer k\r\n"));
+
+        let (url, server) = serve_once("HTTP/1.1 404 Not Found\r\n\r\nmodel not found");
+        let missing = build(&LlmConfig {
+            backend: BackendConfig::OllamaHttp { endpoint: url },
+            ..LlmConfig::default()
+        });
+        assert!(missing.complete("hi").unwrap_err().contains("404"));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_config_defaults_and_mock() {
+        assert_eq!(config_for("unconfigured"), LlmConfig::default());
+        let config: LlmConfig =
+            serde_json::from_str(r#"{"kind":"ollama_http","model":"phi3"}"#).unwrap();
+        assert_eq!(
+            config.backend,
+            BackendConfig::OllamaHttp {
+                endpoint: default_ollama_endpoint()
+            }
+        );
+        assert_eq!(config.timeout_secs, 30);
+
+        let mock = build(&LlmConfig {
+            backend: BackendConfig::Mock {
+                response: "[1]".to_string(),
+            },
+            ..LlmConfig::default()
+        });
+        assert_eq!(mock.complete("anything").unwrap(), "[1]");
+        assert!(MockBackend {
+            response: String::new()
+        }
+        .complete("x")
+        .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_cli_timeout_kills_the_child() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("ollama_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("ollama");
+        let pid_file = dir.join("pid");
+        fs::write(
+            &script,
+            format!(
+                "#!/bin/sh\necho $$ > {}\nexec sleep 30\n",
+                pid_file.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let cli = OllamaCli {
+            binary: Some(script.display().to_string()),
+            model: "mistral".to_string(),
+            timeout: Duration::from_millis(300),
+        };
+        assert_eq!(cli.complete("hi").unwrap_err(), "ollama timed out");
+        let pid = fs::read_to_string(&pid_file).unwrap();
+        let alive = Command::new("kill")
+            .args(["-0", pid.trim()])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!alive.success());
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
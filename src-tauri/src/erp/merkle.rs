@@ -0,0 +1,450 @@
+//! merkle.rs — Merkle tree over audit entries, signed roots and inclusion proofs
+//!
+//! The audit log is a linear chain, so proving one entry from the chain alone
+//! needs every entry before it. Here the entries of an org's log (in order)
+//! are also the leaves of a Merkle tree:
+//!
+//!   leaf = SHA-256(0x00 || chain_hash)
+//!   node = SHA-256(0x01 || left || right)
+//!
+//! An odd node at the end of a level is carried up unchanged. Every
+//! `ROOT_INTERVAL` entries the node signs the root over all entries so far
+//! and appends it to `merkle_roots.jsonl` in the org's audit directory.
+//!
+//! `verify_inclusion` is standalone: given one entry, its proof and a signed
+//! root it checks the root signature, the entry's own chain_hash, and the path
+//! from leaf to root — nothing else from the log is needed.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+use crate::erp::audit_log::{self, ErpAuditEntry};
+use crate::erp::errors::ErpError;
+
+/// Audit entries between automatically signed roots.
+pub const ROOT_INTERVAL: u64 = 100;
+
+/// Which side of the running hash a sibling sits on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// One step from a leaf towards the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofStep {
+    pub side: Side,
+    pub hash: String,
+}
+
+/// Inclusion proof for the leaf at `leaf_index` in a tree of `tree_size` leaves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InclusionProof {
+    pub leaf_index: u64,
+    pub tree_size: u64,
+    pub leaf_hash: String,
+    pub path: Vec<ProofStep>,
+    pub root: String,
+}
+
+/// A Merkle root over the first `tree_size` entries, signed by the node key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRoot {
+    pub org_id: String,
+    pub tree_size: u64,
+    pub root: String,
+    /// chain_hash of the last covered entry, tying the root to the linear chain
+    pub last_chain_hash: String,
+    pub signed_at_ms: i64,
+    pub signer_pubkey: String,
+    pub signature: String,
+}
+
+fn sha(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for p in parts {
+        hasher.update(p);
+    }
+    hasher.finalize().into()
+}
+
+/// Leaf hash for an entry's chain_hash.
+pub fn leaf_hash(chain_hash: &str) -> [u8; 32] {
+    sha(&[&[0x00], chain_hash.as_bytes()])
+}
+
+fn node_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    sha(&[&[0x01], left, right])
+}
+
+/// Root over `leaves` (hex). An empty tree has the root of zero bytes.
+pub fn root_of(leaves: &[[u8; 32]]) -> String {
+    if leaves.is_empty() {
+        return hex::encode(sha(&[]));
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [l, r] => node_hash(l, r),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    hex::encode(level[0])
+}
+
+/// The sibling sides a proof for `index` in a tree of `size` must have,
+/// bottom-up. Levels where the node is carried up have no step.
+fn path_shape(mut index: u64, mut size: u64) -> Vec<Side> {
+    let mut sides = Vec::new();
+    while size > 1 {
+        if index % 2 == 1 {
+            sides.push(Side::Left);
+        } else if index + 1 < size {
+            sides.push(Side::Right);
+        }
+        index /= 2;
+        size = size.div_ceil(2);
+    }
+    sides
+}
+
+/// Build the inclusion proof for leaf `index` of `leaves`.
+pub fn prove(leaves: &[[u8; 32]], index: usize) -> Option<InclusionProof> {
+    if index >= leaves.len() {
+        return None;
+    }
+    let mut path = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut i = index;
+    while level.len() > 1 {
+        let sibling = if i % 2 == 1 {
+            Some((Side::Left, level[i - 1]))
+        } else {
+            level.get(i + 1).map(|h| (Side::Right, *h))
+        };
+        if let Some((side, hash)) = sibling {
+            path.push(ProofStep {
+                side,
+                hash: hex::encode(hash),
+            });
+        }
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [l, r] => node_hash(l, r),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+        i /= 2;
+    }
+    Some(InclusionProof {
+        leaf_index: index as u64,
+        tree_size: leaves.len() as u64,
+        leaf_hash: hex::encode(leaves[index]),
+        path,
+        root: hex::encode(level[0]),
+    })
+}
+
+/// Fold a proof's path from its leaf; true if it reaches `proof.root` and
+/// the path has the shape its index and tree size require.
+pub fn verify_path(proof: &InclusionProof) -> bool {
+    let shape = path_shape(proof.leaf_index, proof.tree_size);
+    if proof.leaf_index >= proof.tree_size
+        || shape.len() != proof.path.len()
+        || shape
+            .iter()
+            .zip(&proof.path)
+            .any(|(s, step)| *s != step.side)
+    {
+        return false;
+    }
+    let decode = |h: &str| -> Option<[u8; 32]> { hex::decode(h).ok()?.try_into().ok() };
+    let Some(mut acc) = decode(&proof.leaf_hash) else {
+        return false;
+    };
+    for step in &proof.path {
+        let Some(sibling) = decode(&step.hash) else {
+            return false;
+        };
+        acc = match step.side {
+            Side::Left => node_hash(&sibling, &acc),
+            Side::Right => node_hash(&acc, &sibling),
+        };
+    }
+    hex::encode(acc) == proof.root
+}
+
+impl SignedRoot {
+    /// "mr1" || org_id || tree_size_be_bytes || root || last_chain_hash || signed_at_ms_be_bytes
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(b"mr1");
+        payload.extend_from_slice(self.org_id.as_bytes());
+        payload.extend_from_slice(&self.tree_size.to_be_bytes());
+        payload.extend_from_slice(self.root.as_bytes());
+        payload.extend_from_slice(self.last_chain_hash.as_bytes());
+        payload.extend_from_slice(&self.signed_at_ms.to_be_bytes());
+        payload
+    }
+
+    /// Sign the root over `entries` (all of them, in log order).
+    pub fn sign(signing_key: &SigningKey, org_id: &str, entries: &[ErpAuditEntry]) -> Self {
+        let leaves: Vec<[u8; 32]> = entries.iter().map(|e| leaf_hash(&e.chain_hash)).collect();
+        let mut root = SignedRoot {
+            org_id: org_id.to_string(),
+            tree_size: entries.len() as u64,
+            root: root_of(&leaves),
+            last_chain_hash: entries
+                .last()
+                .map(|e| e.chain_hash.clone())
+                .unwrap_or_else(|| "erp_genesis".to_string()),
+            signed_at_ms: chrono::Utc::now().timestamp_millis(),
+            signer_pubkey: hex::encode(signing_key.verifying_key().to_bytes()),
+            signature: String::new(),
+        };
+        let sig: Signature = signing_key.sign(&root.signing_payload());
+        root.signature = hex::encode(sig.to_bytes());
+        root
+    }
+
+    pub fn verify_signature(&self) -> Result<(), ErpError> {
+        verify_signed(
+            &self.signer_pubkey,
+            &self.signature,
+            &self.signing_payload(),
+        )
+        .map_err(|e| ErpError::SigInvalid(format!("merkle root {}: {}", self.tree_size, e)))
+    }
+}
+
+/// Verify a hex Ed25519 `signature` by hex `pubkey` over `payload` — the
+/// check behind signed roots and evidence manifests.
+pub fn verify_signed(pubkey: &str, signature: &str, payload: &[u8]) -> Result<(), ErpError> {
+    let pubkey: [u8; 32] = hex::decode(pubkey)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| ErpError::SigInvalid("invalid public key".to_string()))?;
+    let key = VerifyingKey::from_bytes(&pubkey)
+        .map_err(|e| ErpError::SigInvalid(format!("invalid public key: {}", e)))?;
+    let sig: [u8; 64] = hex::decode(signature)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| ErpError::SigInvalid("invalid signature".to_string()))?;
+    key.verify(payload, &Signature::from_bytes(&sig))
+        .map_err(|e| ErpError::SigInvalid(e.to_string()))
+}
+
+/// Standalone verifier for an auditor: checks that `entry` is leaf
+/// `proof.leaf_index` of the tree whose root `root` was signed by
+/// `root.signer_pubkey`. Optionally pin the expected signer.
+pub fn verify_inclusion(
+    entry: &ErpAuditEntry,
+    proof: &InclusionProof,
+    root: &SignedRoot,
+    expected_signer: Option<&str>,
+) -> Result<(), ErpError> {
+    if let Some(signer) = expected_signer {
+        if signer != root.signer_pubkey {
+            return Err(ErpError::SigInvalid(format!(
+                "root signed by {}, expected {}",
+                root.signer_pubkey, signer
+            )));
+        }
+    }
+    root.verify_signature()?;
+    if proof.root != root.root || proof.tree_size != root.tree_size {
+        return Err(ErpError::ValidationFail(
+            "proof is not against the signed root".to_string(),
+        ));
+    }
+
+    // The entry must hash to its own chain_hash
+    if audit_log::chain_hash_of(&entry.envelope, &entry.chain_prev_hash) != entry.chain_hash {
+        return Err(ErpError::ValidationFail(format!(
+            "entry {} does not match its chain_hash",
+            entry.envelope.mutation_id
+        )));
+    }
+    if hex::encode(leaf_hash(&entry.chain_hash)) != proof.leaf_hash {
+        return Err(ErpError::ValidationFail(
+            "proof leaf is not this entry".to_string(),
+        ));
+    }
+    if !verify_path(proof) {
+        return Err(ErpError::ValidationFail(
+            "inclusion path does not reach the root".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+// ─── Signed root storage ─────────────────────────────────────────────────────
+
+fn roots_path(org_id: &str) -> std::path::PathBuf {
+    audit_log::org_dir(org_id).join("merkle_roots.jsonl")
+}
+
+/// Signed roots of the org, oldest first.
+pub fn read_roots(org_id: &str) -> Vec<SignedRoot> {
+    let Ok(f) = File::open(roots_path(org_id)) else {
+        return Vec::new();
+    };
+    BufReader::new(f)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| serde_json::from_str::<SignedRoot>(&line).ok())
+        .collect()
+}
+
+/// Sign and publish the root over the first `tree_size` entries of the org.
+pub fn publish_root(org_id: &str, tree_size: u64) -> std::io::Result<SignedRoot> {
+    let entries = audit_log::read_log_bounded(org_id, i64::MIN, i64::MAX)?;
+    let covered = &entries[..(tree_size as usize).min(entries.len())];
+    let root = SignedRoot::sign(&crate::erp::envelope::get_signing_key(), org_id, covered);
+    let line = serde_json::to_string(&root)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+    let path = roots_path(org_id);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(std::io::LineWriter::new(file), "{}", line)?;
+    Ok(root)
+}
+
+/// Inclusion proof for `entries[index]` (the org's log, in order) against the
+/// latest published root that covers it. If none does yet, a root over all of
+/// `entries` is signed and published first.
+pub fn prove_entry(
+    org_id: &str,
+    entries: &[ErpAuditEntry],
+    index: usize,
+) -> Result<(InclusionProof, SignedRoot), ErpError> {
+    let covering = read_roots(org_id)
+        .into_iter()
+        .rev()
+        .find(|r| r.tree_size > index as u64 && r.tree_size <= entries.len() as u64);
+    let signed_root = match covering {
+        Some(r) => r,
+        None => publish_root(org_id, entries.len() as u64)
+            .map_err(|e| ErpError::ValidationFail(e.to_string()))?,
+    };
+    let leaves: Vec<[u8; 32]> = entries[..signed_root.tree_size as usize]
+        .iter()
+        .map(|e| leaf_hash(&e.chain_hash))
+        .collect();
+    let proof = prove(&leaves, index)
+        .ok_or_else(|| ErpError::ValidationFail(format!("no audit entry at position {}", index)))?;
+    if proof.root != signed_root.root {
+        return Err(ErpError::ValidationFail(format!(
+            "published root {} does not match the log",
+            signed_root.tree_size
+        )));
+    }
+    Ok((proof, signed_root))
+}
+
+/// Called after each audit append; every `ROOT_INTERVAL` entries publishes a
+/// signed root on a background thread, so reading the log never runs under
+/// the caller's store or audit lock (as `checkpoint::maybe_checkpoint`).
+/// Best-effort: failures are logged only.
+pub fn maybe_publish_root(org_id: &str, seq: u64) {
+    if seq == 0 || !seq.is_multiple_of(ROOT_INTERVAL) {
+        return;
+    }
+    let org_id = org_id.to_string();
+    std::thread::spawn(move || {
+        if let Err(e) = publish_root(&org_id, seq) {
+            eprintln!("⚠️  ERP merkle root {org_id}/{seq} failed: {e}");
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::signed_envelope;
+    use rand::rngs::OsRng;
+
+    fn leaves(n: usize) -> Vec<[u8; 32]> {
+        (0..n).map(|i| leaf_hash(&format!("h{}", i))).collect()
+    }
+
+    #[test]
+    fn test_every_leaf_proves_for_odd_and_even_sizes() {
+        for n in [1, 2, 3, 5, 8, 13] {
+            let ls = leaves(n);
+            let root = root_of(&ls);
+            for i in 0..n {
+                let proof = prove(&ls, i).unwrap();
+                assert_eq!(proof.root, root);
+                assert!(verify_path(&proof), "leaf {} of {}", i, n);
+            }
+        }
+        assert!(prove(&leaves(3), 3).is_none());
+    }
+
+    #[test]
+    fn test_tampered_proofs_fail() {
+        let ls = leaves(7);
+        let proof = prove(&ls, 4).unwrap();
+
+        let mut wrong_leaf = proof.clone();
+        wrong_leaf.leaf_hash = hex::encode(leaf_hash("other"));
+        assert!(!verify_path(&wrong_leaf));
+
+        // Claiming another position changes the required path shape / hashes
+        let mut moved = proof.clone();
+        moved.leaf_index = 5;
+        assert!(!verify_path(&moved));
+
+        let mut flipped = proof;
+        flipped.path[0].side = match flipped.path[0].side {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        };
+        assert!(!verify_path(&flipped));
+    }
+
+    #[test]
+    fn test_verify_inclusion_against_signed_root() {
+        let key = SigningKey::generate(&mut OsRng);
+        let mut prev = "erp_genesis".to_string();
+        let entries: Vec<ErpAuditEntry> = (1..=5u64)
+            .map(|n| {
+                let envelope = signed_envelope(&key, &format!("m{}", n), "pk", vec![], None, n);
+                let chain_hash = audit_log::chain_hash_of(&envelope, &prev);
+                let entry = ErpAuditEntry {
+                    chain_prev_hash: prev.clone(),
+                    chain_hash: chain_hash.clone(),
+                    envelope,
+                };
+                prev = chain_hash;
+                entry
+            })
+            .collect();
+
+        let root = SignedRoot::sign(&key, "org1", &entries);
+        let ls: Vec<[u8; 32]> = entries.iter().map(|e| leaf_hash(&e.chain_hash)).collect();
+        let proof = prove(&ls, 2).unwrap();
+        assert!(verify_inclusion(&entries[2], &proof, &root, Some(&root.signer_pubkey)).is_ok());
+
+        // Wrong entry, wrong signer, forged root
+        assert!(verify_inclusion(&entries[3], &proof, &root, None).is_err());
+        assert!(verify_inclusion(&entries[2], &proof, &root, Some("00")).is_err());
+        let mut forged = root.clone();
+        forged.tree_size = 4;
+        assert!(verify_inclusion(&entries[2], &proof, &forged, None).is_err());
+    }
+}
@@ -1,16 +1,38 @@
 pub mod abac;
+pub mod anomaly;
+pub mod apply;
+pub mod audit_index;
 pub mod audit_log;
 pub mod caio_llm;
+pub mod caio_tools;
+pub mod checkpoint;
+pub mod coa;
 pub mod coa_templates;
+pub mod conflict;
+pub mod consistency;
 pub mod db;
 pub mod engine;
 pub mod envelope;
 pub mod errors;
+pub mod evidence;
 pub mod fragments;
+pub mod hlc;
 pub mod indexes;
+pub mod journal;
 pub mod ledger;
+pub mod llm_backend;
+pub mod merkle;
+pub mod notes;
 pub mod post;
+pub mod proposals;
+pub mod reorder;
 pub mod replay;
+pub mod satellite;
 pub mod status;
+pub mod stocktake;
 pub mod tauri_api;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod timetravel;
 pub mod types;
+pub mod ydoc;
@@ -0,0 +1,347 @@
+//! notes.rs — Credit / debit notes linked to a source invoice
+//!
+//! A `credit_note` adjusts a posted `invoice_out`, a `debit_note` a posted
+//! `invoice_in`. Each note line references the invoice line it adjusts via
+//! `source_line_id`; the quantity and amount credited across every non-void
+//! note may not exceed what was invoiced on that line.
+//!
+//! Credit note lines with `inventory_effect = increase` return goods to stock;
+//! their InvMoves drive the COGS reversal in `ledger::generate_postings`.
+//! Posted notes reduce the invoice's open balance (`invoice_balance`).
+
+use serde::{Deserialize, Serialize};
+
+use crate::erp::errors::ErpError;
+use crate::erp::ledger::ROUNDING_TOLERANCE;
+use crate::erp::types::{InventoryEffect, TxHeader, TxLine, TxStatus, TxType};
+
+/// The invoice type a note of `note_type` must reference, if it is a note.
+pub fn source_type_for(note_type: &TxType) -> Option<TxType> {
+    match note_type {
+        TxType::CreditNote => Some(TxType::InvoiceOut),
+        TxType::DebitNote => Some(TxType::InvoiceIn),
+        _ => None,
+    }
+}
+
+/// Notes must name their source invoice: the per-line limits are measured
+/// against it, so a note without one could credit any amount.
+pub fn require_note_source(tx_type: &TxType, source_tx_id: Option<&str>) -> Result<(), ErpError> {
+    if source_type_for(tx_type).is_some() && source_tx_id.is_none_or(|s| s.trim().is_empty()) {
+        return Err(ErpError::InvalidField(format!(
+            "{} requires source_tx_id",
+            tx_type.as_str()
+        )));
+    }
+    Ok(())
+}
+
+/// Validate that `source` is a legal source invoice for a new note.
+pub fn validate_note_source(
+    note_type: &TxType,
+    org_id: &str,
+    party_id: Option<&str>,
+    source: &TxHeader,
+) -> Result<(), ErpError> {
+    let expected = source_type_for(note_type).ok_or_else(|| {
+        ErpError::InvalidField(format!(
+            "source_tx_id is only valid on credit/debit notes, not {}",
+            note_type.as_str()
+        ))
+    })?;
+    if source.tx_type != expected {
+        return Err(ErpError::ValidationFail(format!(
+            "{} must reference an {}, but {} is an {}",
+            note_type.as_str(),
+            expected.as_str(),
+            source.tx_id,
+            source.tx_type.as_str()
+        )));
+    }
+    if source.org_id != org_id {
+        return Err(ErpError::ValidationFail(format!(
+            "source tx {} belongs to a different org",
+            source.tx_id
+        )));
+    }
+    if source.status != TxStatus::Posted {
+        return Err(ErpError::ValidationFail(format!(
+            "source tx {} is {} — only posted invoices can be credited or debited",
+            source.tx_id,
+            source.status.as_str()
+        )));
+    }
+    if let (Some(note_party), Some(src_party)) = (party_id, source.party_id.as_deref()) {
+        if note_party != src_party {
+            return Err(ErpError::ValidationFail(format!(
+                "note party {} does not match invoice party {}",
+                note_party, src_party
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Validate a new note line against its source invoice line.
+///
+/// `source_lines` are the lines of the source invoice; `noted_lines` are the
+/// lines already on non-void notes against the same invoice (including earlier
+/// lines of this note).
+pub fn validate_note_line(
+    note_type: &TxType,
+    source_lines: &[TxLine],
+    noted_lines: &[TxLine],
+    line: &TxLine,
+) -> Result<(), ErpError> {
+    let source_line_id = line
+        .source_line_id
+        .as_deref()
+        .ok_or_else(|| ErpError::InvalidField("note lines must set source_line_id".to_string()))?;
+    let source = source_lines
+        .iter()
+        .find(|l| l.line_id == source_line_id)
+        .ok_or_else(|| {
+            ErpError::ValidationFail(format!(
+                "source line {} is not on the source invoice",
+                source_line_id
+            ))
+        })?;
+
+    if let (Some(item), Some(src_item)) = (line.item_id.as_ref(), source.item_id.as_ref()) {
+        if item != src_item {
+            return Err(ErpError::ItemMismatch(item.clone(), src_item.clone()));
+        }
+    }
+
+    // Credit notes may return goods to stock; debit notes may send them back.
+    let allowed = match note_type {
+        TxType::CreditNote => !matches!(line.inventory_effect, InventoryEffect::Decrease),
+        _ => !matches!(line.inventory_effect, InventoryEffect::Increase),
+    };
+    if !allowed {
+        return Err(ErpError::ValidationFail(format!(
+            "inventory effect '{}' is not valid on a {}",
+            line.inventory_effect.as_str(),
+            note_type.as_str()
+        )));
+    }
+
+    if line.qty < 0.0 || line.unit_price < 0.0 {
+        return Err(ErpError::InvalidField(
+            "note line qty and unit_price must not be negative".to_string(),
+        ));
+    }
+
+    let prior: Vec<&TxLine> = noted_lines
+        .iter()
+        .filter(|l| l.source_line_id.as_deref() == Some(source_line_id))
+        .collect();
+    let prior_qty: f64 = prior.iter().map(|l| l.qty).sum();
+    let prior_amount: f64 = prior.iter().map(|l| l.qty * l.unit_price).sum();
+
+    let remaining_qty = source.qty - prior_qty;
+    if line.qty > remaining_qty + ROUNDING_TOLERANCE {
+        return Err(ErpError::NoteExceedsSource(
+            "qty".to_string(),
+            line.qty,
+            remaining_qty.max(0.0),
+        ));
+    }
+    let remaining_amount = source.qty * source.unit_price - prior_amount;
+    let amount = line.qty * line.unit_price;
+    if amount > remaining_amount + ROUNDING_TOLERANCE {
+        return Err(ErpError::NoteExceedsSource(
+            "amount".to_string(),
+            amount,
+            remaining_amount.max(0.0),
+        ));
+    }
+    Ok(())
+}
+
+/// Open balance of an invoice after credit / debit notes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InvoiceBalance {
+    pub tx_id: String,
+    pub gross_total: f64,
+    /// Gross value of posted notes against this invoice
+    pub noted_total: f64,
+    /// Gross value of draft / proposed / approved notes not yet posted
+    pub pending_total: f64,
+    pub open_balance: f64,
+    pub note_tx_ids: Vec<String>,
+}
+
+/// Compute an invoice's open balance from its lines and the notes against it.
+/// `notes` pairs each note header with its lines; void notes are ignored.
+pub fn invoice_balance(
+    invoice: &TxHeader,
+    invoice_lines: &[TxLine],
+    notes: &[(TxHeader, Vec<TxLine>)],
+) -> InvoiceBalance {
+    let gross_total = gross(invoice_lines);
+    let mut noted_total = 0.0;
+    let mut pending_total = 0.0;
+    let mut note_tx_ids = Vec::new();
+    for (note, lines) in notes {
+        if note.source_tx_id.as_deref() != Some(invoice.tx_id.as_str())
+            || note.status == TxStatus::Void
+        {
+            continue;
+        }
+        note_tx_ids.push(note.tx_id.clone());
+        if note.status == TxStatus::Posted {
+            noted_total += gross(lines);
+        } else {
+            pending_total += gross(lines);
+        }
+    }
+    InvoiceBalance {
+        tx_id: invoice.tx_id.clone(),
+        gross_total,
+        noted_total: round2(noted_total),
+        pending_total: round2(pending_total),
+        open_balance: round2(gross_total - noted_total),
+        note_tx_ids,
+    }
+}
+
+/// Gross (tax-inclusive) value of a set of lines.
+fn gross(lines: &[TxLine]) -> f64 {
+    round2(
+        lines
+            .iter()
+            .map(|l| l.qty * l.unit_price * (1.0 + l.tax_rate))
+            .sum(),
+    )
+}
+
+fn round2(v: f64) -> f64 {
+    (v * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(tx_id: &str, tx_type: TxType, status: TxStatus, source: Option<&str>) -> TxHeader {
+        TxHeader {
+            tx_id: tx_id.to_string(),
+            org_id: "org1".to_string(),
+            tx_type,
+            status,
+            party_id: Some("cust1".to_string()),
+            currency: "AUD".to_string(),
+            ref_number: None,
+            description: None,
+            tx_date: "2026-03-01".to_string(),
+            created_at_ms: 0,
+            created_by_pubkey: "pk1".to_string(),
+            site_id: "primary".to_string(),
+            source_tx_id: source.map(str::to_string),
+        }
+    }
+
+    fn line(line_id: &str, qty: f64, unit_price: f64, source: Option<&str>) -> TxLine {
+        TxLine {
+            line_id: line_id.to_string(),
+            tx_id: "tx".to_string(),
+            item_id: Some("widget".to_string()),
+            account_id: None,
+            description: None,
+            qty,
+            unit_price,
+            inventory_effect: InventoryEffect::None,
+            move_ids: vec![],
+            tax_code: Some("GST".to_string()),
+            tax_rate: 0.1,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: source.map(str::to_string),
+            reason_code: None,
+        }
+    }
+
+    #[test]
+    fn test_note_source_rules() {
+        let inv = header("inv1", TxType::InvoiceOut, TxStatus::Posted, None);
+        assert!(validate_note_source(&TxType::CreditNote, "org1", Some("cust1"), &inv).is_ok());
+        // Debit notes reference supplier invoices
+        assert!(validate_note_source(&TxType::DebitNote, "org1", None, &inv).is_err());
+        // Party must match
+        assert!(validate_note_source(&TxType::CreditNote, "org1", Some("x"), &inv).is_err());
+        // Unposted invoices cannot be credited
+        let draft = header("inv2", TxType::InvoiceOut, TxStatus::Draft, None);
+        assert!(validate_note_source(&TxType::CreditNote, "org1", None, &draft).is_err());
+        // A note must name its source; other types need not
+        assert!(require_note_source(&TxType::CreditNote, None).is_err());
+        assert!(require_note_source(&TxType::DebitNote, Some(" ")).is_err());
+        assert!(require_note_source(&TxType::DebitNote, Some("inv3")).is_ok());
+        assert!(require_note_source(&TxType::InvoiceOut, None).is_ok());
+    }
+
+    #[test]
+    fn test_note_line_cannot_exceed_source() {
+        let source = vec![line("src1", 10.0, 50.0, None)];
+        let prior = vec![line("n1", 6.0, 50.0, Some("src1"))];
+
+        let ok = line("n2", 4.0, 50.0, Some("src1"));
+        assert!(validate_note_line(&TxType::CreditNote, &source, &prior, &ok).is_ok());
+
+        let too_many = line("n3", 5.0, 50.0, Some("src1"));
+        assert_eq!(
+            validate_note_line(&TxType::CreditNote, &source, &prior, &too_many),
+            Err(ErpError::NoteExceedsSource("qty".to_string(), 5.0, 4.0))
+        );
+
+        // Price uplift on a credit note exceeds the remaining invoiced amount
+        let too_much = line("n4", 4.0, 60.0, Some("src1"));
+        assert_eq!(
+            validate_note_line(&TxType::CreditNote, &source, &prior, &too_much),
+            Err(ErpError::NoteExceedsSource(
+                "amount".to_string(),
+                240.0,
+                200.0
+            ))
+        );
+
+        let unlinked = line("n5", 1.0, 50.0, None);
+        assert!(validate_note_line(&TxType::CreditNote, &source, &prior, &unlinked).is_err());
+    }
+
+    #[test]
+    fn test_credit_note_cannot_decrease_stock() {
+        let source = vec![line("src1", 10.0, 50.0, None)];
+        let mut ret = line("n1", 1.0, 50.0, Some("src1"));
+        ret.inventory_effect = InventoryEffect::Increase;
+        assert!(validate_note_line(&TxType::CreditNote, &source, &[], &ret).is_ok());
+        ret.inventory_effect = InventoryEffect::Decrease;
+        assert!(validate_note_line(&TxType::CreditNote, &source, &[], &ret).is_err());
+    }
+
+    #[test]
+    fn test_invoice_open_balance() {
+        let inv = header("inv1", TxType::InvoiceOut, TxStatus::Posted, None);
+        let inv_lines = vec![line("src1", 10.0, 100.0, None)];
+        let notes = vec![
+            (
+                header("cn1", TxType::CreditNote, TxStatus::Posted, Some("inv1")),
+                vec![line("n1", 2.0, 100.0, Some("src1"))],
+            ),
+            (
+                header("cn2", TxType::CreditNote, TxStatus::Draft, Some("inv1")),
+                vec![line("n2", 1.0, 100.0, Some("src1"))],
+            ),
+            (
+                header("cn3", TxType::CreditNote, TxStatus::Void, Some("inv1")),
+                vec![line("n3", 5.0, 100.0, Some("src1"))],
+            ),
+        ];
+        let bal = invoice_balance(&inv, &inv_lines, &notes);
+        assert_eq!(bal.gross_total, 1100.0);
+        assert_eq!(bal.noted_total, 220.0);
+        assert_eq!(bal.pending_total, 110.0);
+        assert_eq!(bal.open_balance, 880.0);
+        assert_eq!(bal.note_tx_ids, vec!["cn1", "cn2"]);
+    }
+}
@@ -111,7 +111,10 @@ pub fn validate_post(
 }
 
 /// Validates that an inventory move's qty_delta sign matches the expected direction.
-fn validate_invmove_direction(m: &InvMove, effect: &InventoryEffect) -> Result<(), ErpError> {
+pub(crate) fn validate_invmove_direction(
+    m: &InvMove,
+    effect: &InventoryEffect,
+) -> Result<(), ErpError> {
     match effect.expected_sign() {
         Some(expected) if (m.qty_delta * expected) < 0.0 => Err(ErpError::InventoryEffectMismatch(
             m.qty_delta,
@@ -149,6 +152,7 @@ mod tests {
             created_at_ms: 0,
             created_by_pubkey: "pk1".to_string(),
             site_id: "primary".to_string(),
+            source_tx_id: None,
         }
     }
 
@@ -165,6 +169,10 @@ mod tests {
             move_ids: vec![],
             tax_code: None,
             tax_rate: 0.0,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
         }
     }
 
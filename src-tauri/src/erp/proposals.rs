@@ -0,0 +1,350 @@
+//! proposals.rs — CAIO proposals as signed, reviewable ops
+//!
+//! A proposal is a `ProposalCreate` op holding concrete ops (e.g. a draft
+//! invoice or reorder purchase built by `engine::draft_tx_ops`) that are not
+//! applied, signed by the actor CAIO ran for. Its review fields (org, kind,
+//! title, status, decision) are per-field ops on the `proposal:{id}`
+//! fragment, so the queue is rebuilt from the audit log like any record.
+//!
+//! Reviewing is a signed envelope too:
+//! - `accept` — the held ops plus `status = accepted`, validated and applied
+//!   as one envelope through the remote-edit checks (`engine::apply_validated`),
+//!   so the reviewer needs `proposal.review` and the permissions of the ops.
+//! - `reject` — only `status = rejected` and the reason; nothing is applied.
+//!
+//! A decided proposal cannot be decided again (`satellite::op_checks`).
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::erp::engine::{self, ErpStore, ERP_STORE};
+use crate::erp::envelope::MutationEnvelope;
+use crate::erp::errors::ErpError;
+use crate::erp::fragments;
+use crate::erp::satellite;
+use crate::erp::types::{
+    ActorContext, AddLineRequest, CreateTxRequest, Op, PendingProposal, PolicyContext,
+    ProposalStatus,
+};
+
+/// A draft transaction as CAIO proposes it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedTx {
+    pub tx_type: String,
+    #[serde(default)]
+    pub party_id: Option<String>,
+    pub currency: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub tx_date: String,
+    pub lines: Vec<ProposedLine>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProposedLine {
+    #[serde(default)]
+    pub item_id: Option<String>,
+    #[serde(default)]
+    pub account_id: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub qty: f64,
+    pub unit_price: f64,
+    /// "none" | "increase" | "decrease"
+    #[serde(default = "default_inventory_effect")]
+    pub inventory_effect: String,
+    #[serde(default)]
+    pub tax_rate: Option<f64>,
+}
+
+fn default_inventory_effect() -> String {
+    "none".to_string()
+}
+
+impl ProposedTx {
+    /// Ops creating this draft in `org_id`.
+    pub fn ops(&self, actor: &ActorContext, org_id: &str) -> Result<Vec<Op>, ErpError> {
+        let req = CreateTxRequest {
+            tx_type: self.tx_type.clone(),
+            org_id: org_id.to_string(),
+            party_id: self.party_id.clone(),
+            currency: self.currency.clone(),
+            ref_number: None,
+            description: self.description.clone(),
+            tx_date: self.tx_date.clone(),
+            site_id: None,
+            source_tx_id: None,
+        };
+        let lines: Vec<AddLineRequest> = self
+            .lines
+            .iter()
+            .map(|l| AddLineRequest {
+                tx_id: String::new(),
+                item_id: l.item_id.clone(),
+                account_id: l.account_id.clone(),
+                description: l.description.clone(),
+                qty: l.qty,
+                unit_price: l.unit_price,
+                inventory_effect: l.inventory_effect.clone(),
+                tax_code: l.tax_rate.map(|_| "GST".to_string()),
+                tax_rate: l.tax_rate,
+                debit_amount: None,
+                credit_amount: None,
+                source_line_id: None,
+                reason_code: None,
+            })
+            .collect();
+        Ok(engine::draft_tx_ops(actor, &req, &lines)?.1)
+    }
+}
+
+/// What to queue for review.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewProposal {
+    /// CAIO proposal type: "draft_invoice" | "reorder_proposal" | …
+    pub kind: String,
+    pub title: String,
+    pub rationale: String,
+    pub source_fragment: String,
+    pub ops: Vec<Op>,
+}
+
+/// Queue `proposal` as a signed `ProposalCreate` envelope from `actor`. The
+/// held ops are validated for `actor` now (a dry run), and again for the
+/// reviewer on accept.
+pub fn propose(actor: &ActorContext, proposal: NewProposal) -> Result<PendingProposal, ErpError> {
+    if proposal.ops.is_empty() {
+        return Err(ErpError::ValidationFail(
+            "a proposal must hold at least one op".to_string(),
+        ));
+    }
+    let mut store = ERP_STORE.lock().unwrap();
+    satellite::validate_ops(&store, actor, &proposal.ops)?;
+
+    let proposal_id = Uuid::new_v4().to_string();
+    let fragment_id = fragments::proposal_id(&proposal_id);
+    let mut ops = vec![Op::ProposalCreate {
+        proposal_id: proposal_id.clone(),
+        source_fragment: proposal.source_fragment,
+        ops: proposal.ops.into_iter().map(Box::new).collect(),
+        rationale: proposal.rationale,
+    }];
+    ops.extend(
+        [
+            ("org_id", serde_json::json!(actor.org_id)),
+            ("kind", serde_json::json!(proposal.kind)),
+            ("title", serde_json::json!(proposal.title)),
+            ("proposed_by", serde_json::json!(actor.pubkey)),
+            (
+                "created_at_ms",
+                serde_json::json!(chrono::Utc::now().timestamp_millis()),
+            ),
+            (
+                "status",
+                serde_json::json!(ProposalStatus::Pending.as_str()),
+            ),
+        ]
+        .into_iter()
+        .map(|(key, value)| Op::MapSet {
+            fragment_id: fragment_id.clone(),
+            key: key.to_string(),
+            value,
+        }),
+    );
+    satellite::validate_ops(&store, actor, &ops)?;
+
+    let policy_ctx = PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    let envelope = engine::sign_next(&mut store, actor, ops, policy_ctx)?;
+    engine::commit(store, &envelope);
+    get(&proposal_id).ok_or_else(|| ErpError::ValidationFail("proposal not queued".to_string()))
+}
+
+/// Apply a pending proposal's ops as `actor`, marking it accepted.
+pub fn accept(actor: &ActorContext, proposal_id: &str) -> Result<MutationEnvelope, ErpError> {
+    let mut ops = pending(actor, proposal_id)?.ops;
+    ops.extend(decision_ops(
+        actor,
+        proposal_id,
+        ProposalStatus::Accepted,
+        None,
+    ));
+    engine::apply_validated(actor, ops)
+}
+
+/// Mark a pending proposal rejected; its ops are discarded.
+pub fn reject(
+    actor: &ActorContext,
+    proposal_id: &str,
+    reason: Option<String>,
+) -> Result<MutationEnvelope, ErpError> {
+    pending(actor, proposal_id)?;
+    let reason = reason.filter(|r| !r.trim().is_empty());
+    engine::apply_validated(
+        actor,
+        decision_ops(actor, proposal_id, ProposalStatus::Rejected, reason),
+    )
+}
+
+fn pending(actor: &ActorContext, proposal_id: &str) -> Result<PendingProposal, ErpError> {
+    let p = get(proposal_id)
+        .filter(|p| p.org_id == actor.org_id)
+        .ok_or_else(|| ErpError::ValidationFail(format!("proposal {} not found", proposal_id)))?;
+    if !p.status.is_pending() {
+        return Err(ErpError::ValidationFail(format!(
+            "proposal {} is already {}",
+            proposal_id,
+            p.status.as_str()
+        )));
+    }
+    Ok(p)
+}
+
+/// Decision fields, `status` last: once it is set the proposal is final.
+fn decision_ops(
+    actor: &ActorContext,
+    proposal_id: &str,
+    status: ProposalStatus,
+    reason: Option<String>,
+) -> Vec<Op> {
+    let fragment_id = fragments::proposal_id(proposal_id);
+    let mut fields = vec![
+        ("decided_by", serde_json::json!(actor.pubkey)),
+        (
+            "decided_at_ms",
+            serde_json::json!(chrono::Utc::now().timestamp_millis()),
+        ),
+    ];
+    if let Some(reason) = reason {
+        fields.push(("reason", serde_json::json!(reason)));
+    }
+    fields.push(("status", serde_json::json!(status.as_str())));
+    fields
+        .into_iter()
+        .map(|(key, value)| Op::MapSet {
+            fragment_id: fragment_id.clone(),
+            key: key.to_string(),
+            value,
+        })
+        .collect()
+}
+
+pub fn get(proposal_id: &str) -> Option<PendingProposal> {
+    ERP_STORE
+        .lock()
+        .unwrap()
+        .proposals
+        .get(proposal_id)
+        .cloned()
+}
+
+/// The org's proposals, newest first; `status = None` lists all.
+pub fn list(
+    store: &ErpStore,
+    org_id: &str,
+    status: Option<ProposalStatus>,
+) -> Vec<PendingProposal> {
+    let mut list: Vec<PendingProposal> = store
+        .proposals
+        .values()
+        .filter(|p| p.org_id == org_id && status.as_ref().is_none_or(|s| *s == p.status))
+        .cloned()
+        .collect();
+    list.sort_by(|a, b| {
+        b.created_at_ms
+            .cmp(&a.created_at_ms)
+            .then_with(|| a.proposal_id.cmp(&b.proposal_id))
+    });
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::apply::apply_ops;
+    use crate::erp::types::Role;
+
+    fn actor(role: Role) -> ActorContext {
+        ActorContext {
+            pubkey: "pk-reviewer".to_string(),
+            role,
+            org_id: "org1".to_string(),
+            lamport: 0,
+        }
+    }
+
+    #[test]
+    fn test_queue_and_review_fields() {
+        let staff = actor(Role::Staff);
+        let draft = ProposedTx {
+            tx_type: "invoice_out".to_string(),
+            party_id: None,
+            currency: "AUD".to_string(),
+            description: Some("Monthly retainer".to_string()),
+            tx_date: "2026-10-01".to_string(),
+            lines: vec![ProposedLine {
+                item_id: None,
+                account_id: None,
+                description: Some("Retainer".to_string()),
+                qty: 1.0,
+                unit_price: 500.0,
+                inventory_effect: default_inventory_effect(),
+                tax_rate: Some(0.1),
+            }],
+        };
+        let held = draft.ops(&staff, "org1").unwrap();
+        let mut store = ErpStore::new();
+        // The draft itself is valid for its proposer
+        satellite::validate_ops(&store, &staff, &held).unwrap();
+
+        let create = vec![
+            Op::ProposalCreate {
+                proposal_id: "p1".to_string(),
+                source_fragment: "org:org1:indexes".to_string(),
+                ops: held.iter().cloned().map(Box::new).collect(),
+                rationale: "retainer not invoiced this month".to_string(),
+            },
+            Op::MapSet {
+                fragment_id: fragments::proposal_id("p1"),
+                key: "org_id".to_string(),
+                value: serde_json::json!("org1"),
+            },
+        ];
+        // Without its org the proposal could never be listed or decided
+        let orphan = create[..1].to_vec();
+        assert!(satellite::validate_ops(&store, &staff, &orphan).is_err());
+        satellite::validate_ops(&store, &staff, &create).unwrap();
+        apply_ops(&mut store, &create);
+        assert!(store.transactions.is_empty());
+        assert_eq!(list(&store, "org1", Some(ProposalStatus::Pending)).len(), 1);
+
+        // Staff cannot decide; a manager can, once
+        let reject = decision_ops(&staff, "p1", ProposalStatus::Rejected, None);
+        let err = satellite::validate_ops(&store, &staff, &reject).unwrap_err();
+        assert_eq!(err.code(), "ERR_ABAC_DENY");
+        let manager = actor(Role::Manager);
+        let bare = decision_ops(&manager, "p1", ProposalStatus::Accepted, None);
+        let err = satellite::validate_ops(&store, &manager, &bare).unwrap_err();
+        assert!(err.to_string().contains("held ops"));
+        let mut accept = held.clone();
+        accept.extend(decision_ops(&manager, "p1", ProposalStatus::Accepted, None));
+        satellite::validate_ops(&store, &manager, &accept).unwrap();
+        apply_ops(&mut store, &accept);
+        assert_eq!(store.proposals["p1"].status, ProposalStatus::Accepted);
+        assert_eq!(store.transactions.len(), 1);
+        assert_eq!(store.lines.len(), 1);
+        let again = decision_ops(&manager, "p1", ProposalStatus::Rejected, None);
+        assert!(satellite::validate_ops(&store, &manager, &again).is_err());
+
+        // Proposals of another org cannot be created or decided here
+        let foreign = decision_ops(&manager, "p1", ProposalStatus::Rejected, None);
+        let other = ActorContext {
+            org_id: "org2".to_string(),
+            ..manager
+        };
+        assert!(satellite::validate_ops(&store, &other, &foreign).is_err());
+    }
+}
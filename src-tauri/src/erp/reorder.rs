@@ -0,0 +1,505 @@
+//! reorder.rs — Reorder points from inventory movement history
+//!
+//! Per item and org, a `ReorderPolicy` sets the lead time, safety stock,
+//! cover and preferred supplier. Policies are signed per-field ops on
+//! `reorder:{org_id}:{item_id}` fragments, applied through `apply.rs` like
+//! party records (`set_policy`). Consumption is the daily rate of stock issued
+//! (negative `InvMove`s) over the last `CONSUMPTION_WINDOW_DAYS`.
+//!
+//! An item needs reordering when its stock position — on hand across sites
+//! plus quantities on open purchase drafts — is at or below its reorder point
+//! (the policy's, else `daily usage × (lead time + safety days)`). CAIO then
+//! proposes a draft `invoice_in` for the preferred supplier, queued and
+//! accepted like any other proposal (proposals.rs). Without a known unit cost
+//! no draft is made; the card says why.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::erp::apply;
+use crate::erp::caio_llm::LlmProposal;
+use crate::erp::engine::{self, ErpStore, ERP_STORE};
+use crate::erp::errors::ErpError;
+use crate::erp::fragments;
+use crate::erp::proposals::{ProposedLine, ProposedTx};
+use crate::erp::stocktake;
+use crate::erp::types::{ActorContext, InventoryEffect, Op, PolicyContext, TxStatus, TxType};
+
+/// Days of movement history consumption is averaged over.
+pub const CONSUMPTION_WINDOW_DAYS: f64 = 90.0;
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReorderPolicy {
+    /// Set from the actor's org by `set_policy`
+    #[serde(default)]
+    pub org_id: String,
+    pub item_id: String,
+    /// Fixed reorder point; `None` derives it from consumption
+    #[serde(default)]
+    pub reorder_point: Option<f64>,
+    #[serde(default = "default_lead_time_days")]
+    pub lead_time_days: f64,
+    /// Safety stock, in days of consumption
+    #[serde(default = "default_safety_days")]
+    pub safety_days: f64,
+    /// Days of consumption an order should cover beyond the reorder point
+    #[serde(default = "default_cover_days")]
+    pub cover_days: f64,
+    /// Fixed order quantity; `None` orders up to reorder point + cover
+    #[serde(default)]
+    pub reorder_qty: Option<f64>,
+    /// `None` = the supplier of the item's latest purchase
+    #[serde(default)]
+    pub preferred_supplier_id: Option<String>,
+    /// `None` = the item's latest purchase price
+    #[serde(default)]
+    pub unit_cost: Option<f64>,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+}
+
+fn default_lead_time_days() -> f64 {
+    7.0
+}
+
+fn default_safety_days() -> f64 {
+    7.0
+}
+
+fn default_cover_days() -> f64 {
+    30.0
+}
+
+fn default_currency() -> String {
+    "AUD".to_string()
+}
+
+impl ReorderPolicy {
+    /// Defaults for an item without a configured policy.
+    pub fn for_item(item_id: &str) -> Self {
+        Self {
+            org_id: String::new(),
+            item_id: item_id.to_string(),
+            reorder_point: None,
+            lead_time_days: default_lead_time_days(),
+            safety_days: default_safety_days(),
+            cover_days: default_cover_days(),
+            reorder_qty: None,
+            preferred_supplier_id: None,
+            unit_cost: None,
+            currency: default_currency(),
+        }
+    }
+}
+
+/// The org's policies, keyed by item_id.
+pub fn policies_for(store: &ErpStore, org_id: &str) -> BTreeMap<String, ReorderPolicy> {
+    store
+        .reorder_policies
+        .values()
+        .filter(|p| p.org_id == org_id)
+        .map(|p| (p.item_id.clone(), p.clone()))
+        .collect()
+}
+
+/// Sign and commit an item's policy (`Some`) or its removal (`None`) for the
+/// actor's org. Callers check `InventoryManage` and validate the policy.
+pub fn set_policy(
+    actor: &ActorContext,
+    item_id: &str,
+    policy: Option<ReorderPolicy>,
+) -> Result<(), ErpError> {
+    let policy_ctx = PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    let mut store = ERP_STORE.lock().unwrap();
+    let key = format!("{}:{}", actor.org_id, item_id);
+    let ops = match policy {
+        Some(p) => apply::reorder_policy_ops(&ReorderPolicy {
+            org_id: actor.org_id.clone(),
+            item_id: item_id.to_string(),
+            ..p
+        }),
+        // Nothing to remove
+        None if !store.reorder_policies.contains_key(&key) => return Ok(()),
+        None => vec![Op::MapDel {
+            fragment_id: fragments::reorder_policy_id(&actor.org_id, item_id),
+            key: "item_id".to_string(),
+        }],
+    };
+    let envelope = engine::sign_next(&mut store, actor, ops, policy_ctx)?;
+    engine::commit(store, &envelope);
+    Ok(())
+}
+
+/// An item's stock position against its reorder point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReorderStatus {
+    pub item_id: String,
+    pub on_hand: f64,
+    /// On open (unposted) purchase drafts
+    pub on_order: f64,
+    pub daily_usage: f64,
+    pub lead_time_days: f64,
+    pub reorder_point: f64,
+    pub needs_reorder: bool,
+    pub suggested_qty: f64,
+    pub supplier_id: Option<String>,
+    /// The policy's cost, else the latest receipt cost; `None` if neither
+    pub unit_cost: Option<f64>,
+    pub currency: String,
+    /// The item's latest InvMove fragment, if it has moved
+    pub last_move_fragment: Option<String>,
+}
+
+/// Stock position of every item that has moved in `org_id` or has a policy.
+pub fn analyse(
+    store: &ErpStore,
+    org_id: &str,
+    policies: &BTreeMap<String, ReorderPolicy>,
+    now_ms: i64,
+) -> Vec<ReorderStatus> {
+    let live = |tx_id: &str| {
+        store
+            .transactions
+            .get(tx_id)
+            .filter(|t| t.org_id == org_id && t.status != TxStatus::Void)
+    };
+    let window_start = now_ms - (CONSUMPTION_WINDOW_DAYS * MS_PER_DAY) as i64;
+
+    struct Moves {
+        on_hand: f64,
+        issued: f64,
+        first_ms: i64,
+        last: Option<(i64, String)>,
+    }
+    let mut moves: BTreeMap<&str, Moves> = BTreeMap::new();
+    for m in store.invmoves.values().filter(|m| live(&m.tx_id).is_some()) {
+        let entry = moves.entry(m.item_id.as_str()).or_insert(Moves {
+            on_hand: 0.0,
+            issued: 0.0,
+            first_ms: i64::MAX,
+            last: None,
+        });
+        entry.on_hand += m.qty_delta;
+        if m.qty_delta < 0.0 && m.moved_at_ms >= window_start && m.moved_at_ms <= now_ms {
+            entry.issued -= m.qty_delta;
+        }
+        entry.first_ms = entry.first_ms.min(m.moved_at_ms);
+        if entry
+            .last
+            .as_ref()
+            .is_none_or(|(ms, _)| m.moved_at_ms >= *ms)
+        {
+            entry.last = Some((m.moved_at_ms, m.move_id.clone()));
+        }
+    }
+
+    // Open purchases: stock still to arrive, and each item's latest supplier
+    let mut on_order: HashMap<&str, f64> = HashMap::new();
+    let mut last_supplier: HashMap<&str, (i64, &str)> = HashMap::new();
+    for line in store.lines.values() {
+        let Some(item_id) = line.item_id.as_deref() else {
+            continue;
+        };
+        let Some(tx) = live(&line.tx_id) else {
+            continue;
+        };
+        if !matches!(tx.tx_type, TxType::InvoiceIn | TxType::StockReceipt) {
+            continue;
+        }
+        if tx.status != TxStatus::Posted && line.inventory_effect == InventoryEffect::Increase {
+            *on_order.entry(item_id).or_default() += line.qty;
+        }
+        if let Some(party) = tx.party_id.as_deref() {
+            let latest = last_supplier.entry(item_id).or_insert((i64::MIN, party));
+            if tx.created_at_ms >= latest.0 {
+                *latest = (tx.created_at_ms, party);
+            }
+        }
+    }
+    let costs = stocktake::last_receipt_costs(&store.transactions, &store.lines);
+
+    let items: BTreeSet<&str> = moves
+        .keys()
+        .copied()
+        .chain(policies.keys().map(String::as_str))
+        .collect();
+    items
+        .into_iter()
+        .map(|item_id| {
+            let policy = policies
+                .get(item_id)
+                .cloned()
+                .unwrap_or_else(|| ReorderPolicy::for_item(item_id));
+            let m = moves.get(item_id);
+            let on_hand = m.map_or(0.0, |m| m.on_hand);
+            let on_order = on_order.get(item_id).copied().unwrap_or(0.0);
+            // Average over the window, or since the first move if younger
+            let daily_usage = m.map_or(0.0, |m| {
+                let days = ((now_ms - m.first_ms.max(window_start)) as f64 / MS_PER_DAY)
+                    .clamp(1.0, CONSUMPTION_WINDOW_DAYS);
+                m.issued / days
+            });
+            let reorder_point = policy
+                .reorder_point
+                .unwrap_or(daily_usage * (policy.lead_time_days + policy.safety_days));
+            let position = on_hand + on_order;
+            let needs_reorder =
+                (reorder_point > 0.0 || daily_usage > 0.0) && position <= reorder_point;
+            let suggested_qty = if needs_reorder {
+                policy
+                    .reorder_qty
+                    .unwrap_or_else(|| {
+                        (reorder_point + daily_usage * policy.cover_days - position).ceil()
+                    })
+                    .max(1.0)
+            } else {
+                0.0
+            };
+            ReorderStatus {
+                item_id: item_id.to_string(),
+                on_hand,
+                on_order,
+                daily_usage: (daily_usage * 1000.0).round() / 1000.0,
+                lead_time_days: policy.lead_time_days,
+                reorder_point: (reorder_point * 100.0).round() / 100.0,
+                needs_reorder,
+                suggested_qty,
+                supplier_id: policy
+                    .preferred_supplier_id
+                    .clone()
+                    .or_else(|| last_supplier.get(item_id).map(|(_, p)| p.to_string())),
+                unit_cost: policy.unit_cost.or_else(|| costs.get(item_id).copied()),
+                currency: policy.currency.clone(),
+                last_move_fragment: m
+                    .and_then(|m| m.last.as_ref())
+                    .map(|(_, id)| fragments::invmove_id(id)),
+            }
+        })
+        .collect()
+}
+
+/// `reorder_proposal` cards for items below their reorder point, skipping
+/// items that already have a pending reorder proposal. Cards carry a draft
+/// `invoice_in` when the item has a supplier in the org and a known cost.
+pub fn proposals(
+    store: &ErpStore,
+    org_id: &str,
+    policies: &BTreeMap<String, ReorderPolicy>,
+    now_ms: i64,
+) -> Vec<LlmProposal> {
+    let tx_date = chrono::DateTime::from_timestamp_millis(now_ms)
+        .unwrap_or_default()
+        .format("%Y-%m-%d")
+        .to_string();
+    analyse(store, org_id, policies, now_ms)
+        .into_iter()
+        .filter(|s| s.needs_reorder && !has_pending_reorder(store, org_id, &s.item_id))
+        .map(|s| {
+            let supplier = s
+                .supplier_id
+                .as_deref()
+                .and_then(|id| store.parties.get(id))
+                .filter(|p| p.org_id == org_id);
+            let mut rationale = format!(
+                "{} on hand and {} on order is at or below the reorder point {} \
+                 ({:.2}/day over {} days lead time).",
+                s.on_hand, s.on_order, s.reorder_point, s.daily_usage, s.lead_time_days
+            );
+            if supplier.is_none() {
+                rationale.push_str(" Set a preferred supplier to draft the purchase.");
+            }
+            if s.unit_cost.is_none() {
+                rationale.push_str(
+                    " No receipt cost is known; set a unit cost on the policy to draft the purchase.",
+                );
+            }
+            let draft = supplier.zip(s.unit_cost).map(|(party, unit_cost)| ProposedTx {
+                tx_type: TxType::InvoiceIn.as_str().to_string(),
+                party_id: Some(party.party_id.clone()),
+                currency: s.currency.clone(),
+                description: Some(format!("Reorder {}", s.item_id)),
+                tx_date: tx_date.clone(),
+                lines: vec![ProposedLine {
+                    item_id: Some(s.item_id.clone()),
+                    account_id: None,
+                    description: Some(format!("Reorder {} from {}", s.item_id, party.name)),
+                    qty: s.suggested_qty,
+                    unit_price: unit_cost,
+                    inventory_effect: "increase".to_string(),
+                    tax_rate: None,
+                }],
+            });
+            LlmProposal {
+                id: format!("caio-reorder-{}", s.item_id),
+                r#type: "reorder_proposal".into(),
+                title: format!("Reorder {} × {}", s.suggested_qty, s.item_id),
+                rationale,
+                source_fragment: s
+                    .last_move_fragment
+                    .clone()
+                    .unwrap_or_else(|| fragments::org_indexes_id(org_id)),
+                source: Some("rules".into()),
+                draft,
+                proposal_id: None,
+                citations: vec![],
+            }
+        })
+        .collect()
+}
+
+/// Whether a pending reorder proposal already orders `item_id`.
+fn has_pending_reorder(store: &ErpStore, org_id: &str, item_id: &str) -> bool {
+    store.proposals.values().any(|p| {
+        p.org_id == org_id
+            && p.kind == "reorder_proposal"
+            && p.status.is_pending()
+            && p.ops.iter().any(|op| match op {
+                Op::MapSet { key, value, .. } if key == "data" => {
+                    value.get("item_id").and_then(|v| v.as_str()) == Some(item_id)
+                }
+                _ => false,
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::TxBuilder;
+    use crate::erp::types::{InvMove, Party, PartyKind, PendingProposal};
+
+    const DAY: i64 = 86_400_000;
+
+    fn add_move(store: &mut ErpStore, tx_id: &str, item: &str, qty: f64, at_ms: i64) {
+        let move_id = format!("m{}", store.invmoves.len());
+        store.invmoves.insert(
+            move_id.clone(),
+            InvMove {
+                move_id,
+                tx_id: tx_id.to_string(),
+                tx_line_id: String::new(),
+                item_id: item.to_string(),
+                qty_delta: qty,
+                location_id: None,
+                moved_at_ms: at_ms,
+                moved_by_pubkey: "pk".to_string(),
+                site_id: "primary".to_string(),
+            },
+        );
+    }
+
+    #[test]
+    fn test_reorder_from_consumption() {
+        let now = 100 * DAY;
+        let mut store = ErpStore::new();
+        store.parties.insert(
+            "supp".to_string(),
+            Party {
+                party_id: "supp".to_string(),
+                org_id: "org1".to_string(),
+                name: "Widget Co".to_string(),
+                kind: PartyKind::Supplier,
+                email: None,
+                contact: None,
+                abn: None,
+                created_at_ms: 0,
+            },
+        );
+        TxBuilder::new("rcv", TxType::StockReceipt)
+            .party("supp")
+            .insert(&mut store);
+        TxBuilder::new("iss", TxType::StockIssue)
+            .party("cust")
+            .insert(&mut store);
+        // 200 in 60 days ago; 2/day issued since, 80 left
+        add_move(&mut store, "rcv", "widget", 200.0, now - 60 * DAY);
+        for day in 0..60 {
+            add_move(&mut store, "iss", "widget", -2.0, now - day * DAY);
+        }
+        // A slow item well above its reorder point
+        add_move(&mut store, "rcv", "gadget", 50.0, now - 60 * DAY);
+        add_move(&mut store, "iss", "gadget", -1.0, now - DAY);
+
+        let policies = BTreeMap::from([(
+            "widget".to_string(),
+            ReorderPolicy {
+                lead_time_days: 30.0,
+                preferred_supplier_id: Some("supp".to_string()),
+                unit_cost: Some(4.5),
+                ..ReorderPolicy::for_item("widget")
+            },
+        )]);
+        let status = analyse(&store, "org1", &policies, now);
+        let widget = status.iter().find(|s| s.item_id == "widget").unwrap();
+        assert_eq!(widget.on_hand, 80.0);
+        assert_eq!(widget.daily_usage, 2.0);
+        // 2/day × (30 lead + 7 safety)
+        assert_eq!(widget.reorder_point, 74.0);
+        assert!(!widget.needs_reorder);
+        assert!(
+            !status
+                .iter()
+                .find(|s| s.item_id == "gadget")
+                .unwrap()
+                .needs_reorder
+        );
+
+        // Ten more days of issues: 60 ≤ 74 → order up to 74 + 30 days' cover
+        for day in 1..=10 {
+            add_move(&mut store, "iss", "widget", -2.0, now + day * DAY);
+        }
+        let later = now + 10 * DAY;
+        let cards = proposals(&store, "org1", &policies, later);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].r#type, "reorder_proposal");
+        assert!(cards[0].source_fragment.starts_with("invmove:"));
+        let draft = cards[0].draft.as_ref().unwrap();
+        assert_eq!(draft.tx_type, "invoice_in");
+        assert_eq!(draft.party_id.as_deref(), Some("supp"));
+        assert_eq!(draft.lines[0].qty, 74.0);
+        assert_eq!(draft.lines[0].unit_price, 4.5);
+
+        // Without a policy cost or receipt cost there is no draft at price 0
+        let uncosted = BTreeMap::from([(
+            "widget".to_string(),
+            ReorderPolicy {
+                unit_cost: None,
+                ..policies["widget"].clone()
+            },
+        )]);
+        let cards = proposals(&store, "org1", &uncosted, later);
+        assert!(cards[0].draft.is_none());
+        assert!(cards[0].rationale.contains("No receipt cost"));
+
+        // Once a reorder is pending, the item is not proposed again
+        store.proposals.insert(
+            "p1".to_string(),
+            PendingProposal {
+                proposal_id: "p1".to_string(),
+                source_fragment: cards[0].source_fragment.clone(),
+                ops: vec![Op::MapSet {
+                    fragment_id: "txline:l1".to_string(),
+                    key: "data".to_string(),
+                    value: serde_json::json!({"item_id": "widget"}),
+                }],
+                rationale: String::new(),
+                org_id: "org1".to_string(),
+                kind: "reorder_proposal".to_string(),
+                title: String::new(),
+                proposed_by: String::new(),
+                created_at_ms: None,
+                status: Default::default(),
+                decided_by: None,
+                decided_at_ms: None,
+                reason: None,
+            },
+        );
+        assert!(proposals(&store, "org1", &policies, later).is_empty());
+    }
+}
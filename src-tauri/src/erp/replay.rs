@@ -2,11 +2,11 @@ use crate::erp::errors::ErpError;
 use std::collections::{HashMap, HashSet};
 
 /// Per-actor replay protection state.
-/// Maintained in-process; survives for the lifetime of the engine state.
-/// In Phase B this would be persisted to disk / Primary's canonical store.
+/// `max_lamport` doubles as the engine's per-actor clock (hlc.rs) and is
+/// persisted; `seen_mutations` lives for the lifetime of the engine state.
 #[derive(Debug, Default)]
 pub struct ReplayGuard {
-    /// Per-actor-pubkey maximum lamport clock seen.
+    /// Per-actor-pubkey maximum lamport clock seen (persisted as `actor_clocks`).
     pub max_lamport: HashMap<String, u64>,
     /// Set of mutation_id strings already seen.
     pub seen_mutations: HashSet<String>,
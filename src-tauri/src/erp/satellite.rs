@@ -0,0 +1,1386 @@
+//! satellite.rs — Primary / satellite replication ("Phase B: Primary Mode")
+//!
+//! A node runs `Standalone` (the default), as an org's `Primary`, or as a
+//! `Satellite` of a primary — see `NodeMode`, stored in `{app_data}/erp_node.json`.
+//!
+//! Satellite: envelopes signed on this node are committed locally as usual
+//! and also queued in `erp_satellite/outbox.jsonl` (`EnvelopeQueue`). `sync`
+//! submits the queue in order to the primary (`POST /erp/submit`), on demand
+//! and from a background retry loop, and settles each envelope from the
+//! per-envelope results. Accepted and duplicate envelopes leave the queue.
+//! Rejected ones move to `rejected.jsonl`, and the local store is rebuilt
+//! from the audit log without them; records the log never had are kept.
+//! Envelopes chained after a rejected one are rejected by the primary too
+//! (their `prev_hash` no longer continues the actor chain), so a satellite
+//! never keeps state built on a rejection. Envelopes the primary could not
+//! authenticate (bad signature, node not enrolled yet) are held in the queue
+//! with the rest of that actor's chain and retried — they say nothing about
+//! the work itself.
+//!
+//! Primary: `accept_envelope` runs each envelope through
+//! `engine::apply_remote_envelope_checked` — `MutationEnvelope::verify`, the
+//! actor prev_hash chain and `ReplayGuard` — with ABAC for the actor's
+//! enrolled role and `validate_remote` business checks under the same store
+//! lock. Satellite nodes must be enrolled (`enroll`, `erp_satellites.json`)
+//! by the key they sign with (`node_pubkey`, the envelope's `device_pubkey`)
+//! and the org and role their actors act as; envelopes never carry their
+//! own role.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::erp::abac::{check_abac, Action};
+use crate::erp::apply::{self, apply_ops};
+use crate::erp::audit_index;
+use crate::erp::audit_log;
+use crate::erp::checkpoint::StateSnapshot;
+use crate::erp::coa;
+use crate::erp::db;
+use crate::erp::engine::{self, ErpStore, ERP_STORE};
+use crate::erp::envelope::{self, MutationEnvelope};
+use crate::erp::errors::ErpError;
+use crate::erp::fragments;
+use crate::erp::ledger::validate_balance;
+use crate::erp::notes;
+use crate::erp::post::validate_invmove_direction;
+use crate::erp::status;
+use crate::erp::timetravel;
+use crate::erp::types::{ActorContext, Op, PolicyContext, Posting, Role, TxHeader, TxStatus};
+use crate::erp::ydoc;
+
+/// Path the primary accepts envelope batches on.
+pub const SUBMIT_PATH: &str = "/erp/submit";
+
+/// Envelopes per submit request.
+const MAX_BATCH: usize = 500;
+
+/// Largest request / response body either side accepts.
+const MAX_BODY: usize = 64 * 1024 * 1024;
+
+/// How often a satellite retries submitting its queue.
+const SYNC_INTERVAL: Duration = Duration::from_secs(30);
+
+// ─── Config ──────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum NodeMode {
+    #[default]
+    Standalone,
+    /// Accept satellite envelopes on `0.0.0.0:{listen_port}`
+    Primary { listen_port: u16 },
+    /// Submit local envelopes to `primary_url` (e.g. "http://10.0.0.5:3031")
+    Satellite { primary_url: String },
+}
+
+/// A satellite node the primary accepts envelopes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnrolledActor {
+    /// Hex-encoded Ed25519 node key the satellite signs with (`node_pubkey`)
+    pub pubkey: String,
+    pub org_id: String,
+    /// Role ABAC evaluates the actor's envelopes as
+    pub role: Role,
+    pub label: String,
+    pub enrolled_at_ms: i64,
+}
+
+// ─── Wire format ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitRequest {
+    pub envelopes: Vec<MutationEnvelope>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubmitResponse {
+    pub results: Vec<EnvelopeResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubmitStatus {
+    Accepted,
+    /// Already applied on the primary (e.g. an earlier response was lost)
+    Duplicate,
+    Rejected,
+}
+
+/// The primary's verdict on one envelope.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnvelopeResult {
+    pub mutation_id: String,
+    pub status: SubmitStatus,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+}
+
+impl EnvelopeResult {
+    fn new(mutation_id: &str, status: SubmitStatus) -> Self {
+        EnvelopeResult {
+            mutation_id: mutation_id.to_string(),
+            status,
+            error_code: None,
+            error_message: None,
+        }
+    }
+
+    pub(crate) fn rejected(mutation_id: &str, e: &ErpError) -> Self {
+        EnvelopeResult {
+            error_code: Some(e.code().to_string()),
+            error_message: Some(e.to_string()),
+            ..Self::new(mutation_id, SubmitStatus::Rejected)
+        }
+    }
+}
+
+// ─── Primary ─────────────────────────────────────────────────────────────────
+
+/// Validate and apply satellite envelopes in order.
+pub fn accept_batch(envelopes: &[MutationEnvelope]) -> Vec<EnvelopeResult> {
+    envelopes.iter().map(accept_envelope).collect()
+}
+
+/// Validate and apply one satellite envelope. Enrollment and the signature
+/// are checked before anything else, so unauthenticated peers cannot make
+/// the primary look envelopes up.
+pub fn accept_envelope(envelope: &MutationEnvelope) -> EnvelopeResult {
+    let id = &envelope.mutation_id;
+    let actor = match enrolled_actor(envelope).and_then(|a| envelope.verify().map(|_| a)) {
+        Ok(a) => a,
+        Err(e) => return EnvelopeResult::rejected(id, &e),
+    };
+    if already_applied(envelope) {
+        return EnvelopeResult::new(id, SubmitStatus::Duplicate);
+    }
+    match engine::apply_remote_envelope_checked(envelope, |store| {
+        validate_remote(store, &actor, envelope)
+    }) {
+        Ok(()) => EnvelopeResult::new(id, SubmitStatus::Accepted),
+        Err(e) => EnvelopeResult::rejected(id, &e),
+    }
+}
+
+/// Whether `envelope` was applied before. The audit index (by mutation id)
+/// is only consulted when the envelope does not continue the actor's chain.
+fn already_applied(envelope: &MutationEnvelope) -> bool {
+    let continues_chain = {
+        let store = ERP_STORE.lock().unwrap();
+        let head = store.actor_prev_hash.get(&envelope.actor_pubkey);
+        if store.replay.seen_mutations.contains(&envelope.mutation_id)
+            || head == Some(&envelope.envelope_hash())
+        {
+            return true;
+        }
+        head.map_or(envelope.prev_hash == "genesis", |h| {
+            *h == envelope.prev_hash
+        })
+    };
+    if continues_chain {
+        return false;
+    }
+    let db = engine::ERP_DB.lock().unwrap();
+    let Some(conn) = db.as_ref() else {
+        return false;
+    };
+    audit_index::find_mutation(conn, &audit_log::org_of(envelope), &envelope.mutation_id)
+        .ok()
+        .flatten()
+        .is_some_and(|e| e.envelope.signature == envelope.signature)
+}
+
+/// The enrolled identity `envelope` acts as: its actor, with the org and
+/// role the signing node is enrolled for.
+fn enrolled_actor(envelope: &MutationEnvelope) -> Result<ActorContext, ErpError> {
+    let signer = envelope.signer_pubkey();
+    let enrolled = ENROLLED
+        .lock()
+        .unwrap()
+        .get(signer)
+        .cloned()
+        .ok_or_else(|| {
+            ErpError::NotEnrolled(format!("node {} is not enrolled with this primary", signer))
+        })?;
+    if envelope.org_id != enrolled.org_id || envelope.policy_context.org_id != enrolled.org_id {
+        return Err(ErpError::AbacDeny(format!(
+            "actor is enrolled for org {}, not {}",
+            enrolled.org_id, envelope.org_id
+        )));
+    }
+    Ok(ActorContext {
+        pubkey: envelope.actor_pubkey.clone(),
+        role: enrolled.role,
+        org_id: enrolled.org_id,
+        lamport: envelope.lamport,
+    })
+}
+
+/// ABAC and business validation of a remote envelope against `store`.
+pub fn validate_remote(
+    store: &ErpStore,
+    actor: &ActorContext,
+    envelope: &MutationEnvelope,
+) -> Result<(), ErpError> {
+    validate_ops(store, actor, &envelope.ops)
+}
+
+/// ABAC and business validation of `ops` by `actor`: each op is checked
+/// against the state left by the ops before it (on a scratch copy), then
+/// the records they wrote are validated as a whole.
+pub fn validate_ops(store: &ErpStore, actor: &ActorContext, ops: &[Op]) -> Result<(), ErpError> {
+    validate_proposal_envelope(store, ops)?;
+    let mut scratch = StateSnapshot::capture(store).restore();
+    let mut posted = BTreeSet::new();
+    for op in ops {
+        for (action, ctx) in op_checks(&scratch, &actor.org_id, op, &mut posted)? {
+            check_abac(actor, &action, &ctx)?;
+        }
+        apply_ops(&mut scratch, std::slice::from_ref(op));
+    }
+    for tx in apply::touched(ops)
+        .txs
+        .iter()
+        .filter(|id| !store.transactions.contains_key(*id))
+        .filter_map(|id| scratch.transactions.get(id))
+    {
+        notes::require_note_source(&tx.tx_type, tx.source_tx_id.as_deref())?;
+    }
+    validate_records(&scratch, &actor.org_id, ops, &posted)
+}
+
+/// The ABAC checks `op` needs, given the state before it. Status changes are
+/// also checked against the state machine; txs moving to `posted` are added
+/// to `posted`.
+fn op_checks(
+    store: &ErpStore,
+    org_id: &str,
+    op: &Op,
+    posted: &mut BTreeSet<String>,
+) -> Result<Vec<(Action, PolicyContext)>, ErpError> {
+    let ctx = |tx: Option<&TxHeader>| PolicyContext {
+        org_id: org_id.to_string(),
+        tx_id: tx.map(|t| t.tx_id.clone()),
+        tx_status: tx.map(|t| t.status.clone()),
+    };
+    let tx_of = |tx_id: Option<String>| -> Result<&TxHeader, ErpError> {
+        let tx_id = tx_id.unwrap_or_default();
+        store
+            .transactions
+            .get(&tx_id)
+            .ok_or_else(|| ErpError::ValidationFail(format!("tx {} not found", tx_id)))
+    };
+    let (fragment_id, key, value) = match op {
+        Op::MapSet {
+            fragment_id,
+            key,
+            value,
+        } => (fragment_id, key.as_str(), Some(value)),
+        Op::MapDel { fragment_id, key } => (fragment_id, key.as_str(), None),
+        Op::ArrayInsert { fragment_id, .. } | Op::ArrayDelete { fragment_id, .. } => {
+            (fragment_id, "", None)
+        }
+        Op::ProposalCreate { proposal_id, .. } => {
+            if store.proposals.contains_key(proposal_id) {
+                return Err(ErpError::ValidationFail(format!(
+                    "proposal {} already exists",
+                    proposal_id
+                )));
+            }
+            return Ok(vec![(Action::TxCreate, ctx(None))]);
+        }
+        Op::LinkAdd { .. } => return Ok(vec![(Action::TxCreate, ctx(None))]),
+    };
+    let record_tx_id = value
+        .and_then(|v| v.get("tx_id"))
+        .and_then(|v| v.as_str())
+        .map(str::to_string);
+
+    let (kind, rest) = fragment_id.split_once(':').unwrap_or((fragment_id, ""));
+    let check = match kind {
+        "tx" => {
+            let (tx_id, part) = rest.rsplit_once(':').unwrap_or((rest, ""));
+            match (part, store.transactions.get(tx_id)) {
+                ("hdr", None) => {
+                    let status = value.and_then(|v| v.as_str()).unwrap_or("draft");
+                    if key == "status" && status != "draft" {
+                        return Err(ErpError::InvalidStatus(
+                            "none".to_string(),
+                            status.to_string(),
+                        ));
+                    }
+                    (Action::TxCreate, ctx(None))
+                }
+                ("hdr", Some(tx)) if key == "status" => {
+                    let target =
+                        TxStatus::from_str(value.and_then(|v| v.as_str()).unwrap_or_default())?;
+                    if target != tx.status {
+                        status::transition(&tx.status, &target)?;
+                    }
+                    let action = match target {
+                        _ if target == tx.status => Action::TxEdit,
+                        TxStatus::Approved | TxStatus::Posted => Action::TxPost,
+                        TxStatus::Void => Action::TxVoid,
+                        _ => Action::TxEdit,
+                    };
+                    if target == TxStatus::Posted {
+                        posted.insert(tx_id.to_string());
+                    }
+                    (action, ctx(Some(tx)))
+                }
+                ("hdr" | "lines", Some(tx)) => (Action::TxEdit, ctx(Some(tx))),
+                ("postings", Some(tx)) => (Action::PostingCreate, ctx(Some(tx))),
+                ("lines" | "postings", None) => {
+                    return Err(ErpError::ValidationFail(format!("tx {} not found", tx_id)))
+                }
+                _ => {
+                    return Err(ErpError::ValidationFail(format!(
+                        "unknown fragment {}",
+                        fragment_id
+                    )))
+                }
+            }
+        }
+        "txline" => {
+            let tx_id = record_tx_id.or_else(|| store.lines.get(rest).map(|l| l.tx_id.clone()));
+            (Action::TxEdit, ctx(Some(tx_of(tx_id)?)))
+        }
+        "posting" => {
+            let existing = store.postings.get(fragment_id.as_str());
+            let tx_id = record_tx_id.or_else(|| existing.map(|p| p.tx_id.clone()));
+            let finalizes =
+                value.and_then(|v| v.get("status")).and_then(|s| s.as_str()) == Some("final");
+            let action = if finalizes {
+                Action::PostingFinalize
+            } else {
+                Action::PostingCreate
+            };
+            (action, ctx(Some(tx_of(tx_id)?)))
+        }
+        "invmove" => {
+            let tx_id = record_tx_id.or_else(|| store.invmoves.get(rest).map(|m| m.tx_id.clone()));
+            (Action::InvMoveCreate, ctx(Some(tx_of(tx_id)?)))
+        }
+        "stocktake" => (Action::InvMoveCreate, ctx(None)),
+        "account" => (Action::AccountManage, ctx(None)),
+        "party" => (Action::TxCreate, ctx(None)),
+        // reorder:{org_id}:{item_id}, only within the envelope's org
+        "reorder" => {
+            let in_org = rest.split_once(':').is_some_and(|(org, _)| org == org_id)
+                && (key != "org_id" || value.and_then(|v| v.as_str()) == Some(org_id));
+            if !in_org {
+                return Err(ErpError::ValidationFail(format!(
+                    "reorder policy {} must belong to org {}",
+                    rest, org_id
+                )));
+            }
+            (Action::InventoryManage, ctx(None))
+        }
+        "approval" => (Action::TxPost, ctx(None)),
+        "org" => (Action::IndexUpdate, ctx(None)),
+        // Review fields of a queued proposal; decided proposals are final
+        "proposal" => {
+            let existing = store.proposals.get(rest);
+            if let Some(p) = existing.filter(|p| !p.status.is_pending()) {
+                return Err(ErpError::ValidationFail(format!(
+                    "proposal {} is already {}",
+                    rest,
+                    p.status.as_str()
+                )));
+            }
+            if key == "org_id" && value.and_then(|v| v.as_str()) != Some(org_id) {
+                return Err(ErpError::ValidationFail(format!(
+                    "proposal {} must belong to org {}",
+                    rest, org_id
+                )));
+            }
+            let decides =
+                key == "status" && value.and_then(|v| v.as_str()).unwrap_or("pending") != "pending";
+            if decides && existing.is_none_or(|p| p.org_id != org_id) {
+                return Err(ErpError::ValidationFail(format!(
+                    "proposal {} not found",
+                    rest
+                )));
+            }
+            if decides {
+                (Action::ProposalReview, ctx(None))
+            } else {
+                (Action::TxCreate, ctx(None))
+            }
+        }
+        _ => {
+            return Err(ErpError::ValidationFail(format!(
+                "unknown fragment {}",
+                fragment_id
+            )))
+        }
+    };
+    Ok(vec![check])
+}
+
+/// Proposal invariants over the envelope as a whole: a `ProposalCreate` comes
+/// with its `org_id`, and `status = accepted` comes with the held ops, so a
+/// proposal is never accepted without them being applied.
+fn validate_proposal_envelope(store: &ErpStore, ops: &[Op]) -> Result<(), ErpError> {
+    let sets = |proposal_id: &str, key: &str| {
+        let fragment_id = fragments::proposal_id(proposal_id);
+        ops.iter().find_map(|op| match op {
+            Op::MapSet {
+                fragment_id: f,
+                key: k,
+                value,
+            } if *f == fragment_id && k == key => Some(value),
+            _ => None,
+        })
+    };
+    for op in ops {
+        if let Op::ProposalCreate { proposal_id, .. } = op {
+            if sets(proposal_id, "org_id").is_none() {
+                return Err(ErpError::ValidationFail(format!(
+                    "proposal {} must be created with its org_id",
+                    proposal_id
+                )));
+            }
+        }
+    }
+    for (proposal_id, p) in &store.proposals {
+        if sets(proposal_id, "status").and_then(|v| v.as_str()) != Some("accepted") {
+            continue;
+        }
+        let as_json = |ops: &[Op]| -> Vec<serde_json::Value> {
+            ops.iter()
+                .map(|o| serde_json::to_value(o).unwrap_or_default())
+                .collect()
+        };
+        let held = as_json(&p.ops);
+        let sent = as_json(ops);
+        if held.is_empty() || !sent.windows(held.len()).any(|w| w == held.as_slice()) {
+            return Err(ErpError::ValidationFail(format!(
+                "accepting proposal {} must apply its held ops in the same envelope",
+                proposal_id
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Business invariants over the records `ops` wrote, in the state after them.
+fn validate_records(
+    store: &ErpStore,
+    org_id: &str,
+    ops: &[Op],
+    posted: &BTreeSet<String>,
+) -> Result<(), ErpError> {
+    let touched = apply::touched(ops);
+    let tx_exists = |tx_id: &str, what: &str| {
+        if store.transactions.contains_key(tx_id) {
+            Ok(())
+        } else {
+            Err(ErpError::ValidationFail(format!(
+                "{} references unknown tx {}",
+                what, tx_id
+            )))
+        }
+    };
+
+    for tx in touched
+        .txs
+        .iter()
+        .filter_map(|id| store.transactions.get(id))
+    {
+        if tx.org_id != org_id {
+            return Err(ErpError::ValidationFail(format!(
+                "tx {} belongs to org {}, not {}",
+                tx.tx_id, tx.org_id, org_id
+            )));
+        }
+    }
+    for line in touched.lines.iter().filter_map(|id| store.lines.get(id)) {
+        tx_exists(&line.tx_id, &format!("line {}", line.line_id))?;
+        if let Some(ref acct) = line.account_id {
+            coa::validate_postable(&store.accounts, acct)?;
+        }
+    }
+    for m in touched.moves.iter().filter_map(|id| store.invmoves.get(id)) {
+        tx_exists(&m.tx_id, &format!("invmove {}", m.move_id))?;
+        let line = store.lines.get(&m.tx_line_id).ok_or_else(|| {
+            ErpError::ValidationFail(format!(
+                "invmove {} references unknown line {}",
+                m.move_id, m.tx_line_id
+            ))
+        })?;
+        validate_invmove_direction(m, &line.inventory_effect)?;
+    }
+    for p in touched
+        .postings
+        .iter()
+        .filter_map(|id| store.postings.get(id))
+    {
+        tx_exists(&p.tx_id, &format!("posting {}", p.posting_id))?;
+        coa::validate_postable(&store.accounts, &p.account_id)?;
+    }
+    for tx_id in posted {
+        let finals: Vec<Posting> = store
+            .postings
+            .values()
+            .filter(|p| p.tx_id == *tx_id && p.status == "final")
+            .cloned()
+            .collect();
+        if finals.is_empty() {
+            return Err(ErpError::PostingsMissing(tx_id.clone()));
+        }
+        validate_balance(&finals)?;
+    }
+    Ok(())
+}
+
+// ─── Satellite queue ─────────────────────────────────────────────────────────
+
+/// An envelope the primary rejected, kept for review.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedEnvelope {
+    pub envelope: MutationEnvelope,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    pub rejected_at_ms: i64,
+}
+
+/// Envelopes signed on this satellite and not yet settled by the primary
+/// (`outbox.jsonl`, in signing order), plus the rejected ones (`rejected.jsonl`).
+pub struct EnvelopeQueue {
+    dir: PathBuf,
+    lock: Mutex<()>,
+}
+
+/// Outcome of settling a submit response against the queue.
+#[derive(Debug, Default)]
+pub struct Settled {
+    pub accepted: usize,
+    pub duplicate: usize,
+    pub rejected: Vec<RejectedEnvelope>,
+    /// Left queued because the primary could not authenticate them (or an
+    /// earlier envelope of the same actor)
+    pub held: usize,
+}
+
+/// Rejections that are about the signer rather than the envelope's content.
+fn is_auth_failure(result: &EnvelopeResult) -> bool {
+    matches!(
+        result.error_code.as_deref(),
+        Some("ERR_SIG_INVALID") | Some("ERR_NOT_ENROLLED")
+    )
+}
+
+fn read_jsonl<T: serde::de::DeserializeOwned>(path: &Path) -> Vec<T> {
+    match fs::File::open(path) {
+        Ok(f) => BufReader::new(f)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|l| serde_json::from_str(&l).ok())
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+impl EnvelopeQueue {
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            lock: Mutex::new(()),
+        })
+    }
+
+    fn outbox_path(&self) -> PathBuf {
+        self.dir.join("outbox.jsonl")
+    }
+
+    fn rejected_path(&self) -> PathBuf {
+        self.dir.join("rejected.jsonl")
+    }
+
+    pub fn push(&self, envelope: &MutationEnvelope) -> std::io::Result<()> {
+        let _lock = self.lock.lock().unwrap();
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.outbox_path())?;
+        writeln!(f, "{}", serde_json::to_string(envelope)?)?;
+        f.sync_data()
+    }
+
+    pub fn pending(&self) -> Vec<MutationEnvelope> {
+        let _lock = self.lock.lock().unwrap();
+        read_jsonl(&self.outbox_path())
+    }
+
+    pub fn rejected(&self) -> Vec<RejectedEnvelope> {
+        let _lock = self.lock.lock().unwrap();
+        read_jsonl(&self.rejected_path())
+    }
+
+    /// Drop accepted / duplicate envelopes, move rejected ones to
+    /// `rejected.jsonl`; envelopes without a result stay queued, as do
+    /// authentication failures and every later envelope of that actor.
+    pub fn settle(&self, results: &[EnvelopeResult]) -> std::io::Result<Settled> {
+        let _lock = self.lock.lock().unwrap();
+        let by_id: HashMap<&str, &EnvelopeResult> = results
+            .iter()
+            .map(|r| (r.mutation_id.as_str(), r))
+            .collect();
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let mut settled = Settled::default();
+        let mut keep = Vec::new();
+        let mut held_actors = HashSet::new();
+        for envelope in read_jsonl::<MutationEnvelope>(&self.outbox_path()) {
+            let result = by_id.get(envelope.mutation_id.as_str());
+            if held_actors.contains(&envelope.actor_pubkey)
+                || result.is_some_and(|r| r.status == SubmitStatus::Rejected && is_auth_failure(r))
+            {
+                held_actors.insert(envelope.actor_pubkey.clone());
+                if result.is_some() {
+                    settled.held += 1;
+                }
+                keep.push(envelope);
+                continue;
+            }
+            match result {
+                Some(r) if r.status == SubmitStatus::Accepted => settled.accepted += 1,
+                Some(r) if r.status == SubmitStatus::Duplicate => settled.duplicate += 1,
+                Some(r) => settled.rejected.push(RejectedEnvelope {
+                    envelope,
+                    error_code: r.error_code.clone(),
+                    error_message: r.error_message.clone(),
+                    rejected_at_ms: now_ms,
+                }),
+                None => keep.push(envelope),
+            }
+        }
+
+        if !settled.rejected.is_empty() {
+            let mut f = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(self.rejected_path())?;
+            for r in &settled.rejected {
+                writeln!(f, "{}", serde_json::to_string(r)?)?;
+            }
+            f.sync_data()?;
+        }
+        let tmp = self.dir.join("outbox.jsonl.tmp");
+        let mut f = fs::File::create(&tmp)?;
+        for envelope in &keep {
+            writeln!(f, "{}", serde_json::to_string(envelope)?)?;
+        }
+        f.sync_data()?;
+        fs::rename(tmp, self.outbox_path())?;
+        Ok(settled)
+    }
+}
+
+// ─── Node state ──────────────────────────────────────────────────────────────
+
+lazy_static! {
+    static ref NODE_MODE: Mutex<NodeMode> = Mutex::new(NodeMode::default());
+    static ref ENROLLED: Mutex<HashMap<String, EnrolledActor>> = Mutex::new(HashMap::new());
+    /// Temp-dir queue until `configure` opens the app data one; `None` if
+    /// neither could be opened, in which case satellite commits are not
+    /// queued for the primary (they are still in the local audit log).
+    static ref QUEUE: Mutex<Option<Arc<EnvelopeQueue>>> = Mutex::new(
+        EnvelopeQueue::open(&std::env::temp_dir().join("corngr_erp_satellite"))
+            .map(Arc::new)
+            .map_err(|e| eprintln!("⚠️  Temp satellite queue unavailable: {e}"))
+            .ok(),
+    );
+    static ref NODE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+    /// Serialises `sync` so a manual sync and the retry loop never overlap
+    static ref SYNC_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/// Bumped when the mode changes; the server / retry task of an older
+/// generation exits.
+static NODE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn queue() -> Option<Arc<EnvelopeQueue>> {
+    QUEUE.lock().unwrap().clone()
+}
+
+pub fn mode() -> NodeMode {
+    NODE_MODE.lock().unwrap().clone()
+}
+
+pub fn pending() -> Vec<MutationEnvelope> {
+    queue().map(|q| q.pending()).unwrap_or_default()
+}
+
+pub fn rejected() -> Vec<RejectedEnvelope> {
+    queue().map(|q| q.rejected()).unwrap_or_default()
+}
+
+pub fn enrolled() -> Vec<EnrolledActor> {
+    let mut actors: Vec<EnrolledActor> = ENROLLED.lock().unwrap().values().cloned().collect();
+    actors.sort_by(|a, b| a.label.cmp(&b.label));
+    actors
+}
+
+fn save_json<T: Serialize>(name: &str, value: &T) -> std::io::Result<()> {
+    if let Some(dir) = NODE_DIR.lock().unwrap().as_ref() {
+        fs::write(dir.join(name), serde_json::to_vec_pretty(value)?)?;
+    }
+    Ok(())
+}
+
+/// Load `erp_node.json` / `erp_satellites.json` from `app_data_dir`, open
+/// the satellite queue and start the primary server or satellite retry loop.
+/// Called from lib.rs setup after `engine::init_erp_db`.
+pub fn configure(app_data_dir: &Path) {
+    let load = |name: &str| fs::read_to_string(app_data_dir.join(name)).ok();
+    if let Some(s) = load("erp_node.json") {
+        match serde_json::from_str(&s) {
+            Ok(mode) => *NODE_MODE.lock().unwrap() = mode,
+            Err(e) => eprintln!("⚠️  Invalid erp_node.json: {e}; running standalone"),
+        }
+    }
+    if let Some(s) = load("erp_satellites.json") {
+        match serde_json::from_str::<Vec<EnrolledActor>>(&s) {
+            Ok(actors) => {
+                *ENROLLED.lock().unwrap() =
+                    actors.into_iter().map(|a| (a.pubkey.clone(), a)).collect()
+            }
+            Err(e) => eprintln!("⚠️  Invalid erp_satellites.json: {e}"),
+        }
+    }
+    match EnvelopeQueue::open(&app_data_dir.join("erp_satellite")) {
+        Ok(q) => *QUEUE.lock().unwrap() = Some(Arc::new(q)),
+        Err(e) if QUEUE.lock().unwrap().is_some() => {
+            eprintln!("⚠️  Satellite queue unavailable, using temp dir: {e}")
+        }
+        Err(e) => eprintln!("⚠️  Satellite queue unavailable, not queuing: {e}"),
+    }
+    *NODE_DIR.lock().unwrap() = Some(app_data_dir.to_path_buf());
+    start();
+}
+
+/// Switch node mode, persist it and restart the server / retry loop.
+pub fn set_mode(mode: NodeMode) -> std::io::Result<()> {
+    save_json("erp_node.json", &mode)?;
+    *NODE_MODE.lock().unwrap() = mode;
+    start();
+    Ok(())
+}
+
+/// Enroll (or re-enroll) a satellite actor on this primary.
+pub fn enroll(actor: EnrolledActor) -> std::io::Result<()> {
+    ENROLLED.lock().unwrap().insert(actor.pubkey.clone(), actor);
+    save_json("erp_satellites.json", &enrolled())
+}
+
+pub fn unenroll(pubkey: &str) -> std::io::Result<bool> {
+    let removed = ENROLLED.lock().unwrap().remove(pubkey).is_some();
+    save_json("erp_satellites.json", &enrolled())?;
+    Ok(removed)
+}
+
+fn start() {
+    let generation = NODE_GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    match mode() {
+        NodeMode::Standalone => {}
+        NodeMode::Primary { listen_port } => {
+            tauri::async_runtime::spawn(async move {
+                match tokio::net::TcpListener::bind(("0.0.0.0", listen_port)).await {
+                    Ok(listener) => {
+                        println!("🛰️  ERP primary accepting satellites on :{}", listen_port);
+                        serve(listener, generation, accept_batch).await
+                    }
+                    Err(e) => eprintln!("❌ ERP primary cannot listen on {}: {}", listen_port, e),
+                }
+            });
+        }
+        NodeMode::Satellite { .. } => {
+            tauri::async_runtime::spawn(async move {
+                while NODE_GENERATION.load(Ordering::SeqCst) == generation {
+                    if !pending().is_empty() {
+                        if let Err(e) = sync().await {
+                            eprintln!("⚠️  Satellite sync failed (will retry): {}", e);
+                        }
+                    }
+                    tokio::time::sleep(SYNC_INTERVAL).await;
+                }
+            });
+        }
+    }
+}
+
+/// Queue an envelope committed on this node, if it runs as a satellite.
+/// Called from `engine::commit` for locally signed envelopes only.
+pub fn queue_local(envelope: &MutationEnvelope) {
+    if !matches!(mode(), NodeMode::Satellite { .. }) {
+        return;
+    }
+    let Some(queue) = queue() else {
+        eprintln!(
+            "❌ No satellite queue; envelope {} not queued for the primary",
+            envelope.mutation_id
+        );
+        return;
+    };
+    if let Err(e) = queue.push(envelope) {
+        eprintln!(
+            "❌ Failed to queue envelope {} for the primary: {}",
+            envelope.mutation_id, e
+        );
+    }
+}
+
+// ─── Satellite sync ──────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncReport {
+    pub submitted: usize,
+    pub accepted: usize,
+    pub duplicate: usize,
+    pub rejected: Vec<RejectedEnvelope>,
+    /// Left queued for retry because the primary could not authenticate them
+    pub held: usize,
+    /// Still queued after this sync
+    pub pending: usize,
+    /// Whether the local store was rebuilt without rejected envelopes
+    pub reconciled: bool,
+}
+
+/// Submit queued envelopes to the primary and settle them.
+pub async fn sync() -> Result<SyncReport, ErpError> {
+    let NodeMode::Satellite { primary_url } = mode() else {
+        return Err(ErpError::ValidationFail(
+            "this node is not a satellite".to_string(),
+        ));
+    };
+    let _sync = SYNC_LOCK.lock().await;
+    let queue = queue()
+        .ok_or_else(|| ErpError::ValidationFail("satellite queue unavailable".to_string()))?;
+    let mut report = SyncReport::default();
+
+    for batch in queue.pending().chunks(MAX_BATCH) {
+        let results = submit(&primary_url, batch).await?;
+        let settled = queue
+            .settle(&results)
+            .map_err(|e| ErpError::ValidationFail(format!("satellite queue: {}", e)))?;
+        report.submitted += batch.len();
+        report.accepted += settled.accepted;
+        report.duplicate += settled.duplicate;
+        report.rejected.extend(settled.rejected);
+        report.held += settled.held;
+    }
+
+    if !report.rejected.is_empty() {
+        let excluded: HashSet<String> = queue
+            .rejected()
+            .into_iter()
+            .map(|r| r.envelope.mutation_id)
+            .collect();
+        reconcile(&excluded)?;
+        report.reconciled = true;
+    }
+    report.pending = queue.pending().len();
+    Ok(report)
+}
+
+/// Rebuild the local store (corngr.db, yrs doc) from the audit log without the
+/// `excluded` envelopes. The audit log itself keeps them as local history;
+/// records the log never had (seeded accounts, pre-log data) are kept as is.
+fn reconcile(excluded: &HashSet<String>) -> Result<(), ErpError> {
+    let entries =
+        audit_log::read_all(0, i64::MAX).map_err(|e| ErpError::ValidationFail(e.to_string()))?;
+    let logged = timetravel::rebuild(&entries);
+    let kept: Vec<_> = entries
+        .into_iter()
+        .filter(|e| !excluded.contains(&e.envelope.mutation_id))
+        .collect();
+    let mut rebuilt = timetravel::rebuild(&kept);
+
+    // Keep the live replay guard; everything else comes from the log
+    let mut live = ERP_STORE.lock().unwrap();
+    timetravel::merge_unlogged(&mut rebuilt, &live, &logged);
+    let replay = std::mem::take(&mut live.replay);
+    *live = rebuilt;
+    live.replay = replay;
+    drop(live);
+    // corngr.db before the store lock, as everywhere else
+    engine::persist("replace_all", |conn| {
+        db::replace_all(conn, &ERP_STORE.lock().unwrap())
+    });
+    for org_id in audit_log::orgs() {
+        ydoc::reset(&org_id, excluded);
+    }
+    println!(
+        "🛰️  Satellite store rebuilt without {} rejected envelope(s)",
+        excluded.len()
+    );
+    Ok(())
+}
+
+/// POST `envelopes` to the primary and return its per-envelope results.
+pub async fn submit(
+    primary_url: &str,
+    envelopes: &[MutationEnvelope],
+) -> Result<Vec<EnvelopeResult>, ErpError> {
+    let url = format!("{}{}", primary_url.trim_end_matches('/'), SUBMIT_PATH);
+    let body = serde_json::to_vec(&SubmitRequest {
+        envelopes: envelopes.to_vec(),
+    })
+    .map_err(|e| ErpError::ValidationFail(e.to_string()))?;
+    let unreachable = |e: String| ErpError::ValidationFail(format!("primary {}: {}", url, e));
+
+    let (status, response) = tokio::time::timeout(Duration::from_secs(60), post_json(&url, &body))
+        .await
+        .map_err(|_| unreachable("timed out".to_string()))?
+        .map_err(unreachable)?;
+    if status != 200 {
+        return Err(unreachable(format!(
+            "answered {}: {}",
+            status,
+            String::from_utf8_lossy(&response)
+        )));
+    }
+    let parsed: SubmitResponse =
+        serde_json::from_slice(&response).map_err(|e| unreachable(e.to_string()))?;
+    Ok(parsed.results)
+}
+
+// ─── HTTP transport ──────────────────────────────────────────────────────────
+
+async fn post_json(url: &str, body: &[u8]) -> Result<(u16, Vec<u8>), String> {
+    let (host, port, path) = crate::audit::shipper::parse_http_url(url)?;
+    let mut stream = tokio::net::TcpStream::connect((host.as_str(), port))
+        .await
+        .map_err(|e| e.to_string())?;
+    let head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}:{}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        path,
+        host,
+        port,
+        body.len()
+    );
+    stream
+        .write_all(head.as_bytes())
+        .await
+        .map_err(|e| e.to_string())?;
+    stream.write_all(body).await.map_err(|e| e.to_string())?;
+
+    let mut response = Vec::new();
+    stream
+        .take(MAX_BODY as u64)
+        .read_to_end(&mut response)
+        .await
+        .map_err(|e| e.to_string())?;
+    let split = find_header_end(&response).ok_or("malformed HTTP response")?;
+    let status = String::from_utf8_lossy(&response[..split])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or("malformed HTTP status line")?;
+    Ok((status, response[split + 4..].to_vec()))
+}
+
+fn find_header_end(buf: &[u8]) -> Option<usize> {
+    buf.windows(4).position(|w| w == b"\r\n\r\n")
+}
+
+/// Accept submit requests until the node generation changes.
+pub async fn serve(
+    listener: tokio::net::TcpListener,
+    generation: u64,
+    handler: fn(&[MutationEnvelope]) -> Vec<EnvelopeResult>,
+) {
+    while NODE_GENERATION.load(Ordering::SeqCst) == generation {
+        let accepted = tokio::select! {
+            r = listener.accept() => r,
+            _ = tokio::time::sleep(Duration::from_secs(1)) => continue,
+        };
+        match accepted {
+            Ok((stream, addr)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = handle_http(stream, handler).await {
+                        eprintln!("❌ Satellite request from {} failed: {}", addr, e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("❌ Error accepting satellite connection: {}", e),
+        }
+    }
+}
+
+async fn handle_http(
+    mut stream: tokio::net::TcpStream,
+    handler: fn(&[MutationEnvelope]) -> Vec<EnvelopeResult>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 8192];
+    let header_end = loop {
+        if let Some(i) = find_header_end(&buf) {
+            break i;
+        }
+        if buf.len() > 64 * 1024 {
+            return respond(&mut stream, "431 Request Header Fields Too Large", b"{}").await;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let request_line = head.lines().next().unwrap_or_default();
+    let content_length: usize = head
+        .lines()
+        .filter_map(|l| l.split_once(':'))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, v)| v.trim().parse().ok())
+        .unwrap_or(0);
+    if content_length > MAX_BODY {
+        return respond(&mut stream, "413 Payload Too Large", b"{}").await;
+    }
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    if request_line != format!("POST {} HTTP/1.1", SUBMIT_PATH) {
+        return respond(&mut stream, "404 Not Found", b"{}").await;
+    }
+    match serde_json::from_slice::<SubmitRequest>(&body) {
+        Ok(req) => {
+            // The handler locks the store and writes to disk
+            let results = tokio::task::spawn_blocking(move || handler(&req.envelopes))
+                .await
+                .map_err(std::io::Error::other)?;
+            let json = serde_json::to_vec(&SubmitResponse { results })?;
+            respond(&mut stream, "200 OK", &json).await
+        }
+        Err(e) => {
+            let json = serde_json::to_vec(&serde_json::json!({ "error": e.to_string() }))?;
+            respond(&mut stream, "400 Bad Request", &json).await
+        }
+    }
+}
+
+async fn respond(
+    stream: &mut tokio::net::TcpStream,
+    status: &str,
+    body: &[u8],
+) -> std::io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.shutdown().await
+}
+
+/// Hex public key this node signs envelopes with (what a primary enrolls).
+pub fn node_pubkey() -> String {
+    envelope::get_actor_pubkey_hex()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::engine::AccountRecord;
+    use crate::erp::test_support::{set, signed_envelope, EngineFixture};
+    use ed25519_dalek::SigningKey;
+
+    fn actor(role: Role) -> ActorContext {
+        ActorContext {
+            pubkey: "satellite".to_string(),
+            role,
+            org_id: "org1".to_string(),
+            lamport: 1,
+        }
+    }
+
+    fn envelope(ops: Vec<Op>) -> MutationEnvelope {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let pubkey = hex::encode(key.verifying_key().to_bytes());
+        signed_envelope(
+            &key,
+            &uuid::Uuid::new_v4().to_string(),
+            &pubkey,
+            ops,
+            None,
+            1,
+        )
+    }
+
+    fn create_tx_ops(tx_id: &str) -> Vec<Op> {
+        let hdr = crate::erp::fragments::tx_hdr_id(tx_id);
+        vec![
+            set(&hdr, "tx_id", serde_json::json!(tx_id)),
+            set(&hdr, "status", serde_json::json!("draft")),
+            set(&hdr, "org_id", serde_json::json!("org1")),
+        ]
+    }
+
+    fn line_op(tx_id: &str, account: &str) -> Op {
+        set(
+            "txline:l1",
+            "data",
+            serde_json::json!({
+                "line_id": "l1", "tx_id": tx_id, "item_id": null,
+                "account_id": account, "description": null, "qty": 1.0,
+                "unit_price": 10.0, "inventory_effect": "none", "move_ids": [],
+                "tax_code": null, "tax_rate": 0.0
+            }),
+        )
+    }
+
+    fn store_with_accounts() -> ErpStore {
+        let mut store = ErpStore::new();
+        for (code, is_header) in [("4000", false), ("4999", true)] {
+            store.accounts.insert(
+                code.to_string(),
+                AccountRecord {
+                    code: code.to_string(),
+                    name: code.to_string(),
+                    acct_type: "income".to_string(),
+                    normal_balance: "credit".to_string(),
+                    parent_code: None,
+                    is_header,
+                    active: true,
+                    role: None,
+                },
+            );
+        }
+        store
+    }
+
+    #[test]
+    fn test_validate_remote_create_tx_and_line() {
+        let store = store_with_accounts();
+        let mut ops = create_tx_ops("t1");
+        ops.push(line_op("t1", "4000"));
+        assert!(validate_remote(&store, &actor(Role::Staff), &envelope(ops)).is_ok());
+
+        // Header accounts cannot take lines
+        let mut ops = create_tx_ops("t1");
+        ops.push(line_op("t1", "4999"));
+        let err = validate_remote(&store, &actor(Role::Staff), &envelope(ops)).unwrap_err();
+        assert_eq!(err.code(), "ERR_VALIDATION_FAIL");
+
+        // Lines need an existing tx
+        let err = validate_remote(
+            &store,
+            &actor(Role::Staff),
+            &envelope(vec![line_op("t9", "4000")]),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "ERR_VALIDATION_FAIL");
+
+        // Auditors cannot create
+        let err = validate_remote(
+            &store,
+            &actor(Role::Auditor),
+            &envelope(create_tx_ops("t1")),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "ERR_ABAC_DENY");
+    }
+
+    #[test]
+    fn test_validate_remote_status_changes() {
+        let mut store = store_with_accounts();
+        apply_ops(&mut store, &create_tx_ops("t1"));
+        let status = |s: &str| vec![set("tx:t1:hdr", "status", serde_json::json!(s))];
+
+        // Skipping approval is refused by the state machine
+        let err = validate_remote(
+            &store,
+            &actor(Role::OwnerAdmin),
+            &envelope(status("posted")),
+        )
+        .unwrap_err();
+        assert_eq!(err.code(), "ERR_INVALID_STATUS");
+
+        // Staff may propose but not approve
+        assert!(
+            validate_remote(&store, &actor(Role::Staff), &envelope(status("proposed"))).is_ok()
+        );
+        apply_ops(&mut store, &status("proposed"));
+        let err = validate_remote(&store, &actor(Role::Staff), &envelope(status("approved")))
+            .unwrap_err();
+        assert_eq!(err.code(), "ERR_ABAC_DENY");
+
+        // Posting needs balanced final postings
+        apply_ops(&mut store, &status("approved"));
+        let err = validate_remote(&store, &actor(Role::Finance), &envelope(status("posted")))
+            .unwrap_err();
+        assert_eq!(err.code(), "ERR_POSTINGS_MISSING");
+        let posting = |id: &str, acct: &str, dr: f64, cr: f64| {
+            set(
+                &format!("posting:{}", id),
+                "data",
+                serde_json::json!({
+                    "posting_id": format!("posting:{}", id), "tx_id": "t1",
+                    "account_id": acct, "debit_amount": dr, "credit_amount": cr,
+                    "currency": "AUD", "description": null, "status": "final",
+                    "generated_by": "engine"
+                }),
+            )
+        };
+        let mut ops = status("posted");
+        ops.push(posting("p1", "1000", 10.0, 0.0));
+        ops.push(posting("p2", "4000", 0.0, 10.0));
+        assert!(validate_remote(&store, &actor(Role::Finance), &envelope(ops.clone())).is_ok());
+        ops.pop();
+        let err = validate_remote(&store, &actor(Role::Finance), &envelope(ops)).unwrap_err();
+        assert_eq!(err.code(), "ERR_BALANCE_FAIL");
+    }
+
+    #[test]
+    fn test_queue_settles_results() {
+        let dir = std::env::temp_dir().join(format!("satellite_{}", uuid::Uuid::new_v4()));
+        let queue = EnvelopeQueue::open(&dir).unwrap();
+        let envs: Vec<MutationEnvelope> = (0..3).map(|_| envelope(Vec::new())).collect();
+        for e in &envs {
+            queue.push(e).unwrap();
+        }
+        let results = [
+            EnvelopeResult::new(&envs[0].mutation_id, SubmitStatus::Accepted),
+            EnvelopeResult::rejected(&envs[1].mutation_id, &ErpError::AbacDeny("no".to_string())),
+        ];
+        let settled = queue.settle(&results).unwrap();
+        assert_eq!(settled.accepted, 1);
+        assert_eq!(settled.rejected.len(), 1);
+
+        // Survives reopening
+        let reopened = EnvelopeQueue::open(&dir).unwrap();
+        let pending = reopened.pending();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].mutation_id, envs[2].mutation_id);
+        let rejected = reopened.rejected();
+        assert_eq!(rejected[0].envelope.mutation_id, envs[1].mutation_id);
+        assert_eq!(rejected[0].error_code.as_deref(), Some("ERR_ABAC_DENY"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_engine_envelope_accepted_by_primary() {
+        // Private audit root and store; QUEUE and NODE_MODE are restored below
+        let engine = EngineFixture::new();
+        let dir = engine.dir.join("erp_satellite");
+        let saved_queue = QUEUE
+            .lock()
+            .unwrap()
+            .replace(Arc::new(EnvelopeQueue::open(&dir).unwrap()));
+
+        // Satellite: commit through the engine, which queues the envelope
+        *NODE_MODE.lock().unwrap() = NodeMode::Satellite {
+            primary_url: "http://127.0.0.1:1".to_string(),
+        };
+        let actor = ActorContext {
+            pubkey: "local_node_pubkey_phase_a".to_string(),
+            ..actor(Role::Staff)
+        };
+        let req = crate::erp::types::CreateTxRequest {
+            tx_type: "invoice_out".to_string(),
+            org_id: "org1".to_string(),
+            party_id: None,
+            currency: "AUD".to_string(),
+            ref_number: None,
+            description: None,
+            tx_date: "2026-01-01".to_string(),
+            site_id: None,
+            source_tx_id: None,
+        };
+        let tx = engine::create_tx(&actor, &req).unwrap();
+        *NODE_MODE.lock().unwrap() = NodeMode::Standalone;
+        let queued = pending();
+        *QUEUE.lock().unwrap() = saved_queue;
+        assert_eq!(queued.len(), 1);
+
+        // Primary: fresh store, satellite node enrolled by its signing key
+        *ERP_STORE.lock().unwrap() = ErpStore::new();
+        let result = accept_envelope(&queued[0]);
+        assert_eq!(result.error_code, Some("ERR_NOT_ENROLLED".to_string()));
+        enroll(EnrolledActor {
+            pubkey: node_pubkey(),
+            org_id: "org1".to_string(),
+            role: Role::Staff,
+            label: "till".to_string(),
+            enrolled_at_ms: 0,
+        })
+        .unwrap();
+        let result = accept_envelope(&queued[0]);
+        assert_eq!(
+            result.status,
+            SubmitStatus::Accepted,
+            "{:?}",
+            result.error_message
+        );
+        assert!(ERP_STORE
+            .lock()
+            .unwrap()
+            .transactions
+            .contains_key(&tx.tx_id));
+        unenroll(&node_pubkey()).unwrap();
+    }
+
+    #[test]
+    fn test_queue_holds_unauthenticated_envelopes() {
+        let dir = std::env::temp_dir().join(format!("satellite_{}", uuid::Uuid::new_v4()));
+        let queue = EnvelopeQueue::open(&dir).unwrap();
+        let envs: Vec<MutationEnvelope> = (0..2).map(|_| envelope(Vec::new())).collect();
+        for e in &envs {
+            queue.push(e).unwrap();
+        }
+        let not_enrolled = ErpError::NotEnrolled("node".to_string());
+        let chain = ErpError::ValidationFail("prev_hash".to_string());
+        let results = [
+            EnvelopeResult::rejected(&envs[0].mutation_id, &not_enrolled),
+            EnvelopeResult::rejected(&envs[1].mutation_id, &chain),
+        ];
+        let settled = queue.settle(&results).unwrap();
+        assert_eq!(settled.held, 2);
+        assert!(settled.rejected.is_empty());
+        assert_eq!(queue.pending().len(), 2);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    fn reject_all(envelopes: &[MutationEnvelope]) -> Vec<EnvelopeResult> {
+        envelopes
+            .iter()
+            .map(|e| EnvelopeResult::rejected(&e.mutation_id, &ErpError::SigInvalid("x".into())))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_submit_over_http() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let generation = NODE_GENERATION.load(Ordering::SeqCst);
+        let server = tokio::spawn(serve(listener, generation, reject_all));
+
+        let envs = [envelope(Vec::new()), envelope(Vec::new())];
+        let results = submit(&url, &envs).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].mutation_id, envs[1].mutation_id);
+        assert_eq!(results[1].status, SubmitStatus::Rejected);
+        assert_eq!(results[1].error_code.as_deref(), Some("ERR_SIG_INVALID"));
+
+        let (status, _) = post_json(&format!("{}/other", url), b"{}").await.unwrap();
+        assert_eq!(status, 404);
+        server.abort();
+    }
+}
@@ -0,0 +1,473 @@
+//! stocktake.rs — Count sheets, stock adjustment reason codes and reconciliation
+//!
+//! Workflow:
+//!   1. `freeze` a count sheet for a site (optionally one location) from the
+//!      current stock on hand — the expected quantities are fixed at that moment.
+//!   2. Enter counted quantities (and optionally a reason) per item.
+//!   3. Reconcile: every non-zero variance becomes a line on one `stock_adjust`
+//!      tx with a matching InvMove; the line's reason code picks the gain /
+//!      loss account (`5400` Stock Adjustment Gain / `5401` Stock Adjustment Loss).
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+use crate::erp::errors::ErpError;
+use crate::erp::ledger::ROUNDING_TOLERANCE;
+use crate::erp::types::{InvMove, TxHeader, TxLine, TxStatus, TxType};
+
+// ─── Reason codes ────────────────────────────────────────────────────────────
+
+/// Why stock on hand was adjusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasonCode {
+    /// Unexplained loss (theft, miscount at receipt, …)
+    Shrinkage,
+    /// Written off as damaged / unsaleable
+    Damage,
+    /// More stock on hand than recorded
+    Found,
+}
+
+impl ReasonCode {
+    pub fn parse(s: &str) -> Result<Self, ErpError> {
+        match s {
+            "shrinkage" => Ok(ReasonCode::Shrinkage),
+            "damage" => Ok(ReasonCode::Damage),
+            "found" => Ok(ReasonCode::Found),
+            other => Err(ErpError::InvalidField(format!(
+                "unknown stock adjustment reason: {}",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasonCode::Shrinkage => "shrinkage",
+            ReasonCode::Damage => "damage",
+            ReasonCode::Found => "found",
+        }
+    }
+
+    /// Symbolic ledger account the variance is posted against
+    /// (seeded as 5400 / 5401 in the CoA templates).
+    pub fn account_role(&self) -> &'static str {
+        match self {
+            ReasonCode::Found => "stock_adjustment_gain",
+            ReasonCode::Shrinkage | ReasonCode::Damage => "stock_adjustment_loss",
+        }
+    }
+
+    /// Default reason for a variance that was not given one.
+    pub fn default_for(variance: f64) -> Self {
+        if variance > 0.0 {
+            ReasonCode::Found
+        } else {
+            ReasonCode::Shrinkage
+        }
+    }
+
+    /// Gains must be `found`; losses must be `shrinkage` or `damage`.
+    pub fn validate_variance(&self, variance: f64) -> Result<(), ErpError> {
+        let is_gain = matches!(self, ReasonCode::Found);
+        if (variance > 0.0) != is_gain {
+            return Err(ErpError::ValidationFail(format!(
+                "reason '{}' does not match a variance of {:+}",
+                self.as_str(),
+                variance
+            )));
+        }
+        Ok(())
+    }
+}
+
+// ─── Count sheets ────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CountSheetStatus {
+    Open,
+    Reconciled,
+}
+
+/// One item/location row on a count sheet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountLine {
+    pub item_id: String,
+    pub location_id: Option<String>,
+    /// Stock on hand when the sheet was frozen
+    pub expected_qty: f64,
+    pub counted_qty: Option<f64>,
+    /// Valuation used for the adjustment (last receipt price at freeze time)
+    pub unit_cost: f64,
+    pub reason_code: Option<ReasonCode>,
+}
+
+impl CountLine {
+    /// counted − expected, or `None` while uncounted.
+    pub fn variance(&self) -> Option<f64> {
+        self.counted_qty.map(|c| c - self.expected_qty)
+    }
+}
+
+/// A frozen stocktake count sheet — stored in fragment `stocktake:{id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CountSheet {
+    pub sheet_id: String,
+    pub org_id: String,
+    pub site_id: String,
+    /// `None` = every location at the site
+    pub location_id: Option<String>,
+    pub status: CountSheetStatus,
+    pub frozen_at_ms: i64,
+    pub frozen_by_pubkey: String,
+    pub lines: Vec<CountLine>,
+    /// The `stock_adjust` tx generated on reconciliation
+    pub adjust_tx_id: Option<String>,
+}
+
+/// A non-zero variance ready to become a stock_adjust line + InvMove.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variance {
+    pub item_id: String,
+    pub location_id: Option<String>,
+    pub qty: f64,
+    pub unit_cost: f64,
+    pub reason: ReasonCode,
+}
+
+/// Stock on hand per (item, location) for one site, from InvMoves on non-void txs.
+pub fn stock_on_hand(
+    transactions: &HashMap<String, TxHeader>,
+    invmoves: &HashMap<String, InvMove>,
+    org_id: &str,
+    site_id: &str,
+    location_id: Option<&str>,
+) -> BTreeMap<(String, Option<String>), f64> {
+    let mut soh: BTreeMap<(String, Option<String>), f64> = BTreeMap::new();
+    for m in invmoves.values() {
+        let live = transactions
+            .get(&m.tx_id)
+            .is_some_and(|t| t.org_id == org_id && t.status != TxStatus::Void);
+        if !live || m.site_id != site_id {
+            continue;
+        }
+        if location_id.is_some() && m.location_id.as_deref() != location_id {
+            continue;
+        }
+        *soh.entry((m.item_id.clone(), m.location_id.clone()))
+            .or_default() += m.qty_delta;
+    }
+    soh
+}
+
+/// Most recent purchase price per item, from stock_receipt / invoice_in lines.
+pub fn last_receipt_costs(
+    transactions: &HashMap<String, TxHeader>,
+    lines: &HashMap<String, TxLine>,
+) -> HashMap<String, f64> {
+    let mut latest: HashMap<String, (i64, f64)> = HashMap::new();
+    for line in lines.values() {
+        let Some(ref item_id) = line.item_id else {
+            continue;
+        };
+        let Some(tx) = transactions.get(&line.tx_id) else {
+            continue;
+        };
+        if !matches!(tx.tx_type, TxType::StockReceipt | TxType::InvoiceIn)
+            || tx.status == TxStatus::Void
+        {
+            continue;
+        }
+        let entry = latest
+            .entry(item_id.clone())
+            .or_insert((i64::MIN, line.unit_price));
+        if tx.created_at_ms >= entry.0 {
+            *entry = (tx.created_at_ms, line.unit_price);
+        }
+    }
+    latest.into_iter().map(|(k, (_, c))| (k, c)).collect()
+}
+
+/// Build count lines from a stock-on-hand snapshot.
+pub fn freeze_lines(
+    soh: &BTreeMap<(String, Option<String>), f64>,
+    costs: &HashMap<String, f64>,
+) -> Vec<CountLine> {
+    soh.iter()
+        .map(|((item_id, location_id), qty)| CountLine {
+            item_id: item_id.clone(),
+            location_id: location_id.clone(),
+            expected_qty: *qty,
+            counted_qty: None,
+            unit_cost: costs.get(item_id).copied().unwrap_or(0.0),
+            reason_code: None,
+        })
+        .collect()
+}
+
+/// Record a count on an open sheet. Items not on the sheet (found stock with no
+/// recorded movements) are appended with an expected quantity of zero, valued
+/// at `found_cost`; one is required so found stock is never booked at zero.
+pub fn record_count(
+    sheet: &mut CountSheet,
+    item_id: &str,
+    location_id: Option<&str>,
+    counted_qty: f64,
+    reason_code: Option<ReasonCode>,
+    found_cost: Option<f64>,
+) -> Result<(), ErpError> {
+    if sheet.status != CountSheetStatus::Open {
+        return Err(ErpError::ValidationFail(format!(
+            "count sheet {} is already reconciled",
+            sheet.sheet_id
+        )));
+    }
+    if counted_qty < 0.0 {
+        return Err(ErpError::InvalidField(
+            "counted quantity must not be negative".to_string(),
+        ));
+    }
+    if let (Some(sheet_loc), Some(loc)) = (sheet.location_id.as_deref(), location_id) {
+        if sheet_loc != loc {
+            return Err(ErpError::ValidationFail(format!(
+                "count sheet {} only covers location {}",
+                sheet.sheet_id, sheet_loc
+            )));
+        }
+    }
+    let location_id = location_id
+        .or(sheet.location_id.as_deref())
+        .map(str::to_string);
+
+    match sheet
+        .lines
+        .iter_mut()
+        .find(|l| l.item_id == item_id && l.location_id == location_id)
+    {
+        Some(line) => {
+            line.counted_qty = Some(counted_qty);
+            line.reason_code = reason_code;
+        }
+        None => {
+            let unit_cost = found_cost.ok_or_else(|| {
+                ErpError::ValidationFail(format!(
+                    "item {} has no receipt cost — enter a unit cost for the found stock",
+                    item_id
+                ))
+            })?;
+            if !unit_cost.is_finite() || unit_cost < 0.0 {
+                return Err(ErpError::InvalidField(
+                    "unit cost must not be negative".to_string(),
+                ));
+            }
+            sheet.lines.push(CountLine {
+                item_id: item_id.to_string(),
+                location_id,
+                expected_qty: 0.0,
+                counted_qty: Some(counted_qty),
+                unit_cost,
+                reason_code,
+            })
+        }
+    }
+    Ok(())
+}
+
+/// Variances to adjust. Every line must be counted; reasons default by sign
+/// and must agree with the direction of the variance.
+pub fn variances(sheet: &CountSheet) -> Result<Vec<Variance>, ErpError> {
+    let mut out = Vec::new();
+    for line in &sheet.lines {
+        let variance = line.variance().ok_or_else(|| {
+            ErpError::ValidationFail(format!("item {} has not been counted", line.item_id))
+        })?;
+        if variance.abs() <= ROUNDING_TOLERANCE {
+            continue;
+        }
+        let reason = line
+            .reason_code
+            .unwrap_or_else(|| ReasonCode::default_for(variance));
+        reason.validate_variance(variance)?;
+        out.push(Variance {
+            item_id: line.item_id.clone(),
+            location_id: line.location_id.clone(),
+            qty: variance,
+            unit_cost: line.unit_cost,
+            reason,
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(tx_id: &str, tx_type: TxType, status: TxStatus, created_at_ms: i64) -> TxHeader {
+        TxHeader {
+            tx_id: tx_id.to_string(),
+            org_id: "org1".to_string(),
+            tx_type,
+            status,
+            party_id: None,
+            currency: "AUD".to_string(),
+            ref_number: None,
+            description: None,
+            tx_date: "2026-03-01".to_string(),
+            created_at_ms,
+            created_by_pubkey: "pk1".to_string(),
+            site_id: "primary".to_string(),
+            source_tx_id: None,
+        }
+    }
+
+    fn mv(move_id: &str, tx_id: &str, item: &str, qty: f64, loc: &str) -> InvMove {
+        InvMove {
+            move_id: move_id.to_string(),
+            tx_id: tx_id.to_string(),
+            tx_line_id: "l1".to_string(),
+            item_id: item.to_string(),
+            qty_delta: qty,
+            location_id: Some(loc.to_string()),
+            moved_at_ms: 0,
+            moved_by_pubkey: "pk1".to_string(),
+            site_id: "primary".to_string(),
+        }
+    }
+
+    fn sheet(lines: Vec<CountLine>) -> CountSheet {
+        CountSheet {
+            sheet_id: "s1".to_string(),
+            org_id: "org1".to_string(),
+            site_id: "primary".to_string(),
+            location_id: None,
+            status: CountSheetStatus::Open,
+            frozen_at_ms: 0,
+            frozen_by_pubkey: "pk1".to_string(),
+            lines,
+            adjust_tx_id: None,
+        }
+    }
+
+    #[test]
+    fn test_stock_on_hand_skips_void_and_other_locations() {
+        let txs: HashMap<_, _> = [
+            ("r1", TxType::StockReceipt, TxStatus::Posted),
+            ("i1", TxType::InvoiceOut, TxStatus::Posted),
+            ("v1", TxType::StockReceipt, TxStatus::Void),
+        ]
+        .into_iter()
+        .map(|(id, t, s)| (id.to_string(), tx(id, t, s, 0)))
+        .collect();
+        let moves: HashMap<_, _> = [
+            mv("m1", "r1", "widget", 10.0, "A"),
+            mv("m2", "i1", "widget", -3.0, "A"),
+            mv("m3", "v1", "widget", 50.0, "A"),
+            mv("m4", "r1", "widget", 4.0, "B"),
+        ]
+        .into_iter()
+        .map(|m| (m.move_id.clone(), m))
+        .collect();
+
+        let soh = stock_on_hand(&txs, &moves, "org1", "primary", None);
+        assert_eq!(soh[&("widget".to_string(), Some("A".to_string()))], 7.0);
+        assert_eq!(soh[&("widget".to_string(), Some("B".to_string()))], 4.0);
+
+        let soh_a = stock_on_hand(&txs, &moves, "org1", "primary", Some("A"));
+        assert_eq!(soh_a.len(), 1);
+    }
+
+    #[test]
+    fn test_variances_default_and_validate_reasons() {
+        let mut s = sheet(vec![
+            CountLine {
+                item_id: "widget".into(),
+                location_id: None,
+                expected_qty: 10.0,
+                counted_qty: None,
+                unit_cost: 5.0,
+                reason_code: None,
+            },
+            CountLine {
+                item_id: "gadget".into(),
+                location_id: None,
+                expected_qty: 2.0,
+                counted_qty: None,
+                unit_cost: 8.0,
+                reason_code: None,
+            },
+        ]);
+        // Uncounted lines block reconciliation
+        assert!(variances(&s).is_err());
+
+        record_count(&mut s, "widget", None, 8.0, Some(ReasonCode::Damage), None).unwrap();
+        record_count(&mut s, "gadget", None, 3.0, None, None).unwrap();
+        // Found stock needs a cost
+        assert!(record_count(&mut s, "gizmo", None, 1.0, None, None).is_err());
+        record_count(&mut s, "gizmo", None, 1.0, None, Some(4.0)).unwrap();
+
+        let v = variances(&s).unwrap();
+        assert_eq!(v.len(), 3);
+        assert_eq!((v[0].qty, v[0].reason), (-2.0, ReasonCode::Damage));
+        assert_eq!((v[1].qty, v[1].reason), (1.0, ReasonCode::Found));
+        assert_eq!((v[2].item_id.as_str(), v[2].unit_cost), ("gizmo", 4.0));
+
+        // A gain cannot be booked as shrinkage
+        record_count(
+            &mut s,
+            "gadget",
+            None,
+            3.0,
+            Some(ReasonCode::Shrinkage),
+            None,
+        )
+        .unwrap();
+        assert!(variances(&s).is_err());
+    }
+
+    #[test]
+    fn test_reason_code_accounts() {
+        assert_eq!(ReasonCode::Found.account_role(), "stock_adjustment_gain");
+        assert_eq!(
+            ReasonCode::Shrinkage.account_role(),
+            "stock_adjustment_loss"
+        );
+        assert_eq!(ReasonCode::Damage.account_role(), "stock_adjustment_loss");
+        assert!(ReasonCode::parse("theft").is_err());
+    }
+
+    #[test]
+    fn test_last_receipt_cost() {
+        let txs: HashMap<_, _> = [
+            tx("r1", TxType::StockReceipt, TxStatus::Posted, 100),
+            tx("r2", TxType::StockReceipt, TxStatus::Posted, 200),
+        ]
+        .into_iter()
+        .map(|t| (t.tx_id.clone(), t))
+        .collect();
+        let line = |id: &str, tx_id: &str, price: f64| TxLine {
+            line_id: id.to_string(),
+            tx_id: tx_id.to_string(),
+            item_id: Some("widget".to_string()),
+            account_id: None,
+            description: None,
+            qty: 1.0,
+            unit_price: price,
+            inventory_effect: crate::erp::types::InventoryEffect::Increase,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: 0.0,
+            credit_amount: 0.0,
+            source_line_id: None,
+            reason_code: None,
+        };
+        let lines: HashMap<_, _> = [line("l1", "r1", 4.0), line("l2", "r2", 6.0)]
+            .into_iter()
+            .map(|l| (l.line_id.clone(), l))
+            .collect();
+        assert_eq!(last_receipt_costs(&txs, &lines)["widget"], 6.0);
+    }
+}
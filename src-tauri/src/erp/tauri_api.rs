@@ -1,17 +1,20 @@
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
 
+use crate::erp::audit_index;
 use crate::erp::audit_log::{self, ErpAuditEntry};
 use crate::erp::coa_templates;
-use crate::erp::db;
+use crate::erp::engine::ERP_STORE;
 use crate::erp::engine::{self, AccountRecord};
-use crate::erp::engine::{ERP_DB, ERP_STORE};
 use crate::erp::errors::ErpError;
+use crate::erp::journal;
 use crate::erp::ledger;
+use crate::erp::notes;
 use crate::erp::post::validate_post;
+use crate::erp::reorder::{self, ReorderPolicy, ReorderStatus};
 use crate::erp::types::{
-    ActorContext, AddLineRequest, ApprovalAtom, CreateInvMoveRequest, CreateTxRequest, Posting,
-    TxRef,
+    ActorContext, AddLineRequest, ApprovalAtom, CreateInvMoveRequest,
+    CreateJournalFromTemplateRequest, CreateJournalRequest, CreateTxRequest, Posting, TxRef,
+    TxType,
 };
 
 // ─── Response wrapper ────────────────────────────────────────────────────────
@@ -54,6 +57,30 @@ pub fn erp_create_tx(actor: ActorContext, req: CreateTxRequest) -> ApiResponse<T
     }
 }
 
+/// Create a draft manual journal (explicit debit/credit lines, must balance).
+#[tauri::command]
+pub fn erp_create_journal(actor: ActorContext, req: CreateJournalRequest) -> ApiResponse<TxRef> {
+    match engine::create_journal(&actor, &req) {
+        Ok(tx_ref) => ApiResponse::ok(tx_ref),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Create a draft manual journal from an accrual / prepayment template.
+#[tauri::command]
+pub fn erp_create_journal_from_template(
+    actor: ActorContext,
+    req: CreateJournalFromTemplateRequest,
+) -> ApiResponse<TxRef> {
+    match req
+        .into_journal()
+        .and_then(|journal_req| engine::create_journal(&actor, &journal_req))
+    {
+        Ok(tx_ref) => ApiResponse::ok(tx_ref),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
 /// Add a business line to an existing draft transaction.
 #[tauri::command]
 pub fn erp_add_line(actor: ActorContext, req: AddLineRequest) -> ApiResponse<String> {
@@ -138,36 +165,44 @@ pub fn erp_post_tx(
     match validate_post(&actor, &tx, &lines, &invmoves, &postings, &approvals) {
         Ok(()) => {
             let mut store = ERP_STORE.lock().unwrap();
-            // Update status
-            let tx_for_db = if let Some(t) = store.transactions.get_mut(&tx_id) {
-                t.status = crate::erp::types::TxStatus::Posted;
-                Some(t.clone())
-            } else {
-                None
+            // Signed status change + finalized postings, replayable from the audit log.
+            // Engine posting IDs are already `posting:{uuid}` fragment IDs;
+            // prefix any that are not so apply_ops keys them the same way.
+            let postings: Vec<Posting> = postings
+                .into_iter()
+                .map(|mut p| {
+                    if !p.posting_id.starts_with("posting:") {
+                        p.posting_id = crate::erp::fragments::posting_id(&p.posting_id);
+                    }
+                    p
+                })
+                .collect();
+            let mut ops = vec![crate::erp::types::Op::MapSet {
+                fragment_id: crate::erp::fragments::tx_hdr_id(&tx_id),
+                key: "status".to_string(),
+                value: serde_json::json!("posted"),
+            }];
+            ops.extend(postings.iter().map(|p| crate::erp::types::Op::MapSet {
+                fragment_id: p.posting_id.clone(),
+                key: "data".to_string(),
+                value: serde_json::to_value(p).unwrap_or_default(),
+            }));
+            let policy_ctx = crate::erp::types::PolicyContext {
+                org_id: tx.org_id.clone(),
+                tx_id: Some(tx_id.clone()),
+                tx_status: Some(tx.status.clone()),
             };
-            // Persist finalized postings into the ledger store
-            for p in &postings {
-                store.postings.insert(p.posting_id.clone(), p.clone());
-            }
-            let postings_for_db = postings.clone();
-            let tx_ref = TxRef {
+            let envelope = match engine::sign_next(&mut store, &actor, ops, policy_ctx) {
+                Ok(env) => env,
+                Err(e) => return ApiResponse::err(e),
+            };
+            // Status → posted and finalized postings → ledger store, via apply_ops
+            engine::commit(store, &envelope);
+            ApiResponse::ok(TxRef {
                 tx_id: tx_id.clone(),
                 org_id: tx.org_id.clone(),
                 status: crate::erp::types::TxStatus::Posted,
-            };
-            drop(store);
-            // SQLite write-through (best-effort)
-            if let Ok(db_guard) = ERP_DB.lock() {
-                if let Some(ref conn) = *db_guard {
-                    if let Some(ref tx_h) = tx_for_db {
-                        let _ = db::upsert_tx(conn, tx_h);
-                    }
-                    for p in &postings_for_db {
-                        let _ = db::upsert_posting(conn, p);
-                    }
-                }
-            }
-            ApiResponse::ok(tx_ref)
+            })
         }
         Err(e) => ApiResponse::err(e),
     }
@@ -198,8 +233,25 @@ pub fn erp_transition_status(
 
     let mut store = ERP_STORE.lock().unwrap();
 
-    let tx = match store.transactions.get_mut(&tx_id) {
-        Some(t) => t,
+    // Manual journals must balance on CoA accounts before they can be proposed
+    if target == TxStatus::Proposed {
+        if let Some(tx) = store.transactions.get(&tx_id) {
+            if tx.tx_type == TxType::Journal && tx.status == TxStatus::Draft {
+                let lines: Vec<_> = store
+                    .lines
+                    .values()
+                    .filter(|l| l.tx_id == tx_id)
+                    .cloned()
+                    .collect();
+                if let Err(e) = journal::validate_journal_tx(&store.accounts, &lines) {
+                    return ApiResponse::err(e);
+                }
+            }
+        }
+    }
+
+    let tx = match store.transactions.get(&tx_id) {
+        Some(t) => t.clone(),
         None => {
             return ApiResponse::err(ErpError::ValidationFail(format!("tx {} not found", tx_id)))
         }
@@ -234,16 +286,24 @@ pub fn erp_transition_status(
         }
     }
 
-    tx.status = target.clone();
     let org_id = tx.org_id.clone();
-    let tx_for_db = tx.clone();
-    drop(store);
-    // SQLite write-through (best-effort)
-    if let Ok(db_guard) = ERP_DB.lock() {
-        if let Some(ref conn) = *db_guard {
-            let _ = db::upsert_tx(conn, &tx_for_db);
-        }
-    }
+
+    // Signed status change so the transition is replayable from the audit log
+    let ops = vec![crate::erp::types::Op::MapSet {
+        fragment_id: crate::erp::fragments::tx_hdr_id(&tx_id),
+        key: "status".to_string(),
+        value: serde_json::json!(target.as_str()),
+    }];
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: org_id.clone(),
+        tx_id: Some(tx_id.clone()),
+        tx_status: Some(tx.status.clone()),
+    };
+    let envelope = match engine::sign_next(&mut store, &actor, ops, policy_ctx) {
+        Ok(env) => env,
+        Err(e) => return ApiResponse::err(e),
+    };
+    engine::commit(store, &envelope);
 
     ApiResponse::ok(TxRef {
         tx_id,
@@ -262,6 +322,8 @@ pub struct TxSnapshot {
     pub site_id: String,
     pub line_count: usize,
     pub move_count: usize,
+    /// Credit / debit notes: the invoice this note adjusts
+    pub source_tx_id: Option<String>,
 }
 
 #[tauri::command]
@@ -286,9 +348,48 @@ pub fn erp_get_tx_snapshot(tx_id: String) -> ApiResponse<TxSnapshot> {
         site_id: tx.site_id,
         line_count,
         move_count,
+        source_tx_id: tx.source_tx_id,
     })
 }
 
+/// Open balance of an invoice after the credit / debit notes raised against it.
+#[tauri::command]
+pub fn erp_invoice_balance(tx_id: String) -> ApiResponse<notes::InvoiceBalance> {
+    let store = ERP_STORE.lock().unwrap();
+
+    let invoice = match store.transactions.get(&tx_id) {
+        Some(t) if matches!(t.tx_type, TxType::InvoiceOut | TxType::InvoiceIn) => t.clone(),
+        Some(t) => {
+            return ApiResponse::err(ErpError::ValidationFail(format!(
+                "tx {} is an {}, not an invoice",
+                tx_id,
+                t.tx_type.as_str()
+            )));
+        }
+        None => {
+            return ApiResponse::err(ErpError::ValidationFail(format!("tx {} not found", tx_id)));
+        }
+    };
+
+    let lines_of = |id: &str| -> Vec<_> {
+        store
+            .lines
+            .values()
+            .filter(|l| l.tx_id == id)
+            .cloned()
+            .collect()
+    };
+    let invoice_lines = lines_of(&tx_id);
+    let notes: Vec<_> = store
+        .transactions
+        .values()
+        .filter(|t| t.source_tx_id.as_deref() == Some(tx_id.as_str()))
+        .map(|t| (t.clone(), lines_of(&t.tx_id)))
+        .collect();
+
+    ApiResponse::ok(notes::invoice_balance(&invoice, &invoice_lines, &notes))
+}
+
 /// List all transactions for an org (Phase A: in-memory scan; Phase B: index query).
 /// Returns TxSnapshot array sorted by lamport descending (newest first).
 #[tauri::command]
@@ -314,6 +415,7 @@ pub fn erp_list_txs(org_id: String) -> ApiResponse<Vec<TxSnapshot>> {
                 site_id: tx.site_id.clone(),
                 line_count,
                 move_count,
+                source_tx_id: tx.source_tx_id.clone(),
             }
         })
         .collect();
@@ -324,46 +426,136 @@ pub fn erp_list_txs(org_id: String) -> ApiResponse<Vec<TxSnapshot>> {
     ApiResponse::ok(snapshots)
 }
 
-// ─── M9: Party Master ────────────────────────────────────────────────────────
+// ─── Stocktake ───────────────────────────────────────────────────────────────
 
-use crate::erp::types::{CreatePartyRequest, Party, PartyKind};
+use crate::erp::stocktake::CountSheet;
+use crate::erp::types::{FreezeCountSheetRequest, ReconcileCountSheetRequest, RecordCountRequest};
 
-/// Create a new party (customer / supplier / employee / other).
-/// Returns the new party_id on success.
+/// Stock on hand for one item at one location.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StockOnHandRow {
+    pub item_id: String,
+    pub location_id: Option<String>,
+    pub qty: f64,
+}
+
+/// Current stock on hand per item / location for a site.
 #[tauri::command]
-pub fn erp_create_party(_actor: ActorContext, req: CreatePartyRequest) -> ApiResponse<String> {
-    if req.name.trim().is_empty() {
-        return ApiResponse::err(ErpError::ValidationFail(
-            "party name must not be empty".to_string(),
-        ));
+pub fn erp_stock_on_hand(
+    org_id: String,
+    site_id: Option<String>,
+    location_id: Option<String>,
+) -> ApiResponse<Vec<StockOnHandRow>> {
+    let store = ERP_STORE.lock().unwrap();
+    let site_id = site_id.unwrap_or_else(|| "primary".to_string());
+    ApiResponse::ok(stock_rows(
+        &store,
+        &org_id,
+        &site_id,
+        location_id.as_deref(),
+    ))
+}
+
+fn stock_rows(
+    store: &ErpStore,
+    org_id: &str,
+    site_id: &str,
+    location_id: Option<&str>,
+) -> Vec<StockOnHandRow> {
+    crate::erp::stocktake::stock_on_hand(
+        &store.transactions,
+        &store.invmoves,
+        org_id,
+        site_id,
+        location_id,
+    )
+    .into_iter()
+    .map(|((item_id, location_id), qty)| StockOnHandRow {
+        item_id,
+        location_id,
+        qty,
+    })
+    .collect()
+}
+
+/// Freeze a stocktake count sheet from current stock on hand.
+#[tauri::command]
+pub fn erp_freeze_count_sheet(
+    actor: ActorContext,
+    req: FreezeCountSheetRequest,
+) -> ApiResponse<CountSheet> {
+    match engine::freeze_count_sheet(&actor, &req) {
+        Ok(sheet) => ApiResponse::ok(sheet),
+        Err(e) => ApiResponse::err(e),
     }
+}
 
-    let party_id = format!("party_{}", Uuid::new_v4().simple());
-    let now_ms = chrono::Utc::now().timestamp_millis();
+/// Record a counted quantity (and optional reason code) on an open count sheet.
+#[tauri::command]
+pub fn erp_record_count(actor: ActorContext, req: RecordCountRequest) -> ApiResponse<CountSheet> {
+    match engine::record_count(&actor, &req) {
+        Ok(sheet) => ApiResponse::ok(sheet),
+        Err(e) => ApiResponse::err(e),
+    }
+}
 
-    let party = Party {
-        party_id: party_id.clone(),
-        org_id: req.org_id.clone(),
-        name: req.name.trim().to_string(),
-        kind: PartyKind::from_str(&req.kind),
-        email: req.email.filter(|s| !s.is_empty()),
-        contact: req.contact.filter(|s| !s.is_empty()),
-        abn: req.abn.filter(|s| !s.is_empty()),
-        created_at_ms: now_ms,
-    };
+/// Generate the draft stock_adjust tx for a fully counted sheet.
+#[tauri::command]
+pub fn erp_reconcile_count_sheet(
+    actor: ActorContext,
+    req: ReconcileCountSheetRequest,
+) -> ApiResponse<TxRef> {
+    match engine::reconcile_count_sheet(&actor, &req) {
+        Ok(tx_ref) => ApiResponse::ok(tx_ref),
+        Err(e) => ApiResponse::err(e),
+    }
+}
 
-    // Phase A: direct store write. Phase B: full signed MapSet envelope via engine.
-    let mut store = ERP_STORE.lock().unwrap();
-    store.parties.insert(party_id.clone(), party.clone());
-    drop(store);
-    // SQLite write-through (best-effort)
-    if let Ok(db_guard) = ERP_DB.lock() {
-        if let Some(ref conn) = *db_guard {
-            let _ = db::upsert_party(conn, &party);
-        }
+/// List count sheets for an org, newest first.
+#[tauri::command]
+pub fn erp_list_count_sheets(org_id: String) -> ApiResponse<Vec<CountSheet>> {
+    let store = ERP_STORE.lock().unwrap();
+    let mut sheets: Vec<CountSheet> = store
+        .count_sheets
+        .values()
+        .filter(|s| s.org_id == org_id)
+        .cloned()
+        .collect();
+    sheets.sort_by_key(|s| std::cmp::Reverse(s.frozen_at_ms));
+    ApiResponse::ok(sheets)
+}
+
+// ─── M9: Party Master ────────────────────────────────────────────────────────
+
+use crate::erp::envelope::MutationEnvelope;
+use crate::erp::types::{CreatePartyRequest, Party};
+
+/// Create a new party (customer / supplier / employee / other).
+/// Returns the new party_id on success.
+#[tauri::command]
+pub fn erp_create_party(actor: ActorContext, req: CreatePartyRequest) -> ApiResponse<String> {
+    match engine::create_party(&actor, &req) {
+        Ok(party) => ApiResponse::ok(party.party_id),
+        Err(e) => ApiResponse::err(e),
     }
+}
 
-    ApiResponse::ok(party_id)
+/// Apply a MutationEnvelope signed on another node. It goes through
+/// `satellite::accept_envelope`, so the signing node must be enrolled and the
+/// ops pass ABAC and business validation for its enrolled role.
+/// Returns the applied mutation_id.
+#[tauri::command]
+pub fn erp_apply_remote_envelope(envelope: MutationEnvelope) -> ApiResponse<String> {
+    let result = satellite::accept_envelope(&envelope);
+    match result.status {
+        satellite::SubmitStatus::Rejected => ApiResponse {
+            ok: false,
+            data: None,
+            error_code: result.error_code,
+            error_message: result.error_message,
+        },
+        _ => ApiResponse::ok(result.mutation_id),
+    }
 }
 
 /// List all parties for an org, sorted by name ascending.
@@ -383,16 +575,16 @@ pub fn erp_list_parties(org_id: String) -> ApiResponse<Vec<Party>> {
     ApiResponse::ok(parties)
 }
 
-/// Verify audit chain integrity.
+/// Verify an org's audit chain integrity.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChainVerifyResult {
     pub intact: bool,
 }
 
 #[tauri::command]
-pub fn erp_verify_audit_chain() -> ApiResponse<ChainVerifyResult> {
+pub fn erp_verify_audit_chain(org_id: String) -> ApiResponse<ChainVerifyResult> {
     ApiResponse::ok(ChainVerifyResult {
-        intact: audit_log::verify_chain(),
+        intact: audit_log::verify_chain(&org_id),
     })
 }
 
@@ -448,110 +640,543 @@ fn entry_to_view(entry: &ErpAuditEntry) -> AuditEntryView {
     }
 }
 
-/// Read last N audit log entries, newest first.
+/// Read the org's last N audit log entries, newest first.
 #[tauri::command]
-pub fn erp_get_audit_log(limit: usize) -> ApiResponse<Vec<AuditEntryView>> {
-    match audit_log::read_log(limit) {
+pub fn erp_get_audit_log(org_id: String, limit: usize) -> ApiResponse<Vec<AuditEntryView>> {
+    match audit_log::read_log(&org_id, limit) {
         Ok(entries) => ApiResponse::ok(entries.iter().map(entry_to_view).collect()),
         Err(e) => ApiResponse::err(ErpError::ValidationFail(e.to_string())),
     }
 }
 
+/// One page of a filtered audit log query.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditLogPage {
+    pub org_id: String,
+    pub entries: Vec<IndexedAuditEntryView>,
+    pub next_cursor: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IndexedAuditEntryView {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub view: AuditEntryView,
+}
+
+/// Query the audit log by actor, org, tx, fragment, op kind and time range,
+/// newest first, one page at a time. Backed by the index in corngr.db; without
+/// a database the index is built in memory for the call.
+#[tauri::command]
+pub fn erp_query_audit_log(query: audit_index::AuditQuery) -> ApiResponse<AuditLogPage> {
+    let db_guard = engine::ERP_DB.lock().unwrap();
+    let scratch;
+    let conn = match db_guard.as_ref() {
+        Some(conn) => conn,
+        None => {
+            scratch = match rusqlite::Connection::open_in_memory()
+                .and_then(|c| audit_index::init(&c).map(|_| c))
+            {
+                Ok(c) => c,
+                Err(e) => return ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+            };
+            &scratch
+        }
+    };
+    match audit_index::query(conn, &query) {
+        Ok(page) => ApiResponse::ok(AuditLogPage {
+            org_id: page.org_id,
+            entries: page
+                .entries
+                .iter()
+                .map(|e| IndexedAuditEntryView {
+                    seq: e.seq,
+                    view: entry_to_view(&e.entry),
+                })
+                .collect(),
+            next_cursor: page.next_cursor,
+        }),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
 // ─── M4: Time travel ─────────────────────────────────────────────────────────
 
-/// A simplified snapshot of ERP state reconstructed at a point in time.
+use crate::erp::checkpoint;
+use crate::erp::engine::ErpStore;
+use crate::erp::timetravel;
+
+/// A summary of ERP state reconstructed at a point in time.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TimeTravelSnapshot {
     pub as_of_ms: i64,
     pub tx_count: usize,
     pub posted_count: usize,
+    pub draft_count: usize,
+    pub posting_count: usize,
+    pub account_count: usize,
     pub mutation_count: usize,
     pub chain_intact: bool,
     pub as_of_label: String,
 }
 
-/// Replay the audit log up to `target_ts_ms` and return a reconstructed state summary.
-/// Phase A: compute-on-replay, no checkpoints.
-/// Phase B: checkpoint every N mutations for O(1) reconstruction.
+/// Rebuild the org's ErpStore as of `target_ts_ms` from the nearest verified
+/// checkpoint plus the audit entries after it.
+/// Returns the store and the number of mutations it reflects.
+fn store_as_of(org_id: &str, target_ts_ms: i64) -> Result<(ErpStore, usize), ErpError> {
+    checkpoint::store_as_of(org_id, target_ts_ms)
+        .map(|(store, n)| (store, n as usize))
+        .map_err(|e| ErpError::ValidationFail(e.to_string()))
+}
+
+fn as_of_label(ts_ms: i64) -> String {
+    use chrono::{TimeZone, Utc};
+    match Utc.timestamp_millis_opt(ts_ms) {
+        chrono::LocalResult::Single(dt) => dt.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        _ => "unknown".to_string(),
+    }
+}
+
+/// Reconstruct state at `target_ts_ms` and return a summary.
+/// Replays from the nearest signed checkpoint (see `checkpoint.rs`).
 #[tauri::command]
-pub fn erp_time_travel(target_ts_ms: i64) -> ApiResponse<TimeTravelSnapshot> {
-    // Read all entries up to the target timestamp
-    let entries = match audit_log::read_log_bounded(0, target_ts_ms) {
-        Ok(e) => e,
-        Err(err) => return ApiResponse::err(ErpError::ValidationFail(err.to_string())),
+pub fn erp_time_travel(org_id: String, target_ts_ms: i64) -> ApiResponse<TimeTravelSnapshot> {
+    let (store, mutation_count) = match store_as_of(&org_id, target_ts_ms) {
+        Ok(r) => r,
+        Err(e) => return ApiResponse::err(e),
     };
+    let count_status = |status: &str| {
+        store
+            .transactions
+            .values()
+            .filter(|t| t.status.as_str() == status)
+            .count()
+    };
+
+    ApiResponse::ok(TimeTravelSnapshot {
+        as_of_ms: target_ts_ms,
+        tx_count: store.transactions.len(),
+        posted_count: count_status("posted"),
+        draft_count: count_status("draft"),
+        posting_count: store.postings.len(),
+        account_count: store.accounts.len(),
+        mutation_count,
+        chain_intact: audit_log::verify_chain(&org_id),
+        as_of_label: as_of_label(target_ts_ms),
+    })
+}
 
-    let mutation_count = entries.len();
+/// Trial balance (per-account rollup of posted postings) as of `target_ts_ms`.
+#[tauri::command]
+pub fn erp_time_travel_trial_balance(
+    org_id: String,
+    target_ts_ms: i64,
+) -> ApiResponse<Vec<LedgerAccountRow>> {
+    match store_as_of(&org_id, target_ts_ms) {
+        Ok((store, _)) => ApiResponse::ok(ledger_rows(&store)),
+        Err(e) => ApiResponse::err(e),
+    }
+}
 
-    // Reconstruct minimal state: count distinct tx_ids and estimate posted count
-    // by scanning MapSet ops for status=posted
-    let mut tx_ids = std::collections::HashSet::new();
-    let mut posted_ids = std::collections::HashSet::new();
+/// A transaction exactly as it stood at a point in time.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TxAsOf {
+    pub as_of_ms: i64,
+    pub header: crate::erp::types::TxHeader,
+    pub lines: Vec<crate::erp::types::TxLine>,
+    pub invmoves: Vec<crate::erp::types::InvMove>,
+    pub postings: Vec<Posting>,
+}
 
-    for entry in &entries {
-        for op in &entry.envelope.ops {
-            if let crate::erp::types::Op::MapSet {
-                fragment_id,
-                key,
-                value,
-            } = op
-            {
-                // fragment_id pattern: "tx:{uuid}:hdr"
-                if fragment_id.starts_with("tx:") && fragment_id.ends_with(":hdr") {
-                    let parts: Vec<&str> = fragment_id.split(':').collect();
-                    if parts.len() >= 2 {
-                        let tx_id = parts[1].to_string();
-                        tx_ids.insert(tx_id.clone());
-                        if key == "status" && value.as_str() == Some("posted") {
-                            posted_ids.insert(tx_id);
-                        }
-                    }
-                }
+/// Header, lines, moves and postings of one tx as of `target_ts_ms`.
+#[tauri::command]
+pub fn erp_time_travel_tx(org_id: String, tx_id: String, target_ts_ms: i64) -> ApiResponse<TxAsOf> {
+    let (store, _) = match store_as_of(&org_id, target_ts_ms) {
+        Ok(r) => r,
+        Err(e) => return ApiResponse::err(e),
+    };
+    let Some(header) = store.transactions.get(&tx_id).cloned() else {
+        return ApiResponse::err(ErpError::ValidationFail(format!(
+            "tx {} did not exist at {}",
+            tx_id,
+            as_of_label(target_ts_ms)
+        )));
+    };
+    ApiResponse::ok(TxAsOf {
+        as_of_ms: target_ts_ms,
+        header,
+        lines: store
+            .lines
+            .values()
+            .filter(|l| l.tx_id == tx_id)
+            .cloned()
+            .collect(),
+        invmoves: store
+            .invmoves
+            .values()
+            .filter(|m| m.tx_id == tx_id)
+            .cloned()
+            .collect(),
+        postings: store
+            .postings
+            .values()
+            .filter(|p| p.tx_id == tx_id)
+            .cloned()
+            .collect(),
+    })
+}
+
+/// Stock on hand per item / location for a site as of `target_ts_ms`.
+#[tauri::command]
+pub fn erp_time_travel_stock(
+    org_id: String,
+    site_id: Option<String>,
+    location_id: Option<String>,
+    target_ts_ms: i64,
+) -> ApiResponse<Vec<StockOnHandRow>> {
+    let (store, _) = match store_as_of(&org_id, target_ts_ms) {
+        Ok(r) => r,
+        Err(e) => return ApiResponse::err(e),
+    };
+    ApiResponse::ok(stock_rows(
+        &store,
+        &org_id,
+        &site_id.unwrap_or_else(|| "primary".to_string()),
+        location_id.as_deref(),
+    ))
+}
+
+/// What changed between two points in time: tx status moves, account
+/// movements and stock on hand deltas.
+#[tauri::command]
+pub fn erp_time_travel_diff(
+    org_id: String,
+    from_ts_ms: i64,
+    to_ts_ms: i64,
+) -> ApiResponse<timetravel::StateDiff> {
+    if from_ts_ms > to_ts_ms {
+        return ApiResponse::err(ErpError::InvalidField(
+            "from_ts_ms must not be after to_ts_ms".to_string(),
+        ));
+    }
+    match (
+        store_as_of(&org_id, from_ts_ms),
+        store_as_of(&org_id, to_ts_ms),
+    ) {
+        (Ok((from, _)), Ok((to, _))) => ApiResponse::ok(timetravel::diff(&from, &to)),
+        (Err(e), _) | (_, Err(e)) => ApiResponse::err(e),
+    }
+}
+
+/// A checkpoint as listed for the audit UI (state omitted).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckpointSummary {
+    pub seq: u64,
+    pub chain_hash: String,
+    pub covered_until_ms: i64,
+    pub created_at_ms: i64,
+    pub state_hash: String,
+    pub signer_pubkey: String,
+    pub tx_count: usize,
+    /// Signature (by this node), state hash and chain anchor all verify
+    pub valid: bool,
+    pub error: Option<String>,
+}
+
+/// List the org's signed checkpoints (oldest first) with their integrity status.
+#[tauri::command]
+pub fn erp_list_checkpoints(org_id: String) -> ApiResponse<Vec<CheckpointSummary>> {
+    let trusted = checkpoint::node_key();
+    let rows = checkpoint::read_checkpoints(&org_id)
+        .into_iter()
+        .map(|cp| {
+            let check = audit_log::read_after(&org_id, cp.seq, i64::MIN)
+                .map_err(|e| ErpError::ValidationFail(e.to_string()))
+                .and_then(|(anchor, _)| cp.verify_against(anchor.as_ref(), &trusted));
+            CheckpointSummary {
+                seq: cp.seq,
+                chain_hash: cp.chain_hash,
+                covered_until_ms: cp.covered_until_ms,
+                created_at_ms: cp.created_at_ms,
+                state_hash: cp.state_hash,
+                signer_pubkey: cp.signer_pubkey,
+                tx_count: cp.state.transactions.len(),
+                valid: check.is_ok(),
+                error: check.err().map(|e| e.to_string()),
             }
+        })
+        .collect();
+    ApiResponse::ok(rows)
+}
+
+// ─── Consistency check ───────────────────────────────────────────────────────
+
+use crate::erp::consistency::{self, ConsistencyReport};
+
+/// SQLite and in-memory state compared against a full audit log replay.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConsistencyCheckResult {
+    pub log_entries: usize,
+    pub chain_intact: bool,
+    /// `None` when corngr.db is not open
+    pub sqlite: Option<ConsistencyReport>,
+    pub memory: ConsistencyReport,
+    /// True if corngr.db and ERP_STORE were rebuilt from the log
+    pub rebuilt: bool,
+}
+
+/// Replay the audit log into a fresh store and compare it with `db::load_all`
+/// and the live ERP_STORE. With `rebuild`, corngr.db and ERP_STORE are then
+/// replaced by the replay (owner_admin only; refused if the chain is broken).
+/// Records the log never had (seeded accounts, pre-log rows) are kept from
+/// memory and corngr.db rather than deleted. Reports describe the state
+/// before any rebuild.
+#[tauri::command]
+pub fn erp_consistency_check(
+    actor: ActorContext,
+    rebuild: bool,
+) -> ApiResponse<ConsistencyCheckResult> {
+    use crate::erp::abac::{check_abac, Action};
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    if let Err(e) = check_abac(&actor, &Action::AuditRead, &policy_ctx) {
+        return ApiResponse::err(e);
+    }
+    if rebuild {
+        if let Err(e) = check_abac(&actor, &Action::DbRebuild, &policy_ctx) {
+            return ApiResponse::err(e);
         }
     }
 
-    let as_of_label = {
-        use chrono::{TimeZone, Utc};
-        match Utc.timestamp_millis_opt(target_ts_ms) {
-            chrono::LocalResult::Single(dt) => dt.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
-            _ => "unknown".to_string(),
-        }
+    let entries = match audit_log::read_all(0, i64::MAX) {
+        Ok(e) => e,
+        Err(e) => return ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    };
+    // The store holds every org, so every org's chain must hold
+    let chain_intact = audit_log::orgs()
+        .iter()
+        .all(|org_id| audit_log::verify_chain(org_id));
+    let mut expected = timetravel::rebuild(&entries);
+
+    let memory = consistency::compare("memory", &expected, &ERP_STORE.lock().unwrap());
+
+    let db_guard = engine::ERP_DB.lock().unwrap();
+    let loaded = match db_guard.as_ref().map(crate::erp::db::load_all) {
+        Some(Ok(loaded)) => Some(loaded),
+        Some(Err(e)) => return ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+        None => None,
     };
+    let sqlite = loaded
+        .as_ref()
+        .map(|loaded| consistency::compare("sqlite", &expected, loaded));
+
+    if rebuild {
+        if !chain_intact {
+            return ApiResponse::err(ErpError::ValidationFail(
+                "audit chain is broken; refusing to rebuild from it".to_string(),
+            ));
+        }
+        let Some(conn) = db_guard.as_ref() else {
+            return ApiResponse::err(ErpError::ValidationFail(
+                "corngr.db is not open".to_string(),
+            ));
+        };
+        // `expected` replays the whole log, so any record it lacks is unlogged
+        let mut live = ERP_STORE.lock().unwrap();
+        let nothing_logged = ErpStore::new();
+        timetravel::merge_unlogged(&mut expected, &live, &nothing_logged);
+        if let Some(loaded) = &loaded {
+            timetravel::merge_unlogged(&mut expected, loaded, &nothing_logged);
+        }
+        if let Err(e) = crate::erp::db::replace_all(conn, &expected) {
+            return ApiResponse::err(ErpError::ValidationFail(e.to_string()));
+        }
+        // Keep the live replay guard; everything else comes from the log
+        let replay = std::mem::take(&mut live.replay);
+        *live = expected;
+        live.replay = replay;
+    }
 
-    ApiResponse::ok(TimeTravelSnapshot {
-        as_of_ms: target_ts_ms,
-        tx_count: tx_ids.len(),
-        posted_count: posted_ids.len(),
-        mutation_count,
-        chain_intact: audit_log::verify_chain(),
-        as_of_label,
+    ApiResponse::ok(ConsistencyCheckResult {
+        log_entries: entries.len(),
+        chain_intact,
+        sqlite,
+        memory,
+        rebuilt: rebuild,
     })
 }
 
+// ─── Merkle inclusion proofs ─────────────────────────────────────────────────
+
+use crate::erp::merkle::{self, InclusionProof, SignedRoot};
+
+/// One audit entry with its inclusion proof against a signed root. Everything
+/// an auditor needs to check it with `merkle::verify_inclusion`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditProof {
+    pub entry: ErpAuditEntry,
+    pub proof: InclusionProof,
+    pub signed_root: SignedRoot,
+}
+
+/// Inclusion proof for the org's audit entry of `mutation_id`, against the
+/// latest published root that covers it (see `merkle::prove_entry`).
+#[tauri::command]
+pub fn erp_audit_proof(org_id: String, mutation_id: String) -> ApiResponse<AuditProof> {
+    let entries = match audit_log::read_log_bounded(&org_id, i64::MIN, i64::MAX) {
+        Ok(e) => e,
+        Err(e) => return ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    };
+    let Some(index) = entries
+        .iter()
+        .position(|e| e.envelope.mutation_id == mutation_id)
+    else {
+        return ApiResponse::err(ErpError::InvalidField(format!(
+            "mutation {} is not in the audit log",
+            mutation_id
+        )));
+    };
+
+    match merkle::prove_entry(&org_id, &entries, index) {
+        Ok((proof, signed_root)) => ApiResponse::ok(AuditProof {
+            entry: entries[index].clone(),
+            proof,
+            signed_root,
+        }),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Check an `AuditProof` offline; `expected_signer` pins the root signer key.
+#[tauri::command]
+pub fn erp_verify_audit_proof(
+    proof: AuditProof,
+    expected_signer: Option<String>,
+) -> ApiResponse<bool> {
+    match merkle::verify_inclusion(
+        &proof.entry,
+        &proof.proof,
+        &proof.signed_root,
+        expected_signer.as_deref(),
+    ) {
+        Ok(()) => ApiResponse::ok(true),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Signed Merkle roots published for the org, oldest first.
+#[tauri::command]
+pub fn erp_list_audit_roots(org_id: String) -> ApiResponse<Vec<SignedRoot>> {
+    ApiResponse::ok(merkle::read_roots(&org_id))
+}
+
+// ─── Auditor evidence bundles ────────────────────────────────────────────────
+
+use crate::erp::evidence;
+
+/// Export the actor's org's audit entries issued within `[from_ts_ms, to_ts_ms]` as an
+/// offline-verifiable evidence bundle. Writes
+/// ~/Downloads/corngr_evidence_{org}_YYYY-MM-DD.tar and returns the path.
+#[tauri::command]
+pub fn erp_export_evidence(
+    actor: ActorContext,
+    from_ts_ms: i64,
+    to_ts_ms: i64,
+) -> ApiResponse<String> {
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    if let Err(e) =
+        crate::erp::abac::check_abac(&actor, &crate::erp::abac::Action::AuditRead, &policy_ctx)
+    {
+        return ApiResponse::err(e);
+    }
+    if from_ts_ms > to_ts_ms {
+        return ApiResponse::err(ErpError::InvalidField(
+            "from_ts_ms must not be after to_ts_ms".to_string(),
+        ));
+    }
+    let archive = match evidence::export(&actor.org_id, from_ts_ms, to_ts_ms) {
+        Ok(a) => a,
+        Err(e) => return ApiResponse::err(e),
+    };
+    let dir = home_downloads();
+    let _ = std::fs::create_dir_all(&dir);
+    let path = dir.join(format!(
+        "corngr_evidence_{}_{}.tar",
+        actor.org_id,
+        parquet_date_stamp()
+    ));
+    match std::fs::write(&path, archive) {
+        Ok(()) => ApiResponse::ok(path.to_string_lossy().to_string()),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(format!("File: {e}"))),
+    }
+}
+
+/// Verify an evidence bundle on disk without touching the ERP database.
+/// `expected_exporter` pins the exporting node's public key.
+#[tauri::command]
+pub fn erp_verify_evidence(
+    path: String,
+    expected_exporter: Option<String>,
+) -> ApiResponse<evidence::EvidenceReport> {
+    match std::fs::read(&path) {
+        Ok(bytes) => ApiResponse::ok(evidence::verify_bundle(
+            &bytes,
+            expected_exporter.as_deref(),
+        )),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(format!("{path}: {e}"))),
+    }
+}
+
 // ─── M5: Chart of Accounts ───────────────────────────────────────────────────
 
-/// Seed the Chart of Accounts from a named template.
+/// Seed the Chart of Accounts from a named template or a user-supplied file.
 /// `template_name`: "general_sme_au_gst" | "services_low_inventory" | "product_manufacturing"
-/// Returns the number of accounts seeded.
+/// | "custom". For "custom", `template_path` must point at a .csv or .json template
+/// (columns: code, name, type, normal_balance, parent, role, is_header).
+/// Codes that already exist are rejected unless `merge` is set, in which case the
+/// template overwrites them. Returns the number of accounts seeded.
 #[tauri::command]
-pub fn erp_seed_coa(actor: ActorContext, template_name: String) -> ApiResponse<usize> {
-    // ABAC: only owner_admin / finance can seed CoA
+pub fn erp_seed_coa(
+    actor: ActorContext,
+    template_name: String,
+    template_path: Option<String>,
+    merge: Option<bool>,
+) -> ApiResponse<usize> {
+    // ABAC: only owner_admin / finance manage the CoA
     use crate::erp::types::PolicyContext;
     let ctx = PolicyContext {
         org_id: actor.org_id.clone(),
         tx_id: None,
         tx_status: None,
     };
-    if let Err(e) = crate::erp::abac::check_abac(&actor, &crate::erp::abac::Action::TxCreate, &ctx)
+    if let Err(e) =
+        crate::erp::abac::check_abac(&actor, &crate::erp::abac::Action::AccountManage, &ctx)
     {
         return ApiResponse::err(e);
     }
 
-    let ops = match template_name.as_str() {
-        "general_sme_au_gst" => coa_templates::general_sme_au_gst(),
-        "services_low_inventory" => coa_templates::services_low_inventory(),
-        "product_manufacturing" => coa_templates::product_manufacturing(),
+    let (ops, rows) = match template_name.as_str() {
+        "general_sme_au_gst" => (coa_templates::general_sme_au_gst(), None),
+        "services_low_inventory" => (coa_templates::services_low_inventory(), None),
+        "product_manufacturing" => (coa_templates::product_manufacturing(), None),
+        "custom" => {
+            let Some(path) = template_path else {
+                return ApiResponse::err(ErpError::ValidationFail(
+                    "custom CoA template requires template_path".to_string(),
+                ));
+            };
+            match coa_templates::parse_template_file(std::path::Path::new(&path)) {
+                Ok(rows) => (coa_templates::template_ops(&rows), Some(rows)),
+                Err(e) => return ApiResponse::err(e),
+            }
+        }
         other => {
             return ApiResponse::err(ErpError::ValidationFail(format!(
                 "unknown CoA template: {}",
@@ -559,21 +1184,34 @@ pub fn erp_seed_coa(actor: ActorContext, template_name: String) -> ApiResponse<u
             )))
         }
     };
+    let codes = coa_templates::seeded_codes(&ops);
 
+    // Validate against the chart the envelope is committed into
     let mut store = ERP_STORE.lock().unwrap();
-    engine::seed_coa_ops(&ops, &mut store.accounts);
-    let count = store.accounts.len();
-    let accounts_for_db: Vec<_> = store.accounts.values().cloned().collect();
-    drop(store);
-    // SQLite write-through (best-effort)
-    if let Ok(db_guard) = ERP_DB.lock() {
-        if let Some(ref conn) = *db_guard {
-            for a in &accounts_for_db {
-                let _ = db::upsert_account(conn, a);
-            }
+    if let Some(ref rows) = rows {
+        if let Err(e) = coa_templates::validate_template(&store.accounts, rows) {
+            return ApiResponse::err(e);
+        }
+    }
+    if !merge.unwrap_or(false) {
+        let existing: Vec<&str> = codes
+            .iter()
+            .filter(|c| store.accounts.contains_key(*c))
+            .map(String::as_str)
+            .collect();
+        if !existing.is_empty() {
+            return ApiResponse::err(ErpError::ValidationFail(format!(
+                "accounts already exist: {} (seed with merge to overwrite them)",
+                existing.join(", ")
+            )));
         }
     }
-    ApiResponse::ok(count)
+    let envelope = match engine::sign_next(&mut store, &actor, ops, ctx) {
+        Ok(env) => env,
+        Err(e) => return ApiResponse::err(e),
+    };
+    engine::commit(store, &envelope);
+    ApiResponse::ok(codes.len())
 }
 
 /// UI-friendly CoA account view.
@@ -583,6 +1221,10 @@ pub struct AccountView {
     pub name: String,
     pub acct_type: String,
     pub normal_balance: String,
+    pub parent_code: Option<String>,
+    pub is_header: bool,
+    pub active: bool,
+    pub role: Option<String>,
 }
 
 impl From<&AccountRecord> for AccountView {
@@ -592,6 +1234,10 @@ impl From<&AccountRecord> for AccountView {
             name: r.name.clone(),
             acct_type: r.acct_type.clone(),
             normal_balance: r.normal_balance.clone(),
+            parent_code: r.parent_code.clone(),
+            is_header: r.is_header,
+            active: r.active,
+            role: r.role.clone(),
         }
     }
 }
@@ -605,6 +1251,137 @@ pub fn erp_list_coa() -> ApiResponse<Vec<AccountView>> {
     ApiResponse::ok(accounts)
 }
 
+/// Export the current Chart of Accounts as a re-importable template.
+/// `format`: "csv" | "json". Writes to ~/Downloads/corngr_coa_YYYY-MM-DD.{csv,json}
+/// and returns the path.
+#[tauri::command]
+pub fn erp_export_coa(format: String) -> ApiResponse<String> {
+    let store = ERP_STORE.lock().unwrap();
+    let accounts: Vec<AccountRecord> = store.accounts.values().cloned().collect();
+    drop(store);
+
+    let contents = match format.as_str() {
+        "csv" => coa_templates::export_csv(&accounts),
+        "json" => match coa_templates::export_json(&accounts) {
+            Ok(c) => c,
+            Err(e) => return ApiResponse::err(e),
+        },
+        other => {
+            return ApiResponse::err(ErpError::InvalidField(format!(
+                "unknown CoA export format: {}",
+                other
+            )))
+        }
+    };
+
+    let dir = home_downloads();
+    let _ = std::fs::create_dir_all(&dir);
+    let path = dir.join(format!("corngr_coa_{}.{}", parquet_date_stamp(), format));
+    if let Err(e) = std::fs::write(&path, contents) {
+        return ApiResponse::err(ErpError::ValidationFail(format!("File: {e}")));
+    }
+    ApiResponse::ok(path.to_string_lossy().to_string())
+}
+
+// ─── CoA management ──────────────────────────────────────────────────────────
+
+use crate::erp::coa;
+use crate::erp::types::CreateAccountRequest;
+
+/// Add an account (optionally under a header account).
+#[tauri::command]
+pub fn erp_add_account(actor: ActorContext, req: CreateAccountRequest) -> ApiResponse<AccountView> {
+    match engine::add_account(&actor, &req) {
+        Ok(a) => ApiResponse::ok(AccountView::from(&a)),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Rename an account; the code is immutable.
+#[tauri::command]
+pub fn erp_rename_account(
+    actor: ActorContext,
+    code: String,
+    name: String,
+) -> ApiResponse<AccountView> {
+    match engine::rename_account(&actor, &code, &name) {
+        Ok(a) => ApiResponse::ok(AccountView::from(&a)),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Deactivate an account — rejected while it still carries a balance.
+#[tauri::command]
+pub fn erp_deactivate_account(actor: ActorContext, code: String) -> ApiResponse<AccountView> {
+    match engine::deactivate_account(&actor, &code) {
+        Ok(a) => ApiResponse::ok(AccountView::from(&a)),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Move an account under another header account (`parent_code = None` → top level).
+#[tauri::command]
+pub fn erp_reparent_account(
+    actor: ActorContext,
+    code: String,
+    parent_code: Option<String>,
+) -> ApiResponse<AccountView> {
+    match engine::reparent_account(&actor, &code, parent_code.as_deref()) {
+        Ok(a) => ApiResponse::ok(AccountView::from(&a)),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// A CoA node with balances rolled up from all descendant accounts.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountTreeNode {
+    pub account: AccountView,
+    pub total_debit: f64,
+    pub total_credit: f64,
+    /// Net balance on the account's normal side, including children
+    pub balance: f64,
+    pub children: Vec<AccountTreeNode>,
+}
+
+/// Return the CoA as a parent/child tree with header roll-up balances.
+#[tauri::command]
+pub fn erp_get_coa_tree() -> ApiResponse<Vec<AccountTreeNode>> {
+    let store = ERP_STORE.lock().unwrap();
+    let totals = coa::rollup_totals(&store.accounts, &store.postings);
+
+    fn build(
+        parent: Option<&str>,
+        accounts: &std::collections::HashMap<String, AccountRecord>,
+        totals: &std::collections::HashMap<String, (f64, f64)>,
+    ) -> Vec<AccountTreeNode> {
+        let mut children: Vec<&AccountRecord> = accounts
+            .values()
+            .filter(|a| a.parent_code.as_deref() == parent)
+            .collect();
+        children.sort_by(|a, b| a.code.cmp(&b.code));
+        children
+            .into_iter()
+            .map(|a| {
+                let (dr, cr) = totals.get(&a.code).copied().unwrap_or((0.0, 0.0));
+                let balance = if a.normal_balance == "debit" {
+                    dr - cr
+                } else {
+                    cr - dr
+                };
+                AccountTreeNode {
+                    account: AccountView::from(a),
+                    total_debit: (dr * 100.0).round() / 100.0,
+                    total_credit: (cr * 100.0).round() / 100.0,
+                    balance: (balance * 100.0).round() / 100.0,
+                    children: build(Some(&a.code), accounts, totals),
+                }
+            })
+            .collect()
+    }
+
+    ApiResponse::ok(build(None, &store.accounts, &totals))
+}
+
 // ─── M5: Postings Ledger ─────────────────────────────────────────────────────
 
 /// Per-account balance rollup for the Postings Ledger view.
@@ -624,7 +1401,11 @@ pub struct LedgerAccountRow {
 #[tauri::command]
 pub fn erp_get_ledger_summary() -> ApiResponse<Vec<LedgerAccountRow>> {
     let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(ledger_rows(&store))
+}
 
+/// Per-account rollup of `store.postings`, resolved against the store's CoA.
+fn ledger_rows(store: &ErpStore) -> Vec<LedgerAccountRow> {
     // Aggregate postings by account_id
     let mut agg: std::collections::HashMap<String, (f64, f64, usize)> =
         std::collections::HashMap::new();
@@ -642,10 +1423,10 @@ pub fn erp_get_ledger_summary() -> ApiResponse<Vec<LedgerAccountRow>> {
         .map(|(acct_id, (dr, cr, cnt))| {
             // Try to resolve account name from seeded CoA
             // Symbolic ledger IDs (e.g. "accounts_receivable") are matched by name lookup
-            let found = store.accounts.values().find(|a| {
-                a.code == acct_id
-                    || a.name.to_lowercase().replace(' ', "_") == acct_id.to_lowercase()
-            });
+            let found = store
+                .accounts
+                .values()
+                .find(|a| coa::posting_matches(a, &acct_id));
             let (account_name, acct_type, normal_balance) = found
                 .map(|a| {
                     (
@@ -690,7 +1471,7 @@ pub fn erp_get_ledger_summary() -> ApiResponse<Vec<LedgerAccountRow>> {
             .then(a.account_id.cmp(&b.account_id))
     });
 
-    ApiResponse::ok(rows)
+    rows
 }
 
 // ─── M6: Shatter Import ───────────────────────────────────────────────────────
@@ -733,7 +1514,6 @@ pub fn erp_bulk_import(
 
     let mut tx_ids: Vec<String> = Vec::new();
     let mut errors: Vec<String> = Vec::new();
-    let mut lamport = actor.lamport;
 
     for (i, row) in rows.into_iter().enumerate() {
         // Validate tx_type
@@ -757,18 +1537,10 @@ pub fn erp_bulk_import(
             description: Some(desc),
             tx_date: row.tx_date.clone(),
             site_id: Some("primary".to_string()),
+            source_tx_id: None,
         };
 
-        // Create actor with incrementing lamport
-        let import_actor = ActorContext {
-            pubkey: actor.pubkey.clone(),
-            role: actor.role.clone(),
-            org_id: actor.org_id.clone(),
-            lamport,
-        };
-        lamport += 1;
-
-        let tx_ref = match engine::create_tx(&import_actor, &create_req) {
+        let tx_ref = match engine::create_tx(&actor, &create_req) {
             Ok(r) => r,
             Err(e) => {
                 errors.push(format!("row {}: create_tx: {}", i, e));
@@ -788,15 +1560,12 @@ pub fn erp_bulk_import(
                 inventory_effect: "none".to_string(),
                 tax_code: Some("GST".to_string()),
                 tax_rate: row.tax_rate,
+                debit_amount: None,
+                credit_amount: None,
+                source_line_id: None,
+                reason_code: None,
             };
-            let line_actor = ActorContext {
-                pubkey: actor.pubkey.clone(),
-                role: actor.role.clone(),
-                org_id: actor.org_id.clone(),
-                lamport,
-            };
-            lamport += 1;
-            if let Err(e) = engine::add_line(&line_actor, &line_req) {
+            if let Err(e) = engine::add_line(&actor, &line_req) {
                 errors.push(format!("row {}: add_line: {}", i, e));
                 // tx still created — continue
             }
@@ -1011,6 +1780,7 @@ pub fn erp_export_postings_parquet(org_id: String) -> ApiResponse<String> {
 // ─── M12 — Local LLM CAIO query ──────────────────────────────────────────────
 
 use crate::erp::caio_llm::{query_caio, CaioContext, LlmProposal};
+use crate::erp::caio_tools::{self, ToolRecord};
 
 /// Response envelope for erp_caio_query.
 #[derive(Debug, Serialize)]
@@ -1021,15 +1791,44 @@ pub struct CaioQueryResult {
     pub used_llm: bool,
     /// Human-readable source label for the UI badge
     pub source_label: String,
+    /// Ledger queries the LLM ran; proposals cite them by `call_id`
+    pub tool_calls: Vec<ToolRecord>,
 }
 
 /// Query CAIO with an optional free-text user question.
 ///
 /// 1. Reads live counts from ERP_STORE.
-/// 2. Calls `caio_llm::query_caio()` — tries Ollama, falls back to rules.
-/// 3. Returns proposals + metadata so the frontend can show LLM vs rules badge.
+/// 2. Calls `caio_llm::query_caio()` with the org's LLM backend, running the
+///    read-only ledger tools it asks for; falls back to rules.
+/// 3. Queues proposals that carry a concrete draft as signed proposals for
+///    review by `actor` (`proposal_id` is set on those).
+/// 4. Returns proposals + metadata so the frontend can show LLM vs rules badge.
+///
+/// The tools read the whole ledger, so `actor` must belong to `org_id` and
+/// hold audit.read there; nothing runs otherwise.
 #[tauri::command]
-pub fn erp_caio_query(org_id: String, user_query: String) -> ApiResponse<CaioQueryResult> {
+pub fn erp_caio_query(
+    org_id: String,
+    user_query: String,
+    actor: ActorContext,
+) -> ApiResponse<CaioQueryResult> {
+    if actor.org_id != org_id {
+        return ApiResponse::err(ErpError::AbacDeny(format!(
+            "actor belongs to org {}, not {}",
+            actor.org_id, org_id
+        )));
+    }
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    if let Err(e) =
+        crate::erp::abac::check_abac(&actor, &crate::erp::abac::Action::AuditRead, &policy_ctx)
+    {
+        return ApiResponse::err(e);
+    }
+
     let store = ERP_STORE.lock().unwrap();
 
     let tx_count = store
@@ -1053,8 +1852,15 @@ pub fn erp_caio_query(org_id: String, user_query: String) -> ApiResponse<CaioQue
         .filter(|p| p.org_id == org_id)
         .count();
     let account_count = store.accounts.len();
+    let anomalies = anomaly::detect(&store, &org_id, &anomaly::config_for(&org_id));
+    let reorders = reorder::proposals(
+        &store,
+        &org_id,
+        &reorder::policies_for(&store, &org_id),
+        chrono::Utc::now().timestamp_millis(),
+    );
 
-    drop(store); // release lock before potentially slow Ollama call
+    drop(store); // release lock before potentially slow LLM call
 
     let ctx = CaioContext {
         org_id: org_id.clone(),
@@ -1063,19 +1869,435 @@ pub fn erp_caio_query(org_id: String, user_query: String) -> ApiResponse<CaioQue
         posted_count,
         party_count,
         account_count,
+        anomalies,
+        reorders,
     };
 
-    let (proposals, used_llm) = query_caio(&ctx, &user_query);
+    let backend = llm_backend::backend_for(&org_id);
+    let mut run_tool = |call: &caio_tools::ToolCall, seq: usize| {
+        caio_tools::run(&ERP_STORE.lock().unwrap(), &org_id, call, seq)
+    };
+    let answer = query_caio(backend.as_ref(), &ctx, &user_query, &mut run_tool);
+    let (mut proposals, used_llm, tool_calls) =
+        (answer.proposals, answer.used_llm, answer.tool_calls);
+    for p in proposals.iter_mut() {
+        p.proposal_id = queue_caio_draft(&actor, p, &tool_calls);
+    }
 
     let source_label = if used_llm {
-        "Mistral via Ollama".to_string()
+        backend.label()
     } else {
-        "Deterministic rules (Ollama offline)".to_string()
+        "Deterministic rules (LLM offline)".to_string()
     };
 
     ApiResponse::ok(CaioQueryResult {
         proposals,
         used_llm,
         source_label,
+        tool_calls,
+    })
+}
+
+use crate::erp::anomaly::{self, DetectorConfig};
+
+/// Run CAIO's deterministic anomaly detectors over `org_id` (the org's saved
+/// thresholds unless `config` is given). Read-only: nothing is queued.
+#[tauri::command]
+pub fn erp_detect_anomalies(
+    org_id: String,
+    config: Option<DetectorConfig>,
+) -> ApiResponse<Vec<LlmProposal>> {
+    let config = config.unwrap_or_else(|| anomaly::config_for(&org_id));
+    let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(anomaly::detect(&store, &org_id, &config))
+}
+
+/// The anomaly thresholds CAIO uses for `org_id` (the defaults if none are set).
+#[tauri::command]
+pub fn erp_get_detector_config(org_id: String) -> ApiResponse<DetectorConfig> {
+    ApiResponse::ok(anomaly::config_for(&org_id))
+}
+
+/// Set the anomaly thresholds for the actor's org; owner_admin only, like
+/// the LLM config.
+#[tauri::command]
+pub fn erp_set_detector_config(
+    actor: ActorContext,
+    config: DetectorConfig,
+) -> ApiResponse<DetectorConfig> {
+    if let Err(e) = check_node_manage(&actor) {
+        return ApiResponse::err(e);
+    }
+    if let Err(msg) = config.validate() {
+        return ApiResponse::err(ErpError::ValidationFail(msg));
+    }
+    match anomaly::set_config(&actor.org_id, config.clone()) {
+        Ok(()) => ApiResponse::ok(config),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    }
+}
+
+/// Stock position vs reorder point for every item of `org_id`.
+#[tauri::command]
+pub fn erp_reorder_status(org_id: String) -> ApiResponse<Vec<ReorderStatus>> {
+    let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(reorder::analyse(
+        &store,
+        &org_id,
+        &reorder::policies_for(&store, &org_id),
+        chrono::Utc::now().timestamp_millis(),
+    ))
+}
+
+#[tauri::command]
+pub fn erp_list_reorder_policies(org_id: String) -> ApiResponse<Vec<ReorderPolicy>> {
+    let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(
+        reorder::policies_for(&store, &org_id)
+            .into_values()
+            .collect(),
+    )
+}
+
+/// Set an item's reorder point, lead time and preferred supplier for the
+/// actor's org as a signed mutation; `policy = None` removes it.
+#[tauri::command]
+pub fn erp_set_reorder_policy(
+    actor: ActorContext,
+    item_id: String,
+    policy: Option<ReorderPolicy>,
+) -> ApiResponse<Option<ReorderPolicy>> {
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    if let Err(e) = crate::erp::abac::check_abac(
+        &actor,
+        &crate::erp::abac::Action::InventoryManage,
+        &policy_ctx,
+    ) {
+        return ApiResponse::err(e);
+    }
+    if let Some(p) = &policy {
+        let supplier_ok = p.preferred_supplier_id.as_ref().is_none_or(|id| {
+            let store = ERP_STORE.lock().unwrap();
+            store
+                .parties
+                .get(id)
+                .is_some_and(|party| party.org_id == actor.org_id)
+        });
+        let invalid = if p.item_id != item_id {
+            Some(format!("policy is for {}, not {}", p.item_id, item_id))
+        } else if !supplier_ok {
+            Some("preferred supplier not found in this org".to_string())
+        } else if [p.lead_time_days, p.safety_days, p.cover_days]
+            .iter()
+            .chain(p.reorder_point.iter())
+            .any(|v| !v.is_finite() || *v < 0.0)
+            || p.reorder_qty.is_some_and(|q| !q.is_finite() || q <= 0.0)
+        {
+            Some("reorder quantities and days must be non-negative".to_string())
+        } else {
+            None
+        };
+        if let Some(msg) = invalid {
+            return ApiResponse::err(ErpError::ValidationFail(msg));
+        }
+    }
+    match reorder::set_policy(&actor, &item_id, policy.clone()) {
+        Ok(()) => ApiResponse::ok(policy.map(|p| ReorderPolicy {
+            org_id: actor.org_id.clone(),
+            ..p
+        })),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+use crate::erp::llm_backend::{self, LlmConfig};
+
+/// The LLM backend CAIO uses for the actor's org (the default if none is
+/// set). Carries the API key, so owner_admin only like setting it.
+#[tauri::command]
+pub fn erp_get_llm_config(actor: ActorContext) -> ApiResponse<LlmConfig> {
+    if let Err(e) = check_node_manage(&actor) {
+        return ApiResponse::err(e);
+    }
+    ApiResponse::ok(llm_backend::config_for(&actor.org_id))
+}
+
+/// Set the LLM backend, model, endpoint and timeout for the actor's org.
+#[tauri::command]
+pub fn erp_set_llm_config(actor: ActorContext, config: LlmConfig) -> ApiResponse<LlmConfig> {
+    if let Err(e) = check_node_manage(&actor) {
+        return ApiResponse::err(e);
+    }
+    match llm_backend::set_config(&actor.org_id, config.clone()) {
+        Ok(()) => ApiResponse::ok(config),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    }
+}
+
+/// Queue the draft a CAIO card carries; failures leave the card unqueued.
+/// The queued rationale names the tool calls the card cites, so the signed
+/// proposal records what data it was drafted from.
+fn queue_caio_draft(
+    actor: &ActorContext,
+    card: &LlmProposal,
+    tool_calls: &[ToolRecord],
+) -> Option<String> {
+    let draft = card.draft.as_ref()?;
+    let cited: Vec<String> = tool_calls
+        .iter()
+        .filter(|r| card.citations.contains(&r.call_id))
+        .map(|r| format!("{} {} {}", r.call_id, r.tool, r.args))
+        .collect();
+    let rationale = if cited.is_empty() {
+        card.rationale.clone()
+    } else {
+        format!("{} (data: {})", card.rationale, cited.join("; "))
+    };
+    let queued = draft.ops(actor, &actor.org_id).and_then(|ops| {
+        proposals::propose(
+            actor,
+            NewProposal {
+                kind: card.r#type.clone(),
+                title: card.title.clone(),
+                rationale,
+                source_fragment: card.source_fragment.clone(),
+                ops,
+            },
+        )
+    });
+    match queued {
+        Ok(p) => Some(p.proposal_id),
+        Err(e) => {
+            eprintln!("⚠️  CAIO draft {} not queued: {}", card.id, e);
+            None
+        }
+    }
+}
+
+// ─── CAIO proposal review ────────────────────────────────────────────────────
+
+use crate::erp::proposals::{self, NewProposal};
+use crate::erp::types::{PendingProposal, ProposalStatus};
+
+/// The actor's org proposals, newest first; `status` filters
+/// ("pending" | "accepted" | "rejected").
+#[tauri::command]
+pub fn erp_list_proposals(
+    actor: ActorContext,
+    status: Option<ProposalStatus>,
+) -> ApiResponse<Vec<PendingProposal>> {
+    let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(proposals::list(&store, &actor.org_id, status))
+}
+
+/// Apply a pending proposal's ops through the normal validation path.
+#[tauri::command]
+pub fn erp_accept_proposal(
+    actor: ActorContext,
+    proposal_id: String,
+) -> ApiResponse<MutationEnvelope> {
+    match proposals::accept(&actor, &proposal_id) {
+        Ok(envelope) => ApiResponse::ok(envelope),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Reject a pending proposal; the rejection is a signed audit log entry.
+#[tauri::command]
+pub fn erp_reject_proposal(
+    actor: ActorContext,
+    proposal_id: String,
+    reason: Option<String>,
+) -> ApiResponse<MutationEnvelope> {
+    match proposals::reject(&actor, &proposal_id, reason) {
+        Ok(envelope) => ApiResponse::ok(envelope),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+// ─── Primary / satellite nodes ───────────────────────────────────────────────
+
+use crate::erp::satellite::{self, EnrolledActor, NodeMode, RejectedEnvelope, SyncReport};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeStatus {
+    pub mode: NodeMode,
+    /// Key envelopes signed on this node carry (what a primary enrolls)
+    pub node_pubkey: String,
+    /// Satellite envelopes not yet settled by the primary
+    pub pending: usize,
+    pub rejected: Vec<RejectedEnvelope>,
+    /// Satellite actors enrolled on this primary
+    pub enrolled: Vec<EnrolledActor>,
+}
+
+pub(crate) fn check_node_manage(actor: &ActorContext) -> Result<(), ErpError> {
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    crate::erp::abac::check_abac(actor, &crate::erp::abac::Action::NodeManage, &policy_ctx)
+}
+
+#[tauri::command]
+pub fn erp_node_status() -> ApiResponse<NodeStatus> {
+    ApiResponse::ok(NodeStatus {
+        mode: satellite::mode(),
+        node_pubkey: satellite::node_pubkey(),
+        pending: satellite::pending().len(),
+        rejected: satellite::rejected(),
+        enrolled: satellite::enrolled(),
     })
 }
+
+/// Switch between standalone, primary and satellite mode (owner_admin only).
+#[tauri::command]
+pub fn erp_set_node_mode(actor: ActorContext, mode: NodeMode) -> ApiResponse<NodeMode> {
+    if let Err(e) = check_node_manage(&actor) {
+        return ApiResponse::err(e);
+    }
+    match satellite::set_mode(mode) {
+        Ok(()) => ApiResponse::ok(satellite::mode()),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    }
+}
+
+/// Allow a satellite key to submit envelopes to this primary as `role`.
+#[tauri::command]
+pub fn erp_enroll_satellite(
+    actor: ActorContext,
+    pubkey: String,
+    org_id: String,
+    role: crate::erp::types::Role,
+    label: String,
+) -> ApiResponse<EnrolledActor> {
+    if let Err(e) = check_node_manage(&actor) {
+        return ApiResponse::err(e);
+    }
+    if hex::decode(&pubkey).map(|b| b.len()) != Ok(32) {
+        return ApiResponse::err(ErpError::ValidationFail(format!(
+            "{} is not a hex Ed25519 public key",
+            pubkey
+        )));
+    }
+    let enrolled = EnrolledActor {
+        pubkey,
+        org_id,
+        role,
+        label,
+        enrolled_at_ms: chrono::Utc::now().timestamp_millis(),
+    };
+    match satellite::enroll(enrolled.clone()) {
+        Ok(()) => ApiResponse::ok(enrolled),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    }
+}
+
+#[tauri::command]
+pub fn erp_remove_satellite(actor: ActorContext, pubkey: String) -> ApiResponse<bool> {
+    if let Err(e) = check_node_manage(&actor) {
+        return ApiResponse::err(e);
+    }
+    match satellite::unenroll(&pubkey) {
+        Ok(removed) => ApiResponse::ok(removed),
+        Err(e) => ApiResponse::err(ErpError::ValidationFail(e.to_string())),
+    }
+}
+
+/// Submit queued envelopes to the primary now (satellite mode only).
+#[tauri::command]
+pub async fn erp_satellite_sync() -> ApiResponse<SyncReport> {
+    match satellite::sync().await {
+        Ok(report) => ApiResponse::ok(report),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Token for joining the actor's org ERP room (`erp:{org_id}`) on the
+/// collab server; pass it as the `erpToken` query parameter.
+#[tauri::command]
+pub fn erp_room_token(actor: ActorContext) -> ApiResponse<crate::erp::ydoc::RoomToken> {
+    match crate::erp::ydoc::issue_room_token(&actor) {
+        Ok(token) => ApiResponse::ok(token),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+// ─── Concurrent edit conflicts ───────────────────────────────────────────────
+
+use crate::erp::conflict::{self, Conflict, FragmentVersion, Resolution};
+
+/// The ABAC check for conflict commands: they expose other actors' edits.
+fn check_conflict_access(
+    actor: &ActorContext,
+    action: crate::erp::abac::Action,
+) -> Result<(), ErpError> {
+    let policy_ctx = crate::erp::types::PolicyContext {
+        org_id: actor.org_id.clone(),
+        tx_id: None,
+        tx_status: None,
+    };
+    crate::erp::abac::check_abac(actor, &action, &policy_ctx)
+}
+
+/// Remote edits of the actor's org rejected as concurrent, oldest first.
+/// Needs `audit.read`.
+#[tauri::command]
+pub fn erp_list_conflicts(
+    actor: ActorContext,
+    include_resolved: bool,
+) -> ApiResponse<Vec<Conflict>> {
+    if let Err(e) = check_conflict_access(&actor, crate::erp::abac::Action::AuditRead) {
+        return ApiResponse::err(e);
+    }
+    ApiResponse::ok(conflict::list(&actor.org_id, include_resolved))
+}
+
+/// Settle a conflict; returns the envelope applying the resolution, if any.
+/// Needs `proposal.review`, plus the permissions of any ops it applies.
+#[tauri::command]
+pub fn erp_resolve_conflict(
+    actor: ActorContext,
+    conflict_id: String,
+    resolution: Resolution,
+) -> ApiResponse<Option<MutationEnvelope>> {
+    if let Err(e) = check_conflict_access(&actor, crate::erp::abac::Action::ProposalReview) {
+        return ApiResponse::err(e);
+    }
+    match conflict::resolve(&actor, &conflict_id, resolution) {
+        Ok(envelope) => ApiResponse::ok(envelope),
+        Err(e) => ApiResponse::err(e),
+    }
+}
+
+/// Current versions of the given fragments, for edits the UI is about to
+/// sign (fragments never written are omitted).
+#[tauri::command]
+pub fn erp_fragment_versions(
+    fragment_ids: Vec<String>,
+) -> ApiResponse<std::collections::BTreeMap<String, FragmentVersion>> {
+    let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(
+        fragment_ids
+            .into_iter()
+            .filter_map(|f| store.fragment_versions.get(&f).cloned().map(|v| (f, v)))
+            .collect(),
+    )
+}
+
+// ─── Actor clocks ────────────────────────────────────────────────────────────
+
+use crate::erp::hlc::{self, ActorClock};
+
+/// The actor's last and next clock plus chain head, for clients that sign
+/// envelopes themselves (and to resync a device that lost its state).
+#[tauri::command]
+pub fn erp_actor_clock(actor_pubkey: String) -> ApiResponse<ActorClock> {
+    let store = ERP_STORE.lock().unwrap();
+    ApiResponse::ok(hlc::actor_clock(&store, &actor_pubkey))
+}
@@ -0,0 +1,163 @@
+//! test_support.rs — Store, op and envelope fixtures shared by the module tests
+
+use ed25519_dalek::SigningKey;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::erp::audit_log::{self, Rotation};
+use crate::erp::engine::{ErpStore, ERP_STORE};
+use crate::erp::envelope::{sign_with_key, MutationEnvelope};
+use crate::erp::types::{InventoryEffect, Op, PolicyContext, TxHeader, TxLine, TxStatus, TxType};
+
+lazy_static::lazy_static! {
+    static ref ENGINE_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// Exclusive use of the engine globals for one test: `ERP_STORE` starts
+/// empty and the ERP audit log is written under a private root (`dir`),
+/// removed on drop. Tests that commit through `engine` hold one.
+pub struct EngineFixture {
+    pub dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl EngineFixture {
+    pub fn new() -> Self {
+        // A failed test must not fail the ones after it
+        let lock = ENGINE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = std::env::temp_dir().join(format!("erp_engine_{}", uuid::Uuid::new_v4()));
+        audit_log::configure(&dir, Rotation::Monthly);
+        *ERP_STORE.lock().unwrap_or_else(PoisonError::into_inner) = ErpStore::new();
+        Self { dir, _lock: lock }
+    }
+}
+
+impl Drop for EngineFixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A `MapSet` of `key` on `fragment_id`.
+pub fn set(fragment_id: &str, key: &str, value: serde_json::Value) -> Op {
+    Op::MapSet {
+        fragment_id: fragment_id.to_string(),
+        key: key.to_string(),
+        value,
+    }
+}
+
+/// An org1 envelope over `ops` from `actor_pubkey`, signed by `key` on the
+/// genesis prev hash.
+pub fn signed_envelope(
+    key: &SigningKey,
+    mutation_id: &str,
+    actor_pubkey: &str,
+    ops: Vec<Op>,
+    tx_id: Option<&str>,
+    lamport: u64,
+) -> MutationEnvelope {
+    sign_with_key(
+        key,
+        mutation_id.to_string(),
+        actor_pubkey,
+        "org1",
+        ops,
+        PolicyContext {
+            org_id: "org1".to_string(),
+            tx_id: tx_id.map(str::to_string),
+            tx_status: None,
+        },
+        "genesis".to_string(),
+        lamport,
+    )
+    .unwrap()
+}
+
+/// Builds a transaction header and its lines and inserts them into a store.
+pub struct TxBuilder {
+    header: TxHeader,
+    amounts: Vec<f64>,
+}
+
+impl TxBuilder {
+    /// A posted org1 tx in AUD dated 2026-10-01, without party or lines.
+    pub fn new(tx_id: &str, tx_type: TxType) -> Self {
+        Self {
+            header: TxHeader {
+                tx_id: tx_id.to_string(),
+                org_id: "org1".to_string(),
+                tx_type,
+                status: TxStatus::Posted,
+                party_id: None,
+                currency: "AUD".to_string(),
+                ref_number: None,
+                description: None,
+                tx_date: "2026-10-01".to_string(),
+                created_at_ms: 0,
+                created_by_pubkey: "pk".to_string(),
+                site_id: "primary".to_string(),
+                source_tx_id: None,
+            },
+            amounts: Vec::new(),
+        }
+    }
+
+    pub fn party(mut self, party_id: &str) -> Self {
+        self.header.party_id = Some(party_id.to_string());
+        self
+    }
+
+    pub fn date(mut self, tx_date: &str) -> Self {
+        self.header.tx_date = tx_date.to_string();
+        self
+    }
+
+    pub fn ref_number(mut self, ref_number: &str) -> Self {
+        self.header.ref_number = Some(ref_number.to_string());
+        self
+    }
+
+    /// One line per amount: qty 1 at `unit_price` = amount, ids `{tx_id}-l{i}`.
+    /// A journal instead gets a debit line `{tx_id}-l{i}` and a balancing
+    /// credit line `{tx_id}-c{i}` per amount, with `unit_price` 0.
+    pub fn lines(mut self, amounts: &[f64]) -> Self {
+        self.amounts = amounts.to_vec();
+        self
+    }
+
+    pub fn insert(self, store: &mut ErpStore) {
+        let tx_id = self.header.tx_id.clone();
+        let journal = self.header.tx_type == TxType::Journal;
+        let line = |line_id: String, unit_price: f64, debit: f64, credit: f64| TxLine {
+            line_id,
+            tx_id: tx_id.clone(),
+            item_id: None,
+            account_id: None,
+            description: None,
+            qty: 1.0,
+            unit_price,
+            inventory_effect: InventoryEffect::None,
+            move_ids: vec![],
+            tax_code: None,
+            tax_rate: 0.0,
+            debit_amount: debit,
+            credit_amount: credit,
+            source_line_id: None,
+            reason_code: None,
+        };
+        let mut lines = Vec::new();
+        for (i, amount) in self.amounts.iter().enumerate() {
+            if journal {
+                lines.push(line(format!("{}-l{}", tx_id, i), 0.0, *amount, 0.0));
+                lines.push(line(format!("{}-c{}", tx_id, i), 0.0, 0.0, *amount));
+            } else {
+                lines.push(line(format!("{}-l{}", tx_id, i), *amount, 0.0, 0.0));
+            }
+        }
+        for l in lines {
+            store.lines.insert(l.line_id.clone(), l);
+        }
+        store.transactions.insert(tx_id, self.header);
+    }
+}
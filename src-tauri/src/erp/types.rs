@@ -166,7 +166,9 @@ pub struct ActorContext {
     pub pubkey: String,
     pub role: Role,
     pub org_id: String,
-    /// Lamport clock — caller must track and increment per envelope.
+    /// Ignored by the engine, which assigns each actor's clock when it signs
+    /// (see hlc.rs); kept so existing callers still deserialize.
+    #[serde(default)]
     pub lamport: u64,
 }
 
@@ -202,6 +204,9 @@ pub struct TxHeader {
     pub created_by_pubkey: String,
     /// ADR-0001 §5 — optional site identifier, defaults to "primary"
     pub site_id: String,
+    /// Credit / debit notes only — the invoice tx this note adjusts
+    #[serde(default)]
+    pub source_tx_id: Option<String>,
 }
 
 /// A single business line on a transaction — stored in fragment `txline:{id}`.
@@ -220,6 +225,18 @@ pub struct TxLine {
     pub move_ids: Vec<String>,
     pub tax_code: Option<String>,
     pub tax_rate: f64,
+    /// Journal lines: explicit debit amount (exactly one of debit/credit is non-zero).
+    #[serde(default)]
+    pub debit_amount: f64,
+    /// Journal lines: explicit credit amount.
+    #[serde(default)]
+    pub credit_amount: f64,
+    /// Credit / debit note lines: the invoice line this line adjusts.
+    #[serde(default)]
+    pub source_line_id: Option<String>,
+    /// Stock adjust lines: "shrinkage" | "damage" | "found" (see stocktake::ReasonCode)
+    #[serde(default)]
+    pub reason_code: Option<String>,
 }
 
 /// An inventory movement — stored in fragment `invmove:{id}`.
@@ -302,6 +319,69 @@ pub enum Op {
     },
 }
 
+/// A `LinkAdd` relation between two fragments, as materialised by `apply_ops`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FragmentLink {
+    pub from_fragment: String,
+    pub to_fragment: String,
+    pub rel_type: String,
+}
+
+/// A `ProposalCreate` awaiting review — its ops are held, not applied.
+/// The review fields live in the `proposal:{id}` fragment (see proposals.rs);
+/// they are omitted while unset so older checkpoints keep their state hash.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingProposal {
+    pub proposal_id: String,
+    pub source_fragment: String,
+    pub ops: Vec<Op>,
+    pub rationale: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub org_id: String,
+    /// CAIO proposal type: "draft_invoice" | "reorder_proposal" | …
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub proposed_by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_ms: Option<i64>,
+    /// Absent = pending
+    #[serde(default, skip_serializing_if = "ProposalStatus::is_pending")]
+    pub status: ProposalStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decided_at_ms: Option<i64>,
+    /// Why a proposal was rejected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    #[default]
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+impl ProposalStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProposalStatus::Pending => "pending",
+            ProposalStatus::Accepted => "accepted",
+            ProposalStatus::Rejected => "rejected",
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        *self == ProposalStatus::Pending
+    }
+}
+
 /// Request payload for create_tx.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateTxRequest {
@@ -314,6 +394,9 @@ pub struct CreateTxRequest {
     pub tx_date: String,
     /// ADR-0001 §5: defaults to "primary" if absent
     pub site_id: Option<String>,
+    /// Credit / debit notes only — the invoice being credited or debited
+    #[serde(default)]
+    pub source_tx_id: Option<String>,
 }
 
 /// Request payload for add_line.
@@ -328,6 +411,15 @@ pub struct AddLineRequest {
     pub inventory_effect: String,
     pub tax_code: Option<String>,
     pub tax_rate: Option<f64>,
+    /// Journal lines only — explicit debit / credit columns
+    pub debit_amount: Option<f64>,
+    pub credit_amount: Option<f64>,
+    /// Credit / debit note lines only — the source invoice line being adjusted
+    #[serde(default)]
+    pub source_line_id: Option<String>,
+    /// Stock adjust lines only — "shrinkage" | "damage" | "found"
+    #[serde(default)]
+    pub reason_code: Option<String>,
 }
 
 /// Request payload for create_invmove.
@@ -397,3 +489,113 @@ pub struct CreatePartyRequest {
     pub contact: Option<String>,
     pub abn: Option<String>,
 }
+
+// ─── CoA management ──────────────────────────────────────────────────────────
+
+/// Request payload for erp_add_account.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAccountRequest {
+    pub code: String,
+    pub name: String,
+    pub acct_type: String, // "asset" | "liability" | "equity" | "income" | "expense"
+    pub normal_balance: String, // "debit" | "credit"
+    pub parent_code: Option<String>,
+    /// Header accounts aggregate children for reporting; defaults to false
+    pub is_header: Option<bool>,
+    /// Symbolic ledger role, e.g. "accounts_receivable" (see `coa::SYMBOLIC_ROLES`)
+    pub role: Option<String>,
+}
+
+// ─── Manual journals ─────────────────────────────────────────────────────────
+
+/// One line of a manual journal entry with explicit debit/credit columns.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalLineInput {
+    /// CoA account code
+    pub account_id: String,
+    #[serde(default)]
+    pub debit: f64,
+    #[serde(default)]
+    pub credit: f64,
+    pub description: Option<String>,
+}
+
+/// Request payload for erp_create_journal.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateJournalRequest {
+    pub org_id: String,
+    pub currency: String,
+    pub ref_number: Option<String>,
+    pub description: Option<String>,
+    pub tx_date: String,
+    pub site_id: Option<String>,
+    pub lines: Vec<JournalLineInput>,
+}
+
+/// Request payload for erp_create_journal_from_template.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateJournalFromTemplateRequest {
+    pub org_id: String,
+    pub currency: String,
+    pub ref_number: Option<String>,
+    pub description: Option<String>,
+    pub tx_date: String,
+    pub site_id: Option<String>,
+    pub template: crate::erp::journal::JournalTemplate,
+}
+
+impl CreateJournalFromTemplateRequest {
+    /// Expand the template into a plain journal request.
+    pub fn into_journal(self) -> Result<CreateJournalRequest, ErpError> {
+        let lines = self.template.lines()?;
+        Ok(CreateJournalRequest {
+            description: self
+                .description
+                .or_else(|| Some(self.template.label().to_string())),
+            org_id: self.org_id,
+            currency: self.currency,
+            ref_number: self.ref_number,
+            tx_date: self.tx_date,
+            site_id: self.site_id,
+            lines,
+        })
+    }
+}
+
+// ─── Stocktake ───────────────────────────────────────────────────────────────
+
+/// Request payload for erp_freeze_count_sheet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeCountSheetRequest {
+    pub org_id: String,
+    /// Defaults to "primary"
+    pub site_id: Option<String>,
+    /// `None` counts every location at the site
+    pub location_id: Option<String>,
+}
+
+/// Request payload for erp_record_count.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordCountRequest {
+    pub sheet_id: String,
+    pub item_id: String,
+    pub location_id: Option<String>,
+    pub counted_qty: f64,
+    /// "shrinkage" | "damage" | "found"; defaults by variance sign
+    pub reason_code: Option<String>,
+    /// Valuation for an item not on the sheet; defaults to its last receipt price
+    #[serde(default)]
+    pub unit_cost: Option<f64>,
+}
+
+/// Request payload for erp_reconcile_count_sheet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconcileCountSheetRequest {
+    pub sheet_id: String,
+    pub tx_date: String,
+    pub ref_number: Option<String>,
+    pub description: Option<String>,
+    /// Defaults to the currency of the org's latest transaction
+    #[serde(default)]
+    pub currency: Option<String>,
+}
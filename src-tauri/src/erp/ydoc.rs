@@ -0,0 +1,475 @@
+//! ydoc.rs — Per-org yrs mirror of the ERP fragments
+//!
+//! Each org has a yrs `Doc` whose root types are the ERP fragments
+//! (SCHEMA_SPEC §3): `tx:{id}:hdr`, `txline:{id}`, `posting:{id}`,
+//! `account:{code}`, `party:{id}` … are `Y.Map`s keyed by field, and
+//! `tx:{id}:lines` / `tx:{id}:postings` are `Y.Array`s. Values are the op
+//! values as plain JSON-like `Any`s.
+//!
+//! The engine stays the only writer. `engine::commit` mirrors the ops of
+//! every envelope it applied (`append_and_mirror`) and publishes the resulting yrs
+//! update (`subscribe`), which the collab server relays to the org's room
+//! (`erp:{org_id}`). Clients never write the doc directly: raw yrs updates
+//! to an ERP room are refused, and a client changes ERP state by sending a
+//! signed envelope (`submit_text`) that goes through the same validation as
+//! a satellite's (`satellite::accept_envelope`).
+//!
+//! Joining `erp:{org_id}` takes a `RoomToken` for that org, issued and
+//! signed by this node (`issue_room_token`); its role, not the client's
+//! own query parameters, decides whether the client may submit envelopes.
+//!
+//! Docs are built on first use by replaying the org's audit chain, so they
+//! always match the log rather than any cached state. When the store is
+//! rebuilt (satellite reconcile), `reset` rebuilds the doc without the
+//! rejected envelopes and publishes `DocEvent::Reset`: the collab server
+//! swaps the new doc into the live room and sends every client
+//! `reset_notice` before closing it. A client must then drop its local copy
+//! and rejoin with a fresh doc, since the rebuilt doc has new client IDs and
+//! merging it into the old one would duplicate array content.
+
+use ed25519_dalek::{Signature, Signer, Verifier};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+use yrs::{Any, Array, Doc, Map, ReadTxn, StateVector, Transact};
+
+use crate::erp::audit_log::{self, ErpAuditEntry};
+use crate::erp::envelope::{get_signing_key, MutationEnvelope};
+use crate::erp::errors::ErpError;
+use crate::erp::satellite::{self, EnvelopeResult, SubmitStatus};
+use crate::erp::types::{ActorContext, Op, Role};
+
+/// Collab rooms named `erp:{org_id}` carry an org's ERP doc.
+pub const ROOM_PREFIX: &str = "erp:";
+
+/// Updates buffered for slow subscribers before they lag.
+const UPDATE_CHANNEL_CAPACITY: usize = 1024;
+
+/// How long a `RoomToken` can be used to join a room.
+const ROOM_TOKEN_TTL_MS: i64 = 5 * 60 * 1000;
+
+/// A yrs update produced by mirroring one envelope.
+#[derive(Debug, Clone)]
+pub struct DocUpdate {
+    pub org_id: String,
+    pub mutation_id: String,
+    /// yrs v1 update
+    pub update: Vec<u8>,
+}
+
+/// What the collab server relays, in commit order.
+#[derive(Debug, Clone)]
+pub enum DocEvent {
+    Update(DocUpdate),
+    /// The org's doc was rebuilt; rooms must swap it in and resync clients.
+    Reset {
+        org_id: String,
+    },
+}
+
+lazy_static! {
+    static ref DOCS: Mutex<HashMap<String, Doc>> = Mutex::new(HashMap::new());
+    static ref UPDATES: broadcast::Sender<DocEvent> = broadcast::channel(UPDATE_CHANNEL_CAPACITY).0;
+}
+
+/// The org an ERP room belongs to, if `room` is one.
+pub fn room_org(room: &str) -> Option<&str> {
+    room.strip_prefix(ROOM_PREFIX).filter(|org| !org.is_empty())
+}
+
+pub fn room_name(org_id: &str) -> String {
+    format!("{}{}", ROOM_PREFIX, org_id)
+}
+
+/// The org's mirror doc, built from its audit chain on first use.
+pub fn doc(org_id: &str) -> Doc {
+    let mut docs = DOCS.lock().unwrap();
+    docs.entry(org_id.to_string())
+        .or_insert_with(|| build(org_id, &HashSet::new()))
+        .clone()
+}
+
+fn build(org_id: &str, excluded: &HashSet<String>) -> Doc {
+    let doc = Doc::new();
+    for entry in audit_log::read_chain::<ErpAuditEntry>(org_id) {
+        if !excluded.contains(&entry.envelope.mutation_id) {
+            apply_ops(&doc, &entry.envelope.ops);
+        }
+    }
+    doc
+}
+
+/// Rebuild the org's doc from its audit chain without the `excluded`
+/// envelopes (after the store itself was rebuilt, e.g. satellite reconcile)
+/// and tell the collab server to swap it into the live room.
+pub fn reset(org_id: &str, excluded: &HashSet<String>) {
+    let mut docs = DOCS.lock().unwrap();
+    docs.insert(org_id.to_string(), build(org_id, excluded));
+    // Sent under the docs lock, so it is ordered with the updates
+    let _ = UPDATES.send(DocEvent::Reset {
+        org_id: org_id.to_string(),
+    });
+}
+
+/// The text frame sent to clients of a room whose doc was reset.
+pub fn reset_notice(org_id: &str) -> String {
+    serde_json::json!({ "kind": "erp_reset", "org_id": org_id }).to_string()
+}
+
+/// Receive the updates `append_and_mirror` produces and doc resets, in
+/// commit order.
+pub fn subscribe() -> broadcast::Receiver<DocEvent> {
+    UPDATES.subscribe()
+}
+
+/// Run `append` (the audit append of an applied envelope), then mirror the
+/// envelope into its org's doc and publish the update. Called from
+/// `engine::commit`. Both run under the docs lock, so a doc built from the
+/// chain in between cannot already hold the envelope and get it twice.
+/// Docs not built yet are skipped; they pick the envelope up from the chain
+/// when built.
+pub fn append_and_mirror(envelope: &MutationEnvelope, append: impl FnOnce()) {
+    let docs = DOCS.lock().unwrap();
+    append();
+    let org_id = audit_log::org_of(envelope);
+    let Some(doc) = docs.get(&org_id) else {
+        return;
+    };
+    let update = apply_ops(doc, &envelope.ops);
+    if !update.is_empty() {
+        // No subscribers (no collab server) is fine
+        let _ = UPDATES.send(DocEvent::Update(DocUpdate {
+            org_id,
+            mutation_id: envelope.mutation_id.clone(),
+            update,
+        }));
+    }
+}
+
+/// Apply fragment ops to `doc`; returns the yrs update they produced.
+/// Links and proposals are engine relations, not fragments, and are skipped.
+fn apply_ops(doc: &Doc, ops: &[Op]) -> Vec<u8> {
+    let before = doc.transact().state_vector();
+    for op in ops {
+        match op {
+            Op::MapSet {
+                fragment_id,
+                key,
+                value,
+            } => {
+                let map = doc.get_or_insert_map(fragment_id.as_str());
+                map.insert(&mut doc.transact_mut(), key.as_str(), json_any(value));
+            }
+            Op::MapDel { fragment_id, key } => {
+                let map = doc.get_or_insert_map(fragment_id.as_str());
+                map.remove(&mut doc.transact_mut(), key);
+            }
+            Op::ArrayInsert {
+                fragment_id,
+                index,
+                values,
+            } => {
+                let array = doc.get_or_insert_array(fragment_id.as_str());
+                let mut txn = doc.transact_mut();
+                let index = (*index).min(array.len(&txn));
+                array.insert_range(&mut txn, index, values.iter().map(json_any));
+            }
+            Op::ArrayDelete {
+                fragment_id,
+                index,
+                len,
+            } => {
+                let array = doc.get_or_insert_array(fragment_id.as_str());
+                let mut txn = doc.transact_mut();
+                let size = array.len(&txn);
+                let index = (*index).min(size);
+                let len = (*len).min(size - index);
+                if len > 0 {
+                    array.remove_range(&mut txn, index, len);
+                }
+            }
+            Op::LinkAdd { .. } | Op::ProposalCreate { .. } => {}
+        }
+    }
+    let txn = doc.transact();
+    if txn.state_vector() == before {
+        Vec::new()
+    } else {
+        txn.encode_diff_v1(&before)
+    }
+}
+
+fn json_any(value: &serde_json::Value) -> Any {
+    Any::from_json(&value.to_string()).unwrap_or(Any::Null)
+}
+
+/// The org doc's state as a yrs v1 update, relative to `since`.
+pub fn encode_state(org_id: &str, since: &StateVector) -> Vec<u8> {
+    doc(org_id).transact().encode_diff_v1(since)
+}
+
+// ─── Room access ─────────────────────────────────────────────────────────────
+
+/// Grants one actor access to one org's ERP room, signed by this node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomToken {
+    pub org_id: String,
+    pub actor_pubkey: String,
+    pub role: Role,
+    pub expires_at_ms: i64,
+    /// Node key signature over `signing_payload`
+    pub signature: String,
+}
+
+impl RoomToken {
+    /// "room1" || org_id || 0 || actor_pubkey || 0 || role || expires_at_ms_be_bytes
+    fn signing_payload(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        payload.extend_from_slice(b"room1");
+        payload.extend_from_slice(self.org_id.as_bytes());
+        payload.push(0);
+        payload.extend_from_slice(self.actor_pubkey.as_bytes());
+        payload.push(0);
+        payload.extend_from_slice(self.role.as_str().as_bytes());
+        payload.extend_from_slice(&self.expires_at_ms.to_be_bytes());
+        payload
+    }
+
+    /// Whether the holder may submit envelopes, not just read.
+    pub fn can_write(&self) -> bool {
+        self.role != Role::Auditor
+    }
+}
+
+/// Issue a token for `actor` to join its org's ERP room.
+pub fn issue_room_token(actor: &ActorContext) -> Result<RoomToken, ErpError> {
+    if actor.org_id.is_empty() || actor.role == Role::Engine {
+        return Err(ErpError::AbacDeny(format!(
+            "role {} of org '{}' cannot join ERP rooms",
+            actor.role.as_str(),
+            actor.org_id
+        )));
+    }
+    let mut token = RoomToken {
+        org_id: actor.org_id.clone(),
+        actor_pubkey: actor.pubkey.clone(),
+        role: actor.role.clone(),
+        expires_at_ms: chrono::Utc::now().timestamp_millis() + ROOM_TOKEN_TTL_MS,
+        signature: String::new(),
+    };
+    token.signature = hex::encode(get_signing_key().sign(&token.signing_payload()).to_bytes());
+    Ok(token)
+}
+
+/// Check a `RoomToken` (JSON, as sent in the `erpToken` query parameter)
+/// presented to join the room of `org_id`.
+pub fn check_room_token(token_json: &str, org_id: &str) -> Result<RoomToken, ErpError> {
+    let token: RoomToken = serde_json::from_str(token_json)
+        .map_err(|e| ErpError::AbacDeny(format!("ERP room token unreadable: {}", e)))?;
+    let sig: [u8; 64] = hex::decode(&token.signature)
+        .ok()
+        .and_then(|b| b.try_into().ok())
+        .ok_or_else(|| ErpError::SigInvalid("ERP room token signature invalid".to_string()))?;
+    get_signing_key()
+        .verifying_key()
+        .verify(&token.signing_payload(), &Signature::from_bytes(&sig))
+        .map_err(|e| ErpError::SigInvalid(format!("ERP room token: {}", e)))?;
+    if token.org_id != org_id {
+        return Err(ErpError::AbacDeny(format!(
+            "token is for org {}, not {}",
+            token.org_id, org_id
+        )));
+    }
+    if token.expires_at_ms < chrono::Utc::now().timestamp_millis() {
+        return Err(ErpError::AbacDeny("ERP room token expired".to_string()));
+    }
+    if token.role == Role::Engine {
+        return Err(ErpError::AbacDeny(
+            "engine role cannot join ERP rooms".to_string(),
+        ));
+    }
+    Ok(token)
+}
+
+// ─── Client submissions ──────────────────────────────────────────────────────
+
+/// Text frames a client sends to an ERP room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ErpRoomRequest {
+    /// Apply a signed envelope (signed by an actor enrolled on this node)
+    Envelope { envelope: MutationEnvelope },
+}
+
+/// Reply to an `ErpRoomRequest`, sent to the submitting client only. The
+/// resulting doc update reaches every client of the room separately.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErpRoomReply {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(flatten)]
+    pub result: EnvelopeResult,
+}
+
+/// Handle a text frame sent to the ERP room of `org_id`.
+pub fn submit_text(org_id: &str, text: &str) -> ErpRoomReply {
+    let result = match serde_json::from_str::<ErpRoomRequest>(text) {
+        Ok(ErpRoomRequest::Envelope { envelope }) if envelope.org_id != org_id => {
+            EnvelopeResult::rejected(
+                &envelope.mutation_id,
+                &ErpError::AbacDeny(format!(
+                    "envelope for org {} sent to the room of org {}",
+                    envelope.org_id, org_id
+                )),
+            )
+        }
+        Ok(ErpRoomRequest::Envelope { envelope }) => satellite::accept_envelope(&envelope),
+        Err(e) => EnvelopeResult::rejected(
+            "",
+            &ErpError::ValidationFail(format!("malformed ERP room request: {}", e)),
+        ),
+    };
+    ErpRoomReply {
+        kind: "envelope_result".to_string(),
+        result,
+    }
+}
+
+impl ErpRoomReply {
+    pub fn accepted(&self) -> bool {
+        self.result.status != SubmitStatus::Rejected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::erp::test_support::{set, EngineFixture};
+    use yrs::updates::decoder::Decode;
+    use yrs::Update;
+
+    #[test]
+    fn test_updates_replicate_fragments() {
+        let primary = Doc::new();
+        let ops = vec![
+            set("tx:t1:hdr", "status", serde_json::json!("draft")),
+            set("tx:t1:hdr", "memo", serde_json::json!("rent")),
+            Op::ArrayInsert {
+                fragment_id: "tx:t1:lines".to_string(),
+                index: 5,
+                values: vec![serde_json::json!("l1"), serde_json::json!("l2")],
+            },
+        ];
+        let first = apply_ops(&primary, &ops);
+        let second = apply_ops(
+            &primary,
+            &[
+                Op::MapDel {
+                    fragment_id: "tx:t1:hdr".to_string(),
+                    key: "memo".to_string(),
+                },
+                Op::ArrayDelete {
+                    fragment_id: "tx:t1:lines".to_string(),
+                    index: 0,
+                    len: 9,
+                },
+            ],
+        );
+        // Nothing to mirror
+        assert!(apply_ops(&primary, &[]).is_empty());
+
+        let replica = Doc::new();
+        {
+            let mut txn = replica.transact_mut();
+            txn.apply_update(Update::decode_v1(&first).unwrap());
+        }
+        {
+            let txn = replica.transact();
+            let hdr = txn.get_map("tx:t1:hdr").unwrap();
+            assert_eq!(hdr.get(&txn, "status").unwrap().to_string(&txn), "draft");
+            assert_eq!(txn.get_array("tx:t1:lines").unwrap().len(&txn), 2);
+        }
+        {
+            let mut txn = replica.transact_mut();
+            txn.apply_update(Update::decode_v1(&second).unwrap());
+        }
+        let txn = replica.transact();
+        assert!(txn
+            .get_map("tx:t1:hdr")
+            .unwrap()
+            .get(&txn, "memo")
+            .is_none());
+        assert_eq!(txn.get_array("tx:t1:lines").unwrap().len(&txn), 0);
+    }
+
+    #[test]
+    fn test_reset_swaps_doc_and_notifies() {
+        // Reads the chain from a private audit root
+        let _engine = EngineFixture::new();
+        let org = "org-ydoc-reset";
+        let stale = doc(org);
+        apply_ops(
+            &stale,
+            &[set("tx:t1:hdr", "status", serde_json::json!("draft"))],
+        );
+        let mut events = subscribe();
+
+        reset(org, &HashSet::new());
+        match events.try_recv().unwrap() {
+            DocEvent::Reset { org_id } => assert_eq!(org_id, org),
+            other => panic!("expected a reset, got {:?}", other),
+        }
+        // Rebuilt from the (empty) chain, not the stale doc
+        let fresh = doc(org);
+        assert!(fresh.transact().get_map("tx:t1:hdr").is_none());
+        let notice: serde_json::Value = serde_json::from_str(&reset_notice(org)).unwrap();
+        assert_eq!(notice["kind"], "erp_reset");
+        assert_eq!(notice["org_id"], org);
+    }
+
+    #[test]
+    fn test_room_requests() {
+        assert_eq!(room_org("erp:org1"), Some("org1"));
+        assert_eq!(room_org("erp:"), None);
+        assert_eq!(room_org("doc-1"), None);
+
+        let reply = submit_text("org1", "{\"type\":\"envelope\"}");
+        assert!(!reply.accepted());
+        assert_eq!(
+            reply.result.error_code.as_deref(),
+            Some("ERR_VALIDATION_FAIL")
+        );
+    }
+
+    #[test]
+    fn test_room_token_gates_org_and_role() {
+        let actor = ActorContext {
+            pubkey: "auditor1".to_string(),
+            role: Role::Auditor,
+            org_id: "org1".to_string(),
+            lamport: 0,
+        };
+        let token = issue_room_token(&actor).unwrap();
+        let json = serde_json::to_string(&token).unwrap();
+        let checked = check_room_token(&json, "org1").unwrap();
+        assert!(!checked.can_write());
+        assert!(check_room_token(&json, "org2").is_err());
+
+        // Role edited after signing
+        let mut forged = token.clone();
+        forged.role = Role::OwnerAdmin;
+        let forged = serde_json::to_string(&forged).unwrap();
+        assert_eq!(
+            check_room_token(&forged, "org1").unwrap_err().code(),
+            "ERR_SIG_INVALID"
+        );
+
+        let engine = ActorContext {
+            role: Role::Engine,
+            ..actor
+        };
+        assert!(issue_room_token(&engine).is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use std::sync::Mutex;
@@ -20,6 +20,8 @@ pub mod erp;
 // [Phase 5] Token Revocation System
 lazy_static::lazy_static! {
     static ref REVOKED_TOKENS: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+    /// token_id → user id, so alert responses can revoke a user's tokens
+    static ref ISSUED_TOKENS: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
 }
 
 // ==========================================
@@ -307,6 +309,11 @@ fn request_capability_token(req: CapabilityRequest) -> Result<CapabilityToken, S
             let token_id = format!("cap-{}", Uuid::new_v4());
             let signature = sign_token(&token_id);
 
+            ISSUED_TOKENS
+                .lock()
+                .unwrap()
+                .insert(token_id.clone(), req.user.id.clone());
+
             let token = CapabilityToken {
                 token_id,
                 expires_at: (chrono::Utc::now() + chrono::Duration::seconds(300)).to_rfc3339(),
@@ -337,6 +344,20 @@ fn revoke_capability_token(token_id: String) -> bool {
     true
 }
 
+/// Revoke every capability token issued to `user_id` (security alert response).
+/// Returns the number of tokens revoked.
+fn revoke_tokens_for_user(user_id: &str) -> usize {
+    let issued = ISSUED_TOKENS.lock().unwrap();
+    let mut revoked = REVOKED_TOKENS.lock().unwrap();
+    let mut count = 0;
+    for (token_id, _) in issued.iter().filter(|(_, owner)| *owner == user_id) {
+        if revoked.insert(token_id.clone()) {
+            count += 1;
+        }
+    }
+    count
+}
+
 // ==========================================
 // [Phase 4] Cryptographic Key Management
 // ==========================================
@@ -422,6 +443,73 @@ fn get_audit_log(limit: Option<usize>) -> Result<Vec<audit::AuditEvent>, String>
     audit::read_log(limit.unwrap_or(100)).map_err(|e| e.to_string())
 }
 
+/// The shipper config with its `authorization` redacted (owner_admin only).
+#[tauri::command]
+fn get_audit_shipper_config(
+    actor: erp::types::ActorContext,
+) -> Result<audit::shipper::ShipperConfig, String> {
+    erp::tauri_api::check_node_manage(&actor).map_err(|e| e.to_string())?;
+    Ok(audit::shipper::config().redacted())
+}
+
+/// Replace the shipper config (owner_admin only).
+#[tauri::command]
+fn set_audit_shipper_config(
+    actor: erp::types::ActorContext,
+    config: audit::shipper::ShipperConfig,
+) -> Result<(), String> {
+    erp::tauri_api::check_node_manage(&actor).map_err(|e| e.to_string())?;
+    audit::shipper::set_config(config).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn get_security_alerts() -> Vec<audit::alerts::Alert> {
+    audit::alerts::active_alerts()
+}
+
+#[tauri::command]
+fn acknowledge_security_alert(alert_id: String) -> bool {
+    audit::alerts::acknowledge(&alert_id)
+}
+
+/// The security alert rules (owner_admin only).
+#[tauri::command]
+fn get_alert_rules(
+    actor: erp::types::ActorContext,
+) -> Result<Vec<audit::alerts::AlertRule>, String> {
+    erp::tauri_api::check_node_manage(&actor).map_err(|e| e.to_string())?;
+    Ok(audit::alerts::rules())
+}
+
+/// Replace the security alert rules (owner_admin only).
+#[tauri::command]
+fn set_alert_rules(
+    actor: erp::types::ActorContext,
+    rules: Vec<audit::alerts::AlertRule>,
+) -> Result<(), String> {
+    erp::tauri_api::check_node_manage(&actor).map_err(|e| e.to_string())?;
+    audit::alerts::set_rules(rules).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+fn verify_audit_log() -> bool {
+    audit::verify_log()
+}
+
+#[tauri::command]
+fn get_audit_timeline(
+    from_ms: Option<i64>,
+    to_ms: Option<i64>,
+    limit: Option<usize>,
+) -> Result<Vec<audit::TimelineEvent>, String> {
+    audit::timeline(
+        from_ms.unwrap_or(i64::MIN),
+        to_ms.unwrap_or(i64::MAX),
+        limit.unwrap_or(100),
+    )
+    .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 fn verify_block_signature(
     block_id: String,
@@ -833,7 +921,63 @@ pub fn run() {
                 let _ = handle_awareness.emit("awareness-update-remote", event.payload());
             });
 
-            // 3. Auto-start WebSocket collaboration server
+            // 3. Initialise ERP SQLite store (Phase B M10)
+            // Resolve platform app-data dir then open/warm corngr.db. This also
+            // configures the audit dir, so it runs before anything logs.
+            {
+                use tauri::Manager;
+                let app_data_dir = app
+                    .path()
+                    .app_data_dir()
+                    .unwrap_or_else(|_| std::path::PathBuf::from("."));
+                let db_path = app_data_dir.join("corngr.db");
+                crate::erp::engine::init_erp_db(&db_path);
+                audit::migrate_legacy_log();
+
+                // 4. Start the audit shipper (drains the on-disk outbox)
+                audit::shipper::configure(&app_data_dir);
+
+                // 5. Security alert rules and their automatic responses
+                audit::alerts::configure(&app_data_dir);
+                audit::alerts::register_responder(
+                    audit::alerts::AlertAction::RevokeTokens,
+                    Arc::new(|alert| revoke_tokens_for_user(&alert.user_id) > 0),
+                );
+                let ws_state = server_state.clone();
+                audit::alerts::register_responder(
+                    audit::alerts::AlertAction::DisconnectWs,
+                    Arc::new(move |alert| {
+                        // Responders run synchronously (often on a runtime
+                        // thread), so only free locks are taken: the alert
+                        // then records whether connections were closed
+                        if let Ok(state) = ws_state.try_read() {
+                            if let Some(closed) = state
+                                .server
+                                .as_ref()
+                                .and_then(|s| s.try_disconnect_user(&alert.user_id))
+                            {
+                                return closed > 0;
+                            }
+                        }
+                        // Busy: disconnect once the locks are free, but do not
+                        // report it as done
+                        let ws_state = ws_state.clone();
+                        let user_id = alert.user_id.clone();
+                        tauri::async_runtime::spawn(async move {
+                            if let Some(server) = &ws_state.read().await.server {
+                                server.disconnect_user(&user_id).await;
+                            }
+                        });
+                        false
+                    }),
+                );
+
+                // 6. Primary / satellite node mode (erp_node.json)
+                crate::erp::satellite::configure(&app_data_dir);
+            }
+
+            // 7. Auto-start WebSocket collaboration server (its events go to
+            // the audit dir configured above)
             let server_state_clone = server_state.clone();
             tauri::async_runtime::spawn(async move {
                 println!("🚀 Auto-starting WebSocket collaboration server...");
@@ -849,18 +993,6 @@ pub fn run() {
                 }
             });
 
-            // 4. Initialise ERP SQLite store (Phase B M10)
-            // Resolve platform app-data dir then open/warm corngr.db
-            {
-                use tauri::Manager;
-                let app_data_dir = app
-                    .path()
-                    .app_data_dir()
-                    .unwrap_or_else(|_| std::path::PathBuf::from("."));
-                let db_path = app_data_dir.join("corngr.db");
-                crate::erp::engine::init_erp_db(&db_path);
-            }
-
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -875,6 +1007,14 @@ pub fn run() {
             sign_block,
             verify_block_signature,
             get_audit_log,
+            verify_audit_log,
+            get_audit_timeline,
+            get_audit_shipper_config,
+            set_audit_shipper_config,
+            get_security_alerts,
+            acknowledge_security_alert,
+            get_alert_rules,
+            set_alert_rules,
             list_documents,
             marketplace::fetch_market_index,
             marketplace::install_package,
@@ -887,19 +1027,46 @@ pub fn run() {
             tauri_commands::list_active_rooms,
             // ERP Engine commands (Phase A M1+M2)
             erp::tauri_api::erp_create_tx,
+            erp::tauri_api::erp_create_journal,
+            erp::tauri_api::erp_create_journal_from_template,
             erp::tauri_api::erp_add_line,
             erp::tauri_api::erp_create_invmove,
             erp::tauri_api::erp_generate_postings,
             erp::tauri_api::erp_post_tx,
             erp::tauri_api::erp_get_tx_snapshot,
+            erp::tauri_api::erp_invoice_balance,
+            erp::tauri_api::erp_stock_on_hand,
+            erp::tauri_api::erp_freeze_count_sheet,
+            erp::tauri_api::erp_record_count,
+            erp::tauri_api::erp_reconcile_count_sheet,
+            erp::tauri_api::erp_list_count_sheets,
             erp::tauri_api::erp_verify_audit_chain,
             // ERP Audit Explorer commands (Phase A M4)
             erp::tauri_api::erp_get_audit_log,
+            erp::tauri_api::erp_query_audit_log,
             erp::tauri_api::erp_time_travel,
+            erp::tauri_api::erp_time_travel_trial_balance,
+            erp::tauri_api::erp_time_travel_tx,
+            erp::tauri_api::erp_time_travel_stock,
+            erp::tauri_api::erp_time_travel_diff,
+            erp::tauri_api::erp_list_checkpoints,
+            erp::tauri_api::erp_consistency_check,
+            erp::tauri_api::erp_audit_proof,
+            erp::tauri_api::erp_verify_audit_proof,
+            erp::tauri_api::erp_list_audit_roots,
+            erp::tauri_api::erp_export_evidence,
+            erp::tauri_api::erp_verify_evidence,
             // ERP CoA + Ledger commands (Phase A M5)
             erp::tauri_api::erp_seed_coa,
             erp::tauri_api::erp_list_coa,
+            erp::tauri_api::erp_export_coa,
             erp::tauri_api::erp_get_ledger_summary,
+            // ERP CoA management
+            erp::tauri_api::erp_add_account,
+            erp::tauri_api::erp_rename_account,
+            erp::tauri_api::erp_deactivate_account,
+            erp::tauri_api::erp_reparent_account,
+            erp::tauri_api::erp_get_coa_tree,
             // ERP Shatter Import command (Phase A M6)
             erp::tauri_api::erp_bulk_import,
             // ERP Post Ceremony command (Phase A M7)
@@ -908,15 +1075,44 @@ pub fn run() {
             erp::tauri_api::erp_list_txs,
             // ERP party master (Phase A M9)
             erp::tauri_api::erp_create_party,
+            erp::tauri_api::erp_apply_remote_envelope,
             erp::tauri_api::erp_list_parties,
             // ERP Binary Parquet export (Phase B M11)
             erp::tauri_api::erp_export_parquet,
             erp::tauri_api::erp_export_postings_parquet,
             // ERP Local LLM CAIO (Phase B M12)
             erp::tauri_api::erp_caio_query,
+            // ERP primary / satellite nodes (Phase B)
+            erp::tauri_api::erp_node_status,
+            erp::tauri_api::erp_set_node_mode,
+            erp::tauri_api::erp_enroll_satellite,
+            erp::tauri_api::erp_remove_satellite,
+            erp::tauri_api::erp_satellite_sync,
+            erp::tauri_api::erp_room_token,
+            erp::tauri_api::erp_list_conflicts,
+            erp::tauri_api::erp_resolve_conflict,
+            erp::tauri_api::erp_fragment_versions,
+            erp::tauri_api::erp_actor_clock,
+            erp::tauri_api::erp_list_proposals,
+            erp::tauri_api::erp_accept_proposal,
+            erp::tauri_api::erp_reject_proposal,
+            erp::tauri_api::erp_get_llm_config,
+            erp::tauri_api::erp_set_llm_config,
+            erp::tauri_api::erp_detect_anomalies,
+            erp::tauri_api::erp_get_detector_config,
+            erp::tauri_api::erp_set_detector_config,
+            erp::tauri_api::erp_reorder_status,
+            erp::tauri_api::erp_list_reorder_policies,
+            erp::tauri_api::erp_set_reorder_policy,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                // Last attempt to ship queued audit events before exiting
+                tauri::async_runtime::block_on(audit::shipper::shutdown());
+            }
+        });
 }
 
 #[cfg(test)]
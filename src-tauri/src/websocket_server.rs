@@ -1,4 +1,5 @@
 use crate::audit::{log_event, AuditEvent};
+use crate::erp::ydoc;
 use futures_util::{SinkExt, StreamExt};
 use std::collections::HashMap;
 use std::fs;
@@ -9,7 +10,8 @@ use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::RwLock;
 use tokio_tungstenite::{
     accept_hdr_async,
-    tungstenite::handshake::server::{Request, Response},
+    tungstenite::handshake::server::{ErrorResponse, Request, Response},
+    tungstenite::http::StatusCode,
     tungstenite::Message,
 };
 use y_sync::sync::{Message as YSyncMessage, SyncMessage};
@@ -28,6 +30,16 @@ pub struct Room {
 
 impl Room {
     fn new(name: String) -> Self {
+        // ERP rooms share the engine's mirror doc, which is rebuilt from the
+        // audit log rather than a snapshot
+        if let Some(org_id) = ydoc::room_org(&name) {
+            let doc = ydoc::doc(org_id);
+            return Self {
+                name,
+                doc,
+                clients: Vec::new(),
+            };
+        }
         let doc = Doc::new();
         // Try to load existing data
         if let Ok(data) = load_snapshot(&name) {
@@ -99,6 +111,32 @@ impl CollabServer {
 
         println!("🚀 Collaboration WebSocket server listening on: {}", addr);
 
+        // Relay ERP doc updates (produced only by the engine) to ERP rooms
+        let server = Arc::clone(&self);
+        tokio::spawn(async move {
+            let mut updates = ydoc::subscribe();
+            loop {
+                match updates.recv().await {
+                    Ok(ydoc::DocEvent::Update(u)) => {
+                        let msg = YSyncMessage::Sync(SyncMessage::Update(u.update)).encode_v1();
+                        let _ = server
+                            .broadcast_to_room(&ydoc::room_name(&u.org_id), msg, 0)
+                            .await;
+                    }
+                    Ok(ydoc::DocEvent::Reset { org_id }) => {
+                        server.reset_erp_room(&org_id).await;
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
+                        eprintln!(
+                            "⚠️  ERP room relay skipped {} update(s); clients resync on reconnect",
+                            n
+                        );
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
         loop {
             match listener.accept().await {
                 Ok((stream, addr)) => {
@@ -163,6 +201,7 @@ impl CollabServer {
             // Format: "?userId=user123&userRole=editor"
             let mut user_id = "anonymous".to_string();
             let mut user_role = "viewer".to_string(); // Default to most restrictive
+            let mut erp_token = None;
 
             // Parse query string - handle URL-encoded values
             for param in query.split('&') {
@@ -194,11 +233,40 @@ impl CollabServer {
                                 );
                             }
                         }
+                        "erpToken" => erp_token = Some(decoded_value),
                         _ => {}
                     }
                 }
             }
 
+            // ERP rooms: identity and role come from a node-signed token for
+            // the room's org, never from the query parameters above
+            if let Some(org_id) = ydoc::room_org(extracted_room) {
+                match ydoc::check_room_token(erp_token.as_deref().unwrap_or_default(), org_id) {
+                    Ok(token) => {
+                        user_id = token.actor_pubkey.clone();
+                        user_role = if token.can_write() {
+                            "editor"
+                        } else {
+                            "auditor"
+                        }
+                        .to_string();
+                    }
+                    Err(e) => {
+                        log_event(AuditEvent::new(
+                            &user_id,
+                            "WS_REJECTED",
+                            extracted_room,
+                            &e.to_string(),
+                            "WARN",
+                        ));
+                        let mut denied = ErrorResponse::new(Some(e.to_string()));
+                        *denied.status_mut() = StatusCode::FORBIDDEN;
+                        return Err(denied);
+                    }
+                }
+            }
+
             let mut user_info_guard = user_info_clone.lock().unwrap();
             *user_info_guard = (user_id.clone(), user_role.clone());
             drop(user_info_guard);
@@ -217,6 +285,7 @@ impl CollabServer {
 
         let room_name = room_name_arc.lock().unwrap().clone();
         let (user_id, user_role) = user_info_arc.lock().unwrap().clone();
+        let erp_org = ydoc::room_org(&room_name).map(str::to_string);
 
         let (mut ws_sender, mut ws_receiver) = ws_stream.split();
         let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<Message>();
@@ -238,7 +307,8 @@ impl CollabServer {
 
         let mut send_task = tokio::spawn(async move {
             while let Some(msg) = rx.recv().await {
-                if ws_sender.send(msg).await.is_err() {
+                let closing = matches!(msg, Message::Close(_));
+                if ws_sender.send(msg).await.is_err() || closing {
                     break;
                 }
             }
@@ -294,6 +364,30 @@ impl CollabServer {
                                 continue;  // Skip broadcasting and applying this update
                             }
 
+                            // ERP rooms: the doc is written by the engine only. Clients
+                            // read it via sync step 1 and change it via envelopes.
+                            if let Some(ref org_id) = erp_org {
+                                if is_write_op {
+                                    let _ = tx.send(Message::Text(
+                                        "ERROR: ERP rooms accept signed envelopes only".to_string(),
+                                    ));
+                                    log_event(AuditEvent::new(
+                                        &user_id,
+                                        "WRITE_REJECTED",
+                                        &room_name,
+                                        "Raw yrs update to an ERP room",
+                                        "WARN",
+                                    ));
+                                    continue;
+                                }
+                                if let Ok(YSyncMessage::Sync(SyncMessage::SyncStep1(sv))) = &decoded {
+                                    let diff = ydoc::encode_state(org_id, sv);
+                                    let reply = YSyncMessage::Sync(SyncMessage::SyncStep2(diff)).encode_v1();
+                                    let _ = tx.send(Message::Binary(reply));
+                                    continue;
+                                }
+                            }
+
                             // 1. Broadcast to others (raw protocol message)
                             if let Err(e) = self.broadcast_to_room(
                                 &room_name,
@@ -331,6 +425,54 @@ impl CollabServer {
                                 }
                             }
                         }
+                        Some(Ok(Message::Text(text))) if erp_org.is_some() => {
+                            let org_id = erp_org.as_deref().unwrap_or_default();
+                            if user_role == "auditor" || user_role == "viewer" {
+                                let _ = tx.send(Message::Text(format!(
+                                    "ERROR: Access Denied - Role '{}' has read-only access",
+                                    user_role
+                                )));
+                                log_event(AuditEvent::new(
+                                    &user_id,
+                                    "WRITE_REJECTED",
+                                    &room_name,
+                                    &format!("Role '{}' attempted ERP envelope", user_role),
+                                    "WARN",
+                                ));
+                                continue;
+                            }
+                            // The engine validates and applies (store lock, disk
+                            // I/O) off the async runtime; the doc update reaches
+                            // the room through the relay task
+                            let org = org_id.to_string();
+                            let reply = match tokio::task::spawn_blocking(move || {
+                                ydoc::submit_text(&org, &text)
+                            })
+                            .await
+                            {
+                                Ok(reply) => reply,
+                                Err(e) => {
+                                    eprintln!("❌ ERP envelope handling failed: {}", e);
+                                    continue;
+                                }
+                            };
+                            if !reply.accepted() {
+                                log_event(AuditEvent::new(
+                                    &user_id,
+                                    "ERP_ENVELOPE_REJECTED",
+                                    &room_name,
+                                    &format!(
+                                        "{}: {}",
+                                        reply.result.mutation_id,
+                                        reply.result.error_message.as_deref().unwrap_or_default()
+                                    ),
+                                    "WARN",
+                                ));
+                            }
+                            if let Ok(json) = serde_json::to_string(&reply) {
+                                let _ = tx.send(Message::Text(json));
+                            }
+                        }
                         Some(Ok(Message::Close(_))) | None => {
                             println!("👋 Client {} disconnected", addr);
                             break;
@@ -379,6 +521,10 @@ impl CollabServer {
         let txn = room.doc.transact();
         let state_vector = txn.state_vector();
         let update = txn.encode_diff_v1(&state_vector);
+        // ERP rooms start from the full mirror doc
+        let erp_update = ydoc::room_org(room_name)
+            .map(|_| txn.encode_state_as_update_v1(&StateVector::default()));
+        drop(txn);
 
         println!(
             "📥 Client {} (User: '{}', Role: '{}') joined room '{}' ({} clients)",
@@ -395,7 +541,11 @@ impl CollabServer {
             user_id, user_role, room_name
         );
 
-        if !update.is_empty() {
+        if let Some(update) = erp_update {
+            Ok(Some(
+                YSyncMessage::Sync(SyncMessage::Update(update)).encode_v1(),
+            ))
+        } else if !update.is_empty() {
             // Wrap update in Protocol Message so client understands it
             let msg = SyncMessage::Update(update).encode_v1();
             Ok(Some(msg))
@@ -445,6 +595,27 @@ impl CollabServer {
         Ok(())
     }
 
+    /// Swap the rebuilt doc of `org_id` into its live ERP room and make every
+    /// client resync: each gets `ydoc::reset_notice` and is disconnected, and
+    /// rejoins with a fresh doc.
+    async fn reset_erp_room(&self, org_id: &str) {
+        let mut rooms = self.rooms.write().await;
+        let Some(room) = rooms.get_mut(&ydoc::room_name(org_id)) else {
+            return;
+        };
+        room.doc = ydoc::doc(org_id);
+        let notice = ydoc::reset_notice(org_id);
+        for client in &room.clients {
+            let _ = client.tx.send(Message::Text(notice.clone()));
+            let _ = client.tx.send(Message::Close(None));
+        }
+        println!(
+            "🔄 ERP room '{}' reset; {} client(s) must resync",
+            room.name,
+            room.clients.len()
+        );
+    }
+
     /// Remove a client from a room
     async fn remove_client_from_room(&self, room_name: &str, client_id: u64) {
         let mut rooms = self.rooms.write().await;
@@ -466,6 +637,40 @@ impl CollabServer {
         }
     }
 
+    /// Close every connection of `user_id` (used by security alert
+    /// responses). Returns the number of connections closed.
+    pub async fn disconnect_user(&self, user_id: &str) -> usize {
+        Self::close_user(&*self.rooms.read().await, user_id)
+    }
+
+    /// `disconnect_user` for synchronous callers (alert responders); `None`
+    /// if the rooms are locked for writing right now.
+    pub fn try_disconnect_user(&self, user_id: &str) -> Option<usize> {
+        let rooms = self.rooms.try_read().ok()?;
+        Some(Self::close_user(&rooms, user_id))
+    }
+
+    fn close_user(rooms: &HashMap<String, Room>, user_id: &str) -> usize {
+        let mut closed = 0;
+        for room in rooms.values() {
+            for client in room.clients.iter().filter(|c| c.user_id == user_id) {
+                let _ = client.tx.send(Message::Text(
+                    "ERROR: Disconnected by security policy".to_string(),
+                ));
+                if client.tx.send(Message::Close(None)).is_ok() {
+                    closed += 1;
+                }
+            }
+        }
+        if closed > 0 {
+            println!(
+                "🚫 Disconnected {} connection(s) of user '{}'",
+                closed, user_id
+            );
+        }
+        closed
+    }
+
     /// Get server statistics
     pub async fn get_stats(&self) -> HashMap<String, usize> {
         let rooms = self.rooms.read().await;
@@ -179,6 +179,7 @@ export const CockpitDashboard: React.FC = () => {
             <CAIOSidebar
                 proposals={store.proposals}
                 briefingBullets={store.briefingBullets}
+                actor={store.actor}
                 onAccept={handleAcceptProposal}
                 onDismiss={store.dismissProposal}
             />
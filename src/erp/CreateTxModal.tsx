@@ -32,9 +32,18 @@ export const CreateTxModal: React.FC<CreateTxModalProps> = ({ onClose }) => {
     const [currency, setCurrency] = useState('AUD');
     const [refNumber, setRefNumber] = useState('');
     const [siteId, setSiteId] = useState('primary');
+    const [sourceTxId, setSourceTxId] = useState('');
     const [busy, setBusy] = useState(false);
     const [err, setErr] = useState<string | null>(null);
 
+    // Notes adjust a posted invoice: customer invoices for credit notes,
+    // supplier invoices for debit notes
+    const sourceType = txType === 'credit_note' ? 'invoice_out'
+        : txType === 'debit_note' ? 'invoice_in' : null;
+    const sourceInvoices = sourceType
+        ? store.transactions.filter(t => t.tx_type === sourceType && t.status === 'posted')
+        : [];
+
     const handleSubmit = useCallback(async (e: React.FormEvent) => {
         e.preventDefault();
         setBusy(true);
@@ -54,6 +63,7 @@ export const CreateTxModal: React.FC<CreateTxModalProps> = ({ onClose }) => {
             tx_date: txDate,
             ref_number: refNumber || undefined,
             site_id: siteId || 'primary',
+            source_tx_id: sourceType ? sourceTxId || undefined : undefined,
         };
 
         const result = await store.createTx(req);
@@ -63,7 +73,7 @@ export const CreateTxModal: React.FC<CreateTxModalProps> = ({ onClose }) => {
         } else {
             setErr('Failed to create transaction — check your inputs.');
         }
-    }, [store, txType, description, currency, txDate, refNumber, siteId, onClose]);
+    }, [store, txType, description, currency, txDate, refNumber, siteId, sourceType, sourceTxId, onClose]);
 
     // Close on overlay click or ESC
     const handleOverlayClick = (e: React.MouseEvent) => {
@@ -109,6 +119,24 @@ export const CreateTxModal: React.FC<CreateTxModalProps> = ({ onClose }) => {
                         </select>
                     </div>
 
+                    {sourceType && (
+                        <div className="modal-field">
+                            <label htmlFor="tx-source">Source Invoice</label>
+                            <select
+                                id="tx-source"
+                                className="modal-select"
+                                value={sourceTxId}
+                                onChange={e => setSourceTxId(e.target.value)}
+                                required
+                            >
+                                <option value="">Select a posted invoice…</option>
+                                {sourceInvoices.map(t => (
+                                    <option key={t.tx_id} value={t.tx_id}>{t.tx_id}</option>
+                                ))}
+                            </select>
+                        </div>
+                    )}
+
                     {/* Party picker */}
                     <div className="modal-field">
                         <label htmlFor="tx-party">Party (Customer / Supplier)</label>
@@ -29,7 +29,7 @@ export const AuditExplorer: React.FC<AuditExplorerProps> = ({ onClose }) => {
     const loadLog = useCallback(async () => {
         setLoading(true);
         try {
-            const res = await invoke<ApiResponse<AuditEntryView[]>>('erp_get_audit_log', { limit: 100 });
+            const res = await invoke<ApiResponse<AuditEntryView[]>>('erp_get_audit_log', { orgId: 'org_default', limit: 100 });
             if (res.ok && res.data) setEntries(res.data);
         } catch {
             // Tauri not available in browser — show empty state with placeholder
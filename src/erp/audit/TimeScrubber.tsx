@@ -27,6 +27,7 @@ export const TimeScrubber: React.FC<TimeScrubberProps> = ({ timestamps }) => {
         setLoading(true);
         try {
             const res = await invoke<ApiResponse<TimeTravelSnapshot>>('erp_time_travel', {
+                orgId: 'org_default',
                 targetTsMs: ts,
             });
             if (res.ok && res.data) setSnapshot(res.data);
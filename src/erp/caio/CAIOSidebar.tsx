@@ -1,6 +1,6 @@
 import React, { useState } from 'react';
 import { invoke } from '@tauri-apps/api/core';
-import type { CaioProposal, CreateTxRequest, ApiResponse } from '../types';
+import type { ActorContext, CaioProposal, CreateTxRequest, ApiResponse } from '../types';
 
 const PROPOSAL_ICONS: Record<string, string> = {
     reorder_proposal: '📦',
@@ -43,6 +43,8 @@ function llmToProposal(p: LlmProposal): CaioProposal {
 interface CAIOSidebarProps {
     proposals: CaioProposal[];
     briefingBullets: string[];
+    /** Drafts CAIO proposes are queued for review as this actor. */
+    actor: ActorContext;
     onAccept: (payload: Partial<CreateTxRequest>) => void;
     onDismiss: (id: string) => void;
 }
@@ -50,6 +52,7 @@ interface CAIOSidebarProps {
 export const CAIOSidebar: React.FC<CAIOSidebarProps> = ({
     proposals,
     briefingBullets,
+    actor,
     onAccept,
     onDismiss,
 }) => {
@@ -68,8 +71,9 @@ export const CAIOSidebar: React.FC<CAIOSidebarProps> = ({
         setLlmProposals(null);
         try {
             const res = await invoke<ApiResponse<CaioQueryResult>>('erp_caio_query', {
-                orgId: 'org_default',
+                orgId: actor.org_id,
                 userQuery: query.trim(),
+                actor,
             });
             if (res.ok && res.data) {
                 setLlmProposals(res.data.proposals);
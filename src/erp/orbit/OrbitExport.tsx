@@ -60,7 +60,7 @@ export const OrbitExport: React.FC<OrbitExportProps> = ({ onClose }) => {
         try {
             let entries: AuditEntry[] = [];
             try {
-                const res = await invoke<ApiResponse<AuditEntry[]>>('erp_get_audit_log', { limit: 1000 });
+                const res = await invoke<ApiResponse<AuditEntry[]>>('erp_get_audit_log', { orgId: 'org_default', limit: 1000 });
                 if (res.ok && res.data) entries = res.data;
             } catch {
                 entries = [{
@@ -196,7 +196,7 @@ export function useErpStore(): ErpStore {
             }
 
             // Audit chain
-            const chainRes = await invoke<ApiResponse<{ intact: boolean }>>('erp_verify_audit_chain');
+            const chainRes = await invoke<ApiResponse<{ intact: boolean }>>('erp_verify_audit_chain', { orgId: 'org_default' });
             setAuditChainIntact(chainRes.ok ? (chainRes.data?.intact ?? true) : true);
         } catch (e) {
             setError(String(e));
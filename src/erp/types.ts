@@ -67,6 +67,8 @@ export interface CreateTxRequest {
   description?: string;
   tx_date: string;
   site_id?: string;
+  /** Required on credit/debit notes: the posted invoice they adjust. */
+  source_tx_id?: string;
 }
 
 export interface AddLineRequest {
@@ -24,7 +24,7 @@ export const AuditBadge: React.FC<AuditBadgeProps> = ({ intact }) => {
             <div className="widget-sub" style={{ marginTop: 8 }}>
                 {intact
                     ? 'Merkle chain verified — all envelopes consistent'
-                    : '⚠ Audit log tampered — review erp_audit.jsonl'}
+                    : '⚠ Audit log tampered — review the erp_audit segments'}
             </div>
         </div>
     );